@@ -0,0 +1,222 @@
+//! Timings of the conversion steps on models generated at bench time, so the repository
+//! needs no large fixture files. Run with `cargo bench --features bench`, criterion prints
+//! the numbers and compares them with the previous run.
+
+use chisels_and_importers::{BlockPalette, Converter};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// The default palette of the repository
+const PALETTE: &[u8] = include_bytes!("../blocks.json");
+
+/// Colors of the generated models, indices 0 to 3 are used
+fn colors() -> Vec<dot_vox::Color> {
+    let mut colors = vec![
+        dot_vox::Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255
+        };
+        256
+    ];
+    for (index, [r, g, b]) in [[200, 40, 30], [90, 90, 90], [240, 240, 240], [30, 60, 200]]
+        .into_iter()
+        .enumerate()
+    {
+        colors[index] = dot_vox::Color { r, g, b, a: 255 };
+    }
+    colors
+}
+
+fn model(side: u32, voxels: Vec<dot_vox::Voxel>) -> dot_vox::Model {
+    dot_vox::Model {
+        size: dot_vox::Size {
+            x: side,
+            y: side,
+            z: side,
+        },
+        voxels,
+    }
+}
+
+/// 8 voxels on a side, every voxel filled, like a small decoration
+fn tiny_prop() -> dot_vox::Model {
+    let voxels = (0..512u32)
+        .map(|index| dot_vox::Voxel {
+            x: (index % 8) as u8,
+            y: (index / 8 % 8) as u8,
+            z: (index / 64) as u8,
+            i: (index % 4) as u8,
+        })
+        .collect();
+    model(8, voxels)
+}
+
+/// 128 voxels on a side with a solid ball, its colors in layers
+fn dense_statue() -> dot_vox::Model {
+    let mut voxels = Vec::new();
+    for x in 0..128u8 {
+        for y in 0..128u8 {
+            for z in 0..128u8 {
+                let distance = [x, y, z].map(|axis| (axis as i32 - 64).pow(2));
+                if distance.iter().sum::<i32>() < 62 * 62 {
+                    voxels.push(dot_vox::Voxel { x, y, z, i: z / 32 });
+                }
+            }
+        }
+    }
+    model(128, voxels)
+}
+
+/// 256 voxels on a side, the largest a model can be, with a ring of voxels around the
+/// middle, nearly every chunk is empty
+fn sparse_scene() -> dot_vox::Model {
+    let voxels = (0..1024u32)
+        .map(|step| {
+            let angle = step as f32 / 1024.0 * std::f32::consts::TAU;
+            dot_vox::Voxel {
+                x: (128.0 + 120.0 * angle.cos()) as u8,
+                y: (128.0 + 120.0 * angle.sin()) as u8,
+                z: 128,
+                i: (step % 4) as u8,
+            }
+        })
+        .collect();
+    model(256, voxels)
+}
+
+/// The model as a Magica Voxel file
+fn vox_file(model: &dot_vox::Model, colors: &[dot_vox::Color]) -> Vec<u8> {
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((content.len() as u32).to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(content);
+        bytes
+    }
+    let size = [model.size.x, model.size.y, model.size.z];
+    let mut xyzi = (model.voxels.len() as u32).to_le_bytes().to_vec();
+    for voxel in &model.voxels {
+        // Indices in the file start at 1
+        xyzi.extend([voxel.x, voxel.y, voxel.z, voxel.i + 1]);
+    }
+    let rgba: Vec<u8> = colors.iter().flat_map(|c| [c.r, c.g, c.b, c.a]).collect();
+    let mut children = chunk(b"SIZE", &size.map(u32::to_le_bytes).concat());
+    children.extend(chunk(b"XYZI", &xyzi));
+    children.extend(chunk(b"RGBA", &rgba));
+
+    let mut file = b"VOX ".to_vec();
+    file.extend(150u32.to_le_bytes());
+    file.extend(b"MAIN");
+    file.extend(0u32.to_le_bytes());
+    file.extend((children.len() as u32).to_le_bytes());
+    file.extend(children);
+    file
+}
+
+/// Every step of the conversion for each kind of model
+fn steps(c: &mut Criterion) {
+    let palette = BlockPalette::from_json(PALETTE).unwrap();
+    let converter = Converter::new(&palette);
+    let colors = colors();
+    let models = [
+        ("tiny prop", tiny_prop()),
+        ("dense 128³ statue", dense_statue()),
+        ("sparse 256³ scene", sparse_scene()),
+    ];
+
+    let mut group = c.benchmark_group("steps");
+    group.sample_size(10);
+    for (name, model) in &models {
+        let file = vox_file(model, &colors);
+        group.bench_with_input(BenchmarkId::new("parse", name), &file, |b, file| {
+            b.iter(|| dot_vox::load_bytes(file).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("match", name), model, |b, model| {
+            b.iter(|| converter.match_blocks(model, &colors))
+        });
+        let block_model = converter.match_blocks(model, &colors);
+        let chunk = block_model
+            .chunks()
+            .iter()
+            .find(|chunk| chunk.voxels > 0)
+            .unwrap();
+        group.bench_with_input(BenchmarkId::new("encode chunk", name), chunk, |b, chunk| {
+            b.iter(|| block_model.encode(chunk.offset).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("end to end", name), &file, |b, file| {
+            b.iter(|| {
+                let data = dot_vox::load_bytes(file).unwrap();
+                converter
+                    .convert_model(&data.models[0], &data.palette)
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Small props that all use the 255 colors of a gradient, like a folder of files exported
+/// with one shared Magica Voxel palette
+fn props() -> (Vec<dot_vox::Model>, Vec<dot_vox::Color>) {
+    let colors = (0..=255u8)
+        .map(|index| dot_vox::Color {
+            r: index,
+            g: index.wrapping_mul(7),
+            b: 255 - index,
+            a: 255,
+        })
+        .collect();
+    let models = (0..50u32)
+        .map(|model_index| {
+            let voxels = (0..4096u32)
+                .map(|index| dot_vox::Voxel {
+                    x: (index % 16) as u8,
+                    y: (index / 16 % 16) as u8,
+                    z: (index / 256) as u8,
+                    i: ((index + model_index) % 255) as u8,
+                })
+                .collect();
+            model(16, voxels)
+        })
+        .collect();
+    (models, colors)
+}
+
+/// Converting 50 files like a directory is converted, every file read and converted with a
+/// converter of its own. The palette is loaded once for the whole directory, so its cache
+/// keeps the matches of the first file, or for every file as before the palette was shared.
+fn shared_palette(c: &mut Criterion) {
+    let (models, colors) = props();
+    let files: Vec<_> = models
+        .iter()
+        .map(|model| vox_file(model, &colors))
+        .collect();
+    let convert = |palette: &BlockPalette, file: &[u8]| {
+        let data = dot_vox::load_bytes(file).unwrap();
+        Converter::new(palette)
+            .convert_model(&data.models[0], &data.palette)
+            .unwrap()
+    };
+    let mut group = c.benchmark_group("convert 50 files");
+    group.sample_size(10);
+    group.bench_function("shared palette", |b| {
+        b.iter(|| {
+            let palette = BlockPalette::from_json(PALETTE).unwrap();
+            for file in &files {
+                convert(&palette, file);
+            }
+        })
+    });
+    group.bench_function("palette per file", |b| {
+        b.iter(|| {
+            for file in &files {
+                convert(&BlockPalette::from_json(PALETTE).unwrap(), file);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, steps, shared_palette);
+criterion_main!(benches);
@@ -0,0 +1,119 @@
+;; Example plugin reading PVX files, a made-up format with several models:
+;;
+;;   "PVX1", the number of models (u8), then for every model
+;;   its size (3 × u8), the number of voxels (u16) and every voxel as x, y, z, r, g, b (6 × u8)
+;;
+;; Numbers are little endian. Build it with `wat2wasm plugins/pvx.wat -o pvx.wasm` from the
+;; WebAssembly Binary Toolkit and convert with `chisels_and_importers --plugin pvx.wasm
+;; model.pvx`. The exports are described in src/plugin.rs.
+(module
+  (memory (export "memory") 1)
+
+  ;; Start of the unused memory, nothing is ever freed as every file gets a new instance
+  (global $heap (mut i32) (i32.const 1024))
+
+  ;; Reserves the bytes and returns where they start, 0 if the memory can't grow
+  (func $alloc (export "alloc") (param $len i32) (result i32)
+    (local $start i32)
+    (local $end i32)
+    (local.set $start (global.get $heap))
+    (local.set $end (i32.add (local.get $start) (local.get $len)))
+    (if (i32.gt_u (local.get $end) (i32.mul (memory.size) (i32.const 65536)))
+      (then
+        (if (i32.eq
+              (memory.grow
+                (i32.sub
+                  (i32.shr_u (i32.add (local.get $end) (i32.const 65535)) (i32.const 16))
+                  (memory.size)))
+              (i32.const -1))
+          (then (return (i32.const 0))))))
+    (global.set $heap (local.get $end))
+    (local.get $start))
+
+  ;; Pointer in the upper and length in the lower half
+  (func $slice (param $ptr i32) (param $len i32) (result i64)
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+      (i64.extend_i32_u (local.get $len))))
+
+  ;; Whether the file starts with the magic and the number of models
+  (func $probe (export "probe") (param $ptr i32) (param $len i32) (result i32)
+    (if (i32.lt_u (local.get $len) (i32.const 5))
+      (then (return (i32.const 0))))
+    ;; "PVX1" read as a little endian number
+    (i32.eq (i32.load (local.get $ptr)) (i32.const 0x31585650)))
+
+  ;; Offset of the header of the model in the file, -1 if the file ends before its last voxel
+  (func $find_model (param $ptr i32) (param $len i32) (param $model i32) (result i32)
+    (local $offset i32)
+    (local.set $offset (i32.const 5))
+    (loop $next
+      (if (i32.gt_u (i32.add (local.get $offset) (i32.const 5)) (local.get $len))
+        (then (return (i32.const -1))))
+      (if (local.get $model)
+        (then
+          ;; Skip the header and the voxels of the model before
+          (local.set $offset
+            (i32.add
+              (i32.add (local.get $offset) (i32.const 5))
+              (i32.mul
+                (i32.load16_u (i32.add (local.get $ptr) (i32.add (local.get $offset) (i32.const 3))))
+                (i32.const 6))))
+          (local.set $model (i32.sub (local.get $model) (i32.const 1)))
+          (br $next))))
+    (if (i32.gt_u
+          (i32.add
+            (i32.add (local.get $offset) (i32.const 5))
+            (i32.mul
+              (i32.load16_u (i32.add (local.get $ptr) (i32.add (local.get $offset) (i32.const 3))))
+              (i32.const 6)))
+          (local.get $len))
+      (then (return (i32.const -1))))
+    (local.get $offset))
+
+  ;; Size and position of every model, 24 bytes each
+  (func (export "list_models") (param $ptr i32) (param $len i32) (result i64)
+    (local $count i32)
+    (local $list i32)
+    (local $model i32)
+    (local $offset i32)
+    (local $entry i32)
+    (if (i32.eqz (call $probe (local.get $ptr) (local.get $len)))
+      (then (return (i64.const -1))))
+    (local.set $count (i32.load8_u (i32.add (local.get $ptr) (i32.const 4))))
+    (local.set $list (call $alloc (i32.mul (local.get $count) (i32.const 24))))
+    (if (i32.eqz (local.get $list))
+      (then (return (i64.const -2))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $model) (local.get $count)))
+        (local.set $offset (call $find_model (local.get $ptr) (local.get $len) (local.get $model)))
+        (if (i32.lt_s (local.get $offset) (i32.const 0))
+          (then (return (i64.const -1))))
+        (local.set $offset (i32.add (local.get $ptr) (local.get $offset)))
+        (local.set $entry (i32.add (local.get $list) (i32.mul (local.get $model) (i32.const 24))))
+        (i32.store (local.get $entry) (i32.load8_u (local.get $offset)))
+        (i32.store offset=4 (local.get $entry) (i32.load8_u offset=1 (local.get $offset)))
+        (i32.store offset=8 (local.get $entry) (i32.load8_u offset=2 (local.get $offset)))
+        ;; The models are all placed at the origin
+        (i64.store offset=12 (local.get $entry) (i64.const 0))
+        (i32.store offset=20 (local.get $entry) (i32.const 0))
+        (local.set $model (i32.add (local.get $model) (i32.const 1)))
+        (br $next)))
+    (call $slice (local.get $list) (i32.mul (local.get $count) (i32.const 24))))
+
+  ;; The voxels of the model, already stored as the host expects them
+  (func (export "read_voxels") (param $ptr i32) (param $len i32) (param $model i32) (result i64)
+    (local $offset i32)
+    (if (i32.eqz (call $probe (local.get $ptr) (local.get $len)))
+      (then (return (i64.const -1))))
+    (if (i32.ge_u (local.get $model) (i32.load8_u (i32.add (local.get $ptr) (i32.const 4))))
+      (then (return (i64.const -1))))
+    (local.set $offset (call $find_model (local.get $ptr) (local.get $len) (local.get $model)))
+    (if (i32.lt_s (local.get $offset) (i32.const 0))
+      (then (return (i64.const -1))))
+    (local.set $offset (i32.add (local.get $ptr) (local.get $offset)))
+    (call $slice
+      (i32.add (local.get $offset) (i32.const 5))
+      (i32.mul (i32.load16_u offset=3 (local.get $offset)) (i32.const 6))))
+)
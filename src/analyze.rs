@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use palette::{color_difference::Ciede2000, convert::IntoColorUnclamped, IntoColor, Lab, Srgb};
+
+use crate::{i18n::tr, to_lch, BlockPalette, PaletteBlock};
+
+/// Size of a color space bucket in L, a and b units
+const BUCKET_SIZE: f32 = 10.0;
+/// Range of the a and b axes that is searched for colors
+const AB_RANGE: f32 = 110.0;
+
+/// Bucket of similar colors and how well the palette covers them
+struct Gap<'a> {
+    /// Color in the bucket with the largest distance to its closest block
+    example: Srgb<u8>,
+    delta_e: f32,
+    closest: &'a PaletteBlock,
+    /// Voxels in the bucket, only known when analyzing a model
+    voxels: Option<u32>,
+}
+
+/// Prints the regions of color space the palette covers worst. Without a model every
+/// color that can be displayed is considered, with one only the colors it uses.
+pub fn analyze_palette(palette: &BlockPalette, model: Option<&dot_vox::DotVoxData>, count: usize) {
+    if palette.mapping.is_empty() {
+        eprintln!("{}", tr!("analyze-empty-palette"));
+        return;
+    }
+
+    let mut gaps = match model {
+        Some(model) => model_gaps(palette, model),
+        None => color_space_gaps(palette),
+    };
+    let buckets = gaps.len();
+    gaps.sort_by(|l, r| r.delta_e.total_cmp(&l.delta_e));
+
+    let total_voxels: u32 = gaps.iter().filter_map(|gap| gap.voxels).sum();
+    println!("{}", tr!("analyze-header", count = buckets));
+    for gap in gaps.iter().take(count) {
+        let color = gap.example;
+        print!(
+            "  {}",
+            tr!(
+                "analyze-gap",
+                color = format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue),
+                delta = format!("{:>5.1}", gap.delta_e),
+                block = gap.closest.name,
+            )
+        );
+        match gap.voxels {
+            Some(voxels) => println!(
+                " {}",
+                tr!(
+                    "analyze-share",
+                    share = format!("{:.1}", voxels as f32 / total_voxels as f32 * 100.0)
+                )
+            ),
+            None => println!(),
+        }
+    }
+}
+
+/// Checks the center of every bucket of the Lab space that lies inside the sRGB gamut
+fn color_space_gaps(palette: &BlockPalette) -> Vec<Gap<'_>> {
+    let mut gaps = Vec::new();
+    let steps = (AB_RANGE / BUCKET_SIZE) as i32;
+    for l in 0..(100.0 / BUCKET_SIZE) as i32 {
+        for a in -steps..steps {
+            for b in -steps..steps {
+                let center = |i: i32| (i as f32 + 0.5) * BUCKET_SIZE;
+                let Some(color) = lab_to_srgb(Lab::new(center(l), center(a), center(b))) else {
+                    continue;
+                };
+                let (closest, delta_e) = closest_block(palette, color);
+                gaps.push(Gap {
+                    example: color,
+                    delta_e,
+                    closest,
+                    voxels: None,
+                });
+            }
+        }
+    }
+    gaps
+}
+
+/// Groups the colors used by the models into buckets of the Lab space
+fn model_gaps<'a>(palette: &'a BlockPalette, model: &dot_vox::DotVoxData) -> Vec<Gap<'a>> {
+    let mut color_counts = BTreeMap::<u8, u32>::new();
+    for voxel in model.models.iter().flat_map(|model| &model.voxels) {
+        *color_counts.entry(voxel.i).or_default() += 1;
+    }
+
+    let mut buckets = BTreeMap::<[i32; 3], Gap>::new();
+    for (index, voxels) in color_counts {
+        let color = model.palette[index as usize];
+        let color = Srgb::new(color.r, color.g, color.b);
+        let lab: Lab = color.into_format::<f32>().into_color();
+        let key = [lab.l, lab.a, lab.b].map(|value| (value / BUCKET_SIZE).floor() as i32);
+        let (closest, delta_e) = closest_block(palette, color);
+
+        let gap = buckets.entry(key).or_insert(Gap {
+            example: color,
+            delta_e,
+            closest,
+            voxels: Some(0),
+        });
+        gap.voxels = gap.voxels.map(|count| count + voxels);
+        if delta_e > gap.delta_e {
+            gap.example = color;
+            gap.delta_e = delta_e;
+            gap.closest = closest;
+        }
+    }
+    buckets.into_values().collect()
+}
+
+fn closest_block(palette: &BlockPalette, color: Srgb<u8>) -> (&PaletteBlock, f32) {
+    let lch = to_lch(color);
+    palette
+        .mapping
+        .iter()
+        .map(|block| (block, block.lch.difference(lch)))
+        .min_by(|(_, l), (_, r)| l.total_cmp(r))
+        .unwrap()
+}
+
+fn lab_to_srgb(lab: Lab) -> Option<Srgb<u8>> {
+    let color: Srgb = lab.into_color_unclamped();
+    let in_gamut = [color.red, color.green, color.blue]
+        .iter()
+        .all(|channel| (0.0..=1.0).contains(channel));
+    in_gamut.then(|| color.into_format())
+}
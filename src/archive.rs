@@ -0,0 +1,108 @@
+use std::io::{self, Write};
+
+use chisels_and_importers::gzip::crc32;
+
+use crate::{
+    i18n::tr,
+    jar::{DEFLATED, DIRECTORY_ENTRY, END_OF_DIRECTORY, LOCAL_HEADER, STORED},
+};
+
+/// Zip version 2.0, the first with deflate
+const ZIP_VERSION: u16 = 20;
+/// Flag marking file names as UTF-8
+const UTF8_NAMES: u16 = 1 << 11;
+/// 1980-01-01, the earliest date zip files can store, so archives of the same patterns are
+/// identical
+const DOS_DATE: u16 = (1 << 5) | 1;
+/// Compression level of entries that are deflated
+const ARCHIVE_COMPRESSION: u8 = 6;
+
+/// Zip archive that files are written into one after the other, the output doesn't need to
+/// be seekable, e.g. stdout
+pub struct ZipWriter<W: Write> {
+    output: W,
+    /// Central directory entries of the written files
+    directory: Vec<u8>,
+    count: u16,
+    /// Bytes written so far, the position of the next local header
+    offset: u32,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            directory: Vec::new(),
+            count: 0,
+            offset: 0,
+        }
+    }
+
+    /// Adds a file, deflated unless that doesn't make it smaller like for patterns, which
+    /// are compressed already
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let deflated = miniz_oxide::deflate::compress_to_vec(data, ARCHIVE_COMPRESSION);
+        let (method, contents) = if deflated.len() < data.len() {
+            (DEFLATED, &deflated[..])
+        } else {
+            (STORED, data)
+        };
+        let too_large = || io::Error::other(tr!("archive-entry-too-large", file = name));
+        let compressed_size = u32::try_from(contents.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+
+        // Fields shared by the local header and the central directory entry, from the
+        // version needed to extract up to the length of the name
+        let mut fields = Vec::with_capacity(26);
+        fields.extend(ZIP_VERSION.to_le_bytes());
+        fields.extend(UTF8_NAMES.to_le_bytes());
+        fields.extend(method.to_le_bytes());
+        fields.extend(0u16.to_le_bytes());
+        fields.extend(DOS_DATE.to_le_bytes());
+        fields.extend(crc32(data).to_le_bytes());
+        fields.extend(compressed_size.to_le_bytes());
+        fields.extend(size.to_le_bytes());
+        fields.extend((name.len() as u16).to_le_bytes());
+        // No extra field
+        fields.extend(0u16.to_le_bytes());
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend(LOCAL_HEADER.to_le_bytes());
+        header.extend(&fields);
+        header.extend(name.as_bytes());
+        self.output.write_all(&header)?;
+        self.output.write_all(contents)?;
+
+        self.directory.extend(DIRECTORY_ENTRY.to_le_bytes());
+        self.directory.extend(ZIP_VERSION.to_le_bytes());
+        self.directory.extend(&fields);
+        // No comment, disk number 0, no attributes
+        self.directory.extend([0; 10]);
+        self.directory.extend(self.offset.to_le_bytes());
+        self.directory.extend(name.as_bytes());
+
+        self.count += 1;
+        self.offset = (header.len() as u32)
+            .checked_add(compressed_size)
+            .and_then(|length| self.offset.checked_add(length))
+            .ok_or_else(too_large)?;
+        Ok(())
+    }
+
+    /// Writes the central directory, the archive is incomplete without it
+    pub fn finish(mut self) -> io::Result<()> {
+        self.output.write_all(&self.directory)?;
+        let mut end = Vec::with_capacity(22);
+        end.extend(END_OF_DIRECTORY.to_le_bytes());
+        // Disk numbers
+        end.extend([0; 4]);
+        end.extend(self.count.to_le_bytes());
+        end.extend(self.count.to_le_bytes());
+        end.extend((self.directory.len() as u32).to_le_bytes());
+        end.extend(self.offset.to_le_bytes());
+        // No comment
+        end.extend([0; 2]);
+        self.output.write_all(&end)?;
+        self.output.flush()
+    }
+}
@@ -0,0 +1,45 @@
+use serde::Serialize;
+
+/// Credit for the build, embedded into every written file that has room for it
+#[derive(Debug, Default, Clone, Serialize, clap::Args)]
+pub struct Attribution {
+    /// name of the author, embedded into the written files where possible
+    #[arg(long)]
+    pub author: Option<String>,
+    /// license the build is shared under, e.g. CC-BY-4.0
+    #[arg(long)]
+    pub license: Option<String>,
+    /// short description of the build
+    #[arg(long)]
+    pub description: Option<String>,
+}
+
+impl Attribution {
+    /// Fields that are set, named as in text headers. Line breaks are replaced so every
+    /// field fits onto a single line.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        [
+            ("author", &self.author),
+            ("license", &self.license),
+            ("description", &self.description),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let value = value.as_ref()?.lines().collect::<Vec<_>>().join(" ");
+            Some((name, value))
+        })
+        .collect()
+    }
+
+    /// Fields that are set with the predefined PNG text keywords
+    pub fn png_text(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("Author", &self.author),
+            ("Copyright", &self.license),
+            ("Description", &self.description),
+        ]
+        .into_iter()
+        .filter_map(|(keyword, value)| Some((keyword, value.as_deref()?)))
+        .collect()
+    }
+}
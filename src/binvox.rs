@@ -0,0 +1,149 @@
+//! binvox files (`.binvox`), the voxelized meshes of the `binvox` tool, read into the same data
+//! as Magica Voxel files. binvox only stores which voxels are filled, they all get one color
+//! that `--solid-color` or `--solid-block` replace.
+
+use std::str::FromStr;
+
+use chisels_and_importers::MAX_MODEL_SIZE;
+use dot_vox::DotVoxData;
+use palette::Srgb;
+
+use crate::{
+    console,
+    i18n::tr,
+    imported::{to_vox_data, ImportedModel},
+};
+
+const MAGIC: &str = "#binvox";
+/// Color of the voxels unless `--solid-color` is given
+pub const DEFAULT_COLOR: [u8; 3] = [0x80, 0x80, 0x80];
+
+/// Reads a `.binvox` file into a single model. Grids with a side longer than 256 voxels are
+/// scaled down by the smallest whole factor that fits, a voxel is filled if at least half of
+/// the voxels it stands for are.
+pub fn parse_binvox(bytes: &[u8]) -> Result<DotVoxData, String> {
+    let (dimensions, data) = read_header(bytes)?;
+    // binvox orders the voxels by x, then z, then y, with y pointing up
+    let [size_x, size_z, size_y] = dimensions;
+    let factor = dimensions.iter().max().unwrap().div_ceil(MAX_MODEL_SIZE);
+    if factor > 1 {
+        let message = tr!(
+            "binvox-downsampled",
+            size = format!("{}x{}x{}", size_x, size_y, size_z),
+            factor = factor
+        );
+        console::warn(message);
+    }
+    let [small_x, small_y, small_z] = [size_x, size_y, size_z].map(|side| side.div_ceil(factor));
+    let small_index = |x: u32, y: u32, z: u32| ((x * small_z + z) * small_y + y) as usize;
+
+    // Number of filled voxels for every voxel of the model
+    let mut filled = vec![0u32; (small_x * small_y * small_z) as usize];
+    let total = size_x as u64 * size_y as u64 * size_z as u64;
+    let mut index = 0u64;
+    for run in data.chunks(2) {
+        let &[value, count] = run else {
+            return Err(tr!("binvox-run-incomplete"));
+        };
+        let end = index + count as u64;
+        if end > total {
+            return Err(tr!("binvox-too-many-voxels"));
+        }
+        if value != 0 {
+            for index in index..end {
+                let y = (index % size_y as u64) as u32;
+                let z = (index / size_y as u64 % size_z as u64) as u32;
+                let x = (index / (size_y as u64 * size_z as u64)) as u32;
+                filled[small_index(x / factor, y / factor, z / factor)] += 1;
+            }
+        }
+        index = end;
+    }
+    if index < total {
+        return Err(tr!("binvox-missing-voxels"));
+    }
+
+    // Voxels at the far sides of a grid that isn't a multiple of the factor stand for fewer
+    let covered = |small: u32, size: u32| (size - small * factor).min(factor);
+    let mut voxels = Vec::new();
+    for x in 0..small_x {
+        for z in 0..small_z {
+            for y in 0..small_y {
+                let count = filled[small_index(x, y, z)];
+                let cells = covered(x, size_x) * covered(y, size_y) * covered(z, size_z);
+                if count > 0 && count * 2 >= cells {
+                    // Magica Voxel's y axis points away from the viewer, binvox's z towards
+                    let position = [x, small_z - 1 - z, y].map(|axis| axis as u8);
+                    voxels.push((position, DEFAULT_COLOR));
+                }
+            }
+        }
+    }
+    Ok(to_vox_data(vec![ImportedModel {
+        name: "binvox".to_owned(),
+        position: [0; 3],
+        size: [small_x, small_z, small_y],
+        voxels,
+        hidden: false,
+    }]))
+}
+
+/// Reads the text header, returns the sides of the grid in the order the voxels are stored in
+/// and the run-length encoded voxels after it
+fn read_header(bytes: &[u8]) -> Result<([u32; 3], &[u8]), String> {
+    let mut rest = bytes;
+    let mut next_line = || -> Result<String, String> {
+        let end = rest
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| tr!("binvox-header-incomplete"))?;
+        let line = String::from_utf8_lossy(&rest[..end]).trim().to_owned();
+        rest = &rest[end + 1..];
+        Ok(line)
+    };
+    if !next_line()?.starts_with(MAGIC) {
+        return Err(tr!("binvox-invalid"));
+    }
+    let mut dimensions = None;
+    loop {
+        let line = next_line()?;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("dim") => {
+                let sides: Vec<u32> = words
+                    .map(|word| {
+                        word.parse()
+                            .map_err(|_| tr!("binvox-size-invalid", size = line))
+                    })
+                    .collect::<Result<_, _>>()?;
+                match sides[..] {
+                    [x, z, y] if x > 0 && y > 0 && z > 0 => dimensions = Some([x, z, y]),
+                    _ => return Err(tr!("binvox-size-invalid", size = line)),
+                }
+            }
+            Some("data") => break,
+            // The translation and scale of the mesh don't change the voxels
+            _ => {}
+        }
+    }
+    let dimensions = dimensions.ok_or_else(|| tr!("binvox-size-missing"))?;
+    Ok((dimensions, rest))
+}
+
+/// Gives every voxel the color, e.g. for binvox files which store none
+pub fn paint(data: &mut DotVoxData, color: &str) -> Result<(), String> {
+    let color: Srgb<u8> =
+        Srgb::from_str(color).map_err(|_| tr!("color-code-invalid", color = color))?;
+    for voxel in data.models.iter_mut().flat_map(|model| &mut model.voxels) {
+        voxel.i = 0;
+    }
+    data.palette[0] = dot_vox::Color {
+        r: color.red,
+        g: color.green,
+        b: color.blue,
+        a: 255,
+    };
+    // Material ids are palette indices, which start at 1 in the file
+    data.materials.retain(|material| material.id != 1);
+    Ok(())
+}
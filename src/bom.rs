@@ -0,0 +1,244 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{console::say, i18n::tr};
+
+/// Bits that make up one full block
+const BITS_PER_BLOCK: u64 = 4096;
+
+/// Version of the exported bill of materials schema
+const BOM_VERSION: u32 = 1;
+
+/// Block usage aggregated over every emitted pattern
+#[derive(Default)]
+pub struct BillOfMaterials {
+    blocks: BTreeMap<String, BlockUsage>,
+}
+
+#[derive(Default)]
+struct BlockUsage {
+    bits: u64,
+    chunks: u32,
+}
+
+/// One row of the bill of materials
+#[derive(Serialize)]
+pub struct BomEntry {
+    /// Block id, e.g. `minecraft:white_wool`
+    pub block: String,
+    /// Number of bits placed with this block
+    pub bits: u64,
+    /// Blocks needed to chisel all bits
+    pub full_blocks: u64,
+    /// Share of all non-air bits in the build
+    pub percentage: f64,
+    /// Number of patterns the block appears in
+    pub chunks: u32,
+}
+
+#[derive(Serialize)]
+struct BomFile<'a> {
+    version: u32,
+    materials: &'a [BomEntry],
+}
+
+impl BillOfMaterials {
+    /// Adds the bit counts of one emitted pattern, air must not be included.
+    pub fn add_chunk<'a>(&mut self, counts: impl IntoIterator<Item = (&'a str, u32)>) {
+        let mut chunk_counts = HashMap::<&str, u64>::new();
+        for (block, count) in counts {
+            if count > 0 {
+                *chunk_counts.entry(block).or_default() += count as u64;
+            }
+        }
+
+        for (block, bits) in chunk_counts {
+            let usage = self.blocks.entry(block.to_owned()).or_default();
+            usage.bits += bits;
+            usage.chunks += 1;
+        }
+    }
+
+    /// Adds the blocks of one emitted structure, every full block counts with all its bits
+    pub fn add_blocks<'a>(&mut self, blocks: impl IntoIterator<Item = &'a str>) {
+        let mut structure_counts = HashMap::<&str, u64>::new();
+        for block in blocks {
+            *structure_counts.entry(block).or_default() += 1;
+        }
+
+        for (block, count) in structure_counts {
+            let usage = self.blocks.entry(block.to_owned()).or_default();
+            usage.bits += count * BITS_PER_BLOCK;
+            usage.chunks += 1;
+        }
+    }
+
+    /// Adds the usage of another bill of materials, e.g. of a single model
+    pub fn merge(&mut self, other: &BillOfMaterials) {
+        for (block, other_usage) in &other.blocks {
+            let usage = self.blocks.entry(block.clone()).or_default();
+            usage.bits += other_usage.bits;
+            usage.chunks += other_usage.chunks;
+        }
+    }
+
+    /// Total number of non-air bits
+    pub fn total_bits(&self) -> u64 {
+        self.blocks.values().map(|usage| usage.bits).sum()
+    }
+
+    /// Number of full blocks needed for all bits
+    pub fn full_blocks(&self) -> u64 {
+        self.blocks
+            .values()
+            .map(|usage| usage.bits.div_ceil(BITS_PER_BLOCK))
+            .sum()
+    }
+
+    /// Number of different blocks used
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Rows sorted by descending bit count, ties ordered by block id
+    pub fn entries(&self) -> Vec<BomEntry> {
+        let total_bits = self.total_bits();
+        let mut entries: Vec<_> = self
+            .blocks
+            .iter()
+            .map(|(block, usage)| BomEntry {
+                block: block.clone(),
+                bits: usage.bits,
+                full_blocks: usage.bits.div_ceil(BITS_PER_BLOCK),
+                percentage: if total_bits == 0 {
+                    0.0
+                } else {
+                    usage.bits as f64 / total_bits as f64 * 100.0
+                },
+                chunks: usage.chunks,
+            })
+            .collect();
+        entries.sort_by(|l, r| r.bits.cmp(&l.bits).then_with(|| l.block.cmp(&r.block)));
+        entries
+    }
+
+    /// Prints the blocks needed for the build with their bits and full blocks, the most used
+    /// first
+    pub fn print_table(&self) {
+        let entries = self.entries();
+        if entries.is_empty() {
+            return;
+        }
+        let headers = [
+            tr!("materials-block"),
+            tr!("materials-bits"),
+            tr!("materials-blocks"),
+        ];
+        let total = [
+            tr!("materials-total"),
+            self.total_bits().to_string(),
+            self.full_blocks().to_string(),
+        ];
+        let rows: Vec<[String; 3]> = entries
+            .iter()
+            .map(|entry| {
+                [
+                    entry.block.clone(),
+                    entry.bits.to_string(),
+                    entry.full_blocks.to_string(),
+                ]
+            })
+            .collect();
+        let width = |column: usize| {
+            [&headers, &total]
+                .into_iter()
+                .chain(&rows)
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        };
+        let widths = [width(0), width(1), width(2)];
+
+        say!("{}", tr!("materials-title"));
+        for row in [&headers].into_iter().chain(&rows).chain([&total]) {
+            say!(
+                "  {:<block$}  {:>bits$}  {:>blocks$}",
+                row[0],
+                row[1],
+                row[2],
+                block = widths[0],
+                bits = widths[1],
+                blocks = widths[2]
+            );
+        }
+    }
+
+    /// Writes the bill of materials as JSON if the path ends in `.json`, otherwise as CSV.
+    pub fn write_file(&self, path: &Path) -> std::io::Result<()> {
+        let entries = self.entries();
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+
+        let mut output = Vec::new();
+        if is_json {
+            let file = BomFile {
+                version: BOM_VERSION,
+                materials: &entries,
+            };
+            serde_json::to_writer_pretty(&mut output, &file)?;
+        } else {
+            writeln!(output, "block,bits,full_blocks,percentage,chunks")?;
+            for entry in entries {
+                writeln!(
+                    output,
+                    "{},{},{},{:.2},{}",
+                    entry.block, entry.bits, entry.full_blocks, entry.percentage, entry.chunks
+                )?;
+            }
+        }
+        std::fs::write(path, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two chunks of bits and a structure with one full block
+    fn materials() -> BillOfMaterials {
+        let mut bom = BillOfMaterials::default();
+        bom.add_chunk([("minecraft:stone", 3000), ("minecraft:oak_planks", 1096)]);
+        bom.add_chunk([("minecraft:stone", 4096), ("minecraft:glass", 0)]);
+        bom.add_blocks(["minecraft:glass"]);
+        bom
+    }
+
+    fn written(name: &str) -> String {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join(name);
+        materials().write_file(&path).unwrap();
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn csv_matches_golden_file() {
+        assert_eq!(
+            written("materials.csv"),
+            include_str!("../tests/data/bom.csv")
+        );
+    }
+
+    #[test]
+    fn json_matches_golden_file() {
+        assert_eq!(
+            written("materials.JSON"),
+            include_str!("../tests/data/bom.json")
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::bom::BillOfMaterials;
+
+/// Lines that fit onto a book page
+const LINES_PER_PAGE: usize = 14;
+/// Characters of average width that fit into a line, wider text wraps onto the next line
+const LINE_WIDTH: usize = 18;
+/// Most characters a page can hold when edited in game
+const PAGE_CHARACTERS: usize = 256;
+/// Longest title a signed book can have
+const TITLE_LENGTH: usize = 32;
+const STACK_SIZE: u64 = 64;
+
+/// Creates a `/give` command for a written book listing the blocks and how many are needed.
+/// The command is too long for the chat and has to be run from a command block. The book
+/// is signed by the author if one is given, otherwise by the tool.
+pub fn give_command(title: &str, author: Option<&str>, bom: &BillOfMaterials) -> String {
+    let pages: Vec<_> = split_pages(bom)
+        .iter()
+        .map(|page| {
+            // Pages are JSON text components stored in NBT strings
+            let component = serde_json::json!({ "text": page }).to_string();
+            format!("'{}'", escape_nbt(&component, '\''))
+        })
+        .collect();
+    let title: String = format!("Materials: {}", title)
+        .chars()
+        .take(TITLE_LENGTH)
+        .collect();
+
+    format!(
+        "/give @p written_book{{title:\"{}\",author:\"{}\",pages:[{}]}}",
+        escape_nbt(&title, '"'),
+        escape_nbt(author.unwrap_or(env!("CARGO_PKG_NAME")), '"'),
+        pages.join(",")
+    )
+}
+
+/// Puts every block with its quantity onto the pages, an entry is never split over two pages
+fn split_pages(bom: &BillOfMaterials) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut page_lines = 0;
+    for entry in bom.entries() {
+        let name = block_name(&entry.block);
+        let text = format!("{}\n  {}\n", name, quantity(entry.full_blocks));
+        let lines = name.chars().count().div_ceil(LINE_WIDTH).max(1) + 1;
+
+        let full = page_lines + lines > LINES_PER_PAGE
+            || page.chars().count() + text.chars().count() > PAGE_CHARACTERS;
+        if full && !page.is_empty() {
+            pages.push(std::mem::take(&mut page));
+            page_lines = 0;
+        }
+        page.push_str(&text);
+        page_lines += lines;
+    }
+    if !page.is_empty() || pages.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// Readable name of a block id, e.g. `minecraft:white_wool` becomes `White Wool`. The
+/// namespace of blocks from other mods is added in parentheses.
+fn block_name(block: &str) -> String {
+    let (namespace, path) = block.split_once(':').unwrap_or(("minecraft", block));
+    let name = path
+        .split('_')
+        .map(|word| {
+            let mut characters = word.chars();
+            match characters.next() {
+                Some(first) => first.to_uppercase().chain(characters).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    if namespace == "minecraft" {
+        name
+    } else {
+        format!("{} ({})", name, namespace)
+    }
+}
+
+/// Item count in stacks and single items, e.g. `2 stacks + 13`
+fn quantity(items: u64) -> String {
+    let stacks = items / STACK_SIZE;
+    let rest = items % STACK_SIZE;
+    match (stacks, rest) {
+        (0, rest) => rest.to_string(),
+        (1, 0) => "1 stack".to_owned(),
+        (stacks, 0) => format!("{} stacks", stacks),
+        (1, rest) => format!("1 stack + {}", rest),
+        (stacks, rest) => format!("{} stacks + {}", stacks, rest),
+    }
+}
+
+/// Escapes a string for NBT quoted with the given quote character
+fn escape_nbt(text: &str, quote: char) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        if character == '\\' || character == quote {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines the page takes up in game, with long lines wrapped
+    fn wrapped_lines(page: &str) -> usize {
+        page.lines()
+            .map(|line| line.chars().count().div_ceil(LINE_WIDTH).max(1))
+            .sum()
+    }
+
+    /// Many blocks with short names and names long enough to wrap
+    fn materials() -> BillOfMaterials {
+        let mut bom = BillOfMaterials::default();
+        for index in 0..40u32 {
+            let block = if index % 3 == 0 {
+                format!("somemod:very_long_decorative_block_number_{}", index)
+            } else {
+                format!("minecraft:block_{}", index)
+            };
+            bom.add_chunk([(block.as_str(), (index + 1) * 4096)]);
+        }
+        bom
+    }
+
+    #[test]
+    fn pages_stay_within_limits() {
+        let pages = split_pages(&materials());
+        assert!(pages.len() > 1);
+        for page in &pages {
+            assert!(page.chars().count() <= PAGE_CHARACTERS, "{:?}", page);
+            assert!(wrapped_lines(page) <= LINES_PER_PAGE, "{:?}", page);
+        }
+    }
+
+    #[test]
+    fn entries_are_not_split_over_pages() {
+        let bom = materials();
+        let pages = split_pages(&bom);
+        let entries: Vec<_> = pages
+            .iter()
+            .flat_map(|page| {
+                let lines: Vec<_> = page.lines().collect();
+                assert_eq!(lines.len() % 2, 0, "{:?}", page);
+                lines
+                    .chunks(2)
+                    .map(|entry| entry[0].to_owned())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let expected: Vec<_> = bom
+            .entries()
+            .iter()
+            .map(|entry| block_name(&entry.block))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn page_is_filled_up_to_the_line_limit() {
+        let mut bom = BillOfMaterials::default();
+        for block in ["a", "b", "c", "d", "e", "f", "g", "h"] {
+            bom.add_blocks([format!("minecraft:{}", block).as_str()]);
+        }
+        // Two lines per entry, 7 entries fill the 14 lines of the first page
+        let pages = split_pages(&bom);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(wrapped_lines(&pages[0]), LINES_PER_PAGE);
+        assert_eq!(pages[1], "H\n  1\n");
+    }
+
+    #[test]
+    fn empty_bill_gives_one_empty_page() {
+        assert_eq!(split_pages(&BillOfMaterials::default()), [""]);
+    }
+
+    #[test]
+    fn title_is_shortened_and_escaped() {
+        let mut bom = BillOfMaterials::default();
+        bom.add_blocks(["minecraft:stone"]);
+        let command = give_command(
+            "The \"Great\" Castle of the Northern Realm",
+            Some("Ann"),
+            &bom,
+        );
+        assert!(
+            command.starts_with(
+                r#"/give @p written_book{title:"Materials: The \"Great\" Castle of",author:"Ann","#
+            ),
+            "{}",
+            command
+        );
+        assert!(
+            command.ends_with(r#"pages:['{"text":"Stone\\n  1\\n"}']}"#),
+            "{}",
+            command
+        );
+    }
+
+    #[test]
+    fn quantities_are_given_in_stacks() {
+        assert_eq!(quantity(13), "13");
+        assert_eq!(quantity(64), "1 stack");
+        assert_eq!(quantity(128), "2 stacks");
+        assert_eq!(quantity(77), "1 stack + 13");
+        assert_eq!(quantity(141), "2 stacks + 13");
+    }
+
+    #[test]
+    fn block_names_are_readable() {
+        assert_eq!(block_name("minecraft:white_wool"), "White Wool");
+        assert_eq!(
+            block_name("create:andesite_casing"),
+            "Andesite Casing (create)"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer};
+
+use crate::i18n::tr;
+
+/// Destination for text copied by the program, the system clipboard outside of tests
+pub trait Clipboard {
+    fn set_text(&mut self, text: String) -> Result<(), String>;
+}
+
+pub struct SystemClipboard(arboard::Clipboard);
+
+impl SystemClipboard {
+    /// Connects to the clipboard, which fails on systems without a display server
+    pub fn open() -> Result<Self, String> {
+        arboard::Clipboard::new().map(Self).map_err(|e| match e {
+            arboard::Error::ClipboardNotSupported => {
+                tr!("clipboard-unavailable")
+            }
+            e => e.to_string(),
+        })
+    }
+}
+
+impl Clipboard for SystemClipboard {
+    fn set_text(&mut self, text: String) -> Result<(), String> {
+        self.0.set_text(text).map_err(|e| e.to_string())
+    }
+}
+
+/// Block a pattern goes into on the Minecraft axes, given like `1,0,2` for the pattern
+/// named `x1_y0_z2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkPosition(pub [u32; 3]);
+
+impl FromStr for ChunkPosition {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || tr!("chunk-position-invalid", value = value);
+        let axes: Vec<u32> = value
+            .split(',')
+            .map(|axis| axis.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        <[u32; 3]>::try_from(axes).map(Self).map_err(|_| invalid())
+    }
+}
+
+impl Serialize for ChunkPosition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let [x, y, z] = self.0;
+        serializer.serialize_str(&format!("{},{},{}", x, y, z))
+    }
+}
+
+/// Copies the path of a written pattern, Chisels and Bits has no text form of patterns to
+/// paste in game. Patterns are given with the block they go into. Without a chunk exactly
+/// one pattern must have been written, with a chunk exactly one must go into that block.
+pub fn copy_pattern(
+    clipboard: &mut dyn Clipboard,
+    patterns: &[(&str, [u32; 3])],
+    chunk: Option<ChunkPosition>,
+) -> Result<String, String> {
+    let selected: Vec<_> = patterns
+        .iter()
+        .filter(|(_, block)| chunk.is_none_or(|chunk| chunk.0 == *block))
+        .map(|(file, _)| *file)
+        .collect();
+    let pattern = match (&selected[..], chunk) {
+        ([pattern], _) => pattern,
+        (_, None) => return Err(tr!("clipboard-count", count = patterns.len())),
+        ([], Some(ChunkPosition([x, y, z]))) => {
+            return Err(tr!(
+                "clipboard-no-chunk",
+                chunk = format!("{},{},{}", x, y, z)
+            ))
+        }
+        (_, Some(ChunkPosition([x, y, z]))) => {
+            return Err(tr!(
+                "clipboard-chunk-count",
+                chunk = format!("{},{},{}", x, y, z),
+                count = selected.len()
+            ))
+        }
+    };
+    let path = std::fs::canonicalize(pattern)
+        .map_err(|e| tr!("clipboard-resolve-failed", file = pattern, error = e))?;
+    let path = path.to_string_lossy().into_owned();
+    clipboard.set_text(path.clone())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps the copied text instead of touching the system clipboard
+    #[derive(Default)]
+    struct FakeClipboard(Option<String>);
+
+    impl Clipboard for FakeClipboard {
+        fn set_text(&mut self, text: String) -> Result<(), String> {
+            self.0 = Some(text);
+            Ok(())
+        }
+    }
+
+    /// Creates the pattern files in a temporary directory, which is removed when dropped
+    fn written(names: &[&str]) -> (tempfile::TempDir, Vec<String>) {
+        let directory = tempfile::tempdir().unwrap();
+        let files = names
+            .iter()
+            .map(|name| {
+                let path = directory.path().join(name);
+                std::fs::write(&path, "").unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        (directory, files)
+    }
+
+    fn copy(
+        patterns: &[(&str, [u32; 3])],
+        chunk: Option<&str>,
+    ) -> (Result<String, String>, Option<String>) {
+        let mut clipboard = FakeClipboard::default();
+        let chunk = chunk.map(|chunk| chunk.parse().unwrap());
+        let result = copy_pattern(&mut clipboard, patterns, chunk);
+        (result, clipboard.0)
+    }
+
+    #[test]
+    fn copies_single_pattern() {
+        let (_directory, files) = written(&["pattern.cbsbp"]);
+        let (result, copied) = copy(&[(&files[0], [0, 0, 0])], None);
+        let path = result.unwrap();
+        assert_eq!(copied.as_ref(), Some(&path));
+        assert_eq!(
+            path,
+            std::fs::canonicalize(&files[0]).unwrap().to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn refuses_several_patterns() {
+        let (_directory, files) = written(&["pattern_x0_y0_z0.cbsbp", "pattern_x1_y0_z0.cbsbp"]);
+        let patterns = [
+            (files[0].as_str(), [0, 0, 0]),
+            (files[1].as_str(), [1, 0, 0]),
+        ];
+        let (result, copied) = copy(&patterns, None);
+        assert!(result.unwrap_err().contains("2"));
+        assert_eq!(copied, None);
+    }
+
+    #[test]
+    fn chunk_picks_pattern() {
+        let (_directory, files) = written(&["pattern_x0_y0_z0.cbsbp", "pattern_x1_y0_z2.cbsbp"]);
+        let patterns = [
+            (files[0].as_str(), [0, 0, 0]),
+            (files[1].as_str(), [1, 0, 2]),
+        ];
+        let (result, copied) = copy(&patterns, Some("1,0,2"));
+        assert!(result.unwrap().ends_with("pattern_x1_y0_z2.cbsbp"));
+        assert!(copied.unwrap().ends_with("pattern_x1_y0_z2.cbsbp"));
+
+        let (result, copied) = copy(&patterns, Some("3,0,0"));
+        assert!(result.unwrap_err().contains("3,0,0"));
+        assert_eq!(copied, None);
+    }
+
+    #[test]
+    fn chunk_must_be_three_numbers() {
+        assert_eq!("1, 0,2".parse(), Ok(ChunkPosition([1, 0, 2])));
+        assert!("1,0".parse::<ChunkPosition>().is_err());
+        assert!("1,0,-2".parse::<ChunkPosition>().is_err());
+    }
+}
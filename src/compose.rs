@@ -0,0 +1,454 @@
+//! Several model files placed into one scene with `--compose`, so their patterns line up on a
+//! single grid of blocks
+
+use std::{collections::HashMap, str::FromStr};
+
+use chisels_and_importers::{BLOCK_SIDE, MAX_MODEL_SIZE};
+use dot_vox::DotVoxData;
+use serde::{Serialize, Serializer};
+
+use crate::{
+    i18n::tr,
+    imported::{build_palette, to_vox_data, ImportedModel, MAX_COLORS},
+    scene,
+};
+
+/// Model file and the position of its lowest corner in the scene, in voxels on the axes of
+/// Magica Voxel, given like `roof.vox@0,0,48`
+#[derive(Debug, Clone)]
+pub struct ComposeInput {
+    pub path: String,
+    pub offset: [i32; 3],
+}
+
+impl FromStr for ComposeInput {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let Some((path, offset)) = value.rsplit_once('@') else {
+            return Ok(Self {
+                path: value.to_owned(),
+                offset: [0; 3],
+            });
+        };
+        let invalid = || tr!("compose-offset-invalid", offset = offset);
+        let axes: Vec<i32> = offset
+            .split(',')
+            .map(|axis| axis.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        let offset = <[i32; 3]>::try_from(axes).map_err(|_| invalid())?;
+        Ok(Self {
+            path: path.to_owned(),
+            offset,
+        })
+    }
+}
+
+impl Serialize for ComposeInput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let [x, y, z] = self.offset;
+        serializer.serialize_str(&format!("{}@{},{},{}", self.path, x, y, z))
+    }
+}
+
+/// Color of a palette index with its alpha and the properties of its Magica Voxel material,
+/// sorted so that they can be compared
+type Swatch = ([u8; 4], Vec<(String, String)>);
+
+/// The composed scene as a single model, with the input every voxel comes from
+pub struct Composed {
+    pub data: DotVoxData,
+    /// The voxels of the model with the index of their input as color, transformed along with
+    /// the model to tell which input the chunks come from
+    pub sources: dot_vox::Model,
+}
+
+/// Places the models of the files at their offsets, later files replace the voxels of earlier
+/// ones unless `no_overlap` makes that an error. Files with a scene are placed from the lowest
+/// corner of their voxels, files with a single model from the corner of its size. Colors are
+/// merged by their RGBA value and material, since an index can stand for a different color in
+/// every file.
+pub fn compose(
+    inputs: &[ComposeInput],
+    files: &[DotVoxData],
+    no_overlap: bool,
+) -> Result<Composed, String> {
+    if inputs.len() > u8::MAX as usize {
+        return Err(tr!("compose-too-many", max = u8::MAX));
+    }
+    let mut swatches = Vec::<Swatch>::new();
+    let mut swatch_indices = HashMap::<Swatch, usize>::new();
+    let mut voxels = HashMap::<[i32; 3], (usize, u8)>::new();
+    for (source, (input, data)) in inputs.iter().zip(files).enumerate() {
+        let model = flatten(data)
+            .map_err(|error| tr!("compose-file-failed", file = input.path, error = error))?;
+        // Index of the swatch of every color of the file
+        let indices: Vec<usize> = file_swatches(data)
+            .into_iter()
+            .map(|swatch| {
+                *swatch_indices.entry(swatch.clone()).or_insert_with(|| {
+                    swatches.push(swatch);
+                    swatches.len() - 1
+                })
+            })
+            .collect();
+        for voxel in &model.voxels {
+            let position = [voxel.x, voxel.y, voxel.z].map(|axis| axis as i32);
+            let position = [0, 1, 2].map(|axis| position[axis] + input.offset[axis]);
+            let swatch = indices[voxel.i as usize];
+            let previous = voxels.insert(position, (swatch, source as u8));
+            let overlapped = previous.filter(|&(_, previous)| previous != source as u8);
+            if let (true, Some((_, previous))) = (no_overlap, overlapped) {
+                let [x, y, z] = position;
+                return Err(tr!(
+                    "compose-overlap",
+                    first = inputs[previous as usize].path,
+                    second = input.path,
+                    position = format!("({}, {}, {})", x, y, z)
+                ));
+            }
+        }
+    }
+
+    let mut positions = voxels.keys();
+    let Some(&first) = positions.next() else {
+        return Err(tr!("compose-empty"));
+    };
+    let (mut min, mut max) = (first, first);
+    for position in positions {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+    let size = [0, 1, 2].map(|axis| max[axis] - min[axis] + 1);
+    if size.iter().any(|&side| side > MAX_MODEL_SIZE as i32) {
+        return Err(tr!(
+            "compose-too-large",
+            size = format!("{}x{}x{}", size[0], size[1], size[2]),
+            max = MAX_MODEL_SIZE
+        ));
+    }
+
+    // Sorted so the palette and the voxels don't depend on the order of the hash map
+    let mut voxels: Vec<_> = voxels
+        .into_iter()
+        .map(|(position, voxel)| {
+            (
+                [0, 1, 2].map(|axis| (position[axis] - min[axis]) as u8),
+                voxel,
+            )
+        })
+        .collect();
+    voxels.sort_unstable_by_key(|&(position, _)| position);
+    let size = size.map(|side| side as u32);
+    let sources = dot_vox::Model {
+        size: dot_vox::Size {
+            x: size[0],
+            y: size[1],
+            z: size[2],
+        },
+        voxels: voxels
+            .iter()
+            .map(|&([x, y, z], (_, source))| dot_vox::Voxel { x, y, z, i: source })
+            .collect(),
+    };
+    let mut usage = vec![0; swatches.len()];
+    for &(_, (swatch, _)) in &voxels {
+        usage[swatch] += 1;
+    }
+    let (palette, indices) = merge_palette(&swatches, &usage)?;
+
+    // The scene of a single model, its voxels and palette filled in with the merged swatches
+    let mut data = to_vox_data(vec![ImportedModel {
+        name: "composed".to_owned(),
+        position: [0; 3],
+        size,
+        voxels: Vec::new(),
+        hidden: false,
+    }]);
+    data.models[0].voxels = voxels
+        .into_iter()
+        .map(|([x, y, z], (swatch, _))| dot_vox::Voxel {
+            x,
+            y,
+            z,
+            i: indices[swatch],
+        })
+        .collect();
+    for (color, ([r, g, b, a], _)) in data.palette.iter_mut().zip(&palette) {
+        *color = dot_vox::Color {
+            r: *r,
+            g: *g,
+            b: *b,
+            a: *a,
+        };
+    }
+    data.materials = palette
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (_, properties))| !properties.is_empty())
+        .map(|(index, (_, properties))| dot_vox::Material {
+            // Material ids are palette indices, which start at 1 in the file
+            id: index as u32 + 1,
+            properties: properties.into_iter().collect(),
+        })
+        .collect();
+    Ok(Composed { data, sources })
+}
+
+/// Swatch of every palette index of the file
+fn file_swatches(data: &DotVoxData) -> Vec<Swatch> {
+    let mut materials = HashMap::<u32, Vec<(String, String)>>::new();
+    for material in &data.materials {
+        let mut properties: Vec<_> = material
+            .properties
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        properties.sort_unstable();
+        materials.insert(material.id, properties);
+    }
+    data.palette
+        .iter()
+        .enumerate()
+        .map(|(index, color)| {
+            let material = materials.remove(&(index as u32 + 1)).unwrap_or_default();
+            ([color.r, color.g, color.b, color.a], material)
+        })
+        .collect()
+}
+
+/// Whether the swatch is an opaque color without a material type besides the default, so it
+/// converts the same as any other swatch of its RGB value
+fn is_plain((color, material): &Swatch) -> bool {
+    color[3] == u8::MAX
+        && material
+            .iter()
+            .all(|(key, value)| key != "_type" || value == "_diffuse")
+}
+
+/// Palette of the swatches with the index of every swatch in it. With more swatches than
+/// fit, the plain ones are grouped by median cut into the indices the others leave free.
+fn merge_palette(swatches: &[Swatch], usage: &[u32]) -> Result<(Vec<Swatch>, Vec<u8>), String> {
+    // Only the swatches some voxel uses, sorted so the palette doesn't depend on the order of
+    // the files
+    let mut used: Vec<usize> = (0..swatches.len())
+        .filter(|&swatch| usage[swatch] > 0)
+        .collect();
+    used.sort_unstable_by(|&a, &b| swatches[a].cmp(&swatches[b]));
+    let mut indices = vec![0; swatches.len()];
+    if used.len() <= MAX_COLORS {
+        for (index, &swatch) in used.iter().enumerate() {
+            indices[swatch] = index as u8;
+        }
+        let palette = used
+            .iter()
+            .map(|&swatch| swatches[swatch].clone())
+            .collect();
+        return Ok((palette, indices));
+    }
+
+    let (plain, special): (Vec<usize>, Vec<usize>) = used
+        .into_iter()
+        .partition(|&swatch| is_plain(&swatches[swatch]));
+    if special.len() >= MAX_COLORS {
+        return Err(tr!("compose-too-many-colors", max = MAX_COLORS - 1));
+    }
+    let mut rgb_usage = HashMap::<[u8; 3], u32>::new();
+    for &swatch in &plain {
+        let [r, g, b, _] = swatches[swatch].0;
+        *rgb_usage.entry([r, g, b]).or_default() += usage[swatch];
+    }
+    let (colors, rgb_indices) = build_palette(rgb_usage, MAX_COLORS - special.len());
+    for &swatch in &plain {
+        let [r, g, b, _] = swatches[swatch].0;
+        indices[swatch] = rgb_indices[&[r, g, b]];
+    }
+    let mut palette: Vec<Swatch> = colors
+        .into_iter()
+        .map(|[r, g, b]| ([r, g, b, u8::MAX], Vec::new()))
+        .collect();
+    for swatch in special {
+        indices[swatch] = palette.len() as u8;
+        palette.push(swatches[swatch].clone());
+    }
+    Ok((palette, indices))
+}
+
+/// The single model of a file, or its scene placed together
+fn flatten(data: &DotVoxData) -> Result<dot_vox::Model, String> {
+    let instances = scene::instances(data);
+    if instances.len() > 1 || (!instances.is_empty() && instances.len() < data.models.len()) {
+        return scene::merge(&instances, &data.models);
+    }
+    match &data.models[..] {
+        [model] => Ok(model.clone()),
+        models => Err(tr!("compose-no-scene", count = models.len())),
+    }
+}
+
+/// Input that most voxels of every block sized chunk come from, by chunk offset. Ties go to
+/// the earlier input.
+pub fn chunk_sources(sources: &dot_vox::Model) -> HashMap<(u8, u8, u8), u8> {
+    let mut counts = HashMap::<((u8, u8, u8), u8), u32>::new();
+    for voxel in &sources.voxels {
+        let chunk =
+            [voxel.x, voxel.y, voxel.z].map(|axis| axis / BLOCK_SIDE as u8 * BLOCK_SIDE as u8);
+        *counts
+            .entry(((chunk[0], chunk[1], chunk[2]), voxel.i))
+            .or_default() += 1;
+    }
+    let mut majority = HashMap::<(u8, u8, u8), (u32, u8)>::new();
+    for ((chunk, source), count) in counts {
+        let best = majority.entry(chunk).or_insert((count, source));
+        if (count, std::cmp::Reverse(source)) > (best.0, std::cmp::Reverse(best.1)) {
+            *best = (count, source);
+        }
+    }
+    majority
+        .into_iter()
+        .map(|(chunk, (_, source))| (chunk, source))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{colors, model};
+
+    /// File with a single model of the voxels
+    fn file(
+        voxels: &[[u8; 4]],
+        palette: Vec<dot_vox::Color>,
+        materials: &[(u32, &str)],
+    ) -> DotVoxData {
+        DotVoxData {
+            version: 150,
+            index_map: Vec::new(),
+            models: vec![model([16, 16, 2], voxels)],
+            palette,
+            materials: materials
+                .iter()
+                .map(|&(id, kind)| dot_vox::Material {
+                    id,
+                    properties: [("_type".to_owned(), kind.to_owned())]
+                        .into_iter()
+                        .collect(),
+                })
+                .collect(),
+            scenes: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    fn input(path: &str) -> ComposeInput {
+        path.parse().unwrap()
+    }
+
+    /// Color and material type of the voxel at the position of the composed scene
+    fn swatch_at(data: &DotVoxData, position: [u8; 3]) -> (dot_vox::Color, Option<&str>) {
+        let voxel = data.models[0]
+            .voxels
+            .iter()
+            .find(|voxel| [voxel.x, voxel.y, voxel.z] == position)
+            .unwrap();
+        let material = data
+            .materials
+            .iter()
+            .find(|material| material.id == voxel.i as u32 + 1)
+            .and_then(|material| material.properties.get("_type"))
+            .map(String::as_str);
+        (data.palette[voxel.i as usize], material)
+    }
+
+    #[test]
+    fn alpha_and_materials_are_kept() {
+        const RED: [u8; 3] = [200, 30, 30];
+        let glass = file(&[[0, 0, 0, 0]], colors(&[RED]), &[(1, "_glass")]);
+        let mut translucent = colors(&[RED]);
+        translucent[0].a = 100;
+        let translucent = file(&[[1, 0, 0, 0]], translucent, &[]);
+        let plain = file(&[[2, 0, 0, 0]], colors(&[RED]), &[(1, "_diffuse")]);
+        let emissive = file(&[[3, 0, 0, 0]], colors(&[RED]), &[(1, "_emit")]);
+        let inputs = ["glass.vox", "translucent.vox", "plain.vox", "emissive.vox"].map(input);
+
+        let composed = compose(&inputs, &[glass, translucent, plain, emissive], false).unwrap();
+        let red = |a| dot_vox::Color {
+            r: RED[0],
+            g: RED[1],
+            b: RED[2],
+            a,
+        };
+        let data = &composed.data;
+        assert_eq!(swatch_at(data, [0, 0, 0]), (red(255), Some("_glass")));
+        assert_eq!(swatch_at(data, [1, 0, 0]), (red(100), None));
+        assert_eq!(swatch_at(data, [2, 0, 0]), (red(255), Some("_diffuse")));
+        assert_eq!(swatch_at(data, [3, 0, 0]), (red(255), Some("_emit")));
+    }
+
+    #[test]
+    fn equal_swatches_share_an_index() {
+        const BLUE: [u8; 3] = [0, 51, 204];
+        const GREEN: [u8; 3] = [20, 180, 20];
+        let first = file(&[[0, 0, 0, 0]], colors(&[BLUE]), &[(1, "_glass")]);
+        // The same glass at another index, next to a color the first file doesn't have
+        let second = file(
+            &[[1, 0, 0, 1], [2, 0, 0, 0]],
+            colors(&[GREEN, BLUE]),
+            &[(2, "_glass")],
+        );
+        let composed = compose(&[input("a.vox"), input("b.vox")], &[first, second], false).unwrap();
+
+        let voxels = &composed.data.models[0].voxels;
+        assert_eq!(voxels[0].i, voxels[1].i);
+        assert_ne!(voxels[0].i, voxels[2].i);
+        assert_eq!(composed.data.materials.len(), 1);
+    }
+
+    #[test]
+    fn only_plain_colors_are_reduced() {
+        // 256 opaque colors over two files, more than a palette holds, and one of glass
+        let grays: Vec<[u8; 3]> = (0..=u8::MAX).map(|gray| [gray, gray, 0]).collect();
+        let voxels = |range: std::ops::Range<usize>| -> Vec<[u8; 4]> {
+            range
+                .map(|index| {
+                    [
+                        (index % 16) as u8,
+                        (index / 16) as u8,
+                        0,
+                        (index % 128) as u8,
+                    ]
+                })
+                .collect()
+        };
+        let first = file(&voxels(0..128), colors(&grays[..128]), &[]);
+        let mut second_colors = colors(&grays[128..]);
+        second_colors[200] = dot_vox::Color {
+            r: 1,
+            g: 2,
+            b: 3,
+            a: 255,
+        };
+        let mut second = file(&voxels(128..256), second_colors, &[(201, "_glass")]);
+        second.models[0].voxels.push(dot_vox::Voxel {
+            x: 0,
+            y: 0,
+            z: 1,
+            i: 200,
+        });
+        let composed = compose(&[input("a.vox"), input("b.vox")], &[first, second], false).unwrap();
+
+        let data = &composed.data;
+        let used: std::collections::HashSet<_> =
+            data.models[0].voxels.iter().map(|voxel| voxel.i).collect();
+        assert_eq!(used.len(), MAX_COLORS);
+        let glass = dot_vox::Color {
+            r: 1,
+            g: 2,
+            b: 3,
+            a: 255,
+        };
+        assert_eq!(swatch_at(data, [0, 0, 1]), (glass, Some("_glass")));
+    }
+}
@@ -0,0 +1,279 @@
+//! Defaults for the options from a `chisels.toml` file, so build scripts don't repeat them
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use clap::{parser::ValueSource, Arg, ArgMatches, CommandFactory, FromArgMatches};
+
+use crate::{error::Error, i18n, i18n::tr, Args};
+
+/// Name of the config file looked up in the working directory, then next to the executable
+const CONFIG_FILE: &str = "chisels.toml";
+
+/// Config file the options were read from
+pub struct Config {
+    path: PathBuf,
+    /// Keys that aren't options, e.g. of an older or newer version
+    unknown_keys: Vec<String>,
+}
+
+impl Config {
+    pub fn warn_unknown_keys(&self) {
+        for warning in self.warnings() {
+            eprintln!("{}", warning);
+        }
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        self.unknown_keys
+            .iter()
+            .map(|key| {
+                let message = tr!("config-unknown-key", key = key, file = self.path.display());
+                tr!("warning", message = message)
+            })
+            .collect()
+    }
+}
+
+/// Parses the command line, with the values of the config file as defaults.
+///
+/// The keys of the config file are the long names of the options, e.g. `palette` or
+/// `output-dir` (`output_dir` works too). Options given on the command line always win:
+/// - a key is left out if its option was given, also for options that can be repeated like
+///   `--rotate`, so the values are replaced rather than added to
+/// - a key is left out if its option can't be used with one that was given, e.g. `all-models`
+///   with `-m` or `quiet` with `--verbose`
+/// - flags set to `true` in the config file can't be turned off on the command line, leave
+///   them out of the file instead
+///
+/// With a subcommand only the options of every command like `lang` and `threads` are used.
+pub fn parse_args() -> (Args, Option<Config>) {
+    let command_line: Vec<OsString> = std::env::args_os().collect();
+    let matches = Args::command().get_matches_from(&command_line);
+    let Some(path) = matches
+        .get_one::<PathBuf>("config")
+        .cloned()
+        .or_else(find_config)
+    else {
+        return (from_matches(&matches), None);
+    };
+
+    let (args, unknown_keys) = read_config(&path)
+        .and_then(|table| with_config(command_line, &matches, &table))
+        .unwrap_or_else(|error| {
+            i18n::init(matches.get_one::<String>("lang").map(String::as_str));
+            error.exit()
+        });
+    (args, Some(Config { path, unknown_keys }))
+}
+
+/// Parses the command line again with the keys of the config file added, returns the keys
+/// that aren't options along with the arguments
+fn with_config(
+    command_line: Vec<OsString>,
+    matches: &ArgMatches,
+    table: &toml::Table,
+) -> Result<(Args, Vec<String>), Error> {
+    let (config_arguments, unknown_keys) = to_arguments(table, matches)?;
+    // The values of the config file go after the options of the command line, in front of
+    // them they would be taken as the model instead of a subcommand
+    let mut arguments = command_line;
+    let end = arguments
+        .iter()
+        .position(|argument| argument == "--")
+        .unwrap_or(arguments.len());
+    arguments.splice(end..end, config_arguments);
+    let matches = Args::command().get_matches_from(arguments);
+    Ok((from_matches(&matches), unknown_keys))
+}
+
+fn from_matches(matches: &ArgMatches) -> Args {
+    Args::from_arg_matches(matches).unwrap_or_else(|error| error.exit())
+}
+
+fn find_config() -> Option<PathBuf> {
+    let next_to_executable = std::env::current_exe()
+        .ok()
+        .and_then(|executable| Some(executable.parent()?.join(CONFIG_FILE)));
+    [Some(PathBuf::from(CONFIG_FILE)), next_to_executable]
+        .into_iter()
+        .flatten()
+        .find(|path| path.is_file())
+}
+
+fn read_config(path: &Path) -> Result<toml::Table, Error> {
+    let failed = |error: &dyn std::fmt::Display| {
+        Error::Input(tr!(
+            "config-read-failed",
+            file = path.display(),
+            error = error
+        ))
+    };
+    let contents = std::fs::read_to_string(path).map_err(|e| failed(&e))?;
+    contents.parse().map_err(|e| failed(&e))
+}
+
+/// Turns the keys of the config file into command line arguments, leaving out the ones the
+/// command line overrides. Returns them along with the keys that aren't options.
+fn to_arguments(
+    table: &toml::Table,
+    matches: &ArgMatches,
+) -> Result<(Vec<OsString>, Vec<String>), Error> {
+    let mut command = Args::command();
+    command.build();
+    let given: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect();
+    let with_subcommand = matches.subcommand().is_some();
+
+    let mut arguments = Vec::new();
+    let mut unknown_keys = Vec::new();
+    for (key, value) in table {
+        let name = key.replace('_', "-");
+        let Some(arg) = command.get_arguments().find(|arg| {
+            arg.get_long() == Some(&name) && !["config", "help", "version"].contains(&name.as_str())
+        }) else {
+            unknown_keys.push(key.clone());
+            continue;
+        };
+        let overridden = given
+            .iter()
+            .any(|given| given.get_id() == arg.get_id() || conflicting(&command, arg, given));
+        if overridden || (with_subcommand && !arg.is_global_set()) {
+            continue;
+        }
+
+        let values = match value {
+            toml::Value::Array(values) => &values[..],
+            value => std::slice::from_ref(value),
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) if !arg.get_action().takes_values() => {
+                    if *value {
+                        arguments.push(format!("--{}", name).into());
+                    }
+                    continue;
+                }
+                toml::Value::Boolean(value) => value.to_string(),
+                _ => {
+                    return Err(Error::Input(tr!("config-invalid-value", key = key)));
+                }
+            };
+            arguments.push(format!("--{}={}", name, value).into());
+        }
+    }
+    Ok((arguments, unknown_keys))
+}
+
+/// Whether the options can't be used together, on their own or as part of a group
+fn conflicting(command: &clap::Command, a: &Arg, b: &Arg) -> bool {
+    let conflicts_with = |a: &Arg, b: &Arg| {
+        command
+            .get_arg_conflicts_with(a)
+            .iter()
+            .any(|arg| arg.get_id() == b.get_id())
+    };
+    conflicts_with(a, b)
+        || conflicts_with(b, a)
+        || command.get_groups().any(|group| {
+            !group.clone().is_multiple()
+                && group.get_args().any(|id| id == a.get_id())
+                && group.get_args().any(|id| id == b.get_id())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Arguments of the command line with the config file given as TOML
+    fn parse(command_line: &[&str], config: &str) -> (Args, Vec<String>) {
+        let command_line: Vec<OsString> = ["chisels_and_importers"]
+            .iter()
+            .chain(command_line)
+            .map(OsString::from)
+            .collect();
+        let matches = Args::command().get_matches_from(&command_line);
+        with_config(command_line, &matches, &config.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn keys_fill_in_missing_options() {
+        let (args, unknown_keys) = parse(
+            &["castle.vox"],
+            "palette = \"config.json\"\noutput_dir = \"patterns\"\nthreads = 2\nno-split = true",
+        );
+        assert_eq!(args.palette, "config.json");
+        assert_eq!(args.output_dir, Some(PathBuf::from("patterns")));
+        assert_eq!(args.threads, Some(2));
+        assert!(args.no_split);
+        assert_eq!(args.model.as_deref(), Some("castle.vox"));
+        assert!(unknown_keys.is_empty());
+    }
+
+    #[test]
+    fn command_line_overrides_key() {
+        let (args, _) = parse(
+            &["castle.vox", "--palette", "cli.json"],
+            "palette = \"config.json\"\nthreads = 2",
+        );
+        assert_eq!(args.palette, "cli.json");
+        assert_eq!(args.threads, Some(2));
+    }
+
+    #[test]
+    fn repeated_option_replaces_values() {
+        let rotations = |args: &Args| format!("{:?}", args.rotate);
+        let config = "rotate = [\"y:90\", \"x:180\"]";
+        let (from_config, _) = parse(&["castle.vox"], config);
+        let (replaced, _) = parse(&["castle.vox", "--rotate", "z:90"], config);
+        let (expected, _) = parse(&["castle.vox", "--rotate", "z:90"], "");
+        assert_eq!(from_config.rotate.len(), 2);
+        assert_eq!(rotations(&replaced), rotations(&expected));
+    }
+
+    #[test]
+    fn conflicting_option_leaves_key_out() {
+        let (args, _) = parse(&["castle.vox", "-m", "2"], "all-models = true");
+        assert!(!args.model_group.all_models);
+        assert_eq!(args.model_group.models.map(|models| models.len()), Some(1));
+
+        let (args, _) = parse(&["castle.vox", "--verbose"], "quiet = true");
+        assert!(args.verbose);
+        assert!(!args.quiet);
+    }
+
+    #[test]
+    fn unknown_key_is_warned_about() {
+        let (args, unknown_keys) = parse(&["castle.vox"], "colour = \"red\"\nthreads = 2");
+        assert_eq!(args.threads, Some(2));
+        assert_eq!(unknown_keys, ["colour"]);
+
+        let config = Config {
+            path: PathBuf::from("chisels.toml"),
+            unknown_keys,
+        };
+        let warnings = config.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("'colour'"), "{}", warnings[0]);
+        assert!(warnings[0].contains("chisels.toml"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn invalid_value_is_an_error() {
+        let command_line = vec![OsString::from("chisels_and_importers"), "castle.vox".into()];
+        let matches = Args::command().get_matches_from(&command_line);
+        let table = "palette = { name = \"x\" }".parse().unwrap();
+        assert!(matches!(
+            with_config(command_line, &matches, &table),
+            Err(Error::Input(_))
+        ));
+    }
+}
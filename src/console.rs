@@ -0,0 +1,257 @@
+//! Messages printed while converting. They go to stdout, or to stderr when the patterns are
+//! written to stdout so they don't end up in the pattern data.
+
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    io::{IsTerminal, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::i18n::tr;
+
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Prints the messages of the rest of the run to stderr
+pub fn messages_to_stderr() {
+    TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+pub fn uses_stderr() -> bool {
+    TO_STDERR.load(Ordering::Relaxed)
+}
+
+/// `println!` for messages about the conversion
+macro_rules! say {
+    () => {
+        $crate::console::print_message("\n".to_owned())
+    };
+    ($($arg:tt)*) => {
+        $crate::console::print_message(format!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// `print!` for messages about the conversion
+macro_rules! say_inline {
+    ($($arg:tt)*) => {
+        $crate::console::print_message(format!($($arg)*))
+    };
+}
+
+pub(crate) use say;
+pub(crate) use say_inline;
+
+/// Stream a held back text is printed to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    /// stdout, or stderr when the patterns are written to stdout
+    Messages,
+    Stderr,
+}
+
+thread_local! {
+    /// Texts of the files converted on this thread, innermost last. A thread waiting for the
+    /// chunks of one file may pick up another file, which gets its own entry.
+    static HELD: RefCell<Vec<Vec<(Stream, String)>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Prints a message about the conversion, use [`say`] and [`say_inline`] instead
+pub fn print_message(text: String) {
+    if let Some(text) = hold(Stream::Messages, text) {
+        if uses_stderr() {
+            eprint!("{}", text);
+        } else {
+            print!("{}", text);
+        }
+    }
+}
+
+/// Prints an error line to stderr, held back like the messages
+pub fn print_error(message: impl Display) {
+    if let Some(text) = hold(Stream::Stderr, format!("{}\n", message)) {
+        eprint!("{}", text);
+    }
+}
+
+/// Prints a warning about the conversion to stderr
+pub fn warn(message: impl Display) {
+    print_error(tr!("warning", message = message));
+}
+
+/// Keeps the text for later if messages are held back on this thread, else returns it
+fn hold(stream: Stream, text: String) -> Option<String> {
+    HELD.with_borrow_mut(|held| match held.last_mut() {
+        Some(texts) => {
+            texts.push((stream, text));
+            None
+        }
+        None => Some(text),
+    })
+}
+
+/// Runs `convert` with its messages, warnings and errors held back and returns them in the
+/// order they were printed. Files converted at the same time print them with [`print_held`],
+/// so the lines of one file stay together.
+pub fn held<T>(convert: impl FnOnce() -> T) -> (T, Vec<(Stream, String)>) {
+    HELD.with_borrow_mut(|held| held.push(Vec::new()));
+    let result = convert();
+    let texts = HELD.with_borrow_mut(|held| held.pop()).unwrap_or_default();
+    (result, texts)
+}
+
+/// Prints held back texts in one piece, no other thread prints in between
+pub fn print_held(texts: &[(Stream, String)]) {
+    let mut stdout = std::io::stdout().lock();
+    let mut stderr = std::io::stderr().lock();
+    for (stream, text) in texts {
+        let _ = match stream {
+            Stream::Messages if !uses_stderr() => stdout.write_all(text.as_bytes()),
+            _ => stderr.write_all(text.as_bytes()),
+        };
+    }
+    let _ = stdout.flush();
+}
+
+fn holding() -> bool {
+    HELD.with_borrow(|held| !held.is_empty())
+}
+
+/// Time between redraws of the progress line, so it doesn't slow down the conversion
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Line on stderr showing how far the patterns of a model are, redrawn in place and cleared
+/// when dropped. Nothing is shown when stderr isn't a terminal, e.g. in scripts and logs, or
+/// while messages are held back since the models of several files are converted at once.
+pub struct Progress {
+    name: String,
+    chunks: usize,
+    skipped: usize,
+    visible: bool,
+    state: Mutex<ProgressState>,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    encoded: usize,
+    written: usize,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    /// Progress of encoding the chunks of a model, `skipped` empty chunks aren't encoded
+    pub fn new(name: &str, chunks: usize, skipped: usize, enabled: bool) -> Self {
+        Self {
+            name: name.to_owned(),
+            chunks,
+            skipped,
+            visible: enabled && std::io::stderr().is_terminal() && !holding(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Called from the encoding threads with the number of chunks encoded so far
+    pub fn encoded(&self, count: usize) {
+        self.update(|state| state.encoded = state.encoded.max(count));
+    }
+
+    /// Counts a written pattern
+    pub fn written(&self) {
+        self.update(|state| state.written += 1);
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ProgressState)) {
+        if !self.visible {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        change(&mut state);
+        let done = state.encoded == self.chunks && state.written == self.chunks;
+        if state
+            .drawn
+            .is_some_and(|drawn| drawn.elapsed() < PROGRESS_INTERVAL)
+            && !done
+        {
+            return;
+        }
+        let line = tr!(
+            "progress",
+            name = self.name,
+            chunk = state.encoded,
+            chunks = self.chunks,
+            written = state.written,
+            skipped = self.skipped
+        );
+        eprint!("\r{}\x1b[K", line);
+        state.drawn = Some(Instant::now());
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap();
+        if state.drawn.is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+
+    use super::*;
+
+    #[test]
+    fn files_converted_at_once_keep_their_lines_together() {
+        let barrier = Barrier::new(2);
+        let convert = |file: &str| {
+            held(|| {
+                say!("Converting {}", file);
+                // Both files are in the middle of their conversion here
+                barrier.wait();
+                warn(format!("{} is large", file));
+                say_inline!("{} done", file);
+            })
+            .1
+        };
+        let (first, second) = std::thread::scope(|scope| {
+            let first = scope.spawn(|| convert("tower.vox"));
+            let second = scope.spawn(|| convert("bridge.vox"));
+            (first.join().unwrap(), second.join().unwrap())
+        });
+
+        for (texts, file) in [(first, "tower.vox"), (second, "bridge.vox")] {
+            assert_eq!(
+                texts,
+                [
+                    (Stream::Messages, format!("Converting {}\n", file)),
+                    (Stream::Stderr, format!("Warning: {} is large\n", file)),
+                    (Stream::Messages, format!("{} done", file)),
+                ]
+            );
+        }
+        assert!(!holding());
+    }
+
+    #[test]
+    fn files_picked_up_while_waiting_are_held_apart() {
+        let (inner, outer) = held(|| {
+            say!("outer");
+            let (_, inner) = held(|| say!("inner"));
+            say!("outer again");
+            inner
+        });
+        assert_eq!(inner, [(Stream::Messages, "inner\n".to_owned())]);
+        assert_eq!(
+            outer,
+            [
+                (Stream::Messages, "outer\n".to_owned()),
+                (Stream::Messages, "outer again\n".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Reads pattern files back, the reverse of how [`Pattern`](crate::Pattern)s are encoded.
+
+use std::{collections::BTreeMap, io::Read};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bitstream_io::{BitRead, BitReader};
+use fastnbt::ByteArray;
+use lz4_flex::frame::FrameDecoder;
+use serde::Deserialize;
+
+use crate::{i18n::tr, position_from_index, BLOCK_SIDE};
+
+/// Contents of a pattern file
+pub struct DecodedPattern {
+    /// Block states of the pattern palette in the JSON form of the file, air is the last entry
+    /// in patterns written by this crate
+    pub palette: Vec<String>,
+    /// Palette index of every bit
+    pub entries: Vec<u16>,
+    /// Bytes of packed bit data, patterns may be padded past the last entry
+    pub data_length: usize,
+    /// Bits used for every entry of the packed data
+    pub entry_width: u32,
+    /// Most common block state as stored in the pattern
+    pub primary_state: String,
+    /// Bit count of every block state as stored in the pattern
+    pub state_counts: Vec<(String, u32)>,
+}
+
+impl DecodedPattern {
+    /// Palette index of the bit at the position, on the axes of Minecraft
+    pub fn entry(&self, x: u8, y: u8, z: u8) -> u16 {
+        let (x, y, z) = (x as usize, y as usize, z as usize);
+        self.entries[(x * BLOCK_SIDE + y) * BLOCK_SIDE + z]
+    }
+
+    /// Number of bits of every palette entry, counted from the packed data
+    pub fn counts(&self) -> Vec<u32> {
+        let mut counts = vec![0; self.palette.len()];
+        for &entry in &self.entries {
+            counts[entry as usize] += 1;
+        }
+        counts
+    }
+
+    /// Checks that the packed data holds exactly the bits of one block and that the bits of
+    /// every block state match the statistics stored in the pattern
+    pub fn check(&self) -> Result<(), String> {
+        let total_size = BLOCK_SIDE * BLOCK_SIDE * BLOCK_SIDE;
+        let expected = (total_size * self.entry_width as usize).div_ceil(8);
+        if self.data_length != expected {
+            return Err(tr!(
+                "pattern-check-length",
+                bytes = self.data_length,
+                expected = expected
+            ));
+        }
+
+        // The palette may list a block state several times
+        let mut counted = BTreeMap::<&str, u32>::new();
+        for (state, count) in self.palette.iter().zip(self.counts()) {
+            *counted.entry(state).or_default() += count;
+        }
+        let mut stored = BTreeMap::<&str, u32>::new();
+        for (state, count) in &self.state_counts {
+            *stored.entry(state).or_default() += count;
+        }
+        for (state, &count) in counted.iter().filter(|(_, &count)| count > 0) {
+            let stored_count = stored.get(state).copied().unwrap_or(0);
+            if stored_count != count {
+                return Err(tr!(
+                    "pattern-check-count",
+                    state = state,
+                    bits = count,
+                    expected = stored_count
+                ));
+            }
+        }
+        if let Some((state, count)) = stored
+            .iter()
+            .find(|(state, &count)| count > 0 && !counted.contains_key(*state))
+        {
+            return Err(tr!(
+                "pattern-check-count",
+                state = state,
+                bits = 0,
+                expected = count
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Stages a pattern file is unwrapped in, for looking at what went wrong in an import
+pub struct PatternLayers {
+    /// The pattern JSON after undoing the zlib compression and base64 encoding
+    pub json: Vec<u8>,
+    /// NBT container around the compressed chisel data
+    pub container_nbt: Vec<u8>,
+    /// NBT of the bits, block palette and statistics
+    pub chisel_nbt: Vec<u8>,
+}
+
+/// Decodes a pattern file. Errors name the step that failed, e.g. the LZ4 decompression
+/// of a truncated file.
+pub fn decode_pattern(bytes: &[u8]) -> Result<DecodedPattern, String> {
+    let layers = pattern_layers(bytes)?;
+    let data: Data = fastnbt::from_bytes(&layers.chisel_nbt)
+        .map_err(|e| e.to_string())
+        .map_err(failed("chisel NBT"))?;
+
+    let palette: Vec<_> = data
+        .chiseled_data
+        .palette
+        .into_iter()
+        .map(|entry| entry.state)
+        .collect();
+    let packed: Vec<u8> = data.chiseled_data.data.iter().map(|&b| b as u8).collect();
+    let entry_width = f32::log2(palette.len() as f32).ceil() as u32;
+    let total_size = BLOCK_SIDE * BLOCK_SIDE * BLOCK_SIDE;
+    let mut reader = BitReader::endian(&packed[..], bitstream_io::LittleEndian);
+    let mut entries = Vec::with_capacity(total_size);
+    for index in 0..total_size {
+        let entry: u16 = if entry_width == 0 {
+            0
+        } else {
+            reader.read(entry_width).map_err(|_| {
+                failed("bit stream")(tr!(
+                    "pattern-data-ends",
+                    entries = index,
+                    total = total_size
+                ))
+            })?
+        };
+        if entry as usize >= palette.len() {
+            let (x, y, z) = position_from_index(index);
+            return Err(failed("bit stream")(tr!(
+                "pattern-entry-outside",
+                entry = entry,
+                position = format!("{:?}", (x, y, z)),
+                states = palette.len()
+            )));
+        }
+        entries.push(entry);
+    }
+
+    Ok(DecodedPattern {
+        palette,
+        entries,
+        data_length: packed.len(),
+        entry_width,
+        primary_state: data.statistics.primary_state.state,
+        state_counts: data
+            .statistics
+            .block_states
+            .into_iter()
+            .map(|state| (state.block_information.state, state.count))
+            .collect(),
+    })
+}
+
+/// Undoes the compression and encoding of a pattern file up to the chisel NBT
+pub fn pattern_layers(bytes: &[u8]) -> Result<PatternLayers, String> {
+    let base64 = miniz_oxide::inflate::decompress_to_vec_zlib(bytes)
+        .map_err(|e| e.to_string())
+        .map_err(failed("zlib"))?;
+    let json = STANDARD
+        .decode(base64)
+        .map_err(|e| e.to_string())
+        .map_err(failed("base64"))?;
+    let file: PatternFile = serde_json::from_slice(&json)
+        .map_err(|e| e.to_string())
+        .map_err(failed("pattern JSON"))?;
+    let container_nbt = STANDARD
+        .decode(file.chisel_data)
+        .map_err(|e| e.to_string())
+        .map_err(failed("chiselData base64"))?;
+    let container: DataContainer = fastnbt::from_bytes(&container_nbt)
+        .map_err(|e| e.to_string())
+        .map_err(failed("container NBT"))?;
+
+    let compressed: Vec<u8> = container.data.data.iter().map(|&b| b as u8).collect();
+    let chisel_nbt = if container.data.compressed != 0 {
+        let mut nbt = Vec::new();
+        FrameDecoder::new(&compressed[..])
+            .read_to_end(&mut nbt)
+            .map_err(|e| e.to_string())
+            .map_err(failed("LZ4"))?;
+        nbt
+    } else {
+        compressed
+    };
+    Ok(PatternLayers {
+        json,
+        container_nbt,
+        chisel_nbt,
+    })
+}
+
+/// Error message of a decoding step
+fn failed(stage: &'static str) -> impl Fn(String) -> String {
+    move |error| tr!("pattern-decode-failed", stage = stage, error = error)
+}
+
+#[derive(Deserialize)]
+struct PatternFile {
+    #[serde(rename = "chiselData")]
+    chisel_data: String,
+}
+
+#[derive(Deserialize)]
+struct DataContainer {
+    data: CompressedData,
+}
+
+#[derive(Deserialize)]
+struct CompressedData {
+    data: ByteArray,
+    compressed: u8,
+}
+
+#[derive(Deserialize)]
+struct Data {
+    #[serde(rename = "chiseledData")]
+    chiseled_data: ChiselData,
+    statistics: Statistics,
+}
+
+#[derive(Deserialize)]
+struct ChiselData {
+    data: ByteArray,
+    palette: Vec<PaletteEntry>,
+}
+
+#[derive(Deserialize)]
+struct Statistics {
+    #[serde(rename = "primaryState")]
+    primary_state: PaletteEntry,
+    #[serde(rename = "blockStates")]
+    block_states: Vec<BlockState>,
+}
+
+#[derive(Deserialize)]
+struct BlockState {
+    block_information: PaletteEntry,
+    count: u32,
+}
+
+#[derive(Deserialize)]
+struct PaletteEntry {
+    state: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{minecraft_axes, BlockPalette, Converter};
+
+    const WOOL: [(&str, [u8; 3]); 4] = [
+        ("minecraft:white_wool", [0xff, 0xff, 0xff]),
+        ("minecraft:red_wool", [0xff, 0x00, 0x00]),
+        ("minecraft:blue_wool", [0x00, 0x33, 0xcc]),
+        ("minecraft:yellow_wool", [0xff, 0xff, 0x00]),
+    ];
+
+    fn palette() -> BlockPalette {
+        BlockPalette::from_json(include_bytes!("../blocks.json")).unwrap()
+    }
+
+    fn colors() -> Vec<dot_vox::Color> {
+        WOOL.iter()
+            .map(|&(_, [r, g, b])| dot_vox::Color { r, g, b, a: 255 })
+            .collect()
+    }
+
+    /// Block of the voxel at the position, `None` for air. Uses the given number of blocks.
+    fn block_at([x, y, z]: [u8; 3], blocks: usize) -> Option<u8> {
+        let value = (x as usize + 2 * y as usize + 3 * z as usize) % (blocks + 1);
+        (value < blocks).then_some(value as u8)
+    }
+
+    /// A block sized model filled with the blocks and air in a pattern that has no symmetry
+    /// between the axes
+    fn model(blocks: usize) -> dot_vox::Model {
+        let side = BLOCK_SIDE as u8;
+        let mut voxels = Vec::new();
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    if let Some(i) = block_at([x, y, z], blocks) {
+                        voxels.push(dot_vox::Voxel { x, y, z, i });
+                    }
+                }
+            }
+        }
+        let side = BLOCK_SIDE as u32;
+        dot_vox::Model {
+            size: dot_vox::Size {
+                x: side,
+                y: side,
+                z: side,
+            },
+            voxels,
+        }
+    }
+
+    fn assert_reads_back(pattern: &DecodedPattern, blocks: usize) {
+        pattern.check().unwrap();
+        let side = BLOCK_SIDE as u8;
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    let [mx, my, mz] = minecraft_axes([x, y, z]);
+                    let state = &pattern.palette[pattern.entry(mx, my, mz) as usize];
+                    let expected = match block_at([x, y, z], blocks) {
+                        Some(i) => WOOL[i as usize].0,
+                        None => "minecraft:air",
+                    };
+                    assert!(
+                        state.contains(&format!("\"{}\"", expected)),
+                        "{} at {:?}, expected {}",
+                        state,
+                        (x, y, z),
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn encoded_patterns_decode() {
+        let palette = palette();
+        for blocks in 1..=WOOL.len() {
+            for compression in [0, 6, 9] {
+                let patterns = Converter::new(&palette)
+                    .with_compression(compression)
+                    .convert_model(&model(blocks), &colors())
+                    .unwrap();
+                assert_eq!(patterns.len(), 1);
+                let pattern = decode_pattern(&patterns[0].bytes).unwrap();
+                assert_eq!(pattern.palette.len(), blocks + 1);
+                assert_reads_back(&pattern, blocks);
+            }
+        }
+    }
+
+    #[test]
+    fn full_pattern_decodes() {
+        // Every voxel blue
+        let mut model = model(0);
+        let side = BLOCK_SIDE as u8;
+        model.voxels = (0..side)
+            .flat_map(|x| (0..side).flat_map(move |y| (0..side).map(move |z| [x, y, z])))
+            .map(|[x, y, z]| dot_vox::Voxel { x, y, z, i: 2 })
+            .collect();
+        let patterns = Converter::new(&palette())
+            .convert_model(&model, &colors())
+            .unwrap();
+        let pattern = decode_pattern(&patterns[0].bytes).unwrap();
+        pattern.check().unwrap();
+        assert!(pattern.primary_state.contains("minecraft:blue_wool"));
+        let blue: u32 = pattern
+            .palette
+            .iter()
+            .zip(pattern.counts())
+            .filter(|(state, _)| state.contains("minecraft:blue_wool"))
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(blue as usize, BLOCK_SIDE * BLOCK_SIDE * BLOCK_SIDE);
+    }
+
+    #[test]
+    fn damaged_pattern_names_the_step() {
+        let patterns = Converter::new(&palette())
+            .convert_model(&model(2), &colors())
+            .unwrap();
+        let bytes = &patterns[0].bytes;
+        let error = decode_pattern(&bytes[..bytes.len() / 2]).err().unwrap();
+        assert!(error.contains("zlib"), "{}", error);
+    }
+}
@@ -0,0 +1,117 @@
+//! The stages of written patterns as readable files, for finding out why an import fails
+
+use std::{fmt::Write as _, path::Path};
+
+use chisels_and_importers::decode::pattern_layers;
+use fastnbt::Value;
+
+use crate::i18n::tr;
+
+/// Writes the pattern JSON, the container NBT as SNBT and the chisel NBT of a pattern into the
+/// directory, named after the pattern file
+pub fn dump_pattern(directory: &Path, pattern_file: &str, bytes: &[u8]) -> Result<(), String> {
+    let layers = pattern_layers(bytes)?;
+    let name = Path::new(pattern_file)
+        .file_stem()
+        .map_or_else(|| pattern_file.into(), |stem| stem.to_string_lossy());
+
+    let json: serde_json::Value =
+        serde_json::from_slice(&layers.json).map_err(|e| e.to_string())?;
+    let container: Value = fastnbt::from_bytes(&layers.container_nbt).map_err(|e| e.to_string())?;
+    let mut snbt = String::new();
+    write_snbt(&mut snbt, &container, 0);
+    snbt.push('\n');
+
+    std::fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+    let files = [
+        ("json", serde_json::to_vec_pretty(&json).unwrap()),
+        ("container.snbt", snbt.into_bytes()),
+        ("chisel.nbt", layers.chisel_nbt),
+    ];
+    for (extension, contents) in files {
+        let path = directory.join(format!("{}.{}", name, extension));
+        std::fs::write(&path, contents)
+            .map_err(|e| tr!("file-write-failed", file = path.display(), error = e))?;
+    }
+    Ok(())
+}
+
+/// Appends the value in the text form of commands, with compounds and lists indented
+fn write_snbt(output: &mut String, value: &Value, depth: usize) {
+    let indent = |output: &mut String, depth: usize| output.push_str(&"  ".repeat(depth));
+    match value {
+        Value::Byte(value) => write!(output, "{}b", value).unwrap(),
+        Value::Short(value) => write!(output, "{}s", value).unwrap(),
+        Value::Int(value) => write!(output, "{}", value).unwrap(),
+        Value::Long(value) => write!(output, "{}L", value).unwrap(),
+        Value::Float(value) => write!(output, "{:?}f", value).unwrap(),
+        Value::Double(value) => write!(output, "{:?}d", value).unwrap(),
+        Value::String(value) => write_string(output, value),
+        Value::ByteArray(values) => write_array(output, "B", values.iter(), "b"),
+        Value::IntArray(values) => write_array(output, "I", values.iter(), ""),
+        Value::LongArray(values) => write_array(output, "L", values.iter(), "L"),
+        Value::List(values) if values.is_empty() => output.push_str("[]"),
+        Value::List(values) => {
+            output.push_str("[\n");
+            for (index, value) in values.iter().enumerate() {
+                indent(output, depth + 1);
+                write_snbt(output, value, depth + 1);
+                output.push_str(if index + 1 < values.len() {
+                    ",\n"
+                } else {
+                    "\n"
+                });
+            }
+            indent(output, depth);
+            output.push(']');
+        }
+        Value::Compound(entries) if entries.is_empty() => output.push_str("{}"),
+        Value::Compound(entries) => {
+            // Sorted, the order of the file isn't kept
+            let mut entries: Vec<_> = entries.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+            output.push_str("{\n");
+            for (index, (key, value)) in entries.iter().enumerate() {
+                indent(output, depth + 1);
+                let plain = key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c));
+                if plain && !key.is_empty() {
+                    output.push_str(key);
+                } else {
+                    write_string(output, key);
+                }
+                output.push_str(": ");
+                write_snbt(output, value, depth + 1);
+                output.push_str(if index + 1 < entries.len() {
+                    ",\n"
+                } else {
+                    "\n"
+                });
+            }
+            indent(output, depth);
+            output.push('}');
+        }
+    }
+}
+
+fn write_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+    output.push('"');
+}
+
+fn write_array<T: std::fmt::Display>(
+    output: &mut String,
+    prefix: &str,
+    values: impl Iterator<Item = T>,
+    suffix: &str,
+) {
+    let values: Vec<_> = values.map(|value| format!("{}{}", value, suffix)).collect();
+    write!(output, "[{};{}]", prefix, values.join(",")).unwrap();
+}
@@ -0,0 +1,45 @@
+use std::{fmt, path::Path};
+
+use crate::i18n::tr;
+
+/// Why a conversion failed, the kind decides the exit code
+#[derive(Debug)]
+pub enum Error {
+    /// The model, the palette or the options can't be used
+    Input(String),
+    /// A file can't be written
+    Output(String),
+}
+
+impl Error {
+    /// Output error of a file that can't be written
+    pub fn write(path: impl AsRef<Path>, error: impl fmt::Display) -> Self {
+        Error::Output(tr!(
+            "write-failed",
+            file = path.as_ref().display(),
+            error = error
+        ))
+    }
+
+    /// 2 for input errors like clap's usage errors, 3 for output errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Input(_) => 2,
+            Error::Output(_) => 3,
+        }
+    }
+
+    /// Prints the error and ends the program with its exit code
+    pub fn exit(&self) -> ! {
+        eprintln!("{}", tr!("error", message = self));
+        std::process::exit(self.exit_code())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Input(message) | Error::Output(message) => f.write_str(message),
+        }
+    }
+}
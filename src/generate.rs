@@ -0,0 +1,808 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::{Parser, Subcommand};
+use palette::{Hsl, IntoColor, Srgb};
+
+use crate::{
+    attribution::Attribution, export_models, i18n::tr, lettering, palette_source, picture::Picture,
+    print_summary, Args, BlockPalette, ChunkReport, ModelOrigin, VoxPalette, BLOCK_SIDE,
+    MAX_MODEL_SIZE,
+};
+
+/// Samples per axis when deciding whether a voxel on the surface is filled
+const SAMPLES: u32 = 4;
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum GenerateCommand {
+    /// Ball of the given diameter
+    Sphere(ShapeArgs),
+    /// Upright cylinder
+    Cylinder(ShapeArgs),
+    /// Upper half of a sphere
+    Dome(ShapeArgs),
+    /// Ring lying flat
+    Torus(ShapeArgs),
+    /// Cone with its tip at the top
+    Cone(ShapeArgs),
+    /// Text written with a TrueType or OpenType font
+    Text(TextArgs),
+    /// Mural of a PNG image with one bit per pixel
+    Image(ImageArgs),
+    /// Color ramps and palette blocks for checking how colors are matched in game
+    Calibration(CalibrationArgs),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Sphere,
+    Cylinder,
+    Dome,
+    Torus,
+    Cone,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ShapeArgs {
+    /// width of the shape in bits
+    #[arg(long)]
+    diameter: u32,
+    /// height of cylinders and cones in bits, the diameter if missing
+    #[arg(long)]
+    height: Option<u32>,
+    /// thickness of the ring of a torus in bits, a quarter of the diameter if missing
+    #[arg(long)]
+    tube: Option<u32>,
+    /// only fill the surface, with walls of the given thickness in bits
+    #[arg(long, value_name = "THICKNESS", num_args = 0..=1, default_missing_value = "1")]
+    hollow: Option<u32>,
+    #[command(flatten)]
+    material: Material,
+    #[command(flatten)]
+    output: GeneratedOutput,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct TextArgs {
+    /// text to write, a line break or `\n` starts a new line
+    text: String,
+    /// font file to write the text with
+    #[arg(long)]
+    font: PathBuf,
+    /// height of capital letters in bits
+    #[arg(long, default_value_t = 12)]
+    height: u32,
+    /// thickness of the letters in bits
+    #[arg(long, default_value_t = 1)]
+    depth: u32,
+    /// block for a border around the letters
+    #[arg(long, requires = "block")]
+    outline_block: Option<String>,
+    #[command(flatten)]
+    material: Material,
+    #[command(flatten)]
+    output: GeneratedOutput,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ImageArgs {
+    /// PNG image, transparent pixels are left empty
+    image: PathBuf,
+    /// thickness of the mural in bits
+    #[arg(long, default_value_t = 1)]
+    depth: u32,
+    /// bits per pixel, or "fit" for the largest scale that stays within the limits
+    #[arg(long, default_value = "1")]
+    scale: Scale,
+    /// widest the mural may be, in blocks
+    #[arg(long)]
+    max_width: Option<u32>,
+    /// highest the mural may be, in blocks
+    #[arg(long)]
+    max_height: Option<u32>,
+    /// most patterns the mural may need
+    #[arg(long, default_value_t = 64)]
+    max_patterns: u32,
+    /// shrink images that exceed the limits instead of failing
+    #[arg(long)]
+    resize: bool,
+    /// mix blocks to approximate colors between them
+    #[arg(long)]
+    dither: bool,
+    /// what block palette file to use
+    #[arg(short, long, default_value = "blocks.json")]
+    palette: String,
+    #[command(flatten)]
+    output: GeneratedOutput,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct CalibrationArgs {
+    /// number of palette blocks shown below the color ramps
+    #[arg(long, default_value_t = 16)]
+    blocks: usize,
+    /// show the blocks this Magica Voxel file uses most instead of the palette in name order
+    #[arg(long)]
+    model: Option<String>,
+    /// what block palette file to use
+    #[arg(short, long, default_value = "blocks.json")]
+    palette: String,
+    #[command(flatten)]
+    output: GeneratedOutput,
+}
+
+/// How many bits a pixel of an image takes up
+#[derive(Debug, Clone, Copy)]
+pub enum Scale {
+    Factor(u32),
+    /// Largest factor that fits into the size limits
+    Fit,
+}
+
+impl FromStr for Scale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fit" => Ok(Scale::Fit),
+            factor => match factor.parse() {
+                Ok(factor) if factor > 0 => Ok(Scale::Factor(factor)),
+                _ => Err(tr!("scale-invalid", value = s)),
+            },
+        }
+    }
+}
+
+/// What a generated model is built from
+#[derive(Debug, Clone, clap::Args)]
+struct Material {
+    /// block to build from, e.g. minecraft:quartz_block
+    #[arg(long, required_unless_present = "color", conflicts_with = "color")]
+    block: Option<String>,
+    /// color to match against the palette instead of naming a block, e.g. #d8d0c0
+    #[arg(long)]
+    color: Option<String>,
+    /// what block palette file to use with --color
+    #[arg(short, long, default_value = "blocks.json")]
+    palette: String,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+struct GeneratedOutput {
+    /// the filename for the resulting pattern(s)
+    #[arg(short, long, default_value = "pattern")]
+    output: String,
+    /// render an isometric preview to a PNG file
+    #[arg(long)]
+    preview: Option<String>,
+    /// render the generated colors next to their block colors to a PNG file
+    #[arg(long)]
+    compare: Option<String>,
+    /// write the bill of materials to a CSV file (or JSON with a .json extension)
+    #[arg(long)]
+    bom_file: Option<String>,
+    /// write a command giving a written book that lists the materials to a file
+    #[arg(long)]
+    bom_book: Option<String>,
+    /// write a Markdown description of the build for sharing it
+    #[arg(long)]
+    report_md: Option<String>,
+    /// write a BBCode description of the build for sharing it
+    #[arg(long)]
+    report_bbcode: Option<String>,
+    /// template used instead of the built-in one for the reports
+    #[arg(long)]
+    report_template: Option<String>,
+    /// largest pattern file in bytes, larger patterns are compressed more or lose blocks
+    #[arg(long, value_name = "BYTES")]
+    max_pattern_bytes: Option<usize>,
+    /// fail instead of merging blocks of patterns larger than --max-pattern-bytes
+    #[arg(long, requires = "max_pattern_bytes")]
+    strict: bool,
+    #[command(flatten)]
+    attribution: Attribution,
+}
+
+/// Dimensions of a shape in voxels, Magica Voxel orientation with z up
+struct Dimensions {
+    shape: Shape,
+    diameter: u32,
+    height: u32,
+    tube: u32,
+}
+
+impl Dimensions {
+    fn new(shape: Shape, args: &ShapeArgs) -> Result<Self, String> {
+        let diameter = args.diameter;
+        let height = match shape {
+            Shape::Cylinder | Shape::Cone => args.height.unwrap_or(diameter),
+            Shape::Dome => diameter.div_ceil(2),
+            Shape::Sphere | Shape::Torus => diameter,
+        };
+        let tube = args.tube.unwrap_or((diameter / 4).max(1));
+        for (name, value) in [("diameter", diameter), ("height", height)] {
+            if !(1..=MAX_MODEL_SIZE).contains(&value) {
+                return Err(tr!(
+                    "generate-size-invalid",
+                    name = name,
+                    max = MAX_MODEL_SIZE
+                ));
+            }
+        }
+        if shape == Shape::Torus && !(1..=diameter / 2).contains(&tube) {
+            return Err(tr!("generate-tube-invalid", max = diameter / 2));
+        }
+
+        Ok(Self {
+            shape,
+            diameter,
+            height: if shape == Shape::Torus { tube } else { height },
+            tube,
+        })
+    }
+
+    fn size(&self) -> [u32; 3] {
+        [self.diameter, self.diameter, self.height]
+    }
+
+    /// Whether a point, in voxel units from the lower corner of the shape, lies inside
+    fn contains(&self, [x, y, z]: [f32; 3]) -> bool {
+        let radius = self.diameter as f32 / 2.0;
+        let height = self.height as f32;
+        // Offset from the vertical axis through the center
+        let (dx, dy) = (x - radius, y - radius);
+        let horizontal = dx * dx + dy * dy;
+
+        match self.shape {
+            Shape::Sphere => {
+                let dz = z - radius;
+                horizontal + dz * dz <= radius * radius
+            }
+            Shape::Cylinder => horizontal <= radius * radius,
+            // The flat side of the dome is the bottom of the shape
+            Shape::Dome => horizontal + z * z <= radius * radius,
+            Shape::Torus => {
+                let tube_radius = self.tube as f32 / 2.0;
+                let ring = horizontal.sqrt() - (radius - tube_radius);
+                let dz = z - tube_radius;
+                ring * ring + dz * dz <= tube_radius * tube_radius
+            }
+            Shape::Cone => {
+                let level_radius = radius * (1.0 - z / height);
+                horizontal <= level_radius * level_radius
+            }
+        }
+    }
+
+    /// Whether more than half of the voxel lies inside the shape. Only voxels whose corners
+    /// disagree are sampled, which keeps small shapes round without sampling every voxel.
+    fn fills(&self, [x, y, z]: [u32; 3]) -> bool {
+        let corner = |i: u32| {
+            self.contains([
+                (x + (i & 1)) as f32,
+                (y + (i >> 1 & 1)) as f32,
+                (z + (i >> 2 & 1)) as f32,
+            ])
+        };
+        let inside = (0..8).filter(|&i| corner(i)).count();
+        if inside == 0 || inside == 8 {
+            // Even a fully outside voxel may contain a thin part of the shape, so fall
+            // back to the center
+            return self.contains([x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5]);
+        }
+
+        let offset = |i: u32| (i as f32 + 0.5) / SAMPLES as f32;
+        let mut samples_inside = 0;
+        for i in 0..SAMPLES.pow(3) {
+            let sample = [
+                x as f32 + offset(i % SAMPLES),
+                y as f32 + offset(i / SAMPLES % SAMPLES),
+                z as f32 + offset(i / (SAMPLES * SAMPLES)),
+            ];
+            if self.contains(sample) {
+                samples_inside += 1;
+            }
+        }
+        samples_inside * 2 > SAMPLES.pow(3)
+    }
+}
+
+/// Fills the voxels of the shape. Hollow shapes keep the voxels closer to the outside
+/// than the wall thickness, which leaves walls without holes between faces.
+fn rasterize(dimensions: &Dimensions, wall_thickness: Option<u32>) -> dot_vox::Model {
+    let [size_x, size_y, size_z] = dimensions.size();
+    let index = |[x, y, z]: [u32; 3]| ((x * size_y + y) * size_z + z) as usize;
+    let positions = || {
+        (0..size_x)
+            .flat_map(move |x| (0..size_y).flat_map(move |y| (0..size_z).map(move |z| [x, y, z])))
+    };
+
+    let solid: Vec<_> = positions()
+        .map(|position| dimensions.fills(position))
+        .collect();
+    let mut interior = match wall_thickness {
+        Some(_) => solid.clone(),
+        None => vec![false; solid.len()],
+    };
+    for _ in 0..wall_thickness.unwrap_or(0) {
+        let previous = interior.clone();
+        let filled = |[x, y, z]: [i64; 3]| {
+            let inside = x >= 0
+                && y >= 0
+                && z >= 0
+                && x < size_x as i64
+                && y < size_y as i64
+                && z < size_z as i64;
+            inside && previous[index([x as u32, y as u32, z as u32])]
+        };
+        for position in positions() {
+            let [x, y, z] = position.map(|value| value as i64);
+            let neighbors = [
+                [x - 1, y, z],
+                [x + 1, y, z],
+                [x, y - 1, z],
+                [x, y + 1, z],
+                [x, y, z - 1],
+                [x, y, z + 1],
+            ];
+            interior[index(position)] =
+                previous[index(position)] && neighbors.into_iter().all(filled);
+        }
+    }
+
+    let voxels = positions()
+        .filter(|&position| solid[index(position)] && !interior[index(position)])
+        .map(|[x, y, z]| dot_vox::Voxel {
+            x: x as u8,
+            y: y as u8,
+            z: z as u8,
+            i: 0,
+        })
+        .collect();
+    dot_vox::Model {
+        size: dot_vox::Size {
+            x: size_x,
+            y: size_y,
+            z: size_z,
+        },
+        voxels,
+    }
+}
+
+/// Creates the shape or text and writes its patterns like a converted model
+pub fn generate(command: &GenerateCommand) -> Result<Vec<String>, String> {
+    let (shape, args) = match command {
+        GenerateCommand::Sphere(args) => (Shape::Sphere, args),
+        GenerateCommand::Cylinder(args) => (Shape::Cylinder, args),
+        GenerateCommand::Dome(args) => (Shape::Dome, args),
+        GenerateCommand::Torus(args) => (Shape::Torus, args),
+        GenerateCommand::Cone(args) => (Shape::Cone, args),
+        GenerateCommand::Text(args) => return generate_text(args),
+        GenerateCommand::Image(args) => return generate_image(args),
+        GenerateCommand::Calibration(args) => return generate_calibration(args),
+    };
+    let dimensions = Dimensions::new(shape, args)?;
+    if args.hollow == Some(0) {
+        return Err(tr!("generate-wall-invalid"));
+    }
+
+    let (block_palette, color) = args.material.resolve()?;
+    let model = rasterize(&dimensions, args.hollow);
+    let name = format!("{:?}", shape).to_lowercase();
+    let patterns = export(&name, &args.output, &block_palette, model, &[color])?;
+    Ok(patterns.into_iter().map(|(file, _)| file).collect())
+}
+
+fn generate_text(args: &TextArgs) -> Result<Vec<String>, String> {
+    if args.height == 0 || !(1..=MAX_MODEL_SIZE).contains(&args.depth) {
+        return Err(tr!("text-size-invalid", max = MAX_MODEL_SIZE));
+    }
+    let font_data = std::fs::read(&args.font)
+        .map_err(|e| tr!("file-read-failed", file = args.font.display(), error = e))?;
+    let text = args.text.replace("\\n", "\n");
+    let letters = lettering::render_text(font_data, &text, args.height)?;
+
+    // The outline surrounds the letters, the letters are moved to stay inside it
+    let (mask, outline, offset) = match &args.outline_block {
+        Some(_) => (letters.dilate(), Some(&letters), 1),
+        None => (letters, None, 0),
+    };
+    if mask.width > MAX_MODEL_SIZE || mask.height > MAX_MODEL_SIZE {
+        return Err(tr!(
+            "text-too-large",
+            width = mask.width,
+            height = mask.height,
+            max = MAX_MODEL_SIZE
+        ));
+    }
+
+    let (mut block_palette, color) = args.material.resolve()?;
+    let mut colors = vec![color];
+    if let (Some(outline_block), Some(block)) = (&args.outline_block, &args.material.block) {
+        block_palette = BlockPalette::from_mapping(HashMap::from([
+            ("#ffffff".to_owned(), block.clone()),
+            ("#000000".to_owned(), outline_block.clone()),
+        ]))
+        .unwrap();
+        colors = vec![WHITE, BLACK];
+    }
+
+    let mut wall = Wall::new(mask.width, mask.height, args.depth);
+    for row in 0..mask.height {
+        for column in 0..mask.width {
+            if !mask.get(column, row) {
+                continue;
+            }
+            let is_letter = outline.is_none_or(|letters| {
+                let inside = |value: u32, size: u32| (offset..size + offset).contains(&value);
+                inside(column, letters.width)
+                    && inside(row, letters.height)
+                    && letters.get(column - offset, row - offset)
+            });
+            wall.set(column, row, if is_letter { 0 } else { 1 });
+        }
+    }
+    let model = wall.into_model();
+    let patterns = export("text", &args.output, &block_palette, model, &colors)?;
+    Ok(patterns.into_iter().map(|(file, _)| file).collect())
+}
+
+fn generate_image(args: &ImageArgs) -> Result<Vec<String>, String> {
+    if !(1..=MAX_MODEL_SIZE).contains(&args.depth) {
+        return Err(tr!("image-depth-invalid", max = MAX_MODEL_SIZE));
+    }
+    let mut picture = Picture::read_png(&args.image)?;
+    let block_side = BLOCK_SIDE as u32;
+    let width_limit = args
+        .max_width
+        .map_or(MAX_MODEL_SIZE, |blocks| blocks * block_side);
+    let height_limit = args
+        .max_height
+        .map_or(MAX_MODEL_SIZE, |blocks| blocks * block_side);
+    let width_limit = width_limit.min(MAX_MODEL_SIZE);
+    let height_limit = height_limit.min(MAX_MODEL_SIZE);
+    // Empty chunks are skipped, so this is the most patterns a mural of this size needs
+    let patterns_needed = |width: u32, height: u32| {
+        width.div_ceil(block_side) * height.div_ceil(block_side) * args.depth.div_ceil(block_side)
+    };
+    let fits = |width: u32, height: u32| {
+        width <= width_limit
+            && height <= height_limit
+            && patterns_needed(width, height) <= args.max_patterns
+    };
+
+    let scale = match args.scale {
+        Scale::Factor(factor) => factor,
+        Scale::Fit => (1..=MAX_MODEL_SIZE)
+            .take_while(|&factor| fits(picture.width * factor, picture.height * factor))
+            .last()
+            .unwrap_or(1),
+    };
+    if !fits(picture.width * scale, picture.height * scale) {
+        if !args.resize {
+            return Err(tr!(
+                "image-too-large",
+                width = picture.width * scale,
+                height = picture.height * scale,
+                patterns = patterns_needed(picture.width * scale, picture.height * scale)
+            ));
+        }
+        // Keep the aspect ratio, every side shrinks by the same factor
+        let width = (1..=picture.width)
+            .rev()
+            .find(|&width| {
+                let height = (picture.height * width).div_ceil(picture.width).max(1);
+                fits(width * scale, height * scale)
+            })
+            .ok_or_else(|| tr!("image-limits-too-small"))?;
+        let height = (picture.height * width).div_ceil(picture.width).max(1);
+        picture = picture.resized(width, height);
+    }
+
+    let palette_raw = palette_source::read_palette(&args.palette, false)?;
+    let block_palette = BlockPalette::from_json(&palette_raw)?;
+    let pixels = picture.quantize(&block_palette, args.dither);
+
+    // Only the blocks used by the image need a color, voxels can use at most 256 colors
+    let mut color_indices = HashMap::new();
+    let mut colors = Vec::new();
+    for &block in pixels.iter().flatten() {
+        color_indices.entry(block).or_insert_with(|| {
+            let color = block_palette.mapping[block].color;
+            colors.push(dot_vox::Color {
+                r: color.red,
+                g: color.green,
+                b: color.blue,
+                a: 255,
+            });
+            colors.len() - 1
+        });
+    }
+    if colors.len() > 256 {
+        return Err(tr!("image-too-many-blocks", count = colors.len()));
+    }
+
+    let (width, height) = (picture.width * scale, picture.height * scale);
+    let mut wall = Wall::new(width, height, args.depth);
+    for (index, block) in pixels.iter().enumerate() {
+        let Some(block) = block else {
+            continue;
+        };
+        let column = index as u32 % picture.width;
+        let row = index as u32 / picture.width;
+        for dx in 0..scale {
+            for dy in 0..scale {
+                wall.set(
+                    column * scale + dx,
+                    row * scale + dy,
+                    color_indices[block] as u8,
+                );
+            }
+        }
+    }
+    let model = wall.into_model();
+
+    let patterns = export("image", &args.output, &block_palette, model, &colors)?;
+    let wall_file = format!("{}.wall.csv", args.output.output);
+    write_wall(
+        Path::new(&wall_file),
+        &patterns,
+        height.div_ceil(block_side),
+    )
+    .map_err(|e| tr!("file-write-failed", file = wall_file, error = e))?;
+    Ok(patterns.into_iter().map(|(file, _)| file).collect())
+}
+
+/// Steps of the grayscale ramp and the hue sweeps, one per bit of a block
+const RAMP_STEPS: u32 = BLOCK_SIDE as u32;
+/// Lightness of the hue sweeps, in HSL
+const SWEEP_LIGHTNESS: [f32; 3] = [0.25, 0.5, 0.75];
+/// Height of a ramp or sweep, and the side of a palette block cell, in bits
+const BAND_SIZE: u32 = 2;
+
+/// Builds a wall one block wide from the top: a grayscale ramp from black to white, hue
+/// sweeps at several lightness levels and a grid of palette blocks. The ramps show how
+/// colors between the blocks are matched, the grid how the blocks themselves appear.
+fn generate_calibration(args: &CalibrationArgs) -> Result<Vec<String>, String> {
+    let palette_raw = palette_source::read_palette(&args.palette, false)?;
+    let block_palette = BlockPalette::from_json(&palette_raw)?;
+    let blocks = calibration_blocks(&block_palette, args.model.as_deref())?;
+    let shown = blocks.len().min(args.blocks);
+
+    let cells_per_row = RAMP_STEPS / BAND_SIZE;
+    let grid_rows = (shown as u32).div_ceil(cells_per_row);
+    let height = BAND_SIZE * (1 + SWEEP_LIGHTNESS.len() as u32 + grid_rows);
+    if height > MAX_MODEL_SIZE {
+        return Err(tr!(
+            "calibration-too-many-blocks",
+            max = (MAX_MODEL_SIZE / BAND_SIZE - 1 - SWEEP_LIGHTNESS.len() as u32) * cells_per_row
+        ));
+    }
+
+    let mut colors = Vec::new();
+    let mut add_color = |color: Srgb<u8>| {
+        colors.push(dot_vox::Color {
+            r: color.red,
+            g: color.green,
+            b: color.blue,
+            a: 255,
+        });
+        (colors.len() - 1) as u8
+    };
+    let mut wall = Wall::new(RAMP_STEPS, height, 1);
+    let fill = |wall: &mut Wall, column: u32, band_row: u32, color: u8| {
+        for dx in 0..BAND_SIZE {
+            for dy in 0..BAND_SIZE {
+                wall.set(column + dx, band_row * BAND_SIZE + dy, color);
+            }
+        }
+    };
+
+    for step in 0..RAMP_STEPS {
+        let gray = (step * 255 / (RAMP_STEPS - 1)) as u8;
+        let color = add_color(Srgb::new(gray, gray, gray));
+        for dy in 0..BAND_SIZE {
+            wall.set(step, dy, color);
+        }
+    }
+    for (level, lightness) in SWEEP_LIGHTNESS.into_iter().enumerate() {
+        for step in 0..RAMP_STEPS {
+            let hue = step as f32 * 360.0 / RAMP_STEPS as f32;
+            let color: Srgb = Hsl::new(hue, 1.0, lightness).into_color();
+            let color = add_color(color.into_format());
+            for dy in 0..BAND_SIZE {
+                wall.set(step, (level as u32 + 1) * BAND_SIZE + dy, color);
+            }
+        }
+    }
+    let first_grid_row = 1 + SWEEP_LIGHTNESS.len() as u32;
+    for (i, block) in blocks.iter().take(shown).enumerate() {
+        let color = add_color(block_palette.mapping[*block].color);
+        let column = i as u32 % cells_per_row * BAND_SIZE;
+        fill(
+            &mut wall,
+            column,
+            first_grid_row + i as u32 / cells_per_row,
+            color,
+        );
+    }
+
+    let model = wall.into_model();
+    let patterns = export("calibration", &args.output, &block_palette, model, &colors)?;
+    Ok(patterns.into_iter().map(|(file, _)| file).collect())
+}
+
+/// Palette blocks ordered by how many voxels of the model match them, or by name without
+/// a model
+fn calibration_blocks(palette: &BlockPalette, model: Option<&str>) -> Result<Vec<usize>, String> {
+    let mut blocks: Vec<usize> = (0..palette.mapping.len()).collect();
+    blocks.sort_by(|&l, &r| palette.mapping[l].name.cmp(&palette.mapping[r].name));
+    let Some(model) = model else {
+        return Ok(blocks);
+    };
+
+    let voxel_data =
+        dot_vox::load(model).map_err(|e| tr!("file-read-failed", file = model, error = e))?;
+    let mut usage = vec![0u32; palette.mapping.len()];
+    for voxel in voxel_data.models.iter().flat_map(|model| &model.voxels) {
+        usage[palette.closest_index(voxel_data.palette[voxel.i as usize])] += 1;
+    }
+    blocks.retain(|&block| usage[block] > 0);
+    // Stable, blocks used equally often stay in name order
+    blocks.sort_by_key(|&block| std::cmp::Reverse(usage[block]));
+    Ok(blocks)
+}
+
+/// Flat model built from a grid of colors with the first row at the top. Magica Voxel x
+/// becomes the Minecraft z axis, so the front reads from left to right when facing east
+/// and the depth extends away from the reader.
+struct Wall {
+    width: u32,
+    height: u32,
+    depth: u32,
+    /// Color index of every cell, empty cells are air
+    cells: Vec<Option<u8>>,
+}
+
+impl Wall {
+    fn new(width: u32, height: u32, depth: u32) -> Self {
+        Self {
+            width,
+            height,
+            depth,
+            cells: vec![None; (width * height) as usize],
+        }
+    }
+
+    fn set(&mut self, column: u32, row: u32, color: u8) {
+        self.cells[(row * self.width + column) as usize] = Some(color);
+    }
+
+    fn into_model(self) -> dot_vox::Model {
+        let mut voxels = Vec::new();
+        for (index, color) in self.cells.iter().enumerate() {
+            let Some(color) = *color else {
+                continue;
+            };
+            let column = index as u32 % self.width;
+            let row = index as u32 / self.width;
+            for depth in 0..self.depth {
+                voxels.push(dot_vox::Voxel {
+                    x: column as u8,
+                    y: depth as u8,
+                    z: (self.height - 1 - row) as u8,
+                    i: color,
+                });
+            }
+        }
+        dot_vox::Model {
+            size: dot_vox::Size {
+                x: self.width,
+                y: self.depth,
+                z: self.height,
+            },
+            voxels,
+        }
+    }
+}
+
+/// Lists where every pattern of a mural goes. Columns count from the left and rows from
+/// the top, layers from the front for murals thicker than a block.
+fn write_wall(path: &Path, patterns: &[(String, ChunkReport)], rows: u32) -> std::io::Result<()> {
+    let mut output = String::from("file,column,row,layer\n");
+    for (file, chunk) in patterns {
+        let (x, y, z) = chunk.offset;
+        let block = |offset: u8| offset as u32 / BLOCK_SIDE as u32;
+        output.push_str(&format!(
+            "{},{},{},{}\n",
+            file,
+            block(x) + 1,
+            rows - block(z),
+            block(y) + 1
+        ));
+    }
+    std::fs::write(path, output)
+}
+
+const WHITE: dot_vox::Color = dot_vox::Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
+const BLACK: dot_vox::Color = dot_vox::Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 255,
+};
+
+impl Material {
+    /// Palette and color of the voxels. Naming a block uses a palette of only that block,
+    /// any color matches it.
+    fn resolve(&self) -> Result<(BlockPalette, dot_vox::Color), String> {
+        match (&self.block, &self.color) {
+            (Some(block), _) => {
+                let mapping = HashMap::from([("#ffffff".to_owned(), block.clone())]);
+                Ok((BlockPalette::from_mapping(mapping).unwrap(), WHITE))
+            }
+            (None, Some(color)) => {
+                let color: Srgb<u8> =
+                    Srgb::from_str(color).map_err(|_| tr!("color-code-invalid", color = color))?;
+                let mapping_raw = palette_source::read_palette(&self.palette, false)?;
+                let color = dot_vox::Color {
+                    r: color.red,
+                    g: color.green,
+                    b: color.blue,
+                    a: 255,
+                };
+                Ok((BlockPalette::from_json(&mapping_raw)?, color))
+            }
+            (None, None) => unreachable!("clap requires a block or a color"),
+        }
+    }
+}
+
+/// Writes the patterns of a generated model. The name stands in for the model file in the
+/// options of the conversion.
+fn export(
+    name: &str,
+    output: &GeneratedOutput,
+    block_palette: &BlockPalette,
+    model: dot_vox::Model,
+    colors: &[dot_vox::Color],
+) -> Result<Vec<(String, ChunkReport)>, String> {
+    let mut options = Args::parse_from([env!("CARGO_PKG_NAME"), name]);
+    options.output = output.output.clone();
+    options.preview = output.preview.clone();
+    options.compare = output.compare.clone();
+    options.bom_file = output.bom_file.clone();
+    options.bom_book = output.bom_book.clone();
+    options.report_md = output.report_md.clone();
+    options.report_bbcode = output.report_bbcode.clone();
+    options.report_template = output.report_template.clone();
+    options.max_pattern_bytes = output.max_pattern_bytes;
+    options.strict = output.strict;
+    options.attribution = output.attribution.clone();
+    let (patterns, summary) = export_models(
+        &options,
+        block_palette,
+        &mut [model],
+        VoxPalette {
+            colors,
+            materials: &[],
+            fill_color: None,
+            index_map: None,
+            locked: &BTreeSet::new(),
+        },
+        &[0],
+        ModelOrigin::default(),
+        &mut |_, _| {},
+    )
+    .map_err(|error| error.to_string())?;
+    print_summary(&options, &summary);
+    Ok(patterns)
+}
@@ -0,0 +1,160 @@
+//! Goxel files (`.gox`), read into the same data as Magica Voxel files. Every layer becomes a
+//! model placed in a scene under its name, hidden layers are hidden in the scene. Goxel
+//! stores a color for every voxel, the palette is made from the distinct colors.
+
+use std::collections::HashMap;
+
+use chisels_and_importers::MAX_MODEL_SIZE;
+use dot_vox::DotVoxData;
+
+use crate::{
+    i18n::tr,
+    imported::{to_vox_data, ImportedModel, Reader},
+    picture::Picture,
+};
+
+const MAGIC: &[u8; 4] = b"GOX ";
+/// Side of the cubes of voxels the layers are stored in
+const BRICK_SIDE: i32 = 16;
+
+/// Reads a `.gox` file. The bricks of voxels are stored once as PNG images and placed into
+/// the layers by their index.
+pub fn parse_gox(bytes: &[u8]) -> Result<DotVoxData, String> {
+    let mut reader = Reader::new(bytes);
+    if reader.take(4)? != MAGIC {
+        return Err(tr!("goxel-invalid"));
+    }
+    let version = reader.u32()?;
+
+    let mut bricks = Vec::new();
+    let mut layers = Vec::new();
+    while reader.remaining() > 0 {
+        let chunk_type = reader.take(4)?;
+        let length = reader.u32()? as usize;
+        let data = reader.take(length)?;
+        let _crc = reader.u32()?;
+        match chunk_type {
+            b"BL16" => bricks.push(read_brick(data)?),
+            b"LAYR" => {
+                let layer_number = layers.len() + 1;
+                let mut chunk = Reader::new(data);
+                layers.push(read_layer(&mut chunk, &bricks, version, layer_number)?);
+            }
+            // Previews, materials, cameras and lights aren't needed
+            _ => {}
+        }
+    }
+    Ok(to_vox_data(layers))
+}
+
+/// Voxels of a brick as RGBA, ordered by x, then y, then z
+fn read_brick(png: &[u8]) -> Result<Vec<[u8; 4]>, String> {
+    let picture = Picture::decode_png(png)?;
+    let voxels = picture.pixels();
+    if voxels.len() != (BRICK_SIDE * BRICK_SIDE * BRICK_SIDE) as usize {
+        return Err(tr!(
+            "goxel-brick-invalid",
+            width = picture.width,
+            height = picture.height
+        ));
+    }
+    Ok(voxels.to_vec())
+}
+
+fn read_layer(
+    chunk: &mut Reader,
+    bricks: &[Vec<[u8; 4]>],
+    version: u32,
+    number: usize,
+) -> Result<ImportedModel, String> {
+    let mut voxels = HashMap::new();
+    for _ in 0..chunk.u32()? {
+        let brick = chunk.u32()? as usize;
+        let mut origin = [chunk.i32()?, chunk.i32()?, chunk.i32()?];
+        let _ = chunk.u32()?;
+        // The first version stored the centers of the bricks
+        if version == 1 {
+            origin = origin.map(|axis| axis - BRICK_SIDE / 2);
+        }
+        let brick = bricks
+            .get(brick)
+            .ok_or_else(|| tr!("goxel-missing-brick", layer = number))?;
+        for (index, &[r, g, b, a]) in brick.iter().enumerate() {
+            if a == 0 {
+                continue;
+            }
+            let index = index as i32;
+            let offset = [
+                index % BRICK_SIDE,
+                index / BRICK_SIDE % BRICK_SIDE,
+                index / (BRICK_SIDE * BRICK_SIDE),
+            ];
+            let position = [0, 1, 2].map(|axis| origin[axis] + offset[axis]);
+            voxels.insert(position, [r, g, b]);
+        }
+    }
+
+    // Properties follow as pairs of a key and a value until the end of the chunk
+    let mut name = format!("layer {}", number);
+    let mut hidden = false;
+    while chunk.remaining() > 0 {
+        let key_length = chunk.u32()? as usize;
+        if key_length == 0 {
+            break;
+        }
+        let key = chunk.take(key_length)?;
+        let value_length = chunk.u32()? as usize;
+        let value = chunk.take(value_length)?;
+        match key {
+            b"name" => {
+                name = String::from_utf8_lossy(value)
+                    .trim_end_matches('\0')
+                    .to_owned()
+            }
+            b"visible" => hidden = value.first() == Some(&0),
+            _ => {}
+        }
+    }
+
+    // Layers are unbounded, the model covers the voxels of the layer
+    let Some(&first) = voxels.keys().next() else {
+        return Ok(ImportedModel {
+            name,
+            position: [0; 3],
+            size: [1; 3],
+            voxels: Vec::new(),
+            hidden,
+        });
+    };
+    let (mut min, mut max) = (first, first);
+    for position in voxels.keys() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+    let size = [0, 1, 2].map(|axis| max[axis] - min[axis] + 1);
+    if size.iter().any(|&side| side > MAX_MODEL_SIZE as i32) {
+        return Err(tr!(
+            "goxel-too-large",
+            name = name,
+            size = format!("{}x{}x{}", size[0], size[1], size[2]),
+            max = MAX_MODEL_SIZE
+        ));
+    }
+    let mut voxels: Vec<_> = voxels
+        .into_iter()
+        .map(|(position, color)| {
+            let position = [0, 1, 2].map(|axis| (position[axis] - min[axis]) as u8);
+            (position, color)
+        })
+        .collect();
+    voxels.sort_unstable();
+    Ok(ImportedModel {
+        name,
+        position: min,
+        size: size.map(|side| side as u32),
+        voxels,
+        hidden,
+    })
+}
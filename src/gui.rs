@@ -0,0 +1,279 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
+
+use clap::Parser;
+use eframe::egui;
+
+use crate::{convert, error::Error, i18n::tr, scene::ModelSelector, Args};
+
+/// Palette offered when no other palette has been chosen
+const DEFAULT_PALETTE: &str = "blocks.json";
+/// Directory searched for additional palettes offered in the dropdown
+const PALETTE_DIRECTORY: &str = "palettes";
+
+/// Opens the converter window and returns once it is closed
+pub fn run() -> eframe::Result {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([460.0, 420.0]),
+        ..Default::default()
+    };
+    eframe::run_native(
+        env!("CARGO_PKG_NAME"),
+        options,
+        Box::new(|_| Ok(Box::new(ConverterWindow::new()))),
+    )
+}
+
+/// Options chosen in the window, turned into [`Args`] when converting
+struct ConverterWindow {
+    model: Option<PathBuf>,
+    palettes: Vec<String>,
+    palette: usize,
+    /// Directory the patterns are written to, the working directory if not chosen
+    output_directory: Option<PathBuf>,
+    output_name: String,
+    all_models: bool,
+    preview: bool,
+    bom_file: bool,
+    conversion: Option<Conversion>,
+    result: Option<Result<Vec<String>, String>>,
+}
+
+/// Conversion running on a background thread so the window stays responsive
+struct Conversion {
+    /// Converted and total number of models
+    progress: Arc<Mutex<(usize, usize)>>,
+    thread: JoinHandle<Result<Vec<String>, Error>>,
+}
+
+impl ConverterWindow {
+    fn new() -> Self {
+        let mut palettes = vec![DEFAULT_PALETTE.to_owned()];
+        if let Ok(entries) = std::fs::read_dir(PALETTE_DIRECTORY) {
+            let mut found: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == "json")
+                })
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect();
+            found.sort();
+            palettes.extend(found);
+        }
+
+        Self {
+            model: None,
+            palettes,
+            palette: 0,
+            output_directory: None,
+            output_name: "pattern".to_owned(),
+            all_models: true,
+            preview: false,
+            bom_file: false,
+            conversion: None,
+            result: None,
+        }
+    }
+
+    fn output_prefix(&self, extension: Option<&str>) -> String {
+        let mut name = self.output_name.clone();
+        if let Some(extension) = extension {
+            name = format!("{}.{}", name, extension);
+        }
+        match &self.output_directory {
+            Some(directory) => directory.join(name).to_string_lossy().into_owned(),
+            None => name,
+        }
+    }
+
+    /// Builds the same options the command line would and converts on a new thread
+    fn start_conversion(&mut self, context: &egui::Context) {
+        let Some(model) = &self.model else {
+            return;
+        };
+        // Start from the defaults of the command line, the dialog only returns absolute
+        // paths which can't be mistaken for flags
+        let model = model.to_string_lossy().into_owned();
+        let mut args = Args::parse_from([env!("CARGO_PKG_NAME"), &model]);
+        args.output = self.output_prefix(None);
+        args.palette = self.palettes[self.palette].clone();
+        if self.all_models {
+            args.model_group.all_models = true;
+        } else {
+            args.model_group.models = Some(vec![ModelSelector::Index(1)]);
+        }
+        args.preview = self.preview.then(|| self.output_prefix(Some("png")));
+        args.bom_file = self.bom_file.then(|| self.output_prefix(Some("csv")));
+
+        let progress = Arc::new(Mutex::new((0, 0)));
+        let thread_progress = Arc::clone(&progress);
+        let context = context.clone();
+        let thread = std::thread::spawn(move || {
+            convert(&args, &mut |converted, total| {
+                *thread_progress.lock().unwrap() = (converted, total);
+                context.request_repaint();
+            })
+        });
+        self.conversion = Some(Conversion { progress, thread });
+        self.result = None;
+    }
+
+    /// Collects the result once the conversion thread has ended
+    fn poll_conversion(&mut self) {
+        if !self
+            .conversion
+            .as_ref()
+            .is_some_and(|conversion| conversion.thread.is_finished())
+        {
+            return;
+        }
+        let conversion = self.conversion.take().unwrap();
+        // Show the message of a panic too, e.g. from a bug in the conversion
+        let result = conversion.thread.join().map_err(|panic| {
+            panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| {
+                    panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                })
+                .unwrap_or_else(|| tr!("gui-failed"))
+        });
+        self.result = Some(result.and_then(|converted| converted.map_err(|e| e.to_string())));
+    }
+}
+
+impl eframe::App for ConverterWindow {
+    fn update(&mut self, context: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_conversion();
+
+        egui::CentralPanel::default().show(context, |ui| {
+            let running = self.conversion.is_some();
+            ui.add_enabled_ui(!running, |ui| {
+                egui::Grid::new("options").num_columns(2).show(ui, |ui| {
+                    ui.label(tr!("gui-model"));
+                    ui.horizontal(|ui| {
+                        if ui.button(tr!("gui-browse")).clicked() {
+                            let file = rfd::FileDialog::new()
+                                .add_filter("Magica Voxel", &["vox"])
+                                .pick_file();
+                            self.model = file.or(self.model.take());
+                        }
+                        if let Some(model) = &self.model {
+                            ui.label(model.file_name().unwrap_or_default().to_string_lossy());
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label(tr!("gui-palette"));
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt("palette").show_index(
+                            ui,
+                            &mut self.palette,
+                            self.palettes.len(),
+                            |i| self.palettes[i].clone(),
+                        );
+                        if ui.button(tr!("gui-browse")).clicked() {
+                            if let Some(file) = rfd::FileDialog::new()
+                                .add_filter("JSON", &["json"])
+                                .pick_file()
+                            {
+                                self.palettes.push(file.to_string_lossy().into_owned());
+                                self.palette = self.palettes.len() - 1;
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label(tr!("gui-output"));
+                    ui.horizontal(|ui| {
+                        if ui.button(tr!("gui-browse")).clicked() {
+                            let directory = rfd::FileDialog::new().pick_folder();
+                            self.output_directory = directory.or(self.output_directory.take());
+                        }
+                        if let Some(directory) = &self.output_directory {
+                            ui.label(directory.to_string_lossy());
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label(tr!("gui-name"));
+                    ui.text_edit_singleline(&mut self.output_name);
+                    ui.end_row();
+                });
+
+                ui.checkbox(&mut self.all_models, tr!("gui-all-models"));
+                ui.checkbox(&mut self.preview, tr!("gui-preview"));
+                ui.checkbox(&mut self.bom_file, tr!("gui-bom"));
+
+                let ready = self.model.is_some() && !self.output_name.is_empty();
+                if ui
+                    .add_enabled(ready, egui::Button::new(tr!("gui-convert")))
+                    .clicked()
+                {
+                    self.start_conversion(context);
+                }
+            });
+
+            if let Some(conversion) = &self.conversion {
+                let (converted, total) = *conversion.progress.lock().unwrap();
+                let fraction = if total == 0 {
+                    0.0
+                } else {
+                    converted as f32 / total as f32
+                };
+                ui.add(
+                    egui::ProgressBar::new(fraction)
+                        .text(tr!("gui-progress", converted = converted, total = total))
+                        .animate(true),
+                );
+            }
+
+            ui.separator();
+            match &self.result {
+                Some(Ok(patterns)) => {
+                    ui.label(tr!("gui-written", count = patterns.len()));
+                    egui::ScrollArea::vertical()
+                        .max_height(140.0)
+                        .show(ui, |ui| {
+                            for pattern in patterns {
+                                ui.label(pattern);
+                            }
+                        });
+                    if ui.button(tr!("gui-open-folder")).clicked() {
+                        let directory = self
+                            .output_directory
+                            .clone()
+                            .unwrap_or_else(|| PathBuf::from("."));
+                        if let Err(e) = open_folder(&directory) {
+                            eprintln!("{}", tr!("warning", message = e));
+                        }
+                    }
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                None => {}
+            }
+        });
+    }
+}
+
+/// Shows the directory in the file manager of the system
+fn open_folder(directory: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let program = "explorer";
+    #[cfg(target_os = "macos")]
+    let program = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let program = "xdg-open";
+    std::process::Command::new(program)
+        .arg(directory)
+        .spawn()
+        .map(|_| ())
+}
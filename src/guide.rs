@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use crate::raster::{text_width, Image, Pixel, GLYPH_HEIGHT};
+
+/// Block listed in the legend of a build guide
+pub struct LegendEntry<'a> {
+    pub name: &'a str,
+    pub color: Pixel,
+}
+
+/// Voxel referencing its block by position in the legend
+pub struct GuideVoxel {
+    /// Position in Magica Voxel coordinates (z is up)
+    pub position: [u32; 3],
+    pub legend_index: usize,
+}
+
+/// Side length of one grid cell in pixels, fits a three digit legend number
+const CELL: u32 = 13;
+/// Space above the grid for the layer number
+const HEADER: u32 = GLYPH_HEIGHT + 4;
+/// Cells between two chunk boundary lines
+const CHUNK_CELLS: u32 = 16;
+
+const BACKGROUND: Pixel = [255, 255, 255, 255];
+const GRID_LINE: Pixel = [200, 200, 200, 255];
+const CHUNK_LINE: Pixel = [220, 0, 0, 255];
+const TEXT: Pixel = [0, 0, 0, 255];
+const LIGHT_TEXT: Pixel = [255, 255, 255, 255];
+
+/// Writes one image per horizontal layer plus a legend image into the directory.
+/// Legend numbers shown in the cells start at 1.
+pub fn write_build_guide(
+    directory: &Path,
+    size: [u32; 3],
+    voxels: &[GuideVoxel],
+    legend: &[LegendEntry],
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(directory)?;
+
+    let [width, depth, height] = size;
+    let mut layers: Vec<Vec<&GuideVoxel>> = (0..height).map(|_| Vec::new()).collect();
+    for voxel in voxels {
+        layers[voxel.position[2] as usize].push(voxel);
+    }
+
+    for (layer, layer_voxels) in layers.iter().enumerate() {
+        let mut image = Image::new(width * CELL + 1, HEADER + depth * CELL + 1, BACKGROUND);
+        image.draw_text(2, 2, &format!("layer {}", layer), 1, TEXT);
+
+        for voxel in layer_voxels {
+            let [x, y, _] = voxel.position;
+            // Rows are flipped so the image matches the top view of Magica Voxel
+            let cell_x = (x * CELL) as i64;
+            let cell_y = (HEADER + (depth - 1 - y) * CELL) as i64;
+            let entry = &legend[voxel.legend_index];
+            image.fill_rect(cell_x, cell_y, CELL, CELL, entry.color);
+
+            let label = (voxel.legend_index + 1).to_string();
+            let label_x = cell_x + ((CELL - text_width(&label)) / 2) as i64;
+            let label_y = cell_y + ((CELL - GLYPH_HEIGHT) / 2) as i64;
+            image.draw_text(label_x, label_y, &label, 1, contrasting_text(entry.color));
+        }
+
+        for column in 0..=width {
+            let line = if column % CHUNK_CELLS == 0 {
+                CHUNK_LINE
+            } else {
+                GRID_LINE
+            };
+            image.fill_rect(
+                (column * CELL) as i64,
+                HEADER as i64,
+                1,
+                depth * CELL + 1,
+                line,
+            );
+        }
+        for row in 0..=depth {
+            // Chunks are counted from the bottom row, matching the flipped rows
+            let line = if (depth - row) % CHUNK_CELLS == 0 {
+                CHUNK_LINE
+            } else {
+                GRID_LINE
+            };
+            image.fill_rect(0, (HEADER + row * CELL) as i64, width * CELL + 1, 1, line);
+        }
+
+        image.save_png(&directory.join(format!("layer_{:03}.png", layer)))?;
+    }
+
+    legend_image(legend).save_png(&directory.join("legend.png"))
+}
+
+fn legend_image(legend: &[LegendEntry]) -> Image {
+    const ROW: u32 = CELL + 2;
+    let labels: Vec<_> = legend
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| format!("{} {}", i + 1, entry.name))
+        .collect();
+    let text_columns = labels.iter().map(|label| text_width(label)).max();
+
+    let mut image = Image::new(
+        CELL + 6 + text_columns.unwrap_or(0) + 2,
+        ROW * legend.len() as u32 + 2,
+        BACKGROUND,
+    );
+    for (i, (entry, label)) in legend.iter().zip(&labels).enumerate() {
+        let row_y = (2 + i as u32 * ROW) as i64;
+        image.fill_rect(2, row_y, CELL, CELL, entry.color);
+        image.draw_text(
+            (CELL + 6) as i64,
+            row_y + ((CELL - GLYPH_HEIGHT) / 2) as i64,
+            label,
+            1,
+            TEXT,
+        );
+    }
+    image
+}
+
+/// Picks black or white text depending on how bright the background is
+fn contrasting_text(background: Pixel) -> Pixel {
+    let [r, g, b, _] = background.map(|channel| channel as f32);
+    if 0.299 * r + 0.587 * g + 0.114 * b > 140.0 {
+        TEXT
+    } else {
+        LIGHT_TEXT
+    }
+}
@@ -0,0 +1,69 @@
+//! gzip files, the compression of NBT files
+
+use crate::i18n::tr;
+
+/// Compression level used for gzip files, the default of the gzip tool
+const GZIP_COMPRESSION: u8 = 6;
+
+/// Compresses data into the gzip format Minecraft reads NBT files in
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // Magic number, deflate, no flags, no modification time, no extra flags, unknown OS
+    let mut output = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    output.extend(miniz_oxide::deflate::compress_to_vec(
+        data,
+        GZIP_COMPRESSION,
+    ));
+    output.extend(crc32(data).to_le_bytes());
+    // Length of the uncompressed data modulo 2^32
+    output.extend((data.len() as u32).to_le_bytes());
+    output
+}
+
+/// Decompresses a gzip file, data that isn't compressed is returned as it is since some tools
+/// write NBT files without compression
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if !data.starts_with(&[0x1f, 0x8b]) {
+        return Ok(data.to_vec());
+    }
+    let truncated = || tr!("gzip-ends-early");
+    let flags = *data.get(3).ok_or_else(truncated)?;
+    let mut start = 10;
+    // Extra field, its length comes first
+    if flags & 0x04 != 0 {
+        let length = data.get(start..start + 2).ok_or_else(truncated)?;
+        start += 2 + u16::from_le_bytes([length[0], length[1]]) as usize;
+    }
+    // Zero terminated file name and comment
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            let end = data
+                .get(start..)
+                .and_then(|rest| rest.iter().position(|&byte| byte == 0))
+                .ok_or_else(truncated)?;
+            start += end + 1;
+        }
+    }
+    // Checksum of the header
+    if flags & 0x02 != 0 {
+        start += 2;
+    }
+    let compressed = data.get(start..).ok_or_else(truncated)?;
+    miniz_oxide::inflate::decompress_to_vec(compressed)
+        .map_err(|e| tr!("gzip-decompress-failed", error = format!("{:?}", e)))
+}
+
+/// CRC-32 as used by gzip and PNG
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,51 @@
+use crate::{bom::BillOfMaterials, console::say, i18n::tr};
+
+/// Characters used by the longest bar
+const BAR_WIDTH: usize = 40;
+
+/// Prints a bar chart of how many bits every block takes up. Only the given number of
+/// blocks are listed, the remaining ones are combined into an "others" row.
+pub fn print_histogram(title: &str, bom: &BillOfMaterials, rows: usize) {
+    let entries = bom.entries();
+    if entries.is_empty() {
+        return;
+    }
+
+    let mut bars: Vec<_> = entries
+        .iter()
+        .take(rows)
+        .map(|entry| (entry.block.clone(), entry.bits, entry.percentage))
+        .collect();
+    if entries.len() > rows {
+        let others = &entries[rows..];
+        bars.push((
+            tr!("histogram-others"),
+            others.iter().map(|entry| entry.bits).sum(),
+            others.iter().map(|entry| entry.percentage).sum(),
+        ));
+    }
+
+    let name_width = bars
+        .iter()
+        .map(|(name, _, _)| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let largest = bars
+        .iter()
+        .map(|(_, bits, _)| *bits)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    say!("{}:", title);
+    for (name, bits, percentage) in bars {
+        let length = (bits * BAR_WIDTH as u64).div_ceil(largest) as usize;
+        say!(
+            "  {:<name_width$} {:<BAR_WIDTH$} {:>5.1}% {}",
+            name,
+            "#".repeat(length),
+            percentage,
+            tr!("histogram-bits", bits = bits)
+        );
+    }
+}
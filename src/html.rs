@@ -0,0 +1,236 @@
+use std::{cmp::Reverse, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    attribution::Attribution,
+    guide::{GuideVoxel, LegendEntry},
+};
+
+const TEMPLATE: &str = include_str!("viewer.html");
+
+/// Bits that make up one full block
+const BITS_PER_BLOCK: u64 = 4096;
+
+#[derive(Serialize)]
+struct ViewerModel<'a> {
+    name: &'a str,
+    size: [u32; 3],
+    /// RGB color of every legend entry
+    palette: Vec<[u8; 3]>,
+    materials: Vec<Material<'a>>,
+    /// Greedy meshed faces, seven numbers per face
+    faces: Vec<u32>,
+}
+
+#[derive(Serialize)]
+struct Material<'a> {
+    block: &'a str,
+    color: usize,
+    bits: u64,
+    blocks: u64,
+}
+
+/// Writes a single HTML file with an interactive 3D view of the voxels and their materials.
+pub fn write_html_preview(
+    path: &Path,
+    name: &str,
+    size: [u32; 3],
+    voxels: &[GuideVoxel],
+    legend: &[LegendEntry],
+    attribution: &Attribution,
+) -> std::io::Result<()> {
+    let mut bits = vec![0u64; legend.len()];
+    for voxel in voxels {
+        bits[voxel.legend_index] += 1;
+    }
+    let mut materials: Vec<_> = legend
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| bits[*index] > 0)
+        .map(|(index, entry)| Material {
+            block: entry.name,
+            color: index,
+            bits: bits[index],
+            blocks: bits[index].div_ceil(BITS_PER_BLOCK),
+        })
+        .collect();
+    materials.sort_by_key(|material| Reverse(material.bits));
+
+    let model = ViewerModel {
+        name,
+        size,
+        palette: legend
+            .iter()
+            .map(|entry| [entry.color[0], entry.color[1], entry.color[2]])
+            .collect(),
+        materials,
+        faces: greedy_mesh(size, voxels),
+    };
+
+    // Keep the embedded JSON from closing the script tag
+    let data = serde_json::to_string(&model)?.replace("</", "<\\/");
+    let meta: String = attribution
+        .fields()
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "<meta name=\"{}\" content=\"{}\">\n",
+                name,
+                escape_html(value)
+            )
+        })
+        .collect();
+    let html = TEMPLATE
+        .replace("__TITLE__", &escape_html(name))
+        .replace("__META__", &meta)
+        .replace("__DATA__", &data);
+    std::fs::write(path, html)
+}
+
+/// Escapes text for HTML element content and quoted attribute values
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Merges the visible faces of equally colored neighboring voxels into rectangles.
+/// Every face is `[direction, plane, u, v, width, height, color]`, where the direction is
+/// the axis times two plus one for faces pointing towards negative coordinates.
+fn greedy_mesh(size: [u32; 3], voxels: &[GuideVoxel]) -> Vec<u32> {
+    let size = size.map(|side| side as usize);
+    // Legend index plus one, zero is empty
+    let mut grid = vec![0u32; size[0] * size[1] * size[2]];
+    let grid_index = |p: [usize; 3]| (p[0] * size[1] + p[1]) * size[2] + p[2];
+    for voxel in voxels {
+        let position = voxel.position.map(|coordinate| coordinate as usize);
+        grid[grid_index(position)] = voxel.legend_index as u32 + 1;
+    }
+
+    let mut faces = Vec::new();
+    for axis in 0..3 {
+        let u_axis = (axis + 1) % 3;
+        let v_axis = (axis + 2) % 3;
+        let (width, height) = (size[u_axis], size[v_axis]);
+        let mut positive = vec![0u32; width * height];
+        let mut negative = vec![0u32; width * height];
+
+        for plane in 0..=size[axis] {
+            for v in 0..height {
+                for u in 0..width {
+                    let mut position = [0; 3];
+                    position[u_axis] = u;
+                    position[v_axis] = v;
+                    let before = if plane > 0 {
+                        position[axis] = plane - 1;
+                        grid[grid_index(position)]
+                    } else {
+                        0
+                    };
+                    let after = if plane < size[axis] {
+                        position[axis] = plane;
+                        grid[grid_index(position)]
+                    } else {
+                        0
+                    };
+
+                    let cell = v * width + u;
+                    positive[cell] = if after == 0 { before } else { 0 };
+                    negative[cell] = if before == 0 { after } else { 0 };
+                }
+            }
+
+            for (mask, direction) in [(&mut positive, axis * 2), (&mut negative, axis * 2 + 1)] {
+                merge_mask(mask, width, height, |u, v, w, h, color| {
+                    faces.extend([direction as u32, plane as u32, u, v, w, h, color - 1]);
+                });
+            }
+        }
+    }
+    faces
+}
+
+/// Greedily covers the non-zero cells of the mask with rectangles of equal value
+fn merge_mask(
+    mask: &mut [u32],
+    width: usize,
+    height: usize,
+    mut emit: impl FnMut(u32, u32, u32, u32, u32),
+) {
+    for v in 0..height {
+        let mut u = 0;
+        while u < width {
+            let color = mask[v * width + u];
+            if color == 0 {
+                u += 1;
+                continue;
+            }
+
+            let mut w = 1;
+            while u + w < width && mask[v * width + u + w] == color {
+                w += 1;
+            }
+            let mut h = 1;
+            while v + h < height && (u..u + w).all(|column| mask[(v + h) * width + column] == color)
+            {
+                h += 1;
+            }
+
+            for row in v..v + h {
+                mask[row * width + u..row * width + u + w].fill(0);
+            }
+            emit(u as u32, v as u32, w as u32, h as u32, color);
+            u += w;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribution_is_written_as_escaped_meta_tags() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("castle.html");
+        let attribution = Attribution {
+            author: Some("Ann \"the builder\"".to_owned()),
+            license: Some("CC-BY-4.0".to_owned()),
+            description: Some("Towers <and>\nwalls".to_owned()),
+        };
+        let legend = [LegendEntry {
+            name: "minecraft:stone",
+            color: [125, 125, 125, 255],
+        }];
+        let voxels = [GuideVoxel {
+            position: [0, 0, 0],
+            legend_index: 0,
+        }];
+        write_html_preview(&path, "castle", [1, 1, 1], &voxels, &legend, &attribution).unwrap();
+
+        let html = std::fs::read_to_string(path).unwrap();
+        assert!(html.contains("<meta name=\"author\" content=\"Ann &quot;the builder&quot;\">"));
+        assert!(html.contains("<meta name=\"license\" content=\"CC-BY-4.0\">"));
+        assert!(html.contains("<meta name=\"description\" content=\"Towers &lt;and&gt; walls\">"));
+    }
+
+    #[test]
+    fn no_meta_tags_without_attribution() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("castle.html");
+        write_html_preview(
+            &path,
+            "castle",
+            [1, 1, 1],
+            &[],
+            &[],
+            &Attribution::default(),
+        )
+        .unwrap();
+        assert!(!std::fs::read_to_string(path)
+            .unwrap()
+            .contains("<meta name="));
+    }
+}
@@ -0,0 +1,72 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+const ENGLISH: &str = include_str!("../locales/en.json");
+/// Languages besides English with their catalog
+const LOCALES: &[(&str, &str)] = &[("de", include_str!("../locales/de.json"))];
+
+/// Messages of the selected language, missing ones are taken from English
+struct Catalog {
+    selected: HashMap<String, String>,
+    english: HashMap<String, String>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Selects the language of console messages. Without a language given, the locale of the
+/// environment is used. Unknown languages fall back to English.
+pub fn init(language: Option<&str>) {
+    let environment = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|variable| std::env::var(variable).ok())
+        .find(|value| !value.is_empty());
+    let language = language.map(str::to_owned).or(environment);
+    // Locales look like `de_CH.UTF-8`, only the language is used
+    let language = language
+        .as_deref()
+        .and_then(|language| language.split(['_', '-', '.']).next())
+        .unwrap_or("en")
+        .to_ascii_lowercase();
+
+    let selected = LOCALES
+        .iter()
+        .find(|(name, _)| *name == language)
+        .map(|(_, catalog)| parse(catalog))
+        .unwrap_or_default();
+    let _ = CATALOG.set(Catalog {
+        selected,
+        english: parse(ENGLISH),
+    });
+}
+
+fn parse(catalog: &str) -> HashMap<String, String> {
+    serde_json::from_str(catalog).expect("invalid message catalog")
+}
+
+/// Looks up the message in the selected language and fills in the `{name}` placeholders.
+/// Use the [`tr`] macro instead of calling this directly.
+pub fn translate(key: &str, arguments: &[(&str, String)]) -> String {
+    let catalog = CATALOG.get_or_init(|| Catalog {
+        selected: HashMap::new(),
+        english: parse(ENGLISH),
+    });
+    let template = catalog
+        .selected
+        .get(key)
+        .or_else(|| catalog.english.get(key))
+        .map_or(key, String::as_str);
+
+    let mut message = template.to_owned();
+    for (name, value) in arguments {
+        message = message.replace(&format!("{{{}}}", name), value);
+    }
+    message
+}
+
+/// Translates a message of the catalog, e.g. `tr!("import-wrote", count = files.len())`
+#[macro_export]
+macro_rules! tr {
+    ($key:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::translate($key, &[$((stringify!($name), $value.to_string())),*])
+    };
+}
+pub use crate::tr;
@@ -0,0 +1,233 @@
+//! Models of editors that store a color for every voxel instead of a palette, turned into the
+//! same data as Magica Voxel files
+
+use std::collections::HashMap;
+
+use dot_vox::{DotVoxData, Frame, SceneNode, ShapeModel};
+
+use crate::i18n::tr;
+
+/// Voxels of a Magica Voxel palette can use every index but the last
+pub const MAX_COLORS: usize = 255;
+
+/// Model in Magica Voxel axes, with z pointing up
+pub struct ImportedModel {
+    pub name: String,
+    /// Position of the lowest corner
+    pub position: [i32; 3],
+    pub size: [u32; 3],
+    pub voxels: Vec<([u8; 3], [u8; 3])>,
+    /// Left out when the models are placed together
+    pub hidden: bool,
+}
+
+/// Places the models in a scene under their names and builds the palette
+pub fn to_vox_data(models: Vec<ImportedModel>) -> DotVoxData {
+    let mut usage = HashMap::<[u8; 3], u32>::new();
+    for (_, color) in models.iter().flat_map(|model| &model.voxels) {
+        *usage.entry(*color).or_default() += 1;
+    }
+    let (colors, indices) = build_palette(usage, MAX_COLORS);
+    let mut palette: Vec<_> = colors
+        .iter()
+        .map(|&[r, g, b]| dot_vox::Color { r, g, b, a: 255 })
+        .collect();
+    palette.resize(
+        256,
+        dot_vox::Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        },
+    );
+
+    // A root transform with a group holding a transform and a shape for every model
+    let mut scenes = vec![
+        SceneNode::Transform {
+            attributes: Default::default(),
+            frames: vec![Frame {
+                attributes: Default::default(),
+            }],
+            child: 1,
+            layer_id: 0,
+        },
+        SceneNode::Group {
+            attributes: Default::default(),
+            children: Vec::new(),
+        },
+    ];
+    let mut vox_models = Vec::new();
+    for model in models {
+        let model_id = vox_models.len() as u32;
+        let transform = scenes.len() as u32;
+        if let SceneNode::Group { children, .. } = &mut scenes[1] {
+            children.push(transform);
+        }
+        // Magica Voxel places models by their center
+        let center: Vec<_> = (0..3)
+            .map(|axis| (model.position[axis] + model.size[axis] as i32 / 2).to_string())
+            .collect();
+        let mut attributes: dot_vox::Dict =
+            [("_name".to_owned(), model.name)].into_iter().collect();
+        if model.hidden {
+            attributes.insert("_hidden".to_owned(), "1".to_owned());
+        }
+        scenes.push(SceneNode::Transform {
+            attributes,
+            frames: vec![Frame {
+                attributes: [("_t".to_owned(), center.join(" "))].into_iter().collect(),
+            }],
+            child: transform + 1,
+            layer_id: 0,
+        });
+        scenes.push(SceneNode::Shape {
+            attributes: Default::default(),
+            models: vec![ShapeModel {
+                model_id,
+                attributes: Default::default(),
+            }],
+        });
+        let [x, y, z] = model.size;
+        vox_models.push(dot_vox::Model {
+            size: dot_vox::Size { x, y, z },
+            voxels: model
+                .voxels
+                .into_iter()
+                .map(|([x, y, z], color)| dot_vox::Voxel {
+                    x,
+                    y,
+                    z,
+                    i: indices[&color],
+                })
+                .collect(),
+        });
+    }
+
+    DotVoxData {
+        version: 150,
+        index_map: Vec::new(),
+        models: vox_models,
+        palette,
+        materials: Vec::new(),
+        scenes,
+        layers: Vec::new(),
+    }
+}
+
+/// Palette of the distinct colors with the index of every color in it. With more colors than
+/// `max_colors`, they are grouped by median cut and every group gets its average color.
+pub fn build_palette(
+    usage: HashMap<[u8; 3], u32>,
+    max_colors: usize,
+) -> (Vec<[u8; 3]>, HashMap<[u8; 3], u8>) {
+    let mut colors: Vec<_> = usage.into_iter().collect();
+    // Sorted so the palette doesn't depend on the order of the hash map
+    colors.sort_unstable();
+    if colors.len() <= max_colors {
+        let indices = colors
+            .iter()
+            .enumerate()
+            .map(|(index, (color, _))| (*color, index as u8))
+            .collect();
+        return (
+            colors.into_iter().map(|(color, _)| color).collect(),
+            indices,
+        );
+    }
+
+    let mut groups = vec![colors];
+    while groups.len() < max_colors {
+        // Split the group with the widest channel at the voxel at its middle
+        let widest = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, group)| group.len() > 1)
+            .map(|(index, group)| {
+                let (range, channel) = (0..3)
+                    .map(|channel| {
+                        let values = group.iter().map(|(color, _)| color[channel]);
+                        let range = values.clone().max().unwrap() - values.min().unwrap();
+                        (range, channel)
+                    })
+                    .max()
+                    .unwrap();
+                (range, index, channel)
+            })
+            .max();
+        let Some((_, index, channel)) = widest else {
+            break;
+        };
+        let mut group = groups.swap_remove(index);
+        group.sort_unstable_by_key(|(color, _)| color[channel]);
+        let total: u64 = group.iter().map(|&(_, count)| count as u64).sum();
+        let mut counted = 0;
+        let middle = group
+            .iter()
+            .position(|&(_, count)| {
+                counted += count as u64;
+                counted * 2 >= total
+            })
+            .map_or(1, |position| position.clamp(1, group.len() - 1));
+        let upper = group.split_off(middle);
+        groups.push(group);
+        groups.push(upper);
+    }
+
+    let mut palette = Vec::with_capacity(groups.len());
+    let mut indices = HashMap::new();
+    for (index, group) in groups.iter().enumerate() {
+        let total: u64 = group.iter().map(|&(_, count)| count as u64).sum();
+        let average = [0, 1, 2].map(|channel| {
+            let sum: u64 = group
+                .iter()
+                .map(|&(color, count)| color[channel] as u64 * count as u64)
+                .sum();
+            (sum / total.max(1)) as u8
+        });
+        palette.push(average);
+        for (color, _) in group {
+            indices.insert(*color, index as u8);
+        }
+    }
+    (palette, indices)
+}
+
+/// Reads the little endian values of a file one after the other
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    /// Number of bytes not read yet
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    pub fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .position
+            .checked_add(length)
+            .and_then(|end| self.bytes.get(self.position..end))
+            .ok_or_else(|| tr!("file-ends-early"))?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
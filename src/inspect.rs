@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use chisels_and_importers::{decode::decode_pattern, BLOCK_SIDE};
+use serde_json::Value;
+
+use crate::i18n::tr;
+
+/// Characters marking the palette entries in layer dumps, air is always `.`
+const SYMBOLS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Prints the palette of a pattern file with the bits of every block state, and every layer
+/// from the top if asked to
+pub fn print_pattern(path: &Path, layers: bool) -> Result<(), String> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| tr!("file-read-failed", file = path.display(), error = e))?;
+    let pattern = decode_pattern(&bytes)?;
+
+    let symbols: Vec<char> = pattern
+        .palette
+        .iter()
+        .scan(0, |next, state| {
+            let symbol = if is_air(state) {
+                '.'
+            } else {
+                *next += 1;
+                SYMBOLS.get(*next - 1).map_or('?', |&symbol| symbol as char)
+            };
+            Some(symbol)
+        })
+        .collect();
+    let counts = pattern.counts();
+    println!(
+        "{}",
+        tr!(
+            "inspect-header",
+            file = path.display(),
+            states = pattern.palette.len(),
+            width = pattern.entry_width
+        )
+    );
+    let count_width = counts.iter().max().unwrap_or(&0).to_string().len();
+    for ((state, symbol), count) in pattern.palette.iter().zip(&symbols).zip(&counts) {
+        println!(
+            "  {} {:>width$}  {}",
+            symbol,
+            count,
+            state,
+            width = count_width
+        );
+    }
+    println!("{}", tr!("inspect-primary", state = pattern.primary_state));
+
+    if layers {
+        for y in (0..BLOCK_SIDE as u8).rev() {
+            println!();
+            println!("{}", tr!("inspect-layer", layer = y));
+            for z in 0..BLOCK_SIDE as u8 {
+                let row: String = (0..BLOCK_SIDE as u8)
+                    .map(|x| symbols[pattern.entry(x, y, z) as usize])
+                    .collect();
+                println!("  {}", row);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_air(state: &str) -> bool {
+    serde_json::from_str::<Value>(state)
+        .ok()
+        .and_then(|state| {
+            state
+                .get("Name")?
+                .as_str()
+                .map(|name| name == "minecraft:air")
+        })
+        .unwrap_or(false)
+}
@@ -0,0 +1,246 @@
+use std::{cmp::Reverse, collections::HashMap, str::FromStr};
+
+use dot_vox::DotVoxData;
+use palette::Srgb;
+
+use crate::i18n::tr;
+
+/// State of a position of the model grid
+#[derive(Clone, Copy, PartialEq)]
+enum Cell {
+    /// Air enclosed by voxels
+    Air,
+    /// Air connected to the outside of the model
+    OpenAir,
+    /// Voxel of the color index
+    Voxel(u8),
+    /// Voxel within the shell kept by hollowing
+    Shell,
+}
+
+/// Voxels of a model by position, with the air connected to the outside told apart from the
+/// air enclosed by the model. The space around the model counts as outside.
+struct Grid {
+    size: [usize; 3],
+    cells: Vec<Cell>,
+    /// Positions of the air connected to the outside
+    open_air: Vec<[usize; 3]>,
+}
+
+impl Grid {
+    /// Places the voxels of the model and floods the air from the sides of the model inwards.
+    /// Voxels outside of the size of the model are left out.
+    fn new(model: &dot_vox::Model) -> Self {
+        let size = [model.size.x, model.size.y, model.size.z].map(|side| side as usize);
+        let mut grid = Self {
+            size,
+            cells: vec![Cell::Air; size[0] * size[1] * size[2]],
+            open_air: Vec::new(),
+        };
+        for voxel in &model.voxels {
+            if let Some(index) = grid.index_of(voxel) {
+                grid.cells[index] = Cell::Voxel(voxel.i);
+            }
+        }
+
+        let mut queue: Vec<_> = grid
+            .boundary()
+            .filter(|&position| grid.cell(position) == Cell::Air)
+            .collect();
+        for &position in &queue {
+            grid.set(position, Cell::OpenAir);
+        }
+        while let Some(position) = queue.pop() {
+            grid.open_air.push(position);
+            for neighbor in grid.neighbors(position) {
+                if grid.cell(neighbor) == Cell::Air {
+                    grid.set(neighbor, Cell::OpenAir);
+                    queue.push(neighbor);
+                }
+            }
+        }
+        grid
+    }
+
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (x * self.size[1] + y) * self.size[2] + z
+    }
+
+    fn index_of(&self, voxel: &dot_vox::Voxel) -> Option<usize> {
+        let position = [voxel.x, voxel.y, voxel.z].map(usize::from);
+        (0..3)
+            .all(|axis| position[axis] < self.size[axis])
+            .then(|| self.index(position))
+    }
+
+    fn cell(&self, position: [usize; 3]) -> Cell {
+        self.cells[self.index(position)]
+    }
+
+    fn set(&mut self, position: [usize; 3], cell: Cell) {
+        let index = self.index(position);
+        self.cells[index] = cell;
+    }
+
+    /// Positions next to the position along the axes, within the model
+    fn neighbors(&self, position: [usize; 3]) -> impl Iterator<Item = [usize; 3]> {
+        let size = self.size;
+        (0..3).flat_map(move |axis| {
+            [position[axis].checked_sub(1), Some(position[axis] + 1)]
+                .into_iter()
+                .flatten()
+                .filter(move |&moved| moved < size[axis])
+                .map(move |moved| {
+                    let mut neighbor = position;
+                    neighbor[axis] = moved;
+                    neighbor
+                })
+        })
+    }
+
+    /// Every position of the model
+    fn positions(&self) -> impl Iterator<Item = [usize; 3]> {
+        let size = self.size;
+        (0..size[0])
+            .flat_map(move |x| (0..size[1]).flat_map(move |y| (0..size[2]).map(move |z| [x, y, z])))
+    }
+
+    /// Positions on the sides of the model
+    fn boundary(&self) -> impl Iterator<Item = [usize; 3]> {
+        let size = self.size;
+        self.positions().filter(move |position| {
+            (0..3).any(|axis| position[axis] == 0 || position[axis] == size[axis] - 1)
+        })
+    }
+}
+
+/// Turns the voxels into air that can't be seen from outside of the model, only keeping a
+/// shell of the given thickness around the air that is connected to the outside. The space
+/// around the model counts as outside, enclosed air pockets don't. Returns the number of
+/// removed voxels.
+pub fn hollow(model: &mut dot_vox::Model, thickness: u32) -> usize {
+    let mut grid = Grid::new(model);
+
+    // The first layer of the shell touches open air or the outside, every further layer
+    // touches the one before
+    let mut layer: Vec<_> = grid
+        .boundary()
+        .chain(
+            grid.open_air
+                .iter()
+                .flat_map(|&position| grid.neighbors(position)),
+        )
+        .filter(|&position| matches!(grid.cell(position), Cell::Voxel(_)))
+        .collect();
+    for depth in 0..thickness {
+        let mut next = Vec::new();
+        for &position in &layer {
+            if let Cell::Voxel(_) = grid.cell(position) {
+                grid.set(position, Cell::Shell);
+                if depth + 1 < thickness {
+                    next.extend(grid.neighbors(position));
+                }
+            }
+        }
+        layer = next;
+    }
+
+    let before = model.voxels.len();
+    model.voxels.retain(|voxel| match grid.index_of(voxel) {
+        Some(index) => grid.cells[index] == Cell::Shell,
+        None => true,
+    });
+    before - model.voxels.len()
+}
+
+/// Fills the air enclosed by the model with voxels of the color index, or with the most common
+/// color of their neighbors working inwards from the enclosing voxels. Returns the number of
+/// added voxels.
+pub fn fill(model: &mut dot_vox::Model, color: Option<u8>) -> usize {
+    let mut grid = Grid::new(model);
+    let cavities: Vec<_> = grid
+        .positions()
+        .filter(|&position| grid.cell(position) == Cell::Air)
+        .collect();
+    if let Some(color) = color {
+        for &position in &cavities {
+            grid.set(position, Cell::Voxel(color));
+        }
+    }
+
+    // Without a color the cavities are filled layer by layer from the voxels around them
+    let mut layer: Vec<_> = cavities
+        .iter()
+        .copied()
+        .filter(|&position| {
+            grid.cell(position) == Cell::Air
+                && grid
+                    .neighbors(position)
+                    .any(|neighbor| matches!(grid.cell(neighbor), Cell::Voxel(_)))
+        })
+        .collect();
+    while !layer.is_empty() {
+        // Colors are picked from the voxels before this layer, so they don't depend on the
+        // order of the positions
+        let colored: Vec<_> = layer
+            .iter()
+            .filter_map(|&position| {
+                let mut counts = HashMap::<u8, u32>::new();
+                for neighbor in grid.neighbors(position) {
+                    if let Cell::Voxel(color) = grid.cell(neighbor) {
+                        *counts.entry(color).or_default() += 1;
+                    }
+                }
+                // Ties go to the lower color index
+                let (color, _) = counts
+                    .into_iter()
+                    .max_by_key(|&(color, count)| (count, Reverse(color)))?;
+                Some((position, color))
+            })
+            .collect();
+        for &(position, color) in &colored {
+            grid.set(position, Cell::Voxel(color));
+        }
+        layer = colored
+            .iter()
+            .flat_map(|&(position, _)| grid.neighbors(position))
+            .filter(|&position| grid.cell(position) == Cell::Air)
+            .collect();
+        layer.sort_unstable();
+        layer.dedup();
+    }
+
+    for &position in &cavities {
+        if let Cell::Voxel(color) = grid.cell(position) {
+            let [x, y, z] = position.map(|axis| axis as u8);
+            model.voxels.push(dot_vox::Voxel { x, y, z, i: color });
+        }
+    }
+    cavities.len()
+}
+
+/// Puts the color code into the palette of the file at a color index no voxel uses, so it is
+/// matched to a block like the colors of the models. Returns the color index.
+pub fn add_color(data: &mut DotVoxData, color: &str) -> Result<u8, String> {
+    let color: Srgb<u8> =
+        Srgb::from_str(color).map_err(|_| tr!("color-code-invalid", color = color))?;
+    let mut used = [false; 256];
+    for voxel in data.models.iter().flat_map(|model| &model.voxels) {
+        used[voxel.i as usize] = true;
+    }
+    // The last index is the one Magica Voxel files can't give to voxels
+    let index = (0..data.palette.len().min(256))
+        .rev()
+        .find(|&index| !used[index])
+        .ok_or_else(|| tr!("fill-color-no-index"))?;
+    data.palette[index] = dot_vox::Color {
+        r: color.red,
+        g: color.green,
+        b: color.blue,
+        a: 255,
+    };
+    // Material ids are palette indices, which start at 1 in the file
+    data.materials
+        .retain(|material| material.id as usize != index + 1);
+    Ok(index as u8)
+}
@@ -0,0 +1,116 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::i18n::tr;
+
+pub const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+pub const DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+pub const LOCAL_HEADER: u32 = 0x0403_4b50;
+/// Size of the end of central directory record without the archive comment
+const END_OF_DIRECTORY_SIZE: usize = 22;
+/// Longest archive comment, the end record is searched within this distance of the end
+const MAX_COMMENT: usize = u16::MAX as usize;
+
+pub const STORED: u16 = 0;
+pub const DEFLATED: u16 = 8;
+
+/// Jar (zip) archive read into memory, only the files needed for palettes are decompressed
+pub struct Jar {
+    data: Vec<u8>,
+    entries: HashMap<String, Entry>,
+}
+
+struct Entry {
+    method: u16,
+    compressed_size: usize,
+    /// Position of the local file header
+    offset: usize,
+}
+
+impl Jar {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path)
+            .map_err(|e| tr!("file-read-failed", file = path.display(), error = e))?;
+        let entries =
+            read_directory(&data).ok_or_else(|| tr!("jar-invalid", file = path.display()))?;
+        Ok(Self { data, entries })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Decompressed contents of a file, `None` if the jar doesn't contain it
+    pub fn read(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        let Some(entry) = self.entries.get(name) else {
+            return Ok(None);
+        };
+        let invalid = || tr!("jar-entry-damaged", file = name);
+        let header = self.data.get(entry.offset..).ok_or_else(invalid)?;
+        if u32_at(header, 0) != Some(LOCAL_HEADER) {
+            return Err(invalid());
+        }
+        // The local header repeats the name, but its extra field may differ
+        let name_length = u16_at(header, 26).ok_or_else(invalid)? as usize;
+        let extra_length = u16_at(header, 28).ok_or_else(invalid)? as usize;
+        let start = 30 + name_length + extra_length;
+        let compressed = header
+            .get(start..start + entry.compressed_size)
+            .ok_or_else(invalid)?;
+
+        match entry.method {
+            STORED => Ok(Some(compressed.to_vec())),
+            DEFLATED => miniz_oxide::inflate::decompress_to_vec(compressed)
+                .map(Some)
+                .map_err(|_| invalid()),
+            method => Err(tr!("jar-method-unsupported", file = name, method = method)),
+        }
+    }
+}
+
+/// Lists the files of the archive from its central directory
+fn read_directory(data: &[u8]) -> Option<HashMap<String, Entry>> {
+    // The end record is followed only by the archive comment
+    let search_start = data
+        .len()
+        .saturating_sub(END_OF_DIRECTORY_SIZE + MAX_COMMENT);
+    let end = (search_start..=data.len().checked_sub(END_OF_DIRECTORY_SIZE)?)
+        .rev()
+        .find(|&position| u32_at(data, position) == Some(END_OF_DIRECTORY))?;
+    let count = u16_at(data, end + 10)? as usize;
+    let mut position = u32_at(data, end + 16)? as usize;
+
+    let mut entries = HashMap::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(data, position)? != DIRECTORY_ENTRY {
+            return None;
+        }
+        let method = u16_at(data, position + 10)?;
+        let compressed_size = u32_at(data, position + 20)? as usize;
+        let name_length = u16_at(data, position + 28)? as usize;
+        let extra_length = u16_at(data, position + 30)? as usize;
+        let comment_length = u16_at(data, position + 32)? as usize;
+        let offset = u32_at(data, position + 42)? as usize;
+        let name = data.get(position + 46..position + 46 + name_length)?;
+
+        entries.insert(
+            String::from_utf8_lossy(name).into_owned(),
+            Entry {
+                method,
+                compressed_size,
+                offset,
+            },
+        );
+        position += 46 + name_length + extra_length + comment_length;
+    }
+    Some(entries)
+}
+
+fn u16_at(data: &[u8], position: usize) -> Option<u16> {
+    let bytes = data.get(position..position + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], position: usize) -> Option<u32> {
+    let bytes = data.get(position..position + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
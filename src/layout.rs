@@ -0,0 +1,181 @@
+use std::{collections::HashMap, fmt::Write as _, path::Path};
+
+use chisels_and_importers::{minecraft_axes, ChunkReport, BLOCK_SIDE};
+use serde::Serialize;
+
+use crate::{
+    i18n::tr,
+    summary::{SkipReason, SkippedChunk},
+};
+
+/// Where the patterns of a model go, in blocks along the Minecraft axes
+#[derive(Serialize)]
+pub struct Layout {
+    /// Size of the model in blocks
+    size: [u32; 3],
+    patterns: Vec<PlacedPattern>,
+    /// Chunks no pattern was written for
+    skipped: Vec<SkippedPlacement>,
+}
+
+#[derive(Serialize)]
+struct PlacedPattern {
+    /// File name of the pattern
+    file: String,
+    /// Position of the block the pattern is chiseled into, from the lowest corner of the model
+    offset: [u32; 3],
+    /// Most common block of the pattern
+    block: String,
+    /// Whether the pattern contains only air, only written with --keep-empty
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    empty: bool,
+    /// Model file most voxels of the pattern come from, for models placed together with
+    /// --compose
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SkippedPlacement {
+    /// Position of the block the chunk would go into, from the lowest corner of the model
+    offset: [u32; 3],
+    reason: SkipReason,
+}
+
+/// Position of the block a chunk goes into, on the Minecraft axes. Chunk offsets are in
+/// voxels on the axes of Magica Voxel, whose z axis points up.
+pub fn block_offset(offset: (u8, u8, u8)) -> [u32; 3] {
+    let (x, y, z) = offset;
+    minecraft_axes([x, y, z]).map(|voxels| voxels as u32 / BLOCK_SIDE as u32)
+}
+
+impl Layout {
+    /// Layout of the written and skipped patterns of a model of the given size in voxels,
+    /// with the file every chunk comes from if the model was composed of several, by chunk
+    /// offset
+    pub fn new(
+        size: dot_vox::Size,
+        patterns: &[(String, ChunkReport)],
+        skipped: &[SkippedChunk],
+        sources: Option<&HashMap<(u8, u8, u8), String>>,
+    ) -> Self {
+        let side = BLOCK_SIDE as u32;
+        Self {
+            size: minecraft_axes([size.x, size.y, size.z]).map(|length| length.div_ceil(side)),
+            patterns: patterns
+                .iter()
+                .map(|(file, chunk)| PlacedPattern {
+                    file: Path::new(file)
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                    offset: block_offset(chunk.offset),
+                    block: chunk.dominant_block.clone(),
+                    empty: chunk.non_air_bits == 0,
+                    source: sources.and_then(|sources| sources.get(&chunk.offset).cloned()),
+                })
+                .collect(),
+            skipped: skipped
+                .iter()
+                .map(|chunk| SkippedPlacement {
+                    offset: block_offset(chunk.offset),
+                    reason: chunk.reason,
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the layout as JSON to `<prefix>.layout.json` and as layers of pattern numbers
+    /// for printing to `<prefix>.layout.txt`
+    pub fn write_files(&self, prefix: &str) -> Result<(), (String, std::io::Error)> {
+        let json_path = format!("{}.layout.json", prefix);
+        self.to_json()
+            .and_then(|json| std::fs::write(&json_path, json))
+            .map_err(|e| (json_path, e))?;
+        let text_path = format!("{}.layout.txt", prefix);
+        std::fs::write(&text_path, self.to_text()).map_err(|e| (text_path, e))
+    }
+
+    pub fn to_json(&self) -> std::io::Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Every layer from the top as a grid with the number of the pattern at each block, `.`
+    /// where no pattern goes. Rows run along z, columns along x.
+    pub fn to_text(&self) -> String {
+        let [width, height, length] = self.size;
+        let mut grid = vec![None; (width * height * length) as usize];
+        for (index, pattern) in self.patterns.iter().enumerate() {
+            let [x, y, z] = pattern.offset;
+            grid[((y * length + z) * width + x) as usize] = Some(index);
+        }
+        let cell_width = self.patterns.len().saturating_sub(1).to_string().len();
+
+        let mut text = String::new();
+        for (index, pattern) in self.patterns.iter().enumerate() {
+            let [x, y, z] = pattern.offset;
+            let block = if pattern.empty {
+                tr!("layout-empty")
+            } else {
+                pattern.block.clone()
+            };
+            writeln!(
+                text,
+                "{:>width$}: {} ({}, {}, {}) {}",
+                index,
+                pattern.file,
+                x,
+                y,
+                z,
+                block,
+                width = cell_width
+            )
+            .unwrap();
+        }
+        for y in (0..height).rev() {
+            writeln!(text).unwrap();
+            writeln!(text, "{}", tr!("layout-layer", layer = y)).unwrap();
+            for z in 0..length {
+                let row: Vec<_> = (0..width)
+                    .map(|x| match grid[((y * length + z) * width + x) as usize] {
+                        Some(index) => format!("{:>width$}", index, width = cell_width),
+                        None => format!("{:>width$}", ".", width = cell_width),
+                    })
+                    .collect();
+                writeln!(text, "{}", row.join(" ")).unwrap();
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing, Converter};
+
+    #[test]
+    fn empty_chunk_is_listed_as_skipped() {
+        let palette = testing::palette();
+        // Two chunks long along x, the z axis of Minecraft, with a voxel only in the first chunk
+        let model = testing::model([32, 16, 16], &[[3, 4, 5, 0]]);
+        let colors = testing::colors(&[[128, 128, 128]]);
+        let block_model = Converter::new(&palette).match_blocks(&model, &colors);
+        let patterns: Vec<_> = block_model
+            .encode_all()
+            .into_iter()
+            .map(|pattern| ("castle.json".to_owned(), pattern.unwrap().report))
+            .collect();
+        let skipped = SkippedChunk::of_model(&block_model);
+        let layout = Layout::new(model.size, &patterns, &skipped, None);
+
+        let json: serde_json::Value = serde_json::from_slice(&layout.to_json().unwrap()).unwrap();
+        assert_eq!(json["patterns"].as_array().unwrap().len(), 1);
+        assert_eq!(json["patterns"][0]["offset"], serde_json::json!([0, 0, 0]));
+        assert_eq!(
+            json["skipped"],
+            serde_json::json!([{ "offset": [0, 0, 1], "reason": "empty" }])
+        );
+    }
+}
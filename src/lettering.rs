@@ -0,0 +1,134 @@
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+
+use crate::i18n::tr;
+
+/// Coverage above which a bit of a glyph is filled
+const COVERAGE_THRESHOLD: f32 = 0.5;
+/// Scale the cap height of a font is measured at
+const REFERENCE_SCALE: f32 = 100.0;
+
+/// Two dimensional grid of filled bits, the first row is the top
+pub struct Mask {
+    pub width: u32,
+    pub height: u32,
+    filled: Vec<bool>,
+}
+
+impl Mask {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            filled: vec![false; (width * height) as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        self.filled[(y * self.width + x) as usize]
+    }
+
+    fn set(&mut self, x: u32, y: u32) {
+        self.filled[(y * self.width + x) as usize] = true;
+    }
+
+    /// Grows the mask by one bit in every direction, including diagonals. The result is
+    /// two bits larger on each axis so nothing is cut off at the edges.
+    pub fn dilate(&self) -> Mask {
+        let mut grown = Mask::new(self.width + 2, self.height + 2);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.get(x, y) {
+                    continue;
+                }
+                for dy in 0..3 {
+                    for dx in 0..3 {
+                        grown.set(x + dx, y + dy);
+                    }
+                }
+            }
+        }
+        grown
+    }
+}
+
+/// Rasterizes the text with kerning, scaled so capital letters are `cap_height` bits tall.
+/// Every line of the text is placed below the previous one, aligned to the left.
+pub fn render_text(font_data: Vec<u8>, text: &str, cap_height: u32) -> Result<Mask, String> {
+    let font = FontVec::try_from_vec(font_data).map_err(|_| tr!("font-invalid"))?;
+
+    // Font sizes include room for accents and descenders, measure a capital letter instead
+    let reference = PxScale::from(REFERENCE_SCALE);
+    let measured = font
+        .outline_glyph(font.glyph_id('H').with_scale(reference))
+        .map(|outline| outline.px_bounds().height())
+        .unwrap_or_else(|| font.as_scaled(reference).ascent());
+    let scale = PxScale::from(REFERENCE_SCALE * cap_height as f32 / measured);
+    let scaled = font.as_scaled(scale);
+    let line_height = scaled.height() + scaled.line_gap();
+
+    let mut glyphs = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let baseline = line_number as f32 * line_height + scaled.ascent();
+        let mut caret = 0.0;
+        let mut previous = None;
+        for character in line.chars() {
+            let id = font.glyph_id(character);
+            if let Some(previous) = previous {
+                caret += scaled.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(scale, point(caret, baseline));
+            caret += scaled.h_advance(id);
+            previous = Some(id);
+            glyphs.extend(font.outline_glyph(glyph));
+        }
+    }
+
+    let (Some(left), Some(top)) = (
+        glyphs
+            .iter()
+            .map(|glyph| glyph.px_bounds().min.x as i32)
+            .min(),
+        glyphs
+            .iter()
+            .map(|glyph| glyph.px_bounds().min.y as i32)
+            .min(),
+    ) else {
+        return Err(tr!("text-invisible"));
+    };
+    let right = glyphs
+        .iter()
+        .map(|glyph| glyph.px_bounds().max.x as i32)
+        .max()
+        .unwrap();
+    let bottom = glyphs
+        .iter()
+        .map(|glyph| glyph.px_bounds().max.y as i32)
+        .max()
+        .unwrap();
+
+    let width = (right - left) as u32;
+    let height = (bottom - top) as u32;
+    // Neighboring glyphs may overlap, a bit is filled if either covers it enough
+    let mut coverage = vec![0.0f32; (width * height) as usize];
+    for glyph in &glyphs {
+        let bounds = glyph.px_bounds();
+        let offset_x = bounds.min.x as i32 - left;
+        let offset_y = bounds.min.y as i32 - top;
+        glyph.draw(|x, y, value| {
+            let x = offset_x + x as i32;
+            let y = offset_y + y as i32;
+            if (0..width as i32).contains(&x) && (0..height as i32).contains(&y) {
+                let index = (y as u32 * width + x as u32) as usize;
+                coverage[index] = coverage[index].max(value);
+            }
+        });
+    }
+
+    let mut mask = Mask::new(width, height);
+    for (index, value) in coverage.into_iter().enumerate() {
+        if value >= COVERAGE_THRESHOLD {
+            mask.set(index as u32 % width, index as u32 / width);
+        }
+    }
+    Ok(mask)
+}
@@ -0,0 +1,2036 @@
+//! Converts Magica Voxel models into Chisels and Bits patterns.
+//!
+//! A [`Converter`] matches the colors of a model to the blocks of a [`BlockPalette`] and
+//! encodes every block sized chunk of the model into a [`Pattern`]. Where the patterns are
+//! written is up to the caller:
+//!
+//! ```no_run
+//! use chisels_and_importers::{BlockPalette, Converter};
+//!
+//! let palette = BlockPalette::from_json(&std::fs::read("blocks.json").unwrap()).unwrap();
+//! let voxel_data = dot_vox::load("model.vox").unwrap();
+//! let converter = Converter::new(&palette);
+//! let patterns = converter
+//!     .convert_model(&voxel_data.models[0], &voxel_data.palette)
+//!     .unwrap();
+//! for (index, pattern) in patterns.iter().enumerate() {
+//!     std::fs::write(format!("pattern_{}.cbsbp", index), &pattern.bytes).unwrap();
+//! }
+//! ```
+//!
+//! The patterns only depend on the model, the palette and the options. Colors, blocks and
+//! chunks are always handled in the same order, also when chunks are encoded in parallel, so
+//! converting a model twice gives the same bytes.
+
+pub mod decode;
+pub mod gzip;
+pub mod i18n;
+pub mod limit;
+mod quantize;
+pub mod target;
+
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::Write,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use bitstream_io::{BitWrite, BitWriter};
+use fastnbt::ByteArray;
+use lz4_flex::frame::FrameEncoder;
+use miniz_oxide::deflate::core::{
+    compress_to_output, create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus,
+};
+use palette::{color_difference::Ciede2000, IntoColor, Lch, Oklab, Srgb};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    limit::{PatternLimit, Reduction},
+    target::Target,
+};
+
+/// Converts models into patterns made of the blocks of a palette
+pub struct Converter<'a> {
+    palette: &'a BlockPalette,
+    pattern_limit: Option<PatternLimit>,
+    dither: bool,
+    /// Magica Voxel materials of the colors
+    materials: &'a [dot_vox::Material],
+    emission_threshold: f32,
+    alpha_mode: AlphaMode,
+    /// Colors with a lower alpha are translucent
+    alpha_threshold: u8,
+    keep_empty: bool,
+    target: Target,
+    /// zlib level of the pattern files
+    compression: u8,
+    /// Most colors of a model that are matched to blocks, more are merged
+    max_colors: Option<usize>,
+    /// Blocks of palette indices that aren't matched by color
+    index_map: Option<&'a IndexMap>,
+}
+
+impl<'a> Converter<'a> {
+    pub fn new(palette: &'a BlockPalette) -> Self {
+        Self {
+            palette,
+            pattern_limit: None,
+            dither: false,
+            materials: &[],
+            emission_threshold: 0.0,
+            alpha_mode: AlphaMode::Solid,
+            alpha_threshold: 128,
+            keep_empty: false,
+            target: Target::default(),
+            compression: PATTERN_COMPRESSION,
+            max_colors: None,
+            index_map: None,
+        }
+    }
+
+    /// Keeps the pattern files within a size. Larger patterns are compressed more, then
+    /// their blocks are merged until they fit.
+    pub fn with_pattern_limit(mut self, limit: PatternLimit) -> Self {
+        self.pattern_limit = Some(limit);
+        self
+    }
+
+    /// Matches the voxels one by one, passing the difference to their block on to the
+    /// neighboring voxels so gradients keep their average color instead of forming bands
+    pub fn with_dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Matches the colors given a Magica Voxel material against the palette section of the
+    /// material type, e.g. glass colors only against the `glass` blocks. Emissive materials
+    /// need an emission above the threshold to count.
+    pub fn with_materials(
+        mut self,
+        materials: &'a [dot_vox::Material],
+        emission_threshold: f32,
+    ) -> Self {
+        self.materials = materials;
+        self.emission_threshold = emission_threshold;
+        self
+    }
+
+    /// Converts the voxels whose color has an alpha below the threshold as the mode says
+    pub fn with_alpha(mut self, mode: AlphaMode, threshold: u8) -> Self {
+        self.alpha_mode = mode;
+        self.alpha_threshold = threshold;
+        self
+    }
+
+    /// Encodes the chunks without voxels as well, into patterns of only air, so the patterns
+    /// of a model form a complete grid
+    pub fn with_empty_chunks(mut self, keep_empty: bool) -> Self {
+        self.keep_empty = keep_empty;
+        self
+    }
+
+    /// Writes the patterns for a version of Chisels & Bits other than the default
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Compresses the pattern files with a zlib level from 0 (stored) to 9 (smallest) instead
+    /// of the default of 6
+    pub fn with_compression(mut self, level: u8) -> Self {
+        self.compression = level;
+        self
+    }
+
+    /// Merges the colors of models with more colors than the maximum before matching them,
+    /// so patterns need fewer bits for every voxel. Colors are grouped by median cut in Lab,
+    /// weighted by their number of voxels, and the voxels of a group take its most used color.
+    /// Colors of a palette section like glass are kept as they are.
+    pub fn with_max_colors(mut self, max_colors: usize) -> Self {
+        self.max_colors = Some(max_colors);
+        self
+    }
+
+    /// Turns the voxels of the palette indices of the map into their block instead of the
+    /// block closest to their color, or leaves them out if they are mapped to air
+    pub fn with_index_map(mut self, index_map: &'a IndexMap) -> Self {
+        self.index_map = Some(index_map);
+        self
+    }
+
+    /// Matches every voxel of the model to the closest block of its color, or with dithering
+    /// of its color and the error passed on by its neighbors. Voxels outside the size the
+    /// model declares would not fit any chunk, they are left out with a warning. Voxels of
+    /// translucent colors are left out as well with [`AlphaMode::Skip`], and those of indices
+    /// the index map maps to air. The block model borrows the voxels of the model, they are
+    /// only copied if some are left out or their colors are merged.
+    pub fn match_blocks<'m>(
+        &self,
+        model: &'m dot_vox::Model,
+        colors: &[dot_vox::Color],
+    ) -> BlockModel<'m>
+    where
+        'a: 'm,
+    {
+        let size = model.size;
+        let length = (size.x as usize).div_ceil(BLOCK_SIDE);
+        let width = (size.y as usize).div_ceil(BLOCK_SIDE);
+        let height = (size.z as usize).div_ceil(BLOCK_SIDE);
+        let chunk_index = |x: usize, y: usize, z: usize| (x * width + y) * height + z;
+
+        let mut warnings = Vec::new();
+        let inside = |voxel: &dot_vox::Voxel| {
+            (voxel.x as u32) < size.x && (voxel.y as u32) < size.y && (voxel.z as u32) < size.z
+        };
+        let mut voxels = Cow::Borrowed(&model.voxels[..]);
+        let outside = voxels.iter().filter(|voxel| !inside(voxel)).count();
+        if let Some(first) = voxels.iter().find(|voxel| !inside(voxel)) {
+            warnings.push(tr!(
+                "voxels-outside",
+                count = outside,
+                position = format!("({}, {}, {})", first.x, first.y, first.z),
+                size = format!("{}x{}x{}", size.x, size.y, size.z),
+            ));
+            voxels.to_mut().retain(inside);
+        }
+        // Count the voxels left out of every chunk, so chunks with only such voxels can be
+        // told apart from empty ones
+        let mut chunk_left_out = vec![0u32; length * width * height];
+        let mut leave_out =
+            |voxels: &mut Cow<[dot_vox::Voxel]>, left_out: &dyn Fn(&dot_vox::Voxel) -> bool| {
+                if !voxels.iter().any(left_out) {
+                    return;
+                }
+                voxels.to_mut().retain(|voxel| {
+                    let out = left_out(voxel);
+                    if out {
+                        chunk_left_out[chunk_index(
+                            voxel.x as usize / BLOCK_SIDE,
+                            voxel.y as usize / BLOCK_SIDE,
+                            voxel.z as usize / BLOCK_SIDE,
+                        )] += 1;
+                    }
+                    !out
+                })
+            };
+        if let AlphaMode::Skip = self.alpha_mode {
+            leave_out(&mut voxels, &|voxel| {
+                colors[voxel.i as usize].a < self.alpha_threshold
+            });
+        }
+        if let Some(index_map) = self.index_map {
+            leave_out(&mut voxels, &|voxel| {
+                matches!(index_map.block(voxel.i), Some(None))
+            });
+        }
+        let color_reduction = self
+            .max_colors
+            .and_then(|max_colors| self.merge_colors(&mut voxels, colors, max_colors));
+
+        // Count the voxels of every block sized chunk
+        let mut chunk_occupancy = vec![0u32; length * width * height];
+        for voxel in voxels.iter() {
+            chunk_occupancy[chunk_index(
+                voxel.x as usize / BLOCK_SIDE,
+                voxel.y as usize / BLOCK_SIDE,
+                voxel.z as usize / BLOCK_SIDE,
+            )] += 1;
+        }
+        let mut chunks = Vec::with_capacity(chunk_occupancy.len());
+        for x in 0..length {
+            for y in 0..width {
+                for z in 0..height {
+                    chunks.push(Chunk {
+                        offset: (
+                            (x * BLOCK_SIDE) as u8,
+                            (y * BLOCK_SIDE) as u8,
+                            (z * BLOCK_SIDE) as u8,
+                        ),
+                        voxels: chunk_occupancy[chunk_index(x, y, z)],
+                        left_out: chunk_left_out[chunk_index(x, y, z)],
+                    });
+                }
+            }
+        }
+
+        let (entries, palette_blocks) = if self.dither {
+            self.dithered_entries(&voxels, colors)
+        } else {
+            self.closest_entries(&voxels, colors)
+        };
+
+        // Build an O(1) lookup array for voxels, counting the voxels of every color and block
+        let mut model_data = ModelData::new(size);
+        let mut match_counts = BTreeMap::<(u8, u16), u32>::new();
+        for (voxel, &entry) in voxels.iter().zip(&entries) {
+            model_data.set(voxel.x, voxel.y, voxel.z, entry);
+            *match_counts.entry((voxel.i, entry)).or_default() += 1;
+        }
+
+        let mut color_lch = HashMap::new();
+        let mut color_matches = Vec::with_capacity(match_counts.len());
+        for ((vox_palette_index, entry), voxels) in match_counts {
+            let vox_color = colors[vox_palette_index as usize];
+            let color = Srgb::new(vox_color.r, vox_color.g, vox_color.b);
+            let lch = *color_lch
+                .entry(vox_palette_index)
+                .or_insert_with(|| to_lch(color));
+            let block = palette_blocks[entry as usize];
+            color_matches.push(ColorMatch {
+                index: vox_palette_index,
+                color,
+                block: block.name.clone(),
+                properties: block.properties.clone(),
+                delta_e: block.lch.difference(lch),
+                voxels,
+            });
+        }
+
+        let mut palette: Vec<_> = palette_blocks
+            .iter()
+            .map(|block| PaletteEntry {
+                state: block.state(),
+                block: block.name.clone(),
+            })
+            .collect();
+        // Last entry is always air
+        palette.push(PaletteEntry {
+            state: block_state("minecraft:air", &BTreeMap::new()),
+            block: "minecraft:air".to_owned(),
+        });
+
+        BlockModel {
+            size,
+            voxels,
+            chunks,
+            model_data,
+            palette,
+            palette_blocks,
+            color_lch,
+            pattern_limit: self.pattern_limit,
+            keep_empty: self.keep_empty,
+            target: self.target,
+            compression: self.compression,
+            color_matches,
+            color_reduction,
+            warnings,
+        }
+    }
+
+    /// Gives the voxels of merged colors the color they are merged into, if the model has
+    /// more colors than the maximum
+    fn merge_colors(
+        &self,
+        voxels: &mut Cow<[dot_vox::Voxel]>,
+        colors: &[dot_vox::Color],
+        max_colors: usize,
+    ) -> Option<ColorReduction> {
+        let sections = self.section_palettes(colors);
+        let mut usage = BTreeMap::<u8, u32>::new();
+        let mapped = |color: u8| self.index_map.is_some_and(|map| map.block(color).is_some());
+        for voxel in voxels
+            .iter()
+            .filter(|voxel| !sections.contains_key(&voxel.i) && !mapped(voxel.i))
+        {
+            *usage.entry(voxel.i).or_default() += 1;
+        }
+        if usage.len() <= max_colors {
+            return None;
+        }
+        let replacements = quantize::merge_colors(&usage, colors, max_colors);
+        for voxel in voxels.to_mut() {
+            if let Some(&replacement) = replacements.get(&voxel.i) {
+                voxel.i = replacement;
+            }
+        }
+        let colors_after: BTreeSet<u8> = replacements.values().copied().collect();
+        Some(ColorReduction {
+            colors_before: usage.len(),
+            colors_after: colors_after.len(),
+        })
+    }
+
+    /// Palette section of every color whose material type has one, or of translucent colors
+    /// the `glass` section with [`AlphaMode::Glass`], by color index
+    fn section_palettes(&self, colors: &[dot_vox::Color]) -> HashMap<u8, &'a BlockPalette> {
+        let palette: &'a BlockPalette = self.palette;
+        let mut palettes = HashMap::new();
+        for material in self.materials {
+            // Material ids are palette indices, which start at 1 in the file
+            let Some(color) = material
+                .id
+                .checked_sub(1)
+                .and_then(|id| u8::try_from(id).ok())
+            else {
+                continue;
+            };
+            let Some(kind) = material
+                .properties
+                .get("_type")
+                .and_then(|kind| kind.strip_prefix('_'))
+            else {
+                continue;
+            };
+            let emission = material
+                .properties
+                .get("_emit")
+                .and_then(|emission| emission.parse::<f32>().ok())
+                .unwrap_or(0.0);
+            if kind == "emit" && emission <= self.emission_threshold {
+                continue;
+            }
+            if let Some(section) = palette.materials.get(kind) {
+                palettes.insert(color, section);
+            }
+        }
+        if let (AlphaMode::Glass, Some(glass)) = (self.alpha_mode, palette.materials.get("glass")) {
+            for (color, _) in (0..=u8::MAX)
+                .zip(colors)
+                .filter(|(_, color)| color.a < self.alpha_threshold)
+            {
+                palettes.entry(color).or_insert(glass);
+            }
+        }
+        palettes
+    }
+
+    /// Block the index map gives a color index
+    fn mapped_block(&self, color: u8) -> Option<&'a PaletteBlock> {
+        self.index_map.and_then(|map| map.block(color)).flatten()
+    }
+
+    /// Palette entry of every voxel and the block of every entry, one entry for every color
+    /// ordered by color index so the palette of the patterns is the same in every run
+    fn closest_entries(
+        &self,
+        voxels: &[dot_vox::Voxel],
+        colors: &[dot_vox::Color],
+    ) -> (Vec<u16>, Vec<&'a PaletteBlock>) {
+        let palettes = self.section_palettes(colors);
+        let used: BTreeSet<u8> = voxels.iter().map(|voxel| voxel.i).collect();
+        let mut entry_by_color = HashMap::with_capacity(used.len());
+        let mut blocks = Vec::with_capacity(used.len());
+        for color in used {
+            let palette = palettes.get(&color).copied().unwrap_or(self.palette);
+            entry_by_color.insert(color, blocks.len() as u16);
+            blocks.push(
+                self.mapped_block(color)
+                    .unwrap_or_else(|| palette.closest_block(colors[color as usize])),
+            );
+        }
+        let entries = voxels
+            .iter()
+            .map(|voxel| entry_by_color[&voxel.i])
+            .collect();
+        (entries, blocks)
+    }
+
+    /// Palette entry of every voxel and the block of every entry, one entry for every block
+    /// in the order it is first used. Voxels are matched along z, then y, then x, and the
+    /// difference between the color a voxel should have and its block is spread over the
+    /// neighbors matched later (Floyd-Steinberg in three dimensions). Air takes no error.
+    fn dithered_entries(
+        &self,
+        voxels: &[dot_vox::Voxel],
+        colors: &[dot_vox::Color],
+    ) -> (Vec<u16>, Vec<&'a PaletteBlock>) {
+        let palettes = self.section_palettes(colors);
+        let position = |voxel: &dot_vox::Voxel| (voxel.x, voxel.y, voxel.z);
+        let solid: HashSet<_> = voxels.iter().map(position).collect();
+        let mut order: Vec<usize> = (0..voxels.len()).collect();
+        order.sort_by_key(|&index| position(&voxels[index]));
+
+        let mut errors = HashMap::<(u8, u8, u8), [f32; 3]>::new();
+        // Blocks of different palette sections are different entries
+        let mut entry_by_block = HashMap::new();
+        let mut blocks = Vec::new();
+        let mut entries = vec![0; voxels.len()];
+        for index in order {
+            let voxel = &voxels[index];
+            let error = errors.remove(&position(voxel)).unwrap_or_default();
+            let mapped = self.mapped_block(voxel.i);
+            let color = colors[voxel.i as usize];
+            let wanted: [f32; 3] = std::array::from_fn(|channel| {
+                [color.r, color.g, color.b][channel] as f32 + error[channel]
+            });
+            let block = mapped.unwrap_or_else(|| {
+                let palette = palettes.get(&voxel.i).copied().unwrap_or(self.palette);
+                let [r, g, b] = wanted.map(|value| value.clamp(0.0, 255.0).round() as u8);
+                palette.closest_block(dot_vox::Color { r, g, b, a: 255 })
+            });
+            entries[index] = *entry_by_block
+                .entry(std::ptr::from_ref(block))
+                .or_insert_with(|| {
+                    blocks.push(block);
+                    (blocks.len() - 1) as u16
+                });
+            // Mapped blocks are chosen rather than matched, they pass on no error
+            if mapped.is_some() {
+                continue;
+            }
+
+            let matched = block.color;
+            let matched = [matched.red, matched.green, matched.blue];
+            for ((dx, dy, dz), weight) in DITHER_NEIGHBORS {
+                let neighbor = match (
+                    voxel.x.checked_add_signed(dx),
+                    voxel.y.checked_add_signed(dy),
+                    voxel.z.checked_add_signed(dz),
+                ) {
+                    (Some(x), Some(y), Some(z)) => (x, y, z),
+                    _ => continue,
+                };
+                if !solid.contains(&neighbor) {
+                    continue;
+                }
+                let neighbor_error = errors.entry(neighbor).or_default();
+                for channel in 0..3 {
+                    neighbor_error[channel] +=
+                        (wanted[channel] - matched[channel] as f32) * weight / DITHER_WEIGHTS;
+                }
+            }
+        }
+        (entries, blocks)
+    }
+
+    /// Converts the model into a pattern for every block sized chunk that contains voxels.
+    /// Fails if a pattern doesn't fit a strict pattern limit.
+    pub fn convert_model(
+        &self,
+        model: &dot_vox::Model,
+        colors: &[dot_vox::Color],
+    ) -> Result<Vec<Pattern>, String> {
+        self.match_blocks(model, colors)
+            .encode_all()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Model whose colors are matched to blocks, encoded into patterns chunk by chunk
+pub struct BlockModel<'a> {
+    size: dot_vox::Size,
+    /// Voxels inside the size of the model, borrowed from the model unless some were left out
+    /// or recolored
+    voxels: Cow<'a, [dot_vox::Voxel]>,
+    chunks: Vec<Chunk>,
+    model_data: ModelData,
+    /// Palette of the patterns, air is the last entry
+    palette: Vec<PaletteEntry>,
+    /// Block of every palette entry except air
+    palette_blocks: Vec<&'a PaletteBlock>,
+    /// Every color of the model in the space blocks are matched in
+    color_lch: HashMap<u8, Lch>,
+    pattern_limit: Option<PatternLimit>,
+    /// Whether chunks without voxels are encoded
+    keep_empty: bool,
+    target: Target,
+    compression: u8,
+    /// How well every color of the model is represented by its block
+    pub color_matches: Vec<ColorMatch>,
+    /// Colors merged because the model had more than the maximum
+    pub color_reduction: Option<ColorReduction>,
+    pub warnings: Vec<String>,
+}
+
+impl<'a> BlockModel<'a> {
+    pub fn size(&self) -> dot_vox::Size {
+        self.size
+    }
+
+    /// Voxels of the model that are converted
+    pub fn voxels(&self) -> &[dot_vox::Voxel] {
+        &self.voxels
+    }
+
+    /// Block sized chunks of the model in the order patterns are numbered
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// Blocks the voxels of the model are matched to, once for every palette entry
+    pub fn blocks(&self) -> &[&'a PaletteBlock] {
+        &self.palette_blocks
+    }
+
+    /// Block the voxel is matched to
+    pub fn block(&self, voxel: &dot_vox::Voxel) -> &'a PaletteBlock {
+        let entry = self.model_data.get(voxel.x, voxel.y, voxel.z).unwrap();
+        self.palette_blocks[entry as usize]
+    }
+
+    /// CIEDE2000 difference between the color of the voxel and its block
+    pub fn delta_e(&self, voxel: &dot_vox::Voxel) -> f32 {
+        self.block(voxel).lch.difference(self.color_lch[&voxel.i])
+    }
+
+    /// Whether the chunk is encoded, chunks without voxels only if empty chunks are kept
+    pub fn is_encoded(&self, chunk: &Chunk) -> bool {
+        self.keep_empty || chunk.voxels > 0
+    }
+
+    /// Encodes every chunk that [is encoded](BlockModel::is_encoded) on all threads of the
+    /// rayon thread pool, the patterns are in the order of [`BlockModel::chunks`]
+    pub fn encode_all(&self) -> Vec<Result<Pattern, String>> {
+        self.encode_all_with_progress(&|_| {})
+    }
+
+    /// [`BlockModel::encode_all`], calling `progress` with the number of chunks encoded so far
+    /// after every chunk. It is called from the threads of the pool, in any order.
+    pub fn encode_all_with_progress(
+        &self,
+        progress: &(dyn Fn(usize) + Sync),
+    ) -> Vec<Result<Pattern, String>> {
+        let encoded = AtomicUsize::new(0);
+        // The voxel counts tell the chunks to encode before any bits are packed, so the
+        // patterns are collected into a vector of their final length
+        let chunks: Vec<&Chunk> = self
+            .chunks
+            .iter()
+            .filter(|chunk| self.is_encoded(chunk))
+            .collect();
+        let mut patterns = Vec::with_capacity(chunks.len());
+        chunks
+            .par_iter()
+            .map(|chunk| {
+                let pattern = self.encode(chunk.offset);
+                progress(encoded.fetch_add(1, Ordering::Relaxed) + 1);
+                pattern
+            })
+            .collect_into_vec(&mut patterns);
+        patterns
+    }
+
+    /// Encodes the chunk at the offset. A pattern larger than the pattern limit is reduced
+    /// until it fits, or kept as it is if that fails and the limit is not strict.
+    pub fn encode(&self, offset: (u8, u8, u8)) -> Result<Pattern, String> {
+        let targets: Vec<usize> = (0..self.palette_blocks.len()).collect();
+        let mut chunk = encode_chunk(
+            &self.model_data,
+            &self.palette,
+            &targets,
+            offset,
+            self.compression,
+            self.target,
+        );
+        let (mut reduction, mut oversize) = (None, None);
+        if let Some(limit) = self
+            .pattern_limit
+            .filter(|limit| chunk.pattern.len() > limit.max_bytes)
+        {
+            let chunk_data = ChunkData {
+                model_data: &self.model_data,
+                palette: &self.palette,
+                palette_blocks: &self.palette_blocks,
+                offset,
+                target: self.target,
+            };
+            match limit.fit(&chunk_data, &chunk.block_counts) {
+                Ok((fitted, reduced)) => {
+                    chunk = fitted;
+                    reduction = reduced;
+                }
+                Err(error) if limit.strict => return Err(error),
+                Err(error) => oversize = Some(error),
+            }
+        }
+        Ok(Pattern {
+            offset,
+            bytes: chunk.pattern,
+            report: chunk.report,
+            block_counts: chunk.block_counts,
+            reduction,
+            oversize,
+        })
+    }
+}
+
+/// Block sized part of a model
+#[derive(Clone, Copy)]
+pub struct Chunk {
+    /// Position of the chunk in the model, in voxels on the axes of Magica Voxel
+    pub offset: (u8, u8, u8),
+    /// Voxels inside the chunk, a chunk without voxels would only contain air
+    pub voxels: u32,
+    /// Voxels of the chunk left out as translucent with [`AlphaMode::Skip`] or mapped to air
+    /// by the index map
+    pub left_out: u32,
+}
+
+/// Pattern file of one block sized chunk of a model
+pub struct Pattern {
+    /// Position of the chunk in the model, in voxels on the axes of Magica Voxel
+    pub offset: (u8, u8, u8),
+    /// Contents of the pattern file
+    pub bytes: Vec<u8>,
+    pub report: ChunkReport,
+    /// Bits of every block in the pattern palette, the same block can be listed several times
+    pub block_counts: Vec<(String, u32)>,
+    /// Blocks merged so the pattern fits the pattern limit
+    pub reduction: Option<Reduction>,
+    /// Why the pattern is larger than the pattern limit, if the limit is not strict
+    pub oversize: Option<String>,
+}
+
+/// How well one color of the model is represented by its block
+pub struct ColorMatch {
+    /// Index of the color in the palette of the Magica Voxel file
+    pub index: u8,
+    pub color: Srgb<u8>,
+    pub block: String,
+    /// Block state properties of the block
+    pub properties: BTreeMap<String, String>,
+    /// CIEDE2000 difference between the color and the block
+    pub delta_e: f32,
+    /// Number of voxels with this color
+    pub voxels: u32,
+}
+
+/// Number of colors of a model before and after merging them down to the maximum
+#[derive(Debug, Clone, Copy)]
+pub struct ColorReduction {
+    pub colors_before: usize,
+    pub colors_after: usize,
+}
+
+/// Blocks that colors are matched to, read from a palette file mapping color codes to block ids
+pub struct BlockPalette {
+    pub mapping: Vec<PaletteBlock>,
+    /// Blocks for the colors of a Magica Voxel material type like `glass` or `emit`, given as
+    /// an object of color codes in the palette file
+    pub materials: BTreeMap<String, BlockPalette>,
+    metric: ColorMetric,
+    /// Color of every block in the space of the metric, empty for CIEDE2000 which uses `lch`
+    coordinates: Vec<[f32; 3]>,
+    /// Previously matched colors, shared by all models converted with this palette
+    match_cache: Mutex<HashMap<(u8, u8, u8), usize>>,
+}
+
+/// Block of a palette file in the long form, listed under `blocks`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockEntry {
+    color: String,
+    block: BlockValue,
+    /// Factor for the color difference to the block, below 1 makes the block preferred
+    weight: Option<f32>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Block of a palette entry, an id or an object with the id and block state properties
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BlockValue {
+    Name(String),
+    State(BlockStateValue),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockStateValue {
+    name: String,
+    #[serde(default)]
+    properties: BTreeMap<String, String>,
+}
+
+/// Block listed in the block palette file
+pub struct PaletteBlock {
+    pub name: String,
+    /// Block state properties like `axis`, the same block with other properties is placed
+    /// differently
+    pub properties: BTreeMap<String, String>,
+    /// Color given for the block in the palette file
+    pub color: Srgb<u8>,
+    /// The color in the space blocks are matched in
+    pub lch: Lch,
+    /// Factor the color difference to the block is multiplied with
+    pub weight: f32,
+    /// Groups the block belongs to, e.g. `wool`
+    pub tags: Vec<String>,
+}
+
+impl PaletteBlock {
+    /// Block state as stored in patterns
+    pub fn state(&self) -> String {
+        block_state(&self.name, &self.properties)
+    }
+
+    /// The color as an opaque RGBA pixel
+    pub fn pixel(&self) -> [u8; 4] {
+        [self.color.red, self.color.green, self.color.blue, 255]
+    }
+}
+
+impl BlockPalette {
+    /// Reads a palette file, a JSON object mapping color codes like `#a0b1c2` to block ids.
+    /// Blocks with a weight or tags are listed as objects under `blocks`, blocks named in
+    /// `exclude` are left out and `only_tags` keeps only the blocks with one of the tags.
+    /// Objects inside it map the colors of a material type, named like `glass`.
+    pub fn from_json(data: &[u8]) -> Result<Self, String> {
+        let object: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(data).map_err(|e| tr!("palette-invalid-json", error = e))?;
+        Self::from_object(object, "")
+    }
+
+    /// Reads the blocks of a palette file or of one of its material sections, `path` leads
+    /// to the section in error messages
+    fn from_object(
+        object: serde_json::Map<String, serde_json::Value>,
+        path: &str,
+    ) -> Result<Self, String> {
+        let invalid = |entry: &str, error: String| {
+            tr!(
+                "palette-invalid-entry",
+                entry = format!("{}{}", path, entry),
+                error = error
+            )
+        };
+        let mut entries = Vec::new();
+        let mut sections = Vec::new();
+        let mut exclude: Vec<String> = Vec::new();
+        let mut only_tags: Vec<String> = Vec::new();
+        for (key, value) in object {
+            match key.as_str() {
+                "blocks" => {
+                    let items: Vec<serde_json::Value> =
+                        serde_json::from_value(value).map_err(|e| invalid(&key, e.to_string()))?;
+                    for (index, item) in items.into_iter().enumerate() {
+                        let entry = format!("{}[{}]", key, index);
+                        let block: BlockEntry = serde_json::from_value(item)
+                            .map_err(|e| invalid(&entry, e.to_string()))?;
+                        if Srgb::<u8>::from_str(&block.color).is_err() {
+                            let error = tr!("palette-invalid-color", code = block.color);
+                            return Err(invalid(&entry, error));
+                        }
+                        let weight = block.weight.unwrap_or(1.0);
+                        if !weight.is_finite() || weight <= 0.0 {
+                            return Err(invalid(&entry, tr!("palette-invalid-weight")));
+                        }
+                        entries.push(block);
+                    }
+                }
+                "exclude" => {
+                    exclude =
+                        serde_json::from_value(value).map_err(|e| invalid(&key, e.to_string()))?;
+                }
+                "only_tags" => {
+                    only_tags =
+                        serde_json::from_value(value).map_err(|e| invalid(&key, e.to_string()))?;
+                }
+                _ => match value {
+                    serde_json::Value::String(block) => entries.push(BlockEntry {
+                        color: key,
+                        block: BlockValue::Name(block),
+                        weight: None,
+                        tags: Vec::new(),
+                    }),
+                    // Objects under color codes are blocks with properties
+                    serde_json::Value::Object(state) if key.starts_with('#') => {
+                        let state = serde_json::from_value(serde_json::Value::Object(state))
+                            .map_err(|e| invalid(&key, e.to_string()))?;
+                        entries.push(BlockEntry {
+                            color: key,
+                            block: BlockValue::State(state),
+                            weight: None,
+                            tags: Vec::new(),
+                        });
+                    }
+                    serde_json::Value::Object(section) => sections.push((key, section)),
+                    _ => return Err(invalid(&key, tr!("palette-expected-block"))),
+                },
+            }
+        }
+
+        let mut palette = Self::from_entries(entries)
+            .map_err(|code| tr!("palette-invalid-color", code = code))?;
+        for (kind, section) in sections {
+            let section = Self::from_object(section, &format!("{}{}.", path, kind))?;
+            palette.materials.insert(kind, section);
+        }
+        palette
+            .retain(&|block| !exclude.contains(&block.name))
+            .with_only_tags(&only_tags)
+    }
+
+    /// Creates the palette from color codes mapped to block ids, failing on the first
+    /// invalid color code
+    pub fn from_mapping(block_mapping: HashMap<String, String>) -> Result<Self, String> {
+        let entries = block_mapping
+            .into_iter()
+            .map(|(color, block)| BlockEntry {
+                color,
+                block: BlockValue::Name(block),
+                weight: None,
+                tags: Vec::new(),
+            })
+            .collect();
+        Self::from_entries(entries)
+    }
+
+    /// Creates the palette from its blocks, failing with the first invalid color code
+    fn from_entries(entries: Vec<BlockEntry>) -> Result<Self, String> {
+        let mut blocks: Vec<_> = entries
+            .into_iter()
+            .map(|entry| {
+                let (name, properties) = match entry.block {
+                    BlockValue::Name(name) => (name, BTreeMap::new()),
+                    BlockValue::State(state) => (state.name, state.properties),
+                };
+                (entry.color, name, properties, entry.weight, entry.tags)
+            })
+            .collect();
+        // Ordered by color code, so blocks that match a color equally well are chosen the
+        // same way in every run
+        blocks.sort_by(|l, r| (&l.0, &l.1, &l.2).cmp(&(&r.0, &r.1, &r.2)));
+        let mapping = blocks
+            .into_iter()
+            .map(|(code, name, properties, weight, tags)| {
+                let color = Srgb::from_str(&code).map_err(|_| code.clone())?;
+                Ok(PaletteBlock {
+                    name,
+                    properties,
+                    color,
+                    lch: to_lch(color),
+                    weight: weight.unwrap_or(1.0),
+                    tags,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self {
+            mapping,
+            materials: BTreeMap::new(),
+            metric: ColorMetric::Ciede2000,
+            coordinates: Vec::new(),
+            match_cache: Mutex::default(),
+        })
+    }
+
+    /// Only matches colors against the blocks with one of the tags, also in the material
+    /// sections. Keeps every block if no tags are given, fails if no block has the tags.
+    pub fn with_only_tags(self, tags: &[String]) -> Result<Self, String> {
+        if tags.is_empty() {
+            return Ok(self);
+        }
+        let palette = self.retain(&|block| block.tags.iter().any(|tag| tags.contains(tag)));
+        if palette.mapping.is_empty() {
+            return Err(tr!("palette-no-tagged-blocks", tags = tags.join(", ")));
+        }
+        Ok(palette)
+    }
+
+    /// Keeps the blocks the filter accepts. Material sections left without blocks are
+    /// removed, their colors are matched against the other blocks.
+    fn retain(mut self, keep: &dyn Fn(&PaletteBlock) -> bool) -> Self {
+        self.mapping.retain(keep);
+        self.materials = std::mem::take(&mut self.materials)
+            .into_iter()
+            .map(|(kind, section)| (kind, section.retain(keep)))
+            .filter(|(_, section)| !section.mapping.is_empty())
+            .collect();
+        let metric = self.metric;
+        self.with_metric(metric)
+    }
+
+    /// Matches colors with the metric, also in the material sections
+    pub fn with_metric(mut self, metric: ColorMetric) -> Self {
+        self.metric = metric;
+        self.coordinates = match metric {
+            ColorMetric::Ciede2000 => Vec::new(),
+            _ => self
+                .mapping
+                .iter()
+                .map(|block| metric.coordinates(block.color))
+                .collect(),
+        };
+        self.match_cache = Mutex::default();
+        self.materials = std::mem::take(&mut self.materials)
+            .into_iter()
+            .map(|(kind, section)| (kind, section.with_metric(metric)))
+            .collect();
+        self
+    }
+
+    /// Block whose color is the most similar to the color
+    pub fn closest_block(&self, color: dot_vox::Color) -> &PaletteBlock {
+        &self.mapping[self.closest_index(color)]
+    }
+
+    /// Position of the closest block in the mapping
+    pub fn closest_index(&self, color: dot_vox::Color) -> usize {
+        let key = (color.r, color.g, color.b);
+        let cached = self.match_cache.lock().unwrap().get(&key).copied();
+        match cached {
+            Some(index) => index,
+            None => {
+                let index = self.find_closest(color);
+                self.match_cache.lock().unwrap().insert(key, index);
+                index
+            }
+        }
+    }
+
+    /// Number of colors whose closest block is cached
+    pub fn cached_matches(&self) -> usize {
+        self.match_cache.lock().unwrap().len()
+    }
+
+    fn find_closest(&self, color: dot_vox::Color) -> usize {
+        let color = Srgb::new(color.r, color.g, color.b);
+        match self.metric {
+            ColorMetric::Ciede2000 => {
+                let color = to_lch(color);
+                closest(
+                    self.mapping
+                        .iter()
+                        .map(|block| block.lch.difference(color) * block.weight),
+                )
+            }
+            metric => {
+                let color = metric.coordinates(color);
+                closest(
+                    self.coordinates
+                        .iter()
+                        .zip(&self.mapping)
+                        .map(|(coordinates, block)| {
+                            let distance: f32 = (0..3)
+                                .map(|axis| (coordinates[axis] - color[axis]).powi(2))
+                                .sum();
+                            distance.sqrt() * block.weight
+                        }),
+                )
+            }
+        }
+    }
+}
+
+/// Blocks chosen for Magica Voxel palette indices, their voxels become the block whatever
+/// their color is
+pub struct IndexMap {
+    /// Block of every mapped color index, `None` for indices whose voxels are left out
+    blocks: BTreeMap<u8, Option<PaletteBlock>>,
+}
+
+impl IndexMap {
+    /// Reads an index map file, a JSON object mapping palette indices from 1 to 255 as Magica
+    /// Voxel shows them to block ids or objects with the id and block state properties. `air`
+    /// leaves the voxels of an index out. A block has the color it has in the palette, or the
+    /// color of its index if the palette doesn't have it.
+    pub fn from_json(
+        data: &[u8],
+        palette: &BlockPalette,
+        colors: &[dot_vox::Color],
+    ) -> Result<Self, String> {
+        let object: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(data).map_err(|e| tr!("index-map-invalid-json", error = e))?;
+        Self::from_entries(object, palette, colors)
+    }
+
+    /// Reads the entries of an index map file, palette indices from 1 to 255 with the JSON
+    /// value of their block. A later entry of an index replaces an earlier one.
+    pub fn from_entries(
+        entries: impl IntoIterator<Item = (String, serde_json::Value)>,
+        palette: &BlockPalette,
+        colors: &[dot_vox::Color],
+    ) -> Result<Self, String> {
+        let mut blocks = BTreeMap::new();
+        for (key, value) in entries {
+            let index = key
+                .parse::<u8>()
+                .ok()
+                .filter(|&index| index >= 1)
+                .ok_or_else(|| tr!("index-map-invalid-index", index = key))?;
+            let block: BlockValue = serde_json::from_value(value)
+                .map_err(|e| tr!("palette-invalid-entry", entry = key, error = e))?;
+            let (name, properties) = match block {
+                BlockValue::Name(name) => (name, BTreeMap::new()),
+                BlockValue::State(state) => (state.name, state.properties),
+            };
+            if name == "air" || name == "minecraft:air" {
+                blocks.insert(index - 1, None);
+                continue;
+            }
+            let color = match palette
+                .mapping
+                .iter()
+                .find(|block| block.name == name && block.properties == properties)
+            {
+                Some(block) => block.color,
+                None => {
+                    let color = colors[index as usize - 1];
+                    Srgb::new(color.r, color.g, color.b)
+                }
+            };
+            let block = PaletteBlock {
+                name,
+                properties,
+                color,
+                lch: to_lch(color),
+                weight: 1.0,
+                tags: Vec::new(),
+            };
+            blocks.insert(index - 1, Some(block));
+        }
+        Ok(Self { blocks })
+    }
+
+    /// Adds the blocks of another map, replacing the blocks of the indices both map
+    pub fn merge(&mut self, other: IndexMap) {
+        self.blocks.extend(other.blocks);
+    }
+
+    /// Mapped palette indices, from 1 as Magica Voxel shows them
+    pub fn indices(&self) -> impl Iterator<Item = u8> + '_ {
+        self.blocks.keys().map(|color| color + 1)
+    }
+
+    /// Block of a color index, `Some(None)` if its voxels are left out
+    fn block(&self, color: u8) -> Option<Option<&PaletteBlock>> {
+        self.blocks.get(&color).map(Option::as_ref)
+    }
+}
+
+/// Position of the smallest difference, the first one if several are equally small, so
+/// the best matching block is chosen the same way in every run
+fn closest(differences: impl Iterator<Item = f32>) -> usize {
+    differences
+        .enumerate()
+        .min_by(|(_, l), (_, r)| l.total_cmp(r))
+        .unwrap()
+        .0
+}
+
+/// Neighbors a voxel passes its color error on to, all of them are matched after it
+const DITHER_NEIGHBORS: [((i8, i8, i8), f32); 5] = [
+    ((0, 0, 1), 7.0),
+    ((0, 1, -1), 3.0),
+    ((0, 1, 0), 5.0),
+    ((0, 1, 1), 1.0),
+    ((1, 0, 0), 4.0),
+];
+const DITHER_WEIGHTS: f32 = 20.0;
+
+/// How voxels whose color is more translucent than the alpha threshold are converted
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlphaMode {
+    /// Like every other voxel
+    #[default]
+    Solid,
+    /// Left out, the voxels become air
+    Skip,
+    /// Matched only against the `glass` section of the palette
+    Glass,
+}
+
+impl FromStr for AlphaMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "solid" => Ok(AlphaMode::Solid),
+            "skip" => Ok(AlphaMode::Skip),
+            "glass" => Ok(AlphaMode::Glass),
+            other => Err(tr!("alpha-mode-unknown", mode = other)),
+        }
+    }
+}
+
+/// How the difference between a color and a block is measured when matching them
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMetric {
+    /// CIEDE2000 in Lch
+    #[default]
+    Ciede2000,
+    /// Euclidean distance in OKLab
+    Oklab,
+    /// Euclidean distance of the sRGB values
+    Rgb,
+}
+
+impl ColorMetric {
+    /// Position of the color in the space of a Euclidean metric
+    fn coordinates(self, color: Srgb<u8>) -> [f32; 3] {
+        match self {
+            ColorMetric::Oklab => {
+                let oklab: Oklab = color.into_linear::<f32>().into_color();
+                [oklab.l, oklab.a, oklab.b]
+            }
+            _ => [color.red, color.green, color.blue].map(f32::from),
+        }
+    }
+}
+
+impl FromStr for ColorMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ciede2000" => Ok(ColorMetric::Ciede2000),
+            "oklab" => Ok(ColorMetric::Oklab),
+            "rgb" => Ok(ColorMetric::Rgb),
+            other => Err(tr!("color-metric-unknown", metric = other)),
+        }
+    }
+}
+
+/// Converts a color into the space blocks are matched in. The bytes of Magica Voxel palettes
+/// and palette files are sRGB encoded, `into_linear` decodes them before Lch is computed, so
+/// e.g. #808080 has the lightness 53.6 and not the 76.2 of bytes taken as linear.
+pub fn to_lch(color: Srgb<u8>) -> Lch {
+    color.into_linear::<f32>().into_color()
+}
+
+/// Lookup of the voxels of a model by position, sized to the model
+struct ModelData {
+    /// Length of the x, y and z axes, the axes of Magica Voxel like the voxels
+    size: (usize, usize, usize),
+    /// Palette entry of every voxel plus one, 0 for air. Two bytes for every position, half of
+    /// `Option<u16>`, so 32 MiB for a model of 256 voxels along every side.
+    voxels: Box<[u16]>,
+}
+
+impl ModelData {
+    fn new(size: dot_vox::Size) -> Self {
+        let size = (size.x as usize, size.y as usize, size.z as usize);
+        Self {
+            size,
+            voxels: vec![0; size.0 * size.1 * size.2].into_boxed_slice(),
+        }
+    }
+
+    fn index(&self, x: u8, y: u8, z: u8) -> Option<usize> {
+        let (x, y, z) = (x as usize, y as usize, z as usize);
+        (x < self.size.0 && y < self.size.1 && z < self.size.2)
+            .then(|| (x * self.size.1 + y) * self.size.2 + z)
+    }
+
+    fn set(&mut self, x: u8, y: u8, z: u8, entry: u16) {
+        let index = self.index(x, y, z).expect("voxel outside of the model");
+        self.voxels[index] = entry + 1;
+    }
+
+    /// Palette entry of the voxel at the position, `None` for air and positions outside of
+    /// the model, which the last chunk of an axis may reach into
+    fn get(&self, x: u8, y: u8, z: u8) -> Option<u16> {
+        self.index(x, y, z)
+            .and_then(|index| self.voxels[index].checked_sub(1))
+    }
+}
+
+/// zlib compression level of pattern files
+const PATTERN_COMPRESSION: u8 = 6;
+
+/// Everything needed to encode one block sized chunk of a model
+struct ChunkData<'a> {
+    model_data: &'a ModelData,
+    /// Palette of the model, air is the last entry
+    palette: &'a [PaletteEntry],
+    /// Block of every palette entry except air
+    palette_blocks: &'a [&'a PaletteBlock],
+    offset: (u8, u8, u8),
+    target: Target,
+}
+
+/// Pattern file of a chunk
+struct EncodedChunk {
+    pattern: Vec<u8>,
+    report: ChunkReport,
+    /// Bits of every palette entry except air, in palette order
+    block_counts: Vec<(String, u32)>,
+}
+
+fn encode_chunk(
+    model_data: &ModelData,
+    palette: &[PaletteEntry],
+    targets: &[usize],
+    offset: (u8, u8, u8),
+    compression: u8,
+    target: Target,
+) -> EncodedChunk {
+    let contents = model_to_data(model_data, palette, targets, offset);
+    // Air is the last palette entry
+    let block_counts = palette[..palette.len() - 1]
+        .iter()
+        .zip(&contents.counts)
+        .map(|(entry, count)| (entry.block.clone(), *count))
+        .collect();
+    let pattern = data_to_pattern(
+        ChiselData {
+            data: ByteArray::new(contents.data),
+            palette: contents.palette,
+        },
+        contents.statistics,
+        compression,
+        target,
+    );
+    EncodedChunk {
+        pattern,
+        report: contents.report,
+        block_counts,
+    }
+}
+
+fn data_to_pattern(
+    data: ChiselData,
+    statistics: Statistics,
+    compression: u8,
+    target: Target,
+) -> Vec<u8> {
+    let format = target.format();
+    let output_data = Data {
+        chiseled_data: data,
+        statistics,
+    };
+
+    // Serialize chisel nbt straight into the lz4 compressor
+    let mut compressed_chisel_nbt = Vec::new();
+    let mut lz4_encoder = FrameEncoder::new(&mut compressed_chisel_nbt);
+    fastnbt::to_writer(&mut lz4_encoder, &output_data).unwrap();
+    lz4_encoder.finish().unwrap();
+
+    let container = DataContainer {
+        version: format.container_version(),
+        data: CompressedData {
+            data: ByteArray::new(compressed_chisel_nbt.into_iter().map(|b| b as i8).collect()),
+            compressed: 1u8,
+        },
+    };
+    let mut nbt_base64 =
+        base64::write::EncoderStringWriter::new(&base64::engine::general_purpose::STANDARD);
+    fastnbt::to_writer(&mut nbt_base64, &container).unwrap();
+
+    // Create pattern JSON, base64 encoding and zlib compressing it on the fly
+    let mut pattern = serde_json::Map::new();
+    pattern.insert("chiselData".to_owned(), nbt_base64.into_inner().into());
+    pattern.insert("version".to_owned(), format.file_version().into());
+    let mut pattern_writer = base64::write::EncoderWriter::new(
+        ZlibWriter::new(compression),
+        &base64::engine::general_purpose::STANDARD,
+    );
+    serde_json::to_writer(&mut pattern_writer, &pattern).unwrap();
+    pattern_writer.finish().unwrap().finish()
+}
+
+/// Streaming zlib compressor that produces the same bytes as
+/// [`miniz_oxide::deflate::compress_to_vec_zlib`].
+struct ZlibWriter {
+    compressor: CompressorOxide,
+    output: Vec<u8>,
+}
+
+impl ZlibWriter {
+    fn new(level: u8) -> Self {
+        let flags = create_comp_flags_from_zip_params(level.into(), 1, 0);
+        Self {
+            compressor: CompressorOxide::new(flags),
+            output: Vec::new(),
+        }
+    }
+
+    fn compress(&mut self, data: &[u8], flush: TDEFLFlush) -> std::io::Result<usize> {
+        let output = &mut self.output;
+        let (status, bytes_in) = compress_to_output(&mut self.compressor, data, flush, |out| {
+            output.extend_from_slice(out);
+            true
+        });
+        match status {
+            TDEFLStatus::Okay | TDEFLStatus::Done => Ok(bytes_in),
+            _ => Err(std::io::Error::other(tr!("zlib-failed"))),
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.compress(&[], TDEFLFlush::Finish).unwrap();
+        self.output
+    }
+}
+
+impl Write for ZlibWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.compress(buf, TDEFLFlush::None)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Packed voxels of a chunk with the palette entries they use
+struct ChunkContents<'a> {
+    data: Vec<i8>,
+    /// Entries of the model palette used in the chunk followed by air
+    palette: Vec<&'a PaletteEntry>,
+    statistics: Statistics<'a>,
+    report: ChunkReport,
+    /// Bits of every entry of the model palette except air
+    counts: Vec<u32>,
+}
+
+/// Packs the voxels of the chunk at the offset. The pattern only lists the palette entries
+/// used in the chunk, so a chunk of few blocks needs fewer bits per voxel. The bits of
+/// every entry are placed with its target entry, itself unless it was merged into another.
+fn model_to_data<'a>(
+    model: &ModelData,
+    palette: &'a [PaletteEntry],
+    targets: &[usize],
+    offset: (u8, u8, u8),
+) -> ChunkContents<'a> {
+    let total_size = BLOCK_SIDE * BLOCK_SIDE * BLOCK_SIDE;
+    // Last palette entry is air
+    let air = palette.len() - 1;
+
+    let mut entries = Vec::with_capacity(total_size);
+    let mut counts = vec![0u32; palette.len()];
+    for i in 0..total_size {
+        // The offset is on the axes of the model, so the bit is turned onto them first
+        let (x, y, z) = position_from_index(i);
+        let [x, y, z] = voxel_axes([x, y, z]);
+        let (x, y, z) = (x + offset.0, y + offset.1, z + offset.2);
+
+        let entry = match model.get(x, y, z) {
+            Some(entry) => targets[entry as usize],
+            None => air,
+        };
+        entries.push(entry);
+        counts[entry] += 1;
+    }
+
+    // Used entries in model palette order, air stays the last entry even if unused
+    let used: Vec<usize> = (0..air)
+        .filter(|&entry| counts[entry] > 0)
+        .chain([air])
+        .collect();
+    let mut local_index = vec![0u16; palette.len()];
+    for (index, &entry) in used.iter().enumerate() {
+        local_index[entry] = index as u16;
+    }
+    let entry_width = f32::log2(used.len() as f32).ceil() as u32;
+
+    let mut data: Vec<u8> = Vec::with_capacity((total_size * entry_width as usize).div_ceil(8));
+    let mut writer = BitWriter::endian(&mut data, bitstream_io::LittleEndian);
+    for entry in entries {
+        writer.write(entry_width, local_index[entry]).unwrap();
+    }
+
+    let state = |entry: usize| BlockState {
+        block_information: &palette[entry],
+        count: counts[entry],
+    };
+    // Air is excluded from the report
+    let (_, block_entries) = used.split_last().unwrap();
+    let blocks: Vec<_> = block_entries.iter().map(|&entry| state(entry)).collect();
+    // Most common block, the first one in the palette if several are equally common
+    let primary = block_entries
+        .iter()
+        .copied()
+        .max_by_key(|&entry| (counts[entry], Reverse(entry)));
+    let mut distinct_states: Vec<_> = blocks
+        .iter()
+        .map(|state| &state.block_information.state)
+        .collect();
+    distinct_states.sort();
+    distinct_states.dedup();
+    let report = ChunkReport {
+        offset,
+        non_air_bits: blocks.iter().map(|state| state.count).sum(),
+        distinct_blocks: distinct_states.len(),
+        dominant_block: primary
+            .map(|entry| palette[entry].block.clone())
+            .unwrap_or_default(),
+        palette_width: entry_width,
+    };
+
+    ChunkContents {
+        data: data.into_iter().map(|b| b as i8).collect(),
+        palette: used.iter().map(|&entry| &palette[entry]).collect(),
+        statistics: Statistics {
+            primary_state: &palette[primary.unwrap_or(air)],
+            block_states: used
+                .iter()
+                .map(|&entry| state(entry))
+                .filter(|state| state.count > 0)
+                .collect(),
+        },
+        report,
+        counts: counts[..air].to_vec(),
+    }
+}
+
+/// Voxels along each side of a block, a pattern holds one block
+pub const BLOCK_SIDE: usize = 16;
+
+/// Longest side of a model, voxel coordinates are stored in a byte
+pub const MAX_MODEL_SIZE: u32 = 256;
+
+/// Position on the axes of Minecraft of a position on the axes of Magica Voxel, whose z axis
+/// points up like the y axis of Minecraft. Minecraft's x and z axes are Magica Voxel's y and x.
+pub fn minecraft_axes<T>([x, y, z]: [T; 3]) -> [T; 3] {
+    [y, z, x]
+}
+
+/// Position on the axes of Magica Voxel of a position on the axes of Minecraft, the reverse
+/// of [`minecraft_axes`]
+pub fn voxel_axes<T>([x, y, z]: [T; 3]) -> [T; 3] {
+    [z, x, y]
+}
+
+/// Position of the bit at an index of the pattern data, on the axes of Minecraft. z changes
+/// fastest, then y, then x.
+fn position_from_index(index: usize) -> (u8, u8, u8) {
+    let x = index / (BLOCK_SIDE * BLOCK_SIDE);
+    let y = (index - x * BLOCK_SIDE * BLOCK_SIDE) / BLOCK_SIDE;
+    let z = index - x * BLOCK_SIDE * BLOCK_SIDE - y * BLOCK_SIDE;
+    (x as u8, y as u8, z as u8)
+}
+
+#[derive(Serialize)]
+struct Data<'a> {
+    #[serde(rename = "chiseledData")]
+    chiseled_data: ChiselData<'a>,
+    statistics: Statistics<'a>,
+}
+
+#[derive(Serialize)]
+struct ChiselData<'a> {
+    data: ByteArray,
+    palette: Vec<&'a PaletteEntry>,
+}
+
+#[derive(Serialize)]
+struct Statistics<'a> {
+    #[serde(rename = "primaryState")]
+    primary_state: &'a PaletteEntry,
+    #[serde(rename = "blockStates")]
+    block_states: Vec<BlockState<'a>>,
+}
+
+#[derive(Serialize)]
+struct BlockState<'a> {
+    block_information: &'a PaletteEntry,
+    count: u32,
+}
+
+/// Block state of a pattern palette, properties are only written if there are any
+#[derive(Serialize)]
+struct BlockStateJson<'a> {
+    #[serde(rename = "Name")]
+    name: &'a str,
+    #[serde(rename = "Properties", skip_serializing_if = "BTreeMap::is_empty")]
+    properties: &'a BTreeMap<String, String>,
+}
+
+/// Block state in the JSON form Chisels and Bits reads, e.g. `{"Name":"minecraft:oak_log",
+/// "Properties":{"axis":"x"}}`
+fn block_state(name: &str, properties: &BTreeMap<String, String>) -> String {
+    serde_json::to_string(&BlockStateJson { name, properties }).unwrap()
+}
+
+#[derive(Serialize, Clone)]
+struct PaletteEntry {
+    state: String,
+    #[serde(skip)]
+    block: String,
+}
+
+/// Numbers describing the contents of one pattern
+pub struct ChunkReport {
+    /// Position of the chunk in the model, in voxels on the axes of Magica Voxel
+    pub offset: (u8, u8, u8),
+    pub non_air_bits: u32,
+    /// Number of different non-air block states
+    pub distinct_blocks: usize,
+    /// Most common non-air block
+    pub dominant_block: String,
+    /// Bits used for every entry of the packed data
+    pub palette_width: u32,
+}
+
+impl ChunkReport {
+    /// Share of the bits that are not air
+    pub fn fill_percentage(&self) -> f32 {
+        self.non_air_bits as f32 / (BLOCK_SIDE * BLOCK_SIDE * BLOCK_SIDE) as f32 * 100.0
+    }
+}
+
+impl std::fmt::Display for ChunkReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = tr!(
+            "chunk-report",
+            offset = format!("{:?}", self.offset),
+            bits = self.non_air_bits,
+            fill = format!("{:.1}", self.fill_percentage()),
+            blocks = self.distinct_blocks,
+            block = self.dominant_block,
+            width = self.palette_width,
+        );
+        f.write_str(&message)
+    }
+}
+
+#[derive(Serialize)]
+struct DataContainer {
+    version: u32,
+    data: CompressedData,
+}
+
+#[derive(Serialize)]
+struct CompressedData {
+    data: ByteArray,
+    compressed: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes that compress a bit, like the base64 of a pattern
+    fn sample(length: usize) -> Vec<u8> {
+        (0..length)
+            .map(|i| b"AAAAgICAaGVsbG8="[i * 7 % 16])
+            .collect()
+    }
+
+    #[test]
+    fn zlib_writer_matches_compress_to_vec_zlib() {
+        for length in [0, 1, 100, 70_000] {
+            let data = sample(length);
+            for level in [0, 1, 6, 9] {
+                let expected = miniz_oxide::deflate::compress_to_vec_zlib(&data, level);
+                // Written in uneven pieces like the base64 encoder hands them over
+                let mut writer = ZlibWriter::new(level);
+                for piece in data.chunks(1021) {
+                    writer.write_all(piece).unwrap();
+                }
+                assert_eq!(
+                    writer.finish(),
+                    expected,
+                    "{} bytes at level {}",
+                    length,
+                    level
+                );
+            }
+        }
+    }
+
+    /// Pattern the way it was written before the stages were streamed into each other, every
+    /// stage serializing or compressing its whole input at once
+    fn one_shot_pattern(
+        data: ChiselData,
+        statistics: Statistics,
+        compression: u8,
+        target: Target,
+    ) -> Vec<u8> {
+        use base64::Engine;
+
+        let format = target.format();
+        let chisel_nbt = fastnbt::to_bytes(&Data {
+            chiseled_data: data,
+            statistics,
+        })
+        .unwrap();
+        let mut compressed_chisel_nbt = Vec::new();
+        let mut lz4_encoder = FrameEncoder::new(&mut compressed_chisel_nbt);
+        std::io::copy(&mut chisel_nbt.as_slice(), &mut lz4_encoder).unwrap();
+        lz4_encoder.finish().unwrap();
+
+        let container = DataContainer {
+            version: format.container_version(),
+            data: CompressedData {
+                data: ByteArray::new(compressed_chisel_nbt.into_iter().map(|b| b as i8).collect()),
+                compressed: 1u8,
+            },
+        };
+        let container_nbt = fastnbt::to_bytes(&container).unwrap();
+        let nbt_base64 = base64::engine::general_purpose::STANDARD.encode(container_nbt);
+
+        let mut pattern = serde_json::Map::new();
+        pattern.insert("chiselData".to_owned(), nbt_base64.into());
+        pattern.insert("version".to_owned(), format.file_version().into());
+        let pattern_bytes = serde_json::to_vec(&pattern).unwrap();
+        let pattern_string = base64::engine::general_purpose::STANDARD.encode(pattern_bytes);
+        miniz_oxide::deflate::compress_to_vec_zlib(pattern_string.as_bytes(), compression)
+    }
+
+    #[test]
+    fn streamed_pattern_matches_one_shot_pipeline() {
+        let palette = BlockPalette::from_json(include_bytes!("../blocks.json")).unwrap();
+        // A full chunk of many colors, a chunk of a single voxel and an empty chunk
+        let mut voxels = Vec::new();
+        for x in 0..16u8 {
+            for y in 0..16u8 {
+                for z in 0..16u8 {
+                    let i = ((x as u32 * 73 + y as u32 * 151 + z as u32 * 37) % 48) as u8;
+                    voxels.push(dot_vox::Voxel { x, y, z, i });
+                }
+            }
+        }
+        voxels.push(dot_vox::Voxel {
+            x: 21,
+            y: 3,
+            z: 9,
+            i: 5,
+        });
+        let model = dot_vox::Model {
+            size: dot_vox::Size {
+                x: 48,
+                y: 16,
+                z: 16,
+            },
+            voxels,
+        };
+        let colors: Vec<_> = (0..256u32)
+            .map(|i| dot_vox::Color {
+                r: (i * 53 % 256) as u8,
+                g: (i * 97 % 256) as u8,
+                b: (i * 31 % 256) as u8,
+                a: 255,
+            })
+            .collect();
+        let block_model = Converter::new(&palette).match_blocks(&model, &colors);
+        let targets: Vec<usize> = (0..block_model.palette_blocks.len()).collect();
+        let contents = |offset| {
+            let contents = model_to_data(
+                &block_model.model_data,
+                &block_model.palette,
+                &targets,
+                offset,
+            );
+            let data = ChiselData {
+                data: ByteArray::new(contents.data),
+                palette: contents.palette,
+            };
+            (data, contents.statistics)
+        };
+
+        for offset in [(0, 0, 0), (16, 0, 0), (32, 0, 0)] {
+            for level in [0, 1, 6, 9] {
+                let (data, statistics) = contents(offset);
+                let streamed = data_to_pattern(data, statistics, level, Target::default());
+                let (data, statistics) = contents(offset);
+                let one_shot = one_shot_pattern(data, statistics, level, Target::default());
+                assert_eq!(streamed, one_shot, "chunk at {:?}, level {}", offset, level);
+            }
+        }
+    }
+
+    /// Regression test, the voxel lookup was one voxel short of models with 256 voxel sides
+    #[test]
+    fn voxel_in_last_corner_of_largest_model() {
+        let palette = BlockPalette::from_json(include_bytes!("../blocks.json")).unwrap();
+        let model = dot_vox::Model {
+            size: dot_vox::Size {
+                x: 256,
+                y: 256,
+                z: 256,
+            },
+            voxels: vec![dot_vox::Voxel {
+                x: 255,
+                y: 255,
+                z: 255,
+                i: 0,
+            }],
+        };
+        let colors = [dot_vox::Color {
+            r: 125,
+            g: 125,
+            b: 125,
+            a: 255,
+        }];
+        let block_model = Converter::new(&palette).match_blocks(&model, &colors);
+        assert!(
+            block_model.warnings.is_empty(),
+            "{:?}",
+            block_model.warnings
+        );
+        assert_eq!(block_model.chunks().len(), 16 * 16 * 16);
+
+        let patterns = block_model
+            .encode_all()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].offset, (240, 240, 240));
+        let pattern = decode::decode_pattern(&patterns[0].bytes).unwrap();
+        pattern.check().unwrap();
+        let air = pattern.palette.len() as u16 - 1;
+        assert_ne!(pattern.entry(15, 15, 15), air);
+        assert_eq!(pattern.counts()[..air as usize].iter().sum::<u32>(), 1);
+    }
+
+    #[test]
+    fn primary_state_is_most_common_block() {
+        let palette = BlockPalette::from_mapping(HashMap::from([
+            ("#7d7d7d".to_owned(), "minecraft:stone".to_owned()),
+            ("#a2834f".to_owned(), "minecraft:oak_planks".to_owned()),
+        ]))
+        .unwrap();
+        // The oak planks come first, in the voxels and in the colors
+        let oak = (0..2).map(|x| dot_vox::Voxel {
+            x,
+            y: 0,
+            z: 0,
+            i: 0,
+        });
+        let stone = (0..10).map(|x| dot_vox::Voxel {
+            x,
+            y: 1,
+            z: 0,
+            i: 1,
+        });
+        let model = dot_vox::Model {
+            size: dot_vox::Size {
+                x: 16,
+                y: 16,
+                z: 16,
+            },
+            voxels: oak.chain(stone).collect(),
+        };
+        let colors = [
+            dot_vox::Color {
+                r: 0xa2,
+                g: 0x83,
+                b: 0x4f,
+                a: 255,
+            },
+            dot_vox::Color {
+                r: 0x7d,
+                g: 0x7d,
+                b: 0x7d,
+                a: 255,
+            },
+        ];
+        let patterns = Converter::new(&palette)
+            .convert_model(&model, &colors)
+            .unwrap();
+        assert_eq!(patterns[0].report.dominant_block, "minecraft:stone");
+
+        let pattern = decode::decode_pattern(&patterns[0].bytes).unwrap();
+        pattern.check().unwrap();
+        assert!(
+            pattern.primary_state.contains("minecraft:stone"),
+            "{}",
+            pattern.primary_state
+        );
+    }
+
+    /// Model over several chunks with many colors, some of them close to each other
+    fn colorful_model() -> (dot_vox::Model, Vec<dot_vox::Color>) {
+        let mut voxels = Vec::new();
+        for x in 0..40u8 {
+            for y in 0..20u8 {
+                for z in 0..24u8 {
+                    let hash = (x as u32 * 73 + y as u32 * 151 + z as u32 * 37) % 97;
+                    if hash < 70 {
+                        voxels.push(dot_vox::Voxel {
+                            x,
+                            y,
+                            z,
+                            i: (hash % 48) as u8,
+                        });
+                    }
+                }
+            }
+        }
+        let colors = (0..256u32)
+            .map(|i| dot_vox::Color {
+                r: (i * 53 % 256) as u8,
+                g: (i * 97 % 256) as u8,
+                b: (i * 31 % 256) as u8,
+                a: 255,
+            })
+            .collect();
+        let size = dot_vox::Size {
+            x: 40,
+            y: 20,
+            z: 24,
+        };
+        (dot_vox::Model { size, voxels }, colors)
+    }
+
+    /// Bytes of every pattern, converted with a new palette on a pool of the given threads
+    fn converted_bytes(threads: usize, configure: fn(Converter) -> Converter) -> Vec<Vec<u8>> {
+        let (model, colors) = colorful_model();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let palette = BlockPalette::from_json(include_bytes!("../blocks.json")).unwrap();
+            configure(Converter::new(&palette))
+                .convert_model(&model, &colors)
+                .unwrap()
+                .into_iter()
+                .map(|pattern| pattern.bytes)
+                .collect()
+        })
+    }
+
+    #[test]
+    fn conversion_is_reproducible() {
+        let options: [fn(Converter) -> Converter; 3] = [
+            |converter| converter,
+            |converter| converter.with_dither(true),
+            |converter| converter.with_max_colors(6),
+        ];
+        for configure in options {
+            let first = converted_bytes(1, configure);
+            assert_eq!(first.len(), 3 * 2 * 2);
+            assert_eq!(converted_bytes(1, configure), first);
+            assert_eq!(converted_bytes(8, configure), first);
+        }
+    }
+
+    /// Integration test of the chunk axes, a line through a model of several blocks must come
+    /// back in one piece from the decoded patterns
+    #[test]
+    fn diagonal_line_continues_across_chunks() {
+        // Rises along y and falls along z while running along x, so swapped axes would show
+        let line: Vec<[u8; 3]> = (0..40u32)
+            .map(|i| [i, i * 19 / 39, (39 - i) * 19 / 39].map(|axis| axis as u8))
+            .collect();
+        let model = dot_vox::Model {
+            size: dot_vox::Size {
+                x: 40,
+                y: 20,
+                z: 20,
+            },
+            voxels: line
+                .iter()
+                .map(|&[x, y, z]| dot_vox::Voxel { x, y, z, i: 0 })
+                .collect(),
+        };
+        let colors = [dot_vox::Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        }];
+        let palette = BlockPalette::from_json(include_bytes!("../blocks.json")).unwrap();
+        let patterns = Converter::new(&palette)
+            .convert_model(&model, &colors)
+            .unwrap();
+        // Three blocks along x, two along y and z, the line passes through five of them
+        assert_eq!(patterns.len(), 5);
+
+        let mut decoded = Vec::new();
+        for pattern in &patterns {
+            let (ox, oy, oz) = pattern.offset;
+            let bits = decode::decode_pattern(&pattern.bytes).unwrap();
+            bits.check().unwrap();
+            let air = bits.palette.len() as u16 - 1;
+            for index in 0..BLOCK_SIDE * BLOCK_SIDE * BLOCK_SIDE {
+                let (x, y, z) = position_from_index(index);
+                if bits.entry(x, y, z) != air {
+                    let [vx, vy, vz] = voxel_axes([x, y, z]);
+                    decoded.push([vx + ox, vy + oy, vz + oz]);
+                }
+            }
+        }
+        decoded.sort();
+        assert_eq!(decoded, line);
+        for pair in decoded.windows(2) {
+            let steps = (0..3).map(|axis| pair[1][axis].abs_diff(pair[0][axis]));
+            assert!(steps.max() == Some(1), "{:?} to {:?}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn model_data_takes_two_bytes_per_position() {
+        assert_eq!(std::mem::size_of::<Option<u16>>(), 4);
+        let side = 256;
+        let mut data = ModelData::new(dot_vox::Size {
+            x: side,
+            y: side,
+            z: side,
+        });
+        assert_eq!(std::mem::size_of_val(&*data.voxels), 32 << 20);
+
+        data.set(0, 0, 0, 0);
+        data.set(255, 255, 255, u16::MAX - 1);
+        assert_eq!(data.get(0, 0, 0), Some(0));
+        assert_eq!(data.get(255, 255, 255), Some(u16::MAX - 1));
+        assert_eq!(data.get(1, 0, 0), None);
+    }
+
+    /// Name of the block the palette matches the color code with
+    fn matched(palette: &BlockPalette, code: &str) -> String {
+        let code = u32::from_str_radix(code.trim_start_matches('#'), 16).unwrap();
+        let [_, r, g, b] = code.to_be_bytes();
+        palette
+            .closest_block(dot_vox::Color { r, g, b, a: 255 })
+            .name
+            .clone()
+    }
+
+    #[test]
+    fn srgb_bytes_are_decoded() {
+        // Reference values of the CIE Lch of the sRGB colors
+        for ((r, g, b), (l, chroma, hue)) in [
+            ((128, 128, 128), (53.59, 0.0, None)),
+            ((255, 255, 255), (100.0, 0.0, None)),
+            ((255, 0, 0), (53.24, 104.55, Some(40.0))),
+            ((0, 0, 255), (32.30, 133.81, Some(306.29))),
+        ] {
+            let lch = to_lch(Srgb::new(r, g, b));
+            assert!((lch.l - l).abs() < 0.05, "{:?}", lch);
+            assert!((lch.chroma - chroma).abs() < 0.05, "{:?}", lch);
+            if let Some(hue) = hue {
+                let difference = lch.hue.into_positive_degrees() - hue;
+                assert!(difference.abs() < 0.05, "{:?}", lch);
+            }
+        }
+    }
+
+    #[test]
+    fn dark_grays_match_gray_blocks() {
+        let palette = BlockPalette::from_mapping(HashMap::from([
+            ("#080a0f".to_owned(), "minecraft:black_concrete".to_owned()),
+            ("#36393d".to_owned(), "minecraft:gray_concrete".to_owned()),
+            (
+                "#7d7d73".to_owned(),
+                "minecraft:light_gray_concrete".to_owned(),
+            ),
+            ("#cfd5d6".to_owned(), "minecraft:white_concrete".to_owned()),
+        ]))
+        .unwrap();
+        for (code, block) in [
+            ("#000000", "minecraft:black_concrete"),
+            ("#151515", "minecraft:black_concrete"),
+            ("#2a2a2a", "minecraft:gray_concrete"),
+            ("#404040", "minecraft:gray_concrete"),
+            ("#555555", "minecraft:gray_concrete"),
+            ("#808080", "minecraft:light_gray_concrete"),
+            ("#a0a0a0", "minecraft:light_gray_concrete"),
+            ("#c0c0c0", "minecraft:white_concrete"),
+            ("#ffffff", "minecraft:white_concrete"),
+        ] {
+            assert_eq!(matched(&palette, code), block, "{}", code);
+        }
+    }
+
+    #[test]
+    fn colors_match_nearest_wool() {
+        let palette = BlockPalette::from_json(include_bytes!("../blocks.json")).unwrap();
+        for (code, block) in [
+            ("#cc1111", "minecraft:red_wool"),
+            ("#1f3fb0", "minecraft:blue_wool"),
+            ("#e0e020", "minecraft:yellow_wool"),
+            ("#4d4d4d", "minecraft:gray_wool"),
+            ("#1a1a1a", "minecraft:black_wool"),
+            ("#702c00", "minecraft:brown_wool"),
+        ] {
+            assert_eq!(matched(&palette, code), block, "{}", code);
+        }
+    }
+}
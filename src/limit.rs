@@ -0,0 +1,147 @@
+use palette::color_difference::Ciede2000;
+
+use crate::{encode_chunk, i18n::tr, ChunkData, EncodedChunk};
+
+/// Strongest zlib compression level, slower than the default
+const STRONGEST_COMPRESSION: u8 = 10;
+
+/// Largest size of a pattern file, e.g. because servers reject larger items
+#[derive(Clone, Copy)]
+pub struct PatternLimit {
+    pub max_bytes: usize,
+    /// Fail instead of changing the blocks of a pattern
+    pub strict: bool,
+}
+
+/// Blocks of a chunk that were merged so its pattern fits the limit
+pub struct Reduction {
+    blocks_before: usize,
+    blocks_after: usize,
+    /// Bits placed with a different block than the model asks for
+    changed_bits: u32,
+    total_bits: u32,
+    bytes: usize,
+}
+
+impl Reduction {
+    pub fn describe(&self, file: &str) -> String {
+        tr!(
+            "pattern-limit-reduced",
+            file = file,
+            before = self.blocks_before,
+            after = self.blocks_after,
+            bits = self.changed_bits,
+            share = format!(
+                "{:.1}",
+                self.changed_bits as f32 / self.total_bits as f32 * 100.0
+            ),
+            bytes = self.bytes,
+        )
+    }
+}
+
+impl PatternLimit {
+    /// Encodes a chunk whose pattern is too large again, first with the strongest compression.
+    /// If that is not enough, the palette entry whose bits change the least (bits times color
+    /// difference) is merged into another one until the pattern fits. Fails if a strict limit
+    /// would need to change blocks, or if even a single block is too large.
+    pub(crate) fn fit(
+        &self,
+        chunk: &ChunkData,
+        block_counts: &[(String, u32)],
+    ) -> Result<(EncodedChunk, Option<Reduction>), String> {
+        // Entries of the same block with other properties look different
+        let state = |entry: usize| &chunk.palette[entry].state;
+        let distinct_blocks = |counts: &[u32]| {
+            let mut states: Vec<_> = (0..counts.len())
+                .filter(|&entry| counts[entry] > 0)
+                .map(state)
+                .collect();
+            states.sort();
+            states.dedup();
+            states.len()
+        };
+        let original: Vec<u32> = block_counts.iter().map(|(_, count)| *count).collect();
+        let mut counts = original.clone();
+        // Entry the bits of every entry are placed with, the entry itself unless merged
+        let mut targets: Vec<usize> = (0..counts.len()).collect();
+        let blocks_before = distinct_blocks(&counts);
+        let total_bits = counts.iter().sum();
+        let mut smallest = usize::MAX;
+
+        loop {
+            let used: Vec<usize> = (0..counts.len()).filter(|&i| counts[i] > 0).collect();
+
+            // Patterns only list the entries used in them, merged entries leave the palette
+            let encoded = encode_chunk(
+                chunk.model_data,
+                chunk.palette,
+                &targets,
+                chunk.offset,
+                STRONGEST_COMPRESSION,
+                chunk.target,
+            );
+            smallest = smallest.min(encoded.pattern.len());
+            if encoded.pattern.len() <= self.max_bytes {
+                let bytes = encoded.pattern.len();
+                let changed_bits = (0..counts.len())
+                    .filter(|&entry| state(entry) != state(targets[entry]))
+                    .map(|entry| original[entry])
+                    .sum();
+                // Merging entries of the same block state changes nothing
+                let reduction = (changed_bits > 0).then(|| Reduction {
+                    blocks_before,
+                    blocks_after: distinct_blocks(&counts),
+                    changed_bits,
+                    total_bits,
+                    bytes,
+                });
+                return Ok((encoded, reduction));
+            }
+
+            if used.len() <= 1 {
+                return Err(tr!(
+                    "pattern-limit-single",
+                    bytes = smallest,
+                    max = self.max_bytes
+                ));
+            }
+
+            let cost = |entry: usize, target: usize| {
+                if state(entry) == state(target) {
+                    0.0
+                } else {
+                    let difference = chunk.palette_blocks[entry]
+                        .lch
+                        .difference(chunk.palette_blocks[target].lch);
+                    counts[entry] as f32 * difference
+                }
+            };
+            let (entry, target) = used
+                .iter()
+                .flat_map(|&entry| {
+                    used.iter()
+                        .filter(move |&&target| target != entry)
+                        .map(move |&target| (entry, target))
+                })
+                .min_by(|&(l_entry, l_target), &(r_entry, r_target)| {
+                    cost(l_entry, l_target).total_cmp(&cost(r_entry, r_target))
+                })
+                .unwrap();
+            // A strict limit only allows merging entries of the same block state
+            if self.strict && state(entry) != state(target) {
+                return Err(tr!(
+                    "pattern-limit-compressed",
+                    bytes = smallest,
+                    max = self.max_bytes
+                ));
+            }
+
+            counts[target] += counts[entry];
+            counts[entry] = 0;
+            for merged in targets.iter_mut().filter(|merged| **merged == entry) {
+                *merged = target;
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use dot_vox::DotVoxData;
+
+use crate::{i18n::tr, scene};
+
+/// Prints the number, scene names, size and voxel count of every model in the file, and
+/// warns about models without voxels as they would be skipped
+pub fn print_models(data: &DotVoxData) {
+    let names = scene::model_names(data);
+    let rows: Vec<[String; 4]> = data
+        .models
+        .iter()
+        .enumerate()
+        .map(|(index, model)| {
+            let mut model_names: Vec<_> = names
+                .iter()
+                .filter(|(_, named)| *named == index)
+                .map(|(name, _)| name.as_str())
+                .collect();
+            model_names.dedup();
+            [
+                (index + 1).to_string(),
+                model_names.join(", "),
+                format!("{} × {} × {}", model.size.x, model.size.y, model.size.z),
+                voxel_count(model).to_string(),
+            ]
+        })
+        .collect();
+
+    let header = [
+        tr!("list-index"),
+        tr!("list-name"),
+        tr!("list-size"),
+        tr!("list-voxels"),
+    ];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let print_row = |row: &[String; 4]| {
+        println!(
+            "{:>w0$}  {:<w1$}  {:>w2$}  {:>w3$}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        );
+    };
+    print_row(&header);
+    for row in &rows {
+        print_row(row);
+    }
+
+    for (index, model) in data.models.iter().enumerate() {
+        if voxel_count(model) == 0 {
+            eprintln!(
+                "{}",
+                tr!(
+                    "warning",
+                    message = tr!("list-empty-model", index = index + 1)
+                )
+            );
+        }
+    }
+}
+
+/// Voxels inside the declared size of the model, the ones outside are not converted
+fn voxel_count(model: &dot_vox::Model) -> usize {
+    let size = model.size;
+    model
+        .voxels
+        .iter()
+        .filter(|voxel| {
+            (voxel.x as u32) < size.x && (voxel.y as u32) < size.y && (voxel.z as u32) < size.z
+        })
+        .count()
+}
@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chisels_and_importers::gzip;
+use fastnbt::LongArray;
+use serde::Serialize;
+
+use crate::{
+    attribution::Attribution,
+    metadata::creation_time,
+    structure::{NbtBlockState, Structure},
+};
+
+pub const LITEMATIC_EXTENSION: &str = ".litematic";
+/// Version of the Litematica schematic format that is written
+const LITEMATIC_VERSION: i32 = 6;
+/// Fewest bits Litematica stores a palette index in
+const MIN_BITS: u32 = 2;
+
+/// The structure as a gzip compressed Litematica schematic with a single region. The name
+/// is shown in Litematica's schematic browser next to the author of the attribution.
+pub fn encode(
+    structure: &Structure,
+    name: &str,
+    attribution: &Attribution,
+    data_version: i32,
+) -> Vec<u8> {
+    let [width, height, length] = structure.size.map(|side| side as usize);
+
+    // Air is the first entry, it fills every position without a block
+    let mut palette = vec![NbtBlockState::AIR];
+    let mut palette_indices = HashMap::new();
+    let mut indices = vec![0u32; width * height * length];
+    for &(position, block) in &structure.blocks {
+        let index = *palette_indices.entry(block.state()).or_insert_with(|| {
+            palette.push(NbtBlockState::new(block));
+            palette.len() as u32 - 1
+        });
+        let [x, y, z] = position.map(|axis| axis as usize);
+        // Positions are ordered by y, then z, then x
+        indices[(y * length + z) * width + x] = index;
+    }
+    let bits = ((palette.len() as f32).log2().ceil() as u32).max(MIN_BITS);
+
+    let time = creation_time().as_millis() as i64;
+    let size = Coordinates::new(structure.size);
+    let file = LitematicFile {
+        minecraft_data_version: data_version,
+        version: LITEMATIC_VERSION,
+        metadata: Metadata {
+            name,
+            author: attribution
+                .author
+                .as_deref()
+                .unwrap_or(env!("CARGO_PKG_NAME")),
+            description: attribution.description.as_deref().unwrap_or_default(),
+            region_count: 1,
+            total_volume: (width * height * length) as i32,
+            total_blocks: structure.blocks.len() as i32,
+            time_created: time,
+            time_modified: time,
+            enclosing_size: size,
+        },
+        regions: BTreeMap::from([(
+            name,
+            Region {
+                position: Coordinates::new([0, 0, 0]),
+                size,
+                block_state_palette: palette,
+                block_states: LongArray::new(pack(&indices, bits)),
+                tile_entities: Vec::new(),
+                entities: Vec::new(),
+                pending_block_ticks: Vec::new(),
+                pending_fluid_ticks: Vec::new(),
+            },
+        )]),
+    };
+    gzip::compress(&fastnbt::to_bytes(&file).unwrap())
+}
+
+/// Packs the values into longs with the given number of bits each, starting at the lowest
+/// bits. Unlike the block states of chunks, a value that doesn't fit into the rest of a long
+/// continues in the next one, so only the last long has unused bits.
+fn pack(values: &[u32], bits: u32) -> Vec<i64> {
+    let bits = bits as usize;
+    let mut longs = vec![0u64; (values.len() * bits).div_ceil(64)];
+    for (index, &value) in values.iter().enumerate() {
+        let start = index * bits;
+        let (long, offset) = (start / 64, start % 64);
+        longs[long] |= (value as u64) << offset;
+        if offset + bits > 64 {
+            longs[long + 1] |= (value as u64) >> (64 - offset);
+        }
+    }
+    longs.into_iter().map(|long| long as i64).collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct LitematicFile<'a> {
+    minecraft_data_version: i32,
+    version: i32,
+    metadata: Metadata<'a>,
+    regions: BTreeMap<&'a str, Region<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Metadata<'a> {
+    name: &'a str,
+    author: &'a str,
+    description: &'a str,
+    region_count: i32,
+    total_volume: i32,
+    total_blocks: i32,
+    time_created: i64,
+    time_modified: i64,
+    enclosing_size: Coordinates,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Region<'a> {
+    position: Coordinates,
+    size: Coordinates,
+    block_state_palette: Vec<NbtBlockState<'a>>,
+    block_states: LongArray,
+    tile_entities: Vec<fastnbt::Value>,
+    entities: Vec<fastnbt::Value>,
+    pending_block_ticks: Vec<fastnbt::Value>,
+    pending_fluid_ticks: Vec<fastnbt::Value>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+struct Coordinates {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+impl Coordinates {
+    fn new([x, y, z]: [u32; 3]) -> Self {
+        Self {
+            x: x as i32,
+            y: y as i32,
+            z: z as i32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct WrittenFile {
+        metadata: WrittenMetadata,
+        regions: BTreeMap<String, WrittenRegion>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct WrittenMetadata {
+        name: String,
+        author: String,
+        description: String,
+        total_blocks: i32,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct WrittenRegion {
+        size: WrittenCoordinates,
+        block_state_palette: Vec<WrittenBlockState>,
+        block_states: LongArray,
+    }
+
+    #[derive(Deserialize)]
+    struct WrittenCoordinates {
+        x: i32,
+        y: i32,
+        z: i32,
+    }
+
+    #[derive(Deserialize)]
+    struct WrittenBlockState {
+        #[serde(rename = "Name")]
+        name: String,
+    }
+
+    fn decode(bytes: &[u8]) -> WrittenFile {
+        let bytes = gzip::decompress(bytes).unwrap();
+        fastnbt::from_bytes(&bytes).unwrap()
+    }
+
+    /// Reverse of [`pack`], reading the values the way Litematica does
+    fn unpack(longs: &[i64], bits: u32, count: usize) -> Vec<u32> {
+        let bits = bits as usize;
+        let mask = (1u64 << bits) - 1;
+        (0..count)
+            .map(|index| {
+                let start = index * bits;
+                let (long, offset) = (start / 64, start % 64);
+                let mut value = longs[long] as u64 >> offset;
+                if offset + bits > 64 {
+                    value |= (longs[long + 1] as u64) << (64 - offset);
+                }
+                (value & mask) as u32
+            })
+            .collect()
+    }
+
+    fn metadata(attribution: &Attribution) -> WrittenMetadata {
+        let structure = Structure {
+            offset: [0, 0, 0],
+            size: [1, 1, 1],
+            blocks: Vec::new(),
+        };
+        decode(&encode(&structure, "castle", attribution, 3465)).metadata
+    }
+
+    #[test]
+    fn written_blocks_read_back() {
+        let palette = crate::testing::palette();
+        let blocks: Vec<_> = palette.mapping.iter().take(5).collect();
+        let size = [5, 3, 7];
+        // Five blocks and air take three bits, so some values continue in the next long
+        let structure = Structure {
+            offset: [0, 0, 0],
+            size,
+            blocks: (0..40u32)
+                .map(|i| ([i % 5, i % 3, i % 7], blocks[i as usize % 5]))
+                .collect(),
+        };
+        let file = decode(&encode(&structure, "castle", &Attribution::default(), 3465));
+        assert_eq!(file.metadata.total_blocks, 40);
+
+        let region = &file.regions["castle"];
+        let region_size = [region.size.x, region.size.y, region.size.z];
+        assert_eq!(region_size, size.map(|side| side as i32));
+        let names: Vec<_> = region
+            .block_state_palette
+            .iter()
+            .map(|state| state.name.as_str())
+            .collect();
+        assert_eq!(names.len(), 6);
+        assert_eq!(names[0], "minecraft:air");
+
+        let [width, height, length] = size.map(|side| side as usize);
+        assert_eq!(
+            region.block_states.len(),
+            (width * height * length * 3).div_ceil(64)
+        );
+        let indices = unpack(&region.block_states, 3, width * height * length);
+        let mut expected = vec!["minecraft:air"; indices.len()];
+        for &([x, y, z], block) in &structure.blocks {
+            let [x, y, z] = [x, y, z].map(|axis| axis as usize);
+            expected[(y * length + z) * width + x] = &block.name;
+        }
+        let read: Vec<_> = indices.iter().map(|&index| names[index as usize]).collect();
+        assert_eq!(read, expected);
+    }
+
+    #[test]
+    fn packing_reads_back() {
+        for bits in [2, 3, 5, 7, 12] {
+            let values: Vec<u32> = (0..200).map(|i| (i * 37 + 11) % (1 << bits)).collect();
+            let longs = pack(&values, bits);
+            assert_eq!(longs.len(), (values.len() * bits as usize).div_ceil(64));
+            assert_eq!(unpack(&longs, bits, values.len()), values, "{} bits", bits);
+        }
+    }
+
+    #[test]
+    fn metadata_holds_attribution() {
+        let attribution = Attribution {
+            author: Some("Ann".to_owned()),
+            license: Some("CC-BY-4.0".to_owned()),
+            description: Some("A small castle".to_owned()),
+        };
+        let metadata = metadata(&attribution);
+        assert_eq!(metadata.name, "castle");
+        assert_eq!(metadata.author, "Ann");
+        assert_eq!(metadata.description, "A small castle");
+    }
+
+    #[test]
+    fn tool_is_author_without_attribution() {
+        let metadata = metadata(&Attribution::default());
+        assert_eq!(metadata.author, env!("CARGO_PKG_NAME"));
+        assert_eq!(metadata.description, "");
+    }
+}
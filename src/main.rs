@@ -1,20 +1,111 @@
+mod analyze;
+mod archive;
+mod attribution;
+mod binvox;
+mod bom;
+mod book;
+mod clipboard;
+mod compose;
+mod config;
+mod console;
+mod dump;
+mod error;
+mod generate;
+mod goxel;
+#[cfg(feature = "gui")]
+mod gui;
+mod guide;
+mod histogram;
+mod html;
+mod imported;
+mod inspect;
+mod interior;
+mod jar;
+mod layout;
+mod lettering;
+mod list;
+mod litematic;
+mod mask;
+mod matches;
+mod metadata;
+mod palette_generator;
+mod palette_source;
+mod picture;
+mod plugin;
+mod preview;
+mod quality;
+mod qubicle;
+mod raster;
+mod report;
+mod resample;
+mod scene;
+mod schematic;
+mod server;
+mod slice;
+mod structure;
+mod summary;
+#[cfg(test)]
+mod testing;
+mod text_bundle;
+mod update;
+mod watch;
+
 use std::{
-    collections::{HashMap, HashSet},
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
     str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
-use base64::Engine;
-use bitstream_io::{BitWrite, BitWriter};
-use clap::Parser;
-use fastnbt::ByteArray;
-use lz4_flex::frame::FrameEncoder;
-use palette::{color_difference::Ciede2000, IntoColor, Lch, Srgb};
+use chisels_and_importers::{
+    decode::decode_pattern,
+    i18n::{self, tr},
+    limit::PatternLimit,
+    target::Target,
+    to_lch, AlphaMode, BlockPalette, ChunkReport, ColorMatch, ColorMetric, ColorReduction,
+    Converter, IndexMap, PaletteBlock, BLOCK_SIDE, MAX_MODEL_SIZE,
+};
+use clap::{Parser, Subcommand};
 use serde::Serialize;
 
+use crate::{
+    archive::ZipWriter,
+    attribution::Attribution,
+    bom::BillOfMaterials,
+    clipboard::{ChunkPosition, SystemClipboard},
+    compose::{ComposeInput, Composed},
+    console::{say, Progress},
+    error::Error,
+    guide::{GuideVoxel, LegendEntry},
+    jar::Jar,
+    layout::Layout,
+    mask::IgnoredColor,
+    matches::MatchReport,
+    metadata::Metadata,
+    plugin::Plugin,
+    preview::{CompareOptions, PreviewOptions, PreviewVoxel},
+    raster::Pixel,
+    report::{Markup, ReportModel, ShareReport},
+    resample::{BitsPerVoxel, Scale},
+    scene::{Alignment, Axis, ModelSelector, Rotation},
+    schematic::ReversePalette,
+    slice::SliceSpec,
+    summary::{ModelReport, RunSummary, SkippedChunk},
+    text_bundle::TextBundle,
+};
+
 /// Convert Magica Voxel models into Chisels and Bits patterns
-#[derive(Parser, Debug)]
+#[derive(Parser, Serialize, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 #[command(arg_required_else_help(true))]
+#[command(subcommand_negates_reqs(true), args_conflicts_with_subcommands(true))]
 #[command(help_template(
     "\
 {before-help}{name} {version} by {author}
@@ -25,341 +116,2192 @@ use serde::Serialize;
 "
 ))]
 struct Args {
-    /// path to Magica Voxel file (typically .vox)
-    #[arg()]
-    model: String,
+    #[command(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
+    /// path to Magica Voxel file (typically .vox), Qubicle file (.qb, .qbt), Goxel file (.gox), binvox file (.binvox) or Sponge schematic (.schem), or a directory to convert every model file in it
+    #[arg(required_unless_present_any = ["check_update", "compose"])]
+    model: Option<String>,
+    /// place a model file into a scene shared with the other --compose files, at an offset in voxels (x, y, z with z up), e.g. --compose base.vox@0,0,0 --compose roof.vox@0,0,48
+    #[arg(long, value_name = "FILE@X,Y,Z", conflicts_with_all = ["model", "watch", "list", "metadata"])]
+    compose: Vec<ComposeInput>,
+    /// fail instead of letting later --compose files replace the voxels of earlier ones
+    #[arg(long, requires = "compose")]
+    no_overlap: bool,
+    /// format of the model file: vox, qb, qbt, gox, binvox or schem (defaults to the extension of the file, else vox)
+    #[arg(long)]
+    input_format: Option<InputFormat>,
+    /// WebAssembly module reading model files of another format, tried before the built-in formats unless --input-format is given, e.g. --plugin pvx.wasm (see plugins/pvx.wat)
+    #[arg(long, value_name = "FILE.wasm")]
+    plugin: Vec<PathBuf>,
     /// the filename for the resulting pattern(s)
     #[arg(short, long, default_value = "pattern")]
     output: String,
-    /// what block palette file to use
+    /// directory the output files are written to, the patterns of a directory of models are named after the model files
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+    /// what block palette file to use, can also be a URL or a published name like @community/survival-1.20
     #[arg(short, long, default_value = "blocks.json")]
     palette: String,
+    /// only use cached copies of downloaded palettes
+    #[arg(long)]
+    offline: bool,
     #[clap(flatten)]
     model_group: ModelGroup,
+    /// convert the model again every time it or the palette file is saved, until Ctrl+C is pressed
+    #[arg(long)]
+    #[serde(skip)]
+    watch: bool,
+    /// print the models in the file with their name, size and number of voxels, then exit
+    #[arg(long)]
+    #[serde(skip)]
+    list: bool,
+    /// with -a, convert every model on its own instead of placing them as arranged in the scene
+    #[arg(long)]
+    ignore_scene: bool,
+    /// write the bill of materials to a CSV file (or JSON with a .json extension)
+    #[arg(long)]
+    bom_file: Option<String>,
+    /// print how many blocks of every material the build needs
+    #[arg(long)]
+    materials: bool,
+    /// write a command giving a written book that lists the materials to a file, run it from a command block
+    #[arg(long)]
+    bom_book: Option<String>,
+    /// write a Markdown description of the build for sharing it, e.g. on a forum
+    #[arg(long)]
+    report_md: Option<String>,
+    /// write a BBCode description of the build for sharing it, e.g. on a forum
+    #[arg(long)]
+    report_bbcode: Option<String>,
+    /// template used instead of the built-in one for the reports, with placeholders like {{name}}
+    #[arg(long)]
+    report_template: Option<String>,
+    /// render an isometric preview of the converted model to a PNG file
+    #[arg(long)]
+    preview: Option<String>,
+    /// size of the longest preview image side in pixels
+    #[arg(long, default_value_t = 512)]
+    preview_size: u32,
+    /// render the model in its own colors next to its block colors to a PNG file
+    #[arg(long)]
+    compare: Option<String>,
+    /// add a comparison panel marking voxels whose color difference (delta-E) exceeds this
+    #[arg(long, value_name = "DELTA_E", requires = "compare")]
+    compare_threshold: Option<f32>,
+    /// write layer by layer build guide images into a directory
+    #[arg(long)]
+    build_guide: Option<String>,
+    /// print one layer of the converted model instead of writing patterns, e.g. y=12
+    #[arg(long, value_name = "AXIS=LAYER")]
+    preview_slice: Option<SliceSpec>,
+    /// write an interactive 3D view of the converted model to a single HTML file
+    #[arg(long)]
+    preview_html: Option<String>,
+    /// what to write: pattern (Chisels and Bits patterns), structure (vanilla structure files), schem (a Sponge schematic for WorldEdit) or litematic (a Litematica schematic), all but patterns with a full block for every voxel
+    #[arg(long, default_value = "pattern")]
+    format: OutputFormat,
+    /// how the files of a model with several patterns are named: coordinates (by the block they go into, e.g. pattern_x1_y0_z2.cbsbp) or index (numbered in chunk order)
+    #[arg(long, default_value = "coordinates")]
+    naming: Naming,
+    /// write a single structure even if the model is larger than the 48 blocks structure blocks can load
+    #[arg(long)]
+    no_split: bool,
+    /// keep the empty space of the canvas around the model, by default the model is cropped to its voxels so chunks start at its lowest corner
+    #[arg(long)]
+    no_crop: bool,
+    /// turn the model around an axis of Minecraft before converting it, counterclockwise seen from the positive end of the axis, e.g. y:90. Can be repeated
+    #[arg(long, value_name = "AXIS:DEGREES")]
+    rotate: Vec<Rotation>,
+    /// mirror the model along axes of Minecraft after rotating it, e.g. x
+    #[arg(long, value_delimiter = ',', value_name = "AXIS")]
+    mirror: Vec<Axis>,
+    /// where the model sits in the blocks it is split into along the Minecraft axes: min, center or max, e.g. x=center,z=max
+    #[arg(long, value_delimiter = ',', value_name = "AXIS=ANCHOR")]
+    align: Vec<Alignment>,
+    /// resample the model by this factor before converting it, e.g. 0.5 or 1/4
+    #[arg(long)]
+    scale: Option<Scale>,
+    /// shrink the model until it fits into a single block
+    #[arg(long, conflicts_with = "scale")]
+    fit: bool,
+    /// share of a resampled voxel that has to be covered by voxels for it not to be air
+    #[arg(long, value_name = "SHARE", default_value_t = 0.5)]
+    min_fill: f32,
+    /// turn every voxel into a cube of N bits on each side, 1, 2, 4, 8 or 16
+    #[arg(long, value_name = "N")]
+    bits_per_voxel: Option<BitsPerVoxel>,
+    /// replace the voxels that can't be seen from outside with air, so builds have fewer bits
+    #[arg(long)]
+    hollow: bool,
+    /// voxels kept below the surface with --hollow, at least 1
+    #[arg(
+        long,
+        value_name = "VOXELS",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "hollow"
+    )]
+    hollow_thickness: u32,
+    /// fill the air enclosed by the model, with the most common color around it or --fill-color
+    #[arg(long, conflicts_with = "hollow")]
+    fill_interior: bool,
+    /// color the enclosed air is filled with, e.g. #8a6b4f, matched against the palette like the colors of the model
+    #[arg(long, value_name = "COLOR", requires = "fill_interior")]
+    fill_color: Option<String>,
+    /// give every voxel this color, e.g. for binvox files which store no colors (their voxels are #808080 otherwise)
+    #[arg(long, value_name = "COLOR")]
+    solid_color: Option<String>,
+    /// leave out the voxels of these colors, as palette indices (1-255) or color codes matched within a small tolerance, e.g. 255,#ff00ff
+    #[arg(long, value_delimiter = ',', value_name = "COLOR")]
+    ignore_colors: Vec<IgnoredColor>,
+    /// turn every voxel into this block instead of matching colors against the palette, e.g. minecraft:stone
+    #[arg(long, value_name = "BLOCK", conflicts_with_all = ["solid_color", "only_tags"])]
+    solid_block: Option<String>,
+    /// JSON file of blocks that .schem models turn into other blocks, e.g. {"minecraft:grass_block": "minecraft:moss_block", "minecraft:chest": "#8a6b4f", "minecraft:torch": "minecraft:air"}
+    #[arg(long, value_name = "FILE")]
+    block_map: Option<PathBuf>,
+    /// JSON file of blocks for Magica Voxel palette indices (1-255) whose voxels become the block whatever their color, e.g. {"1": "minecraft:polished_blackstone", "2": {"name": "minecraft:oak_log", "properties": {"axis": "x"}}, "16": "air"}
+    #[arg(long, value_name = "FILE")]
+    index_map: Option<PathBuf>,
+    /// write the block every palette index of the model became, with its color and difference, to a JSON file that --overrides reads back
+    #[arg(long, value_name = "FILE")]
+    report_matches: Option<PathBuf>,
+    /// JSON file written by --report-matches, entries marked "locked": true keep their block and the others are matched again
+    #[arg(long, value_name = "FILE")]
+    overrides: Option<PathBuf>,
+    /// Minecraft data version written into structures and schematics, the default is the one of 1.20.1
+    #[arg(long, default_value_t = structure::DATA_VERSION)]
+    data_version: i32,
+    /// largest pattern file in bytes, for servers that reject large items. Larger patterns
+    /// are compressed more, then their blocks are merged until they fit
+    #[arg(long, value_name = "BYTES")]
+    max_pattern_bytes: Option<usize>,
+    /// fail instead of merging blocks of patterns larger than --max-pattern-bytes
+    #[arg(long, requires = "max_pattern_bytes")]
+    strict: bool,
+    /// print details about every written pattern
+    #[arg(short, long)]
+    verbose: bool,
+    /// don't read every written pattern back to check that it is complete
+    #[arg(long)]
+    no_validate: bool,
+    /// also write patterns of only air for the blocks of the model without voxels, so the patterns form a complete grid
+    #[arg(long)]
+    keep_empty: bool,
+    /// version of Chisels & Bits the patterns are written for, only cb-forge-1.20 (the default) so far
+    #[arg(long)]
+    target: Option<Target>,
+    /// zlib compression level of the pattern files, from 0 (none, fastest) to 9 (smallest, slowest), 6 if not given
+    #[arg(long, value_name = "LEVEL")]
+    compression: Option<CompressionLevel>,
+    /// also write every pattern's JSON, container NBT as SNBT text and uncompressed chisel NBT into this directory, to debug imports
+    #[arg(long, value_name = "DIR")]
+    debug_dump: Option<PathBuf>,
+    /// only use the blocks of the palette with one of these tags, e.g. wool,concrete
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    only_tags: Vec<String>,
+    /// merge the colors of a model down to at most N before picking blocks, so patterns need fewer bits for every voxel
+    #[arg(long, value_name = "N")]
+    max_colors: Option<usize>,
+    /// how color differences are measured when picking blocks: ciede2000, oklab or rgb
+    #[arg(long, default_value = "ciede2000")]
+    color_metric: ColorMetric,
+    /// mix blocks across neighboring voxels to get closer to colors between them, e.g. on gradients
+    #[arg(long)]
+    dither: bool,
+    /// emission a Magica Voxel emit material needs to be matched against the "emit" blocks of the palette
+    #[arg(long, value_name = "EMISSION", default_value_t = 0.0)]
+    emission_threshold: f32,
+    /// palette of light-emitting blocks like glowstone, sea lanterns and froglights for the voxels of emit materials, used instead of the "emit" blocks of the palette. Can be a file, a URL or a published name
+    #[arg(long, value_name = "FILE")]
+    emissive_palette: Option<String>,
+    /// how voxels of translucent colors are converted: solid, skip (left out) or glass (only matched against the "glass" blocks of the palette)
+    #[arg(long, default_value = "solid")]
+    alpha_mode: AlphaMode,
+    /// alpha below which a color counts as translucent
+    #[arg(long, default_value_t = 128)]
+    alpha_threshold: u8,
+    /// print how well the blocks match the model colors
+    #[arg(long)]
+    match_quality: bool,
+    /// print a chart of the blocks used, listing the given number of blocks
+    #[arg(long, value_name = "ROWS", num_args = 0..=1, default_missing_value = "10")]
+    histogram: Option<usize>,
+    /// also write all patterns into one text file that can be shared as a message
+    #[arg(long)]
+    text_bundle: Option<PathBuf>,
+    /// copy the path of the pattern to the clipboard, only if a single pattern is written or --chunk picks one
+    #[arg(long)]
+    clipboard: bool,
+    /// block of the pattern --clipboard copies on the Minecraft axes like in the pattern names, e.g. 1,0,2 for pattern_x1_y0_z2
+    #[arg(long, value_name = "X,Y,Z", requires = "clipboard")]
+    chunk: Option<ChunkPosition>,
+    /// write the source, palette and options of the conversion to <OUTPUT>.meta.json
+    #[arg(long)]
+    metadata: bool,
+    /// write where every pattern goes to <OUTPUT>.layout.json and as printable layers to <OUTPUT>.layout.txt
+    #[arg(long)]
+    layout: bool,
+    /// write the patterns into <OUTPUT>.zip instead of separate files, also done if OUTPUT ends in .zip
+    #[arg(long)]
+    archive: bool,
+    #[command(flatten)]
+    attribution: Attribution,
+    /// check whether a newer release is available
+    #[arg(long)]
+    check_update: bool,
+    /// don't print a summary at the end
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// language of the messages, e.g. "de" (defaults to the system language)
+    #[arg(long, global = true)]
+    #[serde(skip)]
+    lang: Option<String>,
+    /// number of threads used to encode patterns (defaults to one per CPU core)
+    #[arg(long, global = true)]
+    #[serde(skip)]
+    threads: Option<usize>,
+    /// number of model files of a directory converted at the same time, their patterns are encoded on the same threads (defaults to --threads)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    #[serde(skip)]
+    jobs: Option<u32>,
+    /// file with defaults for the options (defaults to chisels.toml in the current directory or next to the executable)
+    #[arg(long, global = true)]
+    #[serde(skip)]
+    config: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Inspect block palette files
+    #[command(subcommand)]
+    Palette(PaletteCommand),
+    /// Run an HTTP server that converts models sent to POST /convert
+    Serve {
+        /// address to listen on, e.g. 0.0.0.0 to accept requests from other machines
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// block palette used when a request contains none
+        #[arg(short, long, default_value = "blocks.json")]
+        palette: String,
+        /// largest accepted request body in bytes
+        #[arg(long, default_value_t = 16 * 1024 * 1024)]
+        max_body_size: usize,
+    },
+    /// Recreate the pattern files of a text bundle
+    ImportTextBundle {
+        /// text bundle written with --text-bundle
+        bundle: PathBuf,
+        /// directory to write the patterns into
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Print the blocks of a pattern file
+    Inspect {
+        /// pattern file to read
+        pattern: PathBuf,
+        /// also print every layer of bits from the top, one character per block
+        #[arg(long)]
+        layers: bool,
+    },
+    /// Check received pattern files against the hashes in their metadata file
+    Verify {
+        /// metadata file written with --metadata
+        #[arg(long)]
+        manifest: PathBuf,
+    },
+    /// Create a pattern of a shape or text without a model file
+    #[command(subcommand)]
+    Generate(Box<generate::GenerateCommand>),
+    /// Open a window to convert models without the command line
+    #[cfg(feature = "gui")]
+    Gui,
 }
 
-#[derive(Debug, clap::Args)]
+#[derive(Subcommand, Debug, Clone)]
+enum PaletteCommand {
+    /// Find colors the palette can only match poorly
+    Analyze {
+        /// block palette file to analyze
+        palette: String,
+        /// only check the colors used by this Magica Voxel file
+        model: Option<String>,
+        /// number of colors to list
+        #[arg(short, long, default_value_t = 10)]
+        count: usize,
+    },
+    /// Create a palette from the block textures of the game or mod jars
+    Generate {
+        /// jar or extracted assets directory to read blocks from, repeat to merge several
+        #[arg(long = "jar", required = true)]
+        jars: Vec<PathBuf>,
+        /// resource pack zip or directory whose textures replace the ones of the jars, repeat to layer several with the last one on top
+        #[arg(long = "resource-pack")]
+        resource_packs: Vec<PathBuf>,
+        /// palette file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        /// only use blocks of these namespaces, e.g. minecraft
+        #[arg(long = "namespace", value_delimiter = ',')]
+        namespaces: Vec<String>,
+        /// write translucent blocks to the glass section instead of leaving them out
+        #[arg(long)]
+        include_translucent: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, clap::Args)]
 #[group(required = false, multiple = false)]
 pub struct ModelGroup {
     /// create pattern(s) for each model in the file
     #[clap(short, long)]
     all_models: bool,
-    /// create pattern(s) for specific models in the file
-    #[clap(short, long, value_delimiter = ',', num_args = 1..)]
-    models: Option<Vec<usize>>,
+    /// create pattern(s) for specific models in the file, by number or by their name in the scene, or for layers of Goxel files
+    #[clap(short, long, visible_alias = "layers", value_delimiter = ',', num_args = 1..)]
+    models: Option<Vec<ModelSelector>>,
 }
 
-fn main() {
-    let args = Args::parse();
+/// Kind of files a model is converted into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    /// Chisels and Bits patterns, one for every block of voxels
+    #[default]
+    Pattern,
+    /// Vanilla structure files with a full block for every voxel
+    Structure,
+    /// A Sponge schematic with a full block for every voxel
+    Schem,
+    /// A Litematica schematic with a full block for every voxel
+    Litematic,
+}
 
-    let voxel_file = &args.model;
-    let voxel_data = dot_vox::load(voxel_file).expect("parsing voxel file");
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Pattern => PATTERN_EXTENSION,
+            OutputFormat::Structure => structure::STRUCTURE_EXTENSION,
+            OutputFormat::Schem => schematic::SCHEMATIC_EXTENSION,
+            OutputFormat::Litematic => litematic::LITEMATIC_EXTENSION,
+        }
+    }
+}
 
-    let mapping_raw = std::fs::read(&args.palette).expect("missing palette");
-    let block_palette = BlockPalette::from_json(&mapping_raw);
+impl FromStr for OutputFormat {
+    type Err = String;
 
-    let mut models = Vec::new();
-    let model_count = voxel_data.models.len();
-    if model_count == 1 || args.model_group.all_models {
-        models.extend(voxel_data.models.iter());
-    } else if let Some(requested) = args.model_group.models {
-        for index in requested.iter() {
-            models.push(
-                voxel_data
-                    .models
-                    .get(index - 1)
-                    .expect("invalid model index"),
-            );
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pattern" => Ok(OutputFormat::Pattern),
+            "structure" => Ok(OutputFormat::Structure),
+            "schem" => Ok(OutputFormat::Schem),
+            "litematic" => Ok(OutputFormat::Litematic),
+            other => Err(tr!("format-unknown", format = other)),
         }
-    } else {
-        eprintln!("Multiple models inside file ({}), pass -a to export all models or -m to export specific models", model_count);
-        return;
     }
+}
 
-    let export_count = models.len();
-    for (i, model) in models.into_iter().enumerate() {
-        let prefix = if export_count == 1 {
-            args.output.clone()
-        } else {
-            format!("{}_{}", &args.output, i)
-        };
+/// zlib level from 0 to 9 the pattern files are compressed with
+#[derive(Debug, Clone, Copy, Serialize)]
+struct CompressionLevel(u8);
 
-        create_patterns(model, &block_palette, &voxel_data, &prefix);
+impl FromStr for CompressionLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<u8>() {
+            Ok(level) if level <= 9 => Ok(CompressionLevel(level)),
+            _ => Err(tr!("compression-level-invalid", level = s)),
+        }
     }
 }
 
-const PATTERN_EXTENSION: &str = ".cbsbp";
+/// Kind of file a model is read from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum InputFormat {
+    /// Magica Voxel
+    #[default]
+    Vox,
+    /// Qubicle 1.x
+    Qb,
+    /// Qubicle 3 binary tree
+    Qbt,
+    /// Goxel
+    Gox,
+    /// binvox, voxelized meshes
+    Binvox,
+    /// Sponge schematic, a voxel for every block
+    Schem,
+}
 
-fn create_patterns(
-    model: &dot_vox::Model,
-    block_palette: &BlockPalette,
-    voxel_data: &dot_vox::DotVoxData,
-    path_prefix: &str,
-) {
-    // Build an O(1) lookup array for voxels
-    let mut model_data: Vec<Option<u8>> =
-        vec![None; VOXEL_MAX_SIDE * VOXEL_MAX_SIDE * VOXEL_MAX_SIDE];
-    let mut used_colors = HashSet::<_>::default();
-    for voxel in model.voxels.iter() {
-        let index = index_from_position(voxel.x, voxel.y, voxel.z);
-        model_data[index] = Some(voxel.i);
-        used_colors.insert(voxel.i);
-    }
-    let model_data = model_data.into_boxed_slice();
-
-    // Translate voxel palette into block palette
-    let mut palette_mapping = HashMap::new();
-    let mut chisel_palette = Vec::with_capacity(used_colors.len() + 1);
-    for vox_palette_index in used_colors {
-        let vox_color = voxel_data.palette.get(vox_palette_index as usize).unwrap();
-        let closest_block = block_palette.closest_block(*vox_color);
-
-        palette_mapping.insert(vox_palette_index, chisel_palette.len() as u8);
-        chisel_palette.push(PaletteEntry {
-            state: format!("{{\"Name\":\"{}\"}}", closest_block),
-        });
+impl InputFormat {
+    /// The format if given, else the one of the extension of the model file
+    fn of(path: &str, format: Option<InputFormat>) -> Self {
+        format
+            .or_else(|| InputFormat::from_extension(Path::new(path)))
+            .unwrap_or_default()
     }
-    // Last entry is always air
-    chisel_palette.push(PaletteEntry {
-        state: "{\"Name\":\"minecraft:air\"}".to_owned(),
-    });
 
-    // Divide voxel model into block sized chunks and create a pattern for each
-    let size = model.size;
-    let length = (size.x as f32 / BLOCK_SIDE as f32).ceil() as usize;
-    let width = (size.y as f32 / BLOCK_SIDE as f32).ceil() as usize;
-    let height = (size.z as f32 / BLOCK_SIDE as f32).ceil() as usize;
-    let one_pattern = length == 1 && width == 1 && height == 1;
-
-    let mut index = 0;
-    for x in 0..length {
-        for y in 0..width {
-            for z in 0..height {
-                let offset = (
-                    (x * BLOCK_SIDE) as u8,
-                    (y * BLOCK_SIDE) as u8,
-                    (z * BLOCK_SIDE) as u8,
-                );
-                let Some((data, statistics)) =
-                    model_to_data(&model_data, &chisel_palette, &palette_mapping, offset)
-                else {
-                    continue;
-                };
+    /// Format of a model file by its extension, `None` for other files
+    fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        extension.parse().ok()
+    }
 
-                let pattern = data_to_pattern(
-                    ChiselData {
-                        data: ByteArray::new(data),
-                        palette: &chisel_palette,
-                    },
-                    statistics,
+    /// Parses the model, schematics are matched against the palette if one is given
+    fn parse(
+        self,
+        bytes: &[u8],
+        palette: Option<&ReversePalette>,
+    ) -> Result<dot_vox::DotVoxData, String> {
+        match self {
+            InputFormat::Vox => dot_vox::load_bytes(bytes).map_err(str::to_owned),
+            InputFormat::Qb => qubicle::parse_qb(bytes),
+            InputFormat::Qbt => qubicle::parse_qbt(bytes),
+            InputFormat::Gox => goxel::parse_gox(bytes),
+            InputFormat::Binvox => binvox::parse_binvox(bytes),
+            InputFormat::Schem => schematic::parse_schem(bytes, palette),
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vox" => Ok(InputFormat::Vox),
+            "qb" => Ok(InputFormat::Qb),
+            "qbt" => Ok(InputFormat::Qbt),
+            "gox" => Ok(InputFormat::Gox),
+            "binvox" => Ok(InputFormat::Binvox),
+            "schem" => Ok(InputFormat::Schem),
+            other => Err(tr!("input-format-unknown", format = other)),
+        }
+    }
+}
+
+/// How the patterns of a model with several blocks are told apart
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Naming {
+    /// By the position of their block in the model, on the Minecraft axes
+    #[default]
+    Coordinates,
+    /// By a number counting the written patterns in chunk order
+    Index,
+}
+
+impl FromStr for Naming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "coordinates" => Ok(Naming::Coordinates),
+            "index" => Ok(Naming::Index),
+            other => Err(tr!("naming-unknown", naming = other)),
+        }
+    }
+}
+
+fn main() {
+    let (args, config) = config::parse_args();
+    i18n::init(args.lang.as_deref());
+    if let Some(config) = &config {
+        config.warn_unknown_keys();
+    }
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap_or_else(|error| Error::Input(tr!("threads-failed", error = error)).exit());
+    }
+
+    match &args.command {
+        Some(Command::Palette(PaletteCommand::Analyze {
+            palette,
+            model,
+            count,
+        })) => {
+            let block_palette = load_palette(palette, false).unwrap_or_else(|error| error.exit());
+            let voxel_data = model
+                .as_ref()
+                .map(|model| load_model(model, None).unwrap_or_else(|error| error.exit()));
+            analyze::analyze_palette(&block_palette, voxel_data.as_ref(), *count);
+            return;
+        }
+        Some(Command::Palette(PaletteCommand::Generate {
+            jars,
+            resource_packs,
+            output,
+            namespaces,
+            include_translucent,
+        })) => {
+            if let Err(error) = palette_generator::generate_palette(
+                jars,
+                resource_packs,
+                output,
+                namespaces,
+                *include_translucent,
+            ) {
+                eprintln!("{}", tr!("palette-generate-failed", error = error));
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Serve {
+            host,
+            port,
+            palette,
+            max_body_size,
+        }) => {
+            let block_palette = load_palette(palette, false).unwrap_or_else(|error| error.exit());
+            if let Err(error) = server::serve(host, *port, block_palette, *max_body_size) {
+                error.exit();
+            }
+            return;
+        }
+        Some(Command::ImportTextBundle { bundle, output }) => {
+            match text_bundle::import(bundle, output) {
+                Ok(files) => println!("{}", tr!("import-wrote", count = files.len())),
+                Err(error) => {
+                    eprintln!(
+                        "{}",
+                        tr!("import-failed", file = bundle.display(), error = error)
+                    );
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Inspect { pattern, layers }) => {
+            if let Err(error) = inspect::print_pattern(pattern, *layers) {
+                eprintln!(
+                    "{}",
+                    tr!("inspect-failed", file = pattern.display(), error = error)
                 );
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Verify { manifest }) => {
+            let valid = metadata::verify(manifest).unwrap_or_else(|error| {
+                Error::Input(tr!(
+                    "verify-failed",
+                    file = manifest.display(),
+                    error = error
+                ))
+                .exit()
+            });
+            std::process::exit(if valid { 0 } else { 1 });
+        }
+        Some(Command::Generate(options)) => {
+            if let Err(error) = generate::generate(options) {
+                eprintln!("{}", tr!("generate-failed", error = error));
+                std::process::exit(1);
+            }
+            return;
+        }
+        #[cfg(feature = "gui")]
+        Some(Command::Gui) => {
+            if let Err(error) = gui::run() {
+                Error::Output(tr!("gui-window-failed", error = error)).exit();
+            }
+            return;
+        }
+        None => {}
+    }
 
-                let output_file = if one_pattern {
-                    format!("{}{}", path_prefix, PATTERN_EXTENSION)
-                } else {
-                    format!("{}_{}{}", path_prefix, index, PATTERN_EXTENSION)
-                };
-                std::fs::write(output_file, &pattern).expect("failed to write pattern file");
-                index += 1;
+    if args.check_update {
+        update::check_for_update();
+    }
+    if args.list {
+        if let Some(model) = &args.model {
+            let data = load_model(model, args.input_format).unwrap_or_else(|error| error.exit());
+            list::print_models(&data);
+        }
+        return;
+    }
+    let mut args = args;
+    let directory = args
+        .model
+        .as_deref()
+        .map(PathBuf::from)
+        .filter(|model| model.is_dir());
+    let result = match directory {
+        Some(directory) => convert_directory(&mut args, &directory),
+        None => {
+            if let Some(output_dir) = args.output_dir.as_ref().filter(|_| args.output != STDIO) {
+                if let Err(e) = std::fs::create_dir_all(output_dir) {
+                    Error::write(output_dir, e).exit();
+                }
+                args.output = output_dir.join(&args.output).to_string_lossy().into_owned();
+            }
+            if args.watch {
+                watch::watch(&args)
+            } else {
+                convert(&args, &mut |_, _| {}).map(|_| ())
             }
         }
+    };
+    if let Err(error) = result {
+        error.exit();
     }
 }
 
-struct BlockPalette {
-    mapping: Vec<(Lch, String)>,
+/// Converts every model file of the directory into patterns named after the file, with every
+/// model of a file unless models are picked. A file that fails is reported and skipped, the
+/// batch then fails at the end.
+fn convert_directory(args: &mut Args, directory: &Path) -> Result<(), Error> {
+    // These write one file for the whole run, every model file would overwrite it
+    let single_file_options = [
+        ("--preview", args.preview.is_some()),
+        ("--compare", args.compare.is_some()),
+        ("--build-guide", args.build_guide.is_some()),
+        ("--preview-html", args.preview_html.is_some()),
+        ("--text-bundle", args.text_bundle.is_some()),
+        ("--bom-file", args.bom_file.is_some()),
+        ("--bom-book", args.bom_book.is_some()),
+        ("--report-matches", args.report_matches.is_some()),
+        ("--report-md", args.report_md.is_some()),
+        ("--report-bbcode", args.report_bbcode.is_some()),
+        ("--clipboard", args.clipboard),
+        ("--watch", args.watch),
+    ];
+    if let Some((option, _)) = single_file_options.iter().find(|(_, used)| *used) {
+        return Err(Error::Input(tr!("batch-unsupported", option = option)));
+    }
+
+    let read_failed = |e: std::io::Error| {
+        Error::Input(tr!(
+            "model-read-failed",
+            file = directory.display(),
+            error = e
+        ))
+    };
+    // Colors matched for one file are reused by the others
+    let resources = Resources::load(args)?;
+    // Files of other formats are converted if a plugin takes them
+    let taken_by_plugin = |path: &Path| {
+        std::fs::read(path).is_ok_and(|bytes| {
+            resources
+                .plugins
+                .iter()
+                .any(|plugin| plugin.probe(&bytes).unwrap_or(false))
+        })
+    };
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(directory).map_err(read_failed)? {
+        let path = entry.map_err(read_failed)?.path();
+        if path.is_file()
+            && (InputFormat::from_extension(&path).is_some() || taken_by_plugin(&path))
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    if files.is_empty() {
+        return Err(Error::Input(tr!(
+            "batch-empty",
+            directory = directory.display()
+        )));
+    }
+    let output_dir = args
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."));
+    std::fs::create_dir_all(&output_dir).map_err(|e| Error::write(&output_dir, e))?;
+    if args.model_group.models.is_none() {
+        args.model_group.all_models = true;
+    }
+
+    let (total, failures) = convert_files(args, &resources, &files, &output_dir)?;
+    print_summary(args, &total);
+    let failed = failures.len();
+    let message = tr!(
+        "batch-summary",
+        converted = files.len() - failed,
+        total = files.len(),
+        failed = failed
+    );
+    match failures.into_iter().next() {
+        None => {
+            say!("{}", message);
+            Ok(())
+        }
+        // Exit with the code of the first failure
+        Some(Error::Input(_)) => Err(Error::Input(message)),
+        Some(Error::Output(_)) => Err(Error::Output(message)),
+    }
 }
 
-impl BlockPalette {
-    fn from_json(data: &[u8]) -> Self {
-        let block_mapping: HashMap<String, String> =
-            serde_json::from_slice(data).expect("invalid json in palette");
-        let mapping = block_mapping
-            .into_iter()
-            .map(|(k, v)| {
-                (
-                    Srgb::from_str(&k)
-                        .expect("invalid color code in palette")
-                        .into_linear::<f32>()
-                        .into_color(),
-                    v,
-                )
-            })
-            .collect();
+/// Converts the model files into patterns in the output directory, named after the files.
+/// Returns the summaries of the converted files added up and the errors of the others, in
+/// the order of the files so the exit code doesn't depend on which file fails first.
+fn convert_files(
+    args: &Args,
+    resources: &Resources,
+    files: &[PathBuf],
+    output_dir: &Path,
+) -> Result<(RunSummary, Vec<Error>), Error> {
+    // Every job converts one file after the other, so at most that many files are in flight.
+    // The chunks of the files are encoded on the threads of the jobs.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(
+            args.jobs
+                .map_or_else(rayon::current_num_threads, |jobs| jobs as usize),
+        )
+        .build()
+        .map_err(|error| Error::Input(tr!("threads-failed", error = error)))?;
+    let next_file = AtomicUsize::new(0);
+    let total = Mutex::new(RunSummary::start());
+    let failures = Mutex::new(Vec::new());
+    let convert_file = |file: &Path| {
+        let mut args = args.clone();
+        let stem = file.file_stem().unwrap_or_default();
+        args.model = Some(file.to_string_lossy().into_owned());
+        args.output = output_dir.join(stem).to_string_lossy().into_owned();
+        say!("{}", tr!("batch-file", file = file.display()));
+        match convert_with(&args, resources, &mut |_, _| {}) {
+            Ok((_, summary)) => {
+                print_summary(&args, &summary);
+                Ok(summary)
+            }
+            Err(error) => {
+                console::print_error(tr!(
+                    "batch-file-failed",
+                    file = file.display(),
+                    error = error
+                ));
+                Err(error)
+            }
+        }
+    };
+    pool.scope(|scope| {
+        for _ in 0..pool.current_num_threads() {
+            scope.spawn(|_| loop {
+                let index = next_file.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(index) else {
+                    break;
+                };
+                // The messages of a file are printed together once it is done
+                let (result, messages) = console::held(|| convert_file(file));
+                console::print_held(&messages);
+                match result {
+                    Ok(summary) => total.lock().unwrap().merge(summary),
+                    Err(error) => failures.lock().unwrap().push((index, error)),
+                }
+            });
+        }
+    });
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort_by_key(|(index, _)| *index);
+    let failures = failures.into_iter().map(|(_, error)| error).collect();
+    Ok((total.into_inner().unwrap(), failures))
+}
 
-        Self { mapping }
+/// Reads the model file, a missing file gets its own message
+fn read_model_file(path: &str) -> Result<Vec<u8>, Error> {
+    if path == STDIO {
+        let mut bytes = Vec::new();
+        return std::io::stdin()
+            .read_to_end(&mut bytes)
+            .map(|_| bytes)
+            .map_err(|e| Error::Input(tr!("model-read-failed", file = path, error = e)));
     }
+    std::fs::read(path).map_err(|e| {
+        Error::Input(match e.kind() {
+            std::io::ErrorKind::NotFound => tr!("model-not-found", file = path),
+            _ => tr!("model-read-failed", file = path, error = e),
+        })
+    })
+}
 
-    fn closest_block(&self, color: dot_vox::Color) -> &str {
-        let color = Srgb::new(color.r, color.g, color.b);
-        let color: Lch = color.into_linear::<f32>().into_color();
+/// Parses a model file in the format, or else the one of its extension
+fn parse_model(
+    path: &str,
+    bytes: &[u8],
+    format: Option<InputFormat>,
+    palette: Option<&ReversePalette>,
+) -> Result<dot_vox::DotVoxData, Error> {
+    InputFormat::of(path, format)
+        .parse(bytes, palette)
+        .map_err(|e| Error::Input(tr!("model-invalid", file = path, error = e)))
+}
 
-        // Select best matching block
-        let mut color_diffs: Vec<_> = self
-            .mapping
-            .iter()
-            .map(|(block_color, block)| (block_color.difference(color), block))
-            .collect();
-        color_diffs.sort_by(|(l, _), (r, _)| l.total_cmp(r));
-        let block_name = color_diffs.first().unwrap().1;
-        block_name.as_str()
+fn load_model(path: &str, format: Option<InputFormat>) -> Result<dot_vox::DotVoxData, Error> {
+    parse_model(path, &read_model_file(path)?, format, None)
+}
+
+/// Colors of the blocks of the palette, with the blocks of the `--block-map` file replaced
+fn reverse_palette(
+    block_palette: &BlockPalette,
+    block_map: Option<&Path>,
+) -> Result<ReversePalette, Error> {
+    let Some(path) = block_map else {
+        return ReversePalette::new(block_palette, HashMap::new()).map_err(Error::Input);
+    };
+    let failed = |error: &dyn std::fmt::Display| {
+        Error::Input(tr!(
+            "block-map-invalid",
+            file = path.display(),
+            error = error
+        ))
+    };
+    let contents = std::fs::read(path).map_err(|e| failed(&e))?;
+    let block_map = serde_json::from_slice(&contents).map_err(|e| failed(&e))?;
+    ReversePalette::new(block_palette, block_map).map_err(|e| failed(&e))
+}
+
+/// Reads the `--index-map` file and warns about mapped indices that no voxel of the models
+/// to convert has
+fn read_index_map(
+    path: &Path,
+    block_palette: &BlockPalette,
+    voxel_data: &dot_vox::DotVoxData,
+    model_indices: &[usize],
+) -> Result<IndexMap, Error> {
+    let failed = |error: &dyn std::fmt::Display| {
+        Error::Input(tr!(
+            "index-map-invalid",
+            file = path.display(),
+            error = error
+        ))
+    };
+    let contents = std::fs::read(path).map_err(|e| failed(&e))?;
+    let index_map = IndexMap::from_json(&contents, block_palette, &voxel_data.palette)
+        .map_err(|e| failed(&e))?;
+    // Indices in the file start at 1
+    let used: HashSet<u8> = model_indices
+        .iter()
+        .flat_map(|&index| &voxel_data.models[index].voxels)
+        .map(|voxel| voxel.i + 1)
+        .collect();
+    let unused: Vec<_> = index_map
+        .indices()
+        .filter(|index| !used.contains(index))
+        .map(|index| index.to_string())
+        .collect();
+    if !unused.is_empty() {
+        console::warn(tr!("index-map-unused", indices = unused.join(", ")));
+    }
+    if palette_reordered(&voxel_data.index_map) {
+        console::warn(tr!("index-map-reordered-palette"));
+    }
+    Ok(index_map)
+}
+
+/// Whether the IMAP chunk of a file shows the palette in another order than it is stored. The
+/// chunk only orders the palette as Magica Voxel shows it, voxels and the index map refer to the
+/// stored order. Either shift of the identity leaves the order unchanged.
+fn palette_reordered(index_map: &[u8]) -> bool {
+    !index_map.is_empty()
+        && !(0..=1).any(|shift| {
+            index_map
+                .iter()
+                .enumerate()
+                .all(|(position, &index)| index as usize == (position + shift) % 256)
+        })
+}
+
+/// Reads the locked entries of the `--overrides` file as an index map
+fn read_overrides(
+    path: &Path,
+    block_palette: &BlockPalette,
+    voxel_data: &dot_vox::DotVoxData,
+) -> Result<IndexMap, Error> {
+    let failed = |error: &dyn std::fmt::Display| {
+        Error::Input(tr!(
+            "overrides-invalid",
+            file = path.display(),
+            error = error
+        ))
+    };
+    let contents = std::fs::read(path).map_err(|e| failed(&e))?;
+    let entries = matches::locked_entries(&contents).map_err(|e| failed(&e))?;
+    IndexMap::from_entries(entries, block_palette, &voxel_data.palette).map_err(|e| failed(&e))
+}
+
+/// Reads the palette from a file, a URL or a published name
+fn load_palette(source: &str, offline: bool) -> Result<BlockPalette, Error> {
+    let mapping_raw = palette_source::read_palette(source, offline).map_err(Error::Input)?;
+    BlockPalette::from_json(&mapping_raw)
+        .map_err(|error| Error::Input(tr!("palette-invalid", file = source, error = error)))
+}
+
+/// What a run loads once and shares between the model files it converts, so a directory
+/// of models reads the palette once, matches every color once and compiles every plugin once
+struct Resources {
+    block_palette: BlockPalette,
+    plugins: Vec<Plugin>,
+}
+
+impl Resources {
+    /// Loads the palette of the options, narrowed to the tags and with the emissive blocks,
+    /// and compiles the plugins
+    fn load(args: &Args) -> Result<Self, Error> {
+        let mut block_palette = match &args.solid_block {
+            // Every color is closest to the only block
+            Some(block) => {
+                BlockPalette::from_mapping(HashMap::from([("#808080".to_owned(), block.clone())]))
+                    .map_err(|error| {
+                    Error::Input(tr!("solid-block-invalid", block = block, error = error))
+                })?
+            }
+            None => load_palette(&args.palette, args.offline)?,
+        }
+        .with_only_tags(&args.only_tags)
+        .map_err(|error| {
+            Error::Input(tr!("palette-invalid", file = args.palette, error = error))
+        })?;
+        if let Some(source) = &args.emissive_palette {
+            let emissive = load_palette(source, args.offline)?;
+            block_palette
+                .materials
+                .insert(EMIT_MATERIAL.to_owned(), emissive);
+        }
+        let block_palette = block_palette.with_metric(args.color_metric);
+        if let AlphaMode::Glass = args.alpha_mode {
+            if !block_palette.materials.contains_key("glass") {
+                return Err(Error::Input(tr!(
+                    "alpha-glass-missing",
+                    file = args.palette
+                )));
+            }
+        }
+        Ok(Self {
+            block_palette,
+            plugins: load_plugins(&args.plugin)?,
+        })
+    }
+}
+
+/// Converts the model file with the given options and returns the written pattern files.
+/// `progress` is called with the number of converted models and the total number of models.
+fn convert(args: &Args, progress: &mut dyn FnMut(usize, usize)) -> Result<Vec<String>, Error> {
+    if args.model.is_none() && args.compose.is_empty() {
+        return Ok(Vec::new());
     }
+    let (patterns, summary) = convert_with(args, &Resources::load(args)?, progress)?;
+    print_summary(args, &summary);
+    Ok(patterns)
 }
 
-type ModelData = Box<[Option<u8>]>;
+/// Converts the model file like [`convert`] with resources loaded before, and returns the
+/// totals of the summary instead of printing them
+fn convert_with(
+    args: &Args,
+    resources: &Resources,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(Vec<String>, RunSummary), Error> {
+    if args.format != OutputFormat::Pattern {
+        let pattern_options = [
+            ("--max-pattern-bytes", args.max_pattern_bytes.is_some()),
+            ("--text-bundle", args.text_bundle.is_some()),
+            ("--clipboard", args.clipboard),
+            ("--layout", args.layout),
+            ("--keep-empty", args.keep_empty),
+            ("--target", args.target.is_some()),
+            ("--compression", args.compression.is_some()),
+            ("--debug-dump", args.debug_dump.is_some()),
+        ];
+        if let Some((option, _)) = pattern_options.iter().find(|(_, used)| *used) {
+            return Err(Error::Input(tr!("format-patterns-only", option = option)));
+        }
+    }
+    if args.output == STDIO {
+        console::messages_to_stderr();
+        let file_options = [
+            ("--metadata", args.metadata),
+            ("--clipboard", args.clipboard),
+            ("--layout", args.layout && !writes_archive(args)),
+        ];
+        if let Some((option, _)) = file_options.iter().find(|(_, used)| *used) {
+            return Err(Error::Input(tr!("stdout-unsupported", option = option)));
+        }
+    } else if writes_archive(args) {
+        let loose_options = [
+            ("--metadata", args.metadata),
+            ("--clipboard", args.clipboard),
+        ];
+        if let Some((option, _)) = loose_options.iter().find(|(_, used)| *used) {
+            return Err(Error::Input(tr!("archive-unsupported", option = option)));
+        }
+    }
+    let block_palette = &resources.block_palette;
+
+    let (mut voxel_data, format, source_sha256, sources) = match &args.model {
+        Some(voxel_file) => {
+            let voxel_bytes = read_model_file(voxel_file)?;
+            let source_sha256 = args.metadata.then(|| metadata::sha256_hex(&voxel_bytes));
+            let format = InputFormat::of(voxel_file, args.input_format);
+            let voxel_data = read_model(args, voxel_file, &voxel_bytes, resources, &mut None)?;
+            (voxel_data, format, source_sha256, None)
+        }
+        None => {
+            let composed = compose_models(args, resources)?;
+            (
+                composed.data,
+                InputFormat::Vox,
+                None,
+                Some(composed.sources),
+            )
+        }
+    };
+    if let Some(color) = &args.solid_color {
+        binvox::paint(&mut voxel_data, color)
+            .map_err(|error| Error::Input(tr!("solid-color-failed", error = error)))?;
+    }
+    let ignored_voxels = mask::remove(&mut voxel_data, &args.ignore_colors);
+    if ignored_voxels > 0 {
+        say!("{}", tr!("ignore-colors-removed", count = ignored_voxels));
+    }
+
+    // The visible layers of Goxel files are placed together unless layers are picked
+    let all_models = args.model_group.all_models
+        || (format == InputFormat::Gox && args.model_group.models.is_none());
+    let instances = scene::instances(&voxel_data);
+    let hides_models = !instances.is_empty() && instances.len() < voxel_data.models.len();
+    if all_models && !args.ignore_scene && (instances.len() > 1 || hides_models) {
+        // Convert the scene as one model, with every model placed where the scene puts it
+        let merged = scene::merge(&instances, &voxel_data.models)
+            .map_err(|error| Error::Input(tr!("scene-failed", error = error)))?;
+        voxel_data.models = vec![merged];
+        say!("{}", tr!("scene-placed", count = instances.len()));
+    }
+
+    let fill_color = args
+        .fill_color
+        .as_deref()
+        .map(|color| interior::add_color(&mut voxel_data, color))
+        .transpose()
+        .map_err(|error| Error::Input(tr!("fill-color-failed", error = error)))?;
 
-fn data_to_pattern(data: ChiselData, statistics: Statistics) -> Vec<u8> {
-    let output_data = Data {
-        chiseled_data: data,
-        statistics,
+    let model_count = voxel_data.models.len();
+    let model_indices: Vec<usize> = if model_count == 1 || all_models {
+        (0..model_count).collect()
+    } else if let Some(requested) = &args.model_group.models {
+        scene::select_models(&voxel_data, requested).map_err(Error::Input)?
+    } else {
+        return Err(Error::Input(tr!("multiple-models", count = model_count)));
     };
+    let all_ignored = model_indices
+        .iter()
+        .all(|&index| voxel_data.models[index].voxels.is_empty());
+    if ignored_voxels > 0 && all_ignored {
+        return Err(Error::Input(tr!(
+            "ignore-colors-all",
+            count = ignored_voxels
+        )));
+    }
+
+    let mut index_map = args
+        .index_map
+        .as_deref()
+        .map(|path| read_index_map(path, block_palette, &voxel_data, &model_indices))
+        .transpose()?;
+    let overrides = args
+        .overrides
+        .as_deref()
+        .map(|path| read_overrides(path, block_palette, &voxel_data))
+        .transpose()?;
+    let locked: BTreeSet<u8> = overrides.iter().flat_map(IndexMap::indices).collect();
+    if let Some(overrides) = overrides {
+        match &mut index_map {
+            Some(index_map) => index_map.merge(overrides),
+            None => index_map = Some(overrides),
+        }
+    }
 
-    let chisel_nbt = fastnbt::to_bytes(&output_data).unwrap();
-    // Compress chisel nbt with lz4
-    let mut compressed_chisel_nbt = Vec::new();
-    let mut lz4_encoder = FrameEncoder::new(&mut compressed_chisel_nbt);
-    std::io::copy(&mut chisel_nbt.as_slice(), &mut lz4_encoder).unwrap();
-    lz4_encoder.finish().unwrap();
-
-    let container = DataContainer {
-        version: 0,
-        data: CompressedData {
-            data: ByteArray::new(compressed_chisel_nbt.into_iter().map(|b| b as i8).collect()),
-            compressed: 1u8,
+    let (written, summary) = export_models(
+        args,
+        block_palette,
+        &mut voxel_data.models,
+        VoxPalette {
+            colors: &voxel_data.palette,
+            materials: &voxel_data.materials,
+            fill_color,
+            index_map: index_map.as_ref(),
+            locked: &locked,
+        },
+        &model_indices,
+        ModelOrigin {
+            source_sha256: source_sha256.as_deref(),
+            sources,
         },
+        progress,
+    )?;
+    Ok((written.into_iter().map(|(file, _)| file).collect(), summary))
+}
+
+/// Parses a model file. Blocks of schematics are turned into the colors of the same blocks
+/// of the palette, read once into `reverse_palette`, with a single block every block is kept.
+fn read_model(
+    args: &Args,
+    voxel_file: &str,
+    voxel_bytes: &[u8],
+    resources: &Resources,
+    reverse_palette: &mut Option<ReversePalette>,
+) -> Result<dot_vox::DotVoxData, Error> {
+    if args.input_format.is_none() {
+        for plugin in &resources.plugins {
+            if let Some(voxel_data) = read_with_plugin(plugin, voxel_file, voxel_bytes)? {
+                return Ok(voxel_data);
+            }
+        }
+    }
+    let format = InputFormat::of(voxel_file, args.input_format);
+    if format == InputFormat::Schem && args.solid_block.is_none() && reverse_palette.is_none() {
+        *reverse_palette = Some(self::reverse_palette(
+            &resources.block_palette,
+            args.block_map.as_deref(),
+        )?);
+    }
+    parse_model(
+        voxel_file,
+        voxel_bytes,
+        args.input_format,
+        reverse_palette.as_ref(),
+    )
+}
+
+/// Compiles the `--plugin` modules
+fn load_plugins(paths: &[PathBuf]) -> Result<Vec<Plugin>, Error> {
+    paths
+        .iter()
+        .map(|path| {
+            Plugin::load(path).map_err(|error| {
+                Error::Input(tr!(
+                    "plugin-load-failed",
+                    file = path.display(),
+                    error = error
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Reads the model with the plugin, `None` if the plugin doesn't take the file
+fn read_with_plugin(
+    plugin: &Plugin,
+    voxel_file: &str,
+    voxel_bytes: &[u8],
+) -> Result<Option<dot_vox::DotVoxData>, Error> {
+    let failed = |error: String| {
+        Error::Input(tr!(
+            "plugin-failed",
+            plugin = plugin.name(),
+            file = voxel_file,
+            error = error
+        ))
     };
-    let container_nbt = fastnbt::to_bytes(&container).unwrap();
-    let nbt_base64 = base64::engine::general_purpose::STANDARD.encode(container_nbt);
+    if !plugin.probe(voxel_bytes).map_err(failed)? {
+        return Ok(None);
+    }
+    say!(
+        "{}",
+        tr!("plugin-reading", plugin = plugin.name(), file = voxel_file)
+    );
+    plugin.read(voxel_bytes).map(Some).map_err(failed)
+}
 
-    // Create pattern JSON
-    let pattern = PatternFile {
-        version: "1.0",
-        chisel_data: nbt_base64,
+/// Reads the `--compose` files and places them into one scene
+fn compose_models(args: &Args, resources: &Resources) -> Result<Composed, Error> {
+    let mut reverse_palette = None;
+    let files = args
+        .compose
+        .iter()
+        .map(|input| {
+            let voxel_bytes = read_model_file(&input.path)?;
+            read_model(
+                args,
+                &input.path,
+                &voxel_bytes,
+                resources,
+                &mut reverse_palette,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let composed = compose::compose(&args.compose, &files, args.no_overlap)
+        .map_err(|error| Error::Input(tr!("compose-failed", error = error)))?;
+    say!("{}", tr!("compose-placed", count = files.len()));
+    Ok(composed)
+}
+
+/// Turns, crops, scales, hollows and fills the model as the options say, then aligns it.
+/// Returns the number of voxels removed by hollowing and added by filling.
+fn prepare_model(
+    args: &Args,
+    model: &mut dot_vox::Model,
+    fill_color: Option<u8>,
+) -> Result<(Option<usize>, Option<usize>), Error> {
+    scene::orient(model, &args.rotate, &args.mirror);
+    if !args.no_crop {
+        scene::crop(model);
+    }
+    let scale = if args.fit {
+        Some(resample::fit_scale(model.size))
+    } else {
+        args.scale
     };
-    let pattern_bytes = serde_json::to_vec(&pattern).unwrap();
-    let pattern_string = base64::engine::general_purpose::STANDARD.encode(pattern_bytes);
-    // zlib compress pattern
-    let compressed_pattern =
-        miniz_oxide::deflate::compress_to_vec_zlib(pattern_string.as_bytes(), 6);
-    compressed_pattern
-}
-
-fn model_to_data<'a>(
-    model: &ModelData,
-    palette: &'a [PaletteEntry],
-    palette_mapping: &HashMap<u8, u8>,
-    offset: (u8, u8, u8),
-) -> Option<(Vec<i8>, Statistics<'a>)> {
-    let total_size = BLOCK_SIDE * BLOCK_SIDE * BLOCK_SIDE;
-
-    let mut data: Vec<u8> = Vec::with_capacity(total_size);
-    let mut writer = BitWriter::endian(&mut data, bitstream_io::LittleEndian);
-
-    let mut block_states = Vec::with_capacity(palette.len());
-    for entry in palette {
-        block_states.push(BlockState {
-            block_information: entry,
-            count: 0,
-        });
+    if let Some(scale) = scale {
+        resample::resample(model, scale, args.min_fill)
+            .map_err(|error| Error::Input(tr!("resample-failed", error = error)))?;
     }
+    if let Some(bits) = args.bits_per_voxel {
+        resample::expand(model, bits)
+            .map_err(|error| Error::Input(tr!("resample-failed", error = error)))?;
+    }
+    let hollowed = args
+        .hollow
+        .then(|| interior::hollow(model, args.hollow_thickness));
+    let filled = args
+        .fill_interior
+        .then(|| interior::fill(model, fill_color));
+    scene::align(model, &args.align)
+        .map_err(|error| Error::Input(tr!("align-failed", error = error)))?;
+    Ok((hollowed, filled))
+}
 
-    let entry_width = f32::log2(palette.len() as f32).ceil() as u32;
-    let mut only_air = true;
-    for i in 0..total_size {
-        let (mut x, mut y, mut z) = position_from_index(i);
-        x += offset.1;
-        y += offset.2;
-        z += offset.0;
-
-        let index = index_from_position(z, x, y);
-        let voxel = model[index];
-        let val = if let Some(v) = voxel {
-            // If voxel is present get mapped index
-            only_air = false;
-            *palette_mapping.get(&v).unwrap()
+/// What the exported models were read from
+#[derive(Default)]
+struct ModelOrigin<'a> {
+    /// Hash of the model file, metadata is only written if it is set
+    source_sha256: Option<&'a str>,
+    /// Input of every voxel of a model placed together from several files with --compose
+    sources: Option<dot_vox::Model>,
+}
+
+/// Writes the patterns and the requested outputs of the models at the given indices and
+/// returns the written patterns
+fn export_models(
+    args: &Args,
+    block_palette: &BlockPalette,
+    models: &mut [dot_vox::Model],
+    vox_palette: VoxPalette,
+    model_indices: &[usize],
+    origin: ModelOrigin,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(Vec<(String, ChunkReport)>, RunSummary), Error> {
+    let ModelOrigin {
+        source_sha256,
+        mut sources,
+    } = origin;
+    let vanilla_palette = args
+        .match_quality
+        .then(|| BlockPalette::from_json(VANILLA_BLOCKS).expect("invalid built-in palette"));
+
+    // Patterns in an archive are named after the output without the extension of the archive
+    let to_stdout = args.output == STDIO;
+    let output = if to_stdout {
+        STDOUT_PREFIX
+    } else {
+        args.output.strip_suffix(".zip").unwrap_or(&args.output)
+    };
+    let archive_path = if to_stdout {
+        STDIO.to_owned()
+    } else {
+        format!("{}.zip", output)
+    };
+    let mut archive = if !writes_archive(args) {
+        None
+    } else if to_stdout {
+        let stdout: Box<dyn Write> = Box::new(std::io::stdout().lock());
+        Some(ZipWriter::new(stdout))
+    } else {
+        let file = File::create(&archive_path).map_err(|e| Error::write(&archive_path, e))?;
+        let file: Box<dyn Write> = Box::new(BufWriter::new(file));
+        Some(ZipWriter::new(file))
+    };
+    // Patterns for stdout without an archive, only a single one can be written
+    let mut stdout_patterns = Vec::new();
+    let mut bom = BillOfMaterials::default();
+    let light_sources = block_palette
+        .materials
+        .get(EMIT_MATERIAL)
+        .map(|emissive| {
+            emissive
+                .mapping
+                .iter()
+                .map(|block| block.name.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut summary = RunSummary::start().with_light_sources(light_sources);
+    let mut text_bundle = args.text_bundle.as_ref().map(|_| TextBundle::default());
+    let mut written_patterns = Vec::new();
+    let mut report_models = Vec::new();
+    let mut match_report = MatchReport::default();
+    let export_count = model_indices.len();
+    progress(0, export_count);
+    for (i, &model_index) in model_indices.iter().enumerate() {
+        let prefix = if export_count == 1 {
+            output.to_string()
         } else {
-            // Last palette entry is air
-            (palette.len() - 1) as u8
+            format!("{}_{}", output, i)
+        };
+        let numbered = |path: &String| {
+            if export_count == 1 {
+                PathBuf::from(path)
+            } else {
+                numbered_path(Path::new(path), i)
+            }
         };
-        writer.write(entry_width, val).unwrap();
-        block_states.get_mut(val as usize).unwrap().count += 1;
+        let outputs = ModelOutputs {
+            preview: args.preview.as_ref().map(|path| PreviewOptions {
+                path: numbered(path),
+                image_size: args.preview_size,
+            }),
+            compare: args.compare.as_ref().map(|path| CompareOptions {
+                path: numbered(path),
+                image_size: args.preview_size,
+                threshold: args.compare_threshold,
+            }),
+            build_guide: args.build_guide.as_ref().map(numbered),
+            slice: args.preview_slice,
+            html_preview: args.preview_html.as_ref().map(numbered),
+            attribution: args.attribution.clone(),
+            pattern_limit: args.max_pattern_bytes.map(|max_bytes| PatternLimit {
+                max_bytes,
+                strict: args.strict,
+            }),
+            dither: args.dither,
+            emission_threshold: args.emission_threshold,
+            alpha_mode: args.alpha_mode,
+            alpha_threshold: args.alpha_threshold,
+            format: args.format,
+            naming: args.naming,
+            split_structures: !args.no_split,
+            data_version: args.data_version,
+            keep_empty: args.keep_empty,
+            target: args.target.unwrap_or_default(),
+            compression: args.compression,
+            max_colors: args.max_colors,
+            debug_dump: args.debug_dump.clone(),
+            progress: !args.quiet,
+        };
+
+        // A model exported several times is only placed in its blocks the first time
+        if !model_indices[..i].contains(&model_index) {
+            let (hollowed, filled) =
+                prepare_model(args, &mut models[model_index], vox_palette.fill_color)?;
+            if !args.quiet {
+                if let Some(removed) = hollowed {
+                    say!("{}", tr!("hollowed", count = removed));
+                }
+                if let Some(added) = filled {
+                    say!("{}", tr!("interior-filled", count = added));
+                }
+            }
+            // The inputs of composed models go through the same steps, so they stay in place
+            if let Some(sources) = &mut sources {
+                prepare_model(args, sources, None)?;
+            }
+        }
+        let size = models[model_index].size;
+        let mut model_bom = BillOfMaterials::default();
+        let mut bundled = Vec::new();
+        let report = create_patterns(
+            &models[model_index],
+            block_palette,
+            vox_palette,
+            &prefix,
+            &mut model_bom,
+            &outputs,
+            &mut |file, pattern| {
+                if text_bundle.is_some() {
+                    bundled.push(pattern.to_vec());
+                }
+                let validate = args.format == OutputFormat::Pattern && !args.no_validate;
+                if let Some(archive) = &mut archive {
+                    // Archive files are read back once they are complete
+                    if validate && to_stdout {
+                        validate_pattern(pattern).map_err(std::io::Error::other)?;
+                    }
+                    return archive.add(&file_name(file), pattern);
+                }
+                if to_stdout {
+                    if validate {
+                        validate_pattern(pattern).map_err(std::io::Error::other)?;
+                    }
+                    stdout_patterns.push(pattern.to_vec());
+                    return Ok(());
+                }
+                std::fs::write(file, pattern)?;
+                if validate {
+                    validate_pattern(&std::fs::read(file)?).map_err(std::io::Error::other)?;
+                }
+                Ok(())
+            },
+        )?;
+        if let Some(text_bundle) = &mut text_bundle {
+            for ((file, chunk), pattern) in report.patterns.iter().zip(bundled) {
+                text_bundle.add(file, chunk.offset, pattern);
+            }
+        }
+        if args.verbose {
+            let mut color_matches: Vec<_> = report.color_matches.iter().collect();
+            color_matches.sort_by_key(|m| (m.index, Reverse(m.voxels)));
+            for m in color_matches {
+                say!(
+                    "{}",
+                    tr!(
+                        "color-mapped",
+                        index = m.index,
+                        color = quality::hex(m.color),
+                        block = m.block,
+                        delta = format!("{:.1}", m.delta_e),
+                        voxels = m.voxels,
+                    )
+                );
+            }
+            for (file, chunk) in &report.patterns {
+                say!("{}: {}", file, chunk);
+            }
+            for (file, description) in &report.structures {
+                say!("{}: {}", file, description);
+            }
+            for skipped in &report.skipped_chunks {
+                say!(
+                    "{}",
+                    tr!(
+                        "skipped-chunk",
+                        offset = format!("{:?}", skipped.offset),
+                        reason = skipped.reason,
+                    )
+                );
+            }
+        }
+        if let Some(vanilla_palette) = &vanilla_palette {
+            quality::print_quality_report(
+                &prefix,
+                &report.color_matches,
+                block_palette,
+                vanilla_palette,
+            );
+        }
+        if let Some(rows) = args.histogram {
+            histogram::print_histogram(&tr!("histogram-model", name = prefix), &model_bom, rows);
+        }
+        if let Some(source_sha256) = source_sha256 {
+            let patterns: Vec<_> = report
+                .patterns
+                .iter()
+                .map(|(file, _)| file.as_str())
+                .chain(report.structures.iter().map(|(file, _)| file.as_str()))
+                .collect();
+            let path = format!("{}.meta.json", prefix);
+            Metadata::new(
+                args,
+                source_sha256,
+                model_index,
+                block_palette,
+                &patterns,
+                &report.skipped_chunks,
+            )
+            .and_then(|metadata| metadata.write_file(Path::new(&path)))
+            .map_err(|e| Error::write(&path, e))?;
+        }
+        if args.layout {
+            // Input files of a composed model, named for every chunk most of whose voxels they gave
+            let chunk_sources: Option<HashMap<_, _>> = sources.as_ref().map(|sources| {
+                compose::chunk_sources(sources)
+                    .into_iter()
+                    .map(|(chunk, source)| (chunk, args.compose[source as usize].path.clone()))
+                    .collect()
+            });
+            let layout = Layout::new(
+                size,
+                &report.patterns,
+                &report.skipped_chunks,
+                chunk_sources.as_ref(),
+            );
+            if let Some(archive) = &mut archive {
+                let name = file_name(&prefix);
+                layout
+                    .to_json()
+                    .and_then(|json| archive.add(&format!("{}.layout.json", name), &json))
+                    .and_then(|_| {
+                        archive.add(&format!("{}.layout.txt", name), layout.to_text().as_bytes())
+                    })
+                    .map_err(|e| Error::write(&archive_path, e))?;
+            } else {
+                layout
+                    .write_files(&prefix)
+                    .map_err(|(path, e)| Error::write(path, e))?;
+            }
+        }
+        summary.add_model(&report);
+        match_report.add(&report.color_matches);
+        report_models.push(ReportModel {
+            name: prefix,
+            size,
+            patterns: report.patterns.len() + report.structures.len(),
+            preview: outputs.preview.map(|preview| preview.path),
+        });
+        written_patterns.extend(report.patterns);
+        bom.merge(&model_bom);
+
+        progress(i + 1, export_count);
     }
 
-    if only_air {
-        return None;
+    if let Some(archive) = archive {
+        archive
+            .finish()
+            .map_err(|e| Error::write(&archive_path, e))?;
+        if args.format == OutputFormat::Pattern && !args.no_validate && !to_stdout {
+            validate_archive(Path::new(&archive_path))
+                .map_err(|error| Error::write(&archive_path, std::io::Error::other(error)))?;
+        }
+    }
+    match &stdout_patterns[..] {
+        [] => {}
+        [pattern] => {
+            let mut stdout = std::io::stdout().lock();
+            stdout
+                .write_all(pattern)
+                .and_then(|_| stdout.flush())
+                .map_err(|e| Error::write(STDIO, e))?;
+        }
+        patterns => return Err(Error::Input(tr!("stdout-multiple", count = patterns.len()))),
+    }
+    if let Some(rows) = args.histogram.filter(|_| export_count > 1) {
+        histogram::print_histogram(&tr!("histogram-all"), &bom, rows);
+    }
+    if args.materials {
+        bom.print_table();
+    }
+    if args.clipboard {
+        let files: Vec<_> = written_patterns
+            .iter()
+            .map(|(file, chunk)| (file.as_str(), layout::block_offset(chunk.offset)))
+            .collect();
+        match SystemClipboard::open()
+            .and_then(|mut clipboard| clipboard::copy_pattern(&mut clipboard, &files, args.chunk))
+        {
+            Ok(path) => say!("{}", tr!("clipboard-copied", path = path)),
+            Err(error) => summary.warn(&error),
+        }
+    }
+    if let (Some(text_bundle), Some(path)) = (&text_bundle, &args.text_bundle) {
+        let blocks: Vec<_> = bom.entries().into_iter().map(|entry| entry.block).collect();
+        text_bundle
+            .write_file(path, output, &args.attribution, &blocks)
+            .map_err(|e| Error::write(path, e))?;
+    }
+    if let Some(bom_book) = &args.bom_book {
+        let title = file_name(output);
+        let author = args.attribution.author.as_deref();
+        std::fs::write(bom_book, book::give_command(&title, author, &bom))
+            .map_err(|e| Error::write(bom_book, e))?;
+    }
+    if let Some(bom_file) = &args.bom_file {
+        bom.write_file(Path::new(bom_file))
+            .map_err(|e| Error::write(bom_file, e))?;
+    }
+    if let Some(path) = &args.report_matches {
+        match_report
+            .write_file(path, vox_palette.colors, vox_palette.locked)
+            .map_err(|e| Error::write(path, e))?;
+    }
+    let reports = [
+        (&args.report_md, Markup::Markdown),
+        (&args.report_bbcode, Markup::BBCode),
+    ];
+    for (path, markup) in reports {
+        let Some(path) = path else {
+            continue;
+        };
+        let report = ShareReport {
+            name: &file_name(output),
+            palette: &args.palette,
+            attribution: &args.attribution,
+            models: &report_models,
+            bom: &bom,
+        };
+        if let Err(error) = report::write_report(
+            &report,
+            markup,
+            args.report_template.as_deref().map(Path::new),
+            Path::new(path),
+        ) {
+            summary.warn(&error);
+        }
     }
 
-    Some((
-        data.into_iter().map(|b| b as i8).collect(),
-        Statistics {
-            primary_state: palette.first().unwrap(),
-            block_states,
-        },
-    ))
+    Ok((written_patterns, summary.with_materials(bom)))
 }
 
-const BLOCK_SIDE: usize = 16;
-
-fn position_from_index(index: usize) -> (u8, u8, u8) {
-    let x = index / (BLOCK_SIDE * BLOCK_SIDE);
-    let y = (index - x * BLOCK_SIDE * BLOCK_SIDE) / BLOCK_SIDE;
-    let z = index - x * BLOCK_SIDE * BLOCK_SIDE - y * BLOCK_SIDE;
-    (x as u8, y as u8, z as u8)
+/// Prints the totals of the run unless it should be quiet
+fn print_summary(args: &Args, summary: &RunSummary) {
+    // Slices are only printed, no patterns are written
+    if !args.quiet && args.preview_slice.is_none() {
+        say!("{}", summary.finish());
+    }
 }
 
-const VOXEL_MAX_SIDE: usize = 255;
-
-fn index_from_position(x: u8, y: u8, z: u8) -> usize {
-    x as usize * VOXEL_MAX_SIDE * VOXEL_MAX_SIDE + y as usize * VOXEL_MAX_SIDE + z as usize
+/// Decodes a written pattern, e.g. to notice writes cut short
+fn validate_pattern(bytes: &[u8]) -> Result<(), String> {
+    decode_pattern(bytes)?.check()
 }
 
-#[derive(Serialize)]
-struct Data<'a> {
-    #[serde(rename = "chiseledData")]
-    chiseled_data: ChiselData<'a>,
-    statistics: Statistics<'a>,
+/// Reads the patterns of a written archive back and decodes them
+fn validate_archive(path: &Path) -> Result<(), String> {
+    let archive = Jar::open(path)?;
+    let extension = OutputFormat::Pattern.extension();
+    for name in archive.names().filter(|name| name.ends_with(extension)) {
+        let bytes = archive.read(name)?.unwrap_or_default();
+        decode_pattern(&bytes)
+            .and_then(|pattern| pattern.check())
+            .map_err(|error| tr!("file-error", file = name, error = error))?;
+    }
+    Ok(())
 }
 
-#[derive(Serialize)]
-struct ChiselData<'a> {
-    data: ByteArray,
-    palette: &'a [PaletteEntry],
+/// Whether the patterns are written into one zip archive instead of separate files
+fn writes_archive(args: &Args) -> bool {
+    args.archive || args.output.ends_with(".zip")
 }
 
-#[derive(Serialize)]
-struct Statistics<'a> {
-    #[serde(rename = "primaryState")]
-    primary_state: &'a PaletteEntry,
-    #[serde(rename = "blockStates")]
-    block_states: Vec<BlockState<'a>>,
+/// Last component of a path, e.g. the name of a pattern inside an archive
+fn file_name(path: &str) -> Cow<'_, str> {
+    Path::new(path)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
 }
 
-#[derive(Serialize)]
-struct BlockState<'a> {
-    block_information: &'a PaletteEntry,
-    count: u32,
+/// Appends a number to the file stem, e.g. `preview.png` becomes `preview_1.png`
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, number),
+    };
+    path.with_file_name(file_name)
 }
 
-#[derive(Serialize, Clone)]
-struct PaletteEntry {
-    state: String,
+/// Optional files created for a model next to its patterns
+#[derive(Default)]
+struct ModelOutputs {
+    preview: Option<PreviewOptions>,
+    compare: Option<CompareOptions>,
+    build_guide: Option<PathBuf>,
+    /// Print a layer to the terminal instead of writing patterns
+    slice: Option<SliceSpec>,
+    html_preview: Option<PathBuf>,
+    /// Credit embedded into the previews
+    attribution: Attribution,
+    /// Largest pattern file, larger patterns are compressed better or lose blocks
+    pattern_limit: Option<PatternLimit>,
+    /// Spread the color error of every voxel over its neighbors
+    dither: bool,
+    /// Emission from which emit materials use the emit blocks of the palette
+    emission_threshold: f32,
+    /// How voxels of colors with an alpha below the threshold are converted
+    alpha_mode: AlphaMode,
+    alpha_threshold: u8,
+    format: OutputFormat,
+    naming: Naming,
+    /// Split structures into parts that structure blocks can load
+    split_structures: bool,
+    /// Minecraft version structures and schematics are written for
+    data_version: i32,
+    /// Write patterns of only air for chunks without voxels
+    keep_empty: bool,
+    /// Version of Chisels & Bits the patterns are written for
+    target: Target,
+    compression: Option<CompressionLevel>,
+    /// Most colors of a model, more are merged
+    max_colors: Option<usize>,
+    /// Directory the stages of every pattern are written into
+    debug_dump: Option<PathBuf>,
+    /// Show how far the chunks are on stderr, if it is a terminal
+    progress: bool,
 }
 
-#[derive(Serialize)]
-struct DataContainer {
-    version: u32,
-    data: CompressedData,
+/// Colors of a Magica Voxel file with the materials given to them
+#[derive(Clone, Copy)]
+struct VoxPalette<'a> {
+    colors: &'a [dot_vox::Color],
+    materials: &'a [dot_vox::Material],
+    /// Color index enclosed air is filled with, given by --fill-color
+    fill_color: Option<u8>,
+    /// Blocks of the palette indices given by --index-map and the locked entries of --overrides
+    index_map: Option<&'a IndexMap>,
+    /// Palette indices locked by --overrides, from 1 as Magica Voxel shows them
+    locked: &'a BTreeSet<u8>,
 }
 
-#[derive(Serialize)]
-struct CompressedData {
-    data: ByteArray,
-    compressed: u8,
+const PATTERN_EXTENSION: &str = ".cbsbp";
+/// Palette section of the blocks for emissive Magica Voxel materials
+const EMIT_MATERIAL: &str = "emit";
+/// Model file or output standing for stdin and stdout
+const STDIO: &str = "-";
+/// Name of the patterns written to stdout
+const STDOUT_PREFIX: &str = "pattern";
+/// Voxels in the difference panel of a comparison that exceed the threshold
+const DIFFERENCE_COLOR: Pixel = [220, 0, 0, 255];
+const MATCH_COLOR: Pixel = [200, 200, 200, 255];
+/// Approximate texture colors of vanilla blocks, used to suggest palette additions
+const VANILLA_BLOCKS: &[u8] = include_bytes!("vanilla_blocks.json");
+
+fn create_patterns(
+    model: &dot_vox::Model,
+    block_palette: &BlockPalette,
+    vox_palette: VoxPalette,
+    path_prefix: &str,
+    bom: &mut BillOfMaterials,
+    outputs: &ModelOutputs,
+    save_pattern: &mut dyn FnMut(&str, &[u8]) -> std::io::Result<()>,
+) -> Result<ModelReport, Error> {
+    let mut converter = Converter::new(block_palette)
+        .with_dither(outputs.dither)
+        .with_materials(vox_palette.materials, outputs.emission_threshold)
+        .with_alpha(outputs.alpha_mode, outputs.alpha_threshold)
+        .with_empty_chunks(outputs.keep_empty)
+        .with_target(outputs.target);
+    if let Some(CompressionLevel(level)) = outputs.compression {
+        converter = converter.with_compression(level);
+    }
+    if let Some(max_colors) = outputs.max_colors {
+        converter = converter.with_max_colors(max_colors);
+    }
+    if let Some(index_map) = vox_palette.index_map {
+        converter = converter.with_index_map(index_map);
+    }
+    if let Some(limit) = outputs.pattern_limit {
+        converter = converter.with_pattern_limit(limit);
+    }
+    let mut block_model = converter.match_blocks(model, vox_palette.colors);
+    let mut report = ModelReport {
+        color_matches: std::mem::take(&mut block_model.color_matches),
+        color_reduction: block_model.color_reduction,
+        warnings: std::mem::take(&mut block_model.warnings),
+        ..ModelReport::default()
+    };
+
+    let voxels = block_model.voxels();
+    let size = block_model.size();
+    let size = [size.x, size.y, size.z];
+    let preview_voxels = || -> Vec<_> {
+        voxels
+            .iter()
+            .map(|voxel| PreviewVoxel {
+                position: [voxel.x as u32, voxel.y as u32, voxel.z as u32],
+                color: block_model.block(voxel).pixel(),
+            })
+            .collect()
+    };
+    if let Some(preview) = &outputs.preview {
+        preview::render_isometric(&preview_voxels(), size, preview.image_size)
+            .save_png_with_text(&preview.path, &outputs.attribution.png_text())
+            .map_err(|e| Error::write(&preview.path, e))?;
+    }
+    if let Some(compare) = &outputs.compare {
+        let recolored = |color: &dyn Fn(&dot_vox::Voxel) -> Pixel| -> Vec<_> {
+            voxels
+                .iter()
+                .map(|voxel| PreviewVoxel {
+                    position: [voxel.x as u32, voxel.y as u32, voxel.z as u32],
+                    color: color(voxel),
+                })
+                .collect()
+        };
+        let original = recolored(&|voxel| {
+            let color = vox_palette.colors[voxel.i as usize];
+            [color.r, color.g, color.b, 255]
+        });
+        let blocks = preview_voxels();
+        let mut panels = vec![("model", &original[..]), ("blocks", &blocks[..])];
+        let difference = compare.threshold.map(|threshold| {
+            recolored(&|voxel| {
+                if block_model.delta_e(voxel) > threshold {
+                    DIFFERENCE_COLOR
+                } else {
+                    MATCH_COLOR
+                }
+            })
+        });
+        if let Some(difference) = &difference {
+            panels.push(("difference", difference));
+        }
+        preview::render_comparison(&panels, size, compare.image_size)
+            .save_png_with_text(&compare.path, &outputs.attribution.png_text())
+            .map_err(|e| Error::write(&compare.path, e))?;
+    }
+    if outputs.build_guide.is_some() || outputs.html_preview.is_some() {
+        // Number the distinct blocks of the model, several colors can map to the same block
+        let mut legend_blocks = block_model.blocks().to_vec();
+        legend_blocks.sort_by(|l, r| l.name.cmp(&r.name));
+        legend_blocks.dedup_by(|l, r| l.name == r.name);
+        let legend: Vec<_> = legend_blocks
+            .iter()
+            .map(|block| LegendEntry {
+                name: &block.name,
+                color: block.pixel(),
+            })
+            .collect();
+        let guide_voxels: Vec<_> = voxels
+            .iter()
+            .map(|voxel| GuideVoxel {
+                position: [voxel.x as u32, voxel.y as u32, voxel.z as u32],
+                legend_index: legend_blocks
+                    .binary_search_by(|block| block.name.cmp(&block_model.block(voxel).name))
+                    .unwrap(),
+            })
+            .collect();
+
+        if let Some(directory) = &outputs.build_guide {
+            guide::write_build_guide(directory, size, &guide_voxels, &legend)
+                .map_err(|e| Error::write(directory, e))?;
+        }
+        if let Some(path) = &outputs.html_preview {
+            html::write_html_preview(
+                path,
+                path_prefix,
+                size,
+                &guide_voxels,
+                &legend,
+                &outputs.attribution,
+            )
+            .map_err(|e| Error::write(path, e))?;
+        }
+    }
+    if let Some(spec) = outputs.slice {
+        if let Err(warning) = slice::print_slice(spec, size, &preview_voxels()) {
+            report.warnings.push(warning);
+        }
+        return Ok(report);
+    }
+
+    if outputs.format != OutputFormat::Pattern {
+        // Schematics can be pasted at any size, only structure blocks have a limit
+        let split = outputs.format == OutputFormat::Structure && outputs.split_structures;
+        let structures = structure::split_model(&block_model, split);
+        let one_structure = structures.len() == 1;
+        for (index, structure) in structures.iter().enumerate() {
+            let output_file = if one_structure {
+                format!("{}{}", path_prefix, outputs.format.extension())
+            } else {
+                format!("{}_{}{}", path_prefix, index, outputs.format.extension())
+            };
+            let blocks: Vec<_> = structure
+                .blocks()
+                .map(|block| block.name.as_str())
+                .collect();
+            let description = tr!(
+                "structure-report",
+                blocks = blocks.len(),
+                states = structure.state_count(),
+                offset = format!("{:?}", structure.offset),
+            );
+            bom.add_blocks(blocks);
+            let data = match outputs.format {
+                OutputFormat::Schem => schematic::encode(structure, outputs.data_version),
+                OutputFormat::Litematic => {
+                    // Named like the file, e.g. `castle` for `castle.litematic`
+                    let name = Path::new(&output_file).file_stem().unwrap_or_default();
+                    litematic::encode(
+                        structure,
+                        &name.to_string_lossy(),
+                        &outputs.attribution,
+                        outputs.data_version,
+                    )
+                }
+                _ => structure.to_nbt(outputs.data_version),
+            };
+            save_pattern(&output_file, &data).map_err(|e| Error::write(&output_file, e))?;
+            report.structures.push((output_file, description));
+        }
+        return Ok(report);
+    }
+
+    report
+        .skipped_chunks
+        .extend(SkippedChunk::of_model(&block_model));
+
+    // Create a pattern for each block sized chunk, named by its block or numbered in chunk
+    // order
+    let one_pattern = block_model.chunks().len() == 1;
+    let encoded_chunks = block_model
+        .chunks()
+        .iter()
+        .filter(|chunk| block_model.is_encoded(chunk));
+    let progress = Progress::new(
+        path_prefix,
+        encoded_chunks.clone().count(),
+        report.skipped_chunks.len(),
+        outputs.progress,
+    );
+    let patterns = block_model.encode_all_with_progress(&|count| progress.encoded(count));
+    let patterns = encoded_chunks.zip(patterns).enumerate();
+    for (index, (chunk, pattern)) in patterns {
+        let output_file = if one_pattern {
+            format!("{}{}", path_prefix, PATTERN_EXTENSION)
+        } else {
+            match outputs.naming {
+                Naming::Coordinates => {
+                    let [x, y, z] = layout::block_offset(chunk.offset);
+                    let suffix = format!("x{}_y{}_z{}", x, y, z);
+                    format!("{}_{}{}", path_prefix, suffix, PATTERN_EXTENSION)
+                }
+                Naming::Index => format!("{}_{}{}", path_prefix, index, PATTERN_EXTENSION),
+            }
+        };
+        let pattern = pattern
+            .map_err(|error| Error::Output(tr!("file-error", file = output_file, error = error)))?;
+        if let Some(reduction) = &pattern.reduction {
+            report.warnings.push(reduction.describe(&output_file));
+        }
+        if let Some(error) = &pattern.oversize {
+            report
+                .warnings
+                .push(tr!("pattern-limit-kept", file = output_file, error = error));
+        }
+        bom.add_chunk(
+            pattern
+                .block_counts
+                .iter()
+                .map(|(block, count)| (block.as_str(), *count)),
+        );
+
+        save_pattern(&output_file, &pattern.bytes).map_err(|e| Error::write(&output_file, e))?;
+        if let Some(directory) = &outputs.debug_dump {
+            dump::dump_pattern(directory, &output_file, &pattern.bytes).map_err(|error| {
+                Error::Output(tr!("debug-dump-failed", file = output_file, error = error))
+            })?;
+        }
+        progress.written();
+        report.patterns.push((output_file, pattern.report));
+    }
+
+    Ok(report)
 }
 
-#[derive(Serialize)]
-struct PatternFile {
-    #[serde(rename = "chiselData")]
-    chisel_data: String,
-    version: &'static str,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{colors, model, vox_file, with_index_map};
+
+    /// Red stored at the first index and blue at the second, shown the other way around in
+    /// Magica Voxel
+    fn reordered_file() -> Vec<u8> {
+        let model = model([2, 1, 1], &[[0, 0, 0, 0], [1, 0, 0, 1]]);
+        let mut index_map: [u8; 256] = std::array::from_fn(|position| (position + 1) as u8);
+        index_map.swap(0, 1);
+        with_index_map(
+            vox_file(&[model], &colors(&[[255, 0, 0], [0, 51, 204]])),
+            &index_map,
+        )
+    }
+
+    /// Block states of the pattern converted from the file with the extra arguments, without
+    /// air
+    fn converted_blocks(file: &[u8], extra: &[&str]) -> Vec<String> {
+        let directory = tempfile::tempdir().unwrap();
+        let model_path = directory.path().join("dragon.vox");
+        std::fs::write(&model_path, file).unwrap();
+        let mut arguments = vec![
+            "chisels_and_importers".into(),
+            model_path.into_os_string(),
+            "--palette".into(),
+            concat!(env!("CARGO_MANIFEST_DIR"), "/blocks.json").into(),
+            "--output-dir".into(),
+            directory.path().as_os_str().to_owned(),
+        ];
+        arguments.extend(extra.iter().map(Into::into));
+        let args = Args::parse_from(arguments);
+        let patterns = convert(&args, &mut |_, _| {}).unwrap();
+        assert_eq!(patterns.len(), 1);
+
+        let pattern = std::fs::read(&patterns[0]).unwrap();
+        let pattern = chisels_and_importers::decode::decode_pattern(&pattern).unwrap();
+        pattern.check().unwrap();
+        pattern
+            .palette
+            .iter()
+            .zip(pattern.counts())
+            .filter(|(state, count)| *count > 0 && !state.contains("minecraft:air"))
+            .map(|(state, _)| state.clone())
+            .collect()
+    }
+
+    fn assert_blocks(states: &[String], blocks: &[&str]) {
+        assert_eq!(states.len(), blocks.len(), "{:?}", states);
+        for block in blocks {
+            assert!(
+                states.iter().any(|state| state.contains(block)),
+                "{:?}",
+                states
+            );
+        }
+    }
+
+    #[test]
+    fn reordered_palette_keeps_stored_colors() {
+        let file = reordered_file();
+        assert!(palette_reordered(
+            &dot_vox::load_bytes(&file).unwrap().index_map
+        ));
+        let states = converted_blocks(&file, &[]);
+        assert_blocks(&states, &["minecraft:red_wool", "minecraft:blue_wool"]);
+    }
+
+    #[test]
+    fn index_map_of_reordered_palette_uses_stored_order() {
+        let directory = tempfile::tempdir().unwrap();
+        let index_map = directory.path().join("index_map.json");
+        std::fs::write(&index_map, r#"{ "1": "minecraft:white_wool" }"#).unwrap();
+
+        let states = converted_blocks(
+            &reordered_file(),
+            &["--index-map", index_map.to_str().unwrap()],
+        );
+        assert_blocks(&states, &["minecraft:white_wool", "minecraft:blue_wool"]);
+    }
+
+    #[test]
+    fn identity_index_maps_are_not_reordered() {
+        let shifted: Vec<u8> = (0..=255u8).map(|index| index.wrapping_add(1)).collect();
+        let unshifted: Vec<u8> = (0..=255u8).collect();
+        assert!(!palette_reordered(&[]));
+        assert!(!palette_reordered(&shifted));
+        assert!(!palette_reordered(&unshifted));
+
+        let file = vox_file(
+            &[model([1, 1, 1], &[[0, 0, 0, 0]])],
+            &colors(&[[255, 0, 0]]),
+        );
+        assert!(dot_vox::load_bytes(&file).unwrap().index_map.is_empty());
+    }
+
+    #[test]
+    fn files_of_a_run_share_matched_colors() {
+        let directory = tempfile::tempdir().unwrap();
+        let palette = colors(&[[255, 0, 0], [0, 0, 255], [0, 255, 0]]);
+        let first = directory.path().join("first.vox");
+        let second = directory.path().join("second.vox");
+        let voxels = [[0, 0, 0, 0], [1, 0, 0, 1], [2, 0, 0, 2]];
+        let first_file = vox_file(&[model([3, 1, 1], &voxels[..2])], &palette);
+        std::fs::write(&first, first_file).unwrap();
+        std::fs::write(&second, vox_file(&[model([3, 1, 1], &voxels)], &palette)).unwrap();
+        let mut args = Args::parse_from([
+            "chisels_and_importers".as_ref(),
+            first.as_os_str(),
+            "--palette".as_ref(),
+            concat!(env!("CARGO_MANIFEST_DIR"), "/blocks.json").as_ref(),
+            "--output-dir".as_ref(),
+            directory.path().as_os_str(),
+        ]);
+        let resources = Resources::load(&args).unwrap();
+
+        convert_with(&args, &resources, &mut |_, _| {}).unwrap();
+        let matched = resources.block_palette.cached_matches();
+        assert!(matched > 0);
+        args.model = Some(second.to_string_lossy().into_owned());
+        convert_with(&args, &resources, &mut |_, _| {}).unwrap();
+        // Only the color the first file doesn't have is matched again
+        assert_eq!(resources.block_palette.cached_matches(), matched + 1);
+    }
+
+    #[test]
+    fn summaries_of_converted_files_are_added_up() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = vox_file(
+            &[model([1, 1, 1], &[[0, 0, 0, 0]])],
+            &colors(&[[255, 0, 0]]),
+        );
+        let files = ["a.vox", "b.vox", "c.vox"].map(|name| directory.path().join(name));
+        std::fs::write(&files[0], &file).unwrap();
+        std::fs::write(&files[1], b"not a model").unwrap();
+        std::fs::write(&files[2], &file).unwrap();
+        let args = Args::parse_from([
+            "chisels_and_importers",
+            &directory.path().to_string_lossy(),
+            "--palette",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/blocks.json"),
+            "--jobs",
+            "2",
+            "--quiet",
+        ]);
+        let resources = Resources::load(&args).unwrap();
+
+        let (total, failures) = convert_files(&args, &resources, &files, directory.path()).unwrap();
+        let line = total.finish().to_string();
+        assert!(
+            line.starts_with("2 patterns written, 0 skipped, 2 bits of 1 blocks"),
+            "{}",
+            line
+        );
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(failures[0], Error::Input(_)));
+    }
+
+    /// Most bytes allocated at once on top of the parsed models while exporting every one of
+    /// `count` copies of a dense model on the thread pool
+    fn export_peak(pool: &rayon::ThreadPool, count: usize) -> usize {
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().join("castle");
+        let args = Args::parse_from([
+            "chisels_and_importers",
+            "castle.vox",
+            "--output",
+            &output.to_string_lossy(),
+            "--quiet",
+        ]);
+        let block_palette = crate::testing::palette();
+        let voxels: Vec<_> = (0..32u8)
+            .flat_map(|x| (0..32u8).flat_map(move |y| (0..32u8).map(move |z| [x, y, z, z % 4])))
+            .collect();
+        let mut models = vec![model([32, 32, 32], &voxels); count];
+        let colors = colors(&[[255, 0, 0], [0, 0, 255], [0, 255, 0], [255, 255, 255]]);
+        let indices: Vec<_> = (0..count).collect();
+        let (_, peak) = crate::testing::peak_allocation(|| {
+            pool.install(|| {
+                export_models(
+                    &args,
+                    &block_palette,
+                    &mut models,
+                    VoxPalette {
+                        colors: &colors,
+                        materials: &[],
+                        fill_color: None,
+                        index_map: None,
+                        locked: &BTreeSet::new(),
+                    },
+                    &indices,
+                    ModelOrigin {
+                        source_sha256: None,
+                        sources: None,
+                    },
+                    &mut |_, _| {},
+                )
+                .unwrap()
+            })
+        });
+        peak
+    }
+
+    #[test]
+    fn exporting_all_models_needs_memory_for_one() {
+        // Encode on this thread, allocations are only counted for the thread making them
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .use_current_thread()
+            .build()
+            .unwrap();
+        let one = export_peak(&pool, 1);
+        let ten = export_peak(&pool, 10);
+        assert!(
+            ten < one + one / 4,
+            "{} bytes for one model, {} for ten",
+            one,
+            ten
+        );
+    }
 }
@@ -0,0 +1,78 @@
+//! Colors whose voxels are left out with `--ignore-colors`, e.g. construction guides or the
+//! ground plane of quick exports
+
+use std::str::FromStr;
+
+use dot_vox::DotVoxData;
+use palette::Srgb;
+use serde::{Serialize, Serializer};
+
+use crate::{i18n::tr, quality::hex};
+
+/// Difference of every channel up to which a color counts as the given one
+const TOLERANCE: u8 = 4;
+
+/// Palette index as Magica Voxel numbers them (1 to 255) or a color code like `#ff00ff`
+#[derive(Debug, Clone, Copy)]
+pub enum IgnoredColor {
+    Index(u8),
+    Color(Srgb<u8>),
+}
+
+impl IgnoredColor {
+    fn matches(&self, index: u8, color: dot_vox::Color) -> bool {
+        match *self {
+            // Indices in the file start at 1
+            IgnoredColor::Index(ignored) => index as u16 + 1 == ignored as u16,
+            IgnoredColor::Color(ignored) => [
+                (ignored.red, color.r),
+                (ignored.green, color.g),
+                (ignored.blue, color.b),
+            ]
+            .iter()
+            .all(|&(a, b)| a.abs_diff(b) <= TOLERANCE),
+        }
+    }
+}
+
+impl FromStr for IgnoredColor {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.starts_with('#') {
+            return Srgb::from_str(value)
+                .map(IgnoredColor::Color)
+                .map_err(|_| tr!("color-code-invalid", color = value));
+        }
+        match value.parse::<u8>() {
+            Ok(index) if index >= 1 => Ok(IgnoredColor::Index(index)),
+            _ => Err(tr!("ignored-color-invalid", value = value)),
+        }
+    }
+}
+
+impl Serialize for IgnoredColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            IgnoredColor::Index(index) => serializer.serialize_u8(*index),
+            IgnoredColor::Color(color) => serializer.serialize_str(&hex(*color)),
+        }
+    }
+}
+
+/// Removes the voxels of the ignored colors from every model and returns how many there were
+pub fn remove(data: &mut DotVoxData, ignored: &[IgnoredColor]) -> usize {
+    let masked: Vec<bool> = (0..=u8::MAX)
+        .map(|index| {
+            let color = data.palette.get(index as usize).copied();
+            color.is_some_and(|color| ignored.iter().any(|ignored| ignored.matches(index, color)))
+        })
+        .collect();
+    let mut removed = 0;
+    for model in &mut data.models {
+        let count = model.voxels.len();
+        model.voxels.retain(|voxel| !masked[voxel.i as usize]);
+        removed += count - model.voxels.len();
+    }
+    removed
+}
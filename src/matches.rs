@@ -0,0 +1,134 @@
+//! The block every color of the model became, written with `--report-matches` and read back
+//! with `--overrides` to keep the blocks picked by hand
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use chisels_and_importers::ColorMatch;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::i18n::tr;
+
+/// Version of the match file format
+const MATCHES_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct MatchFile {
+    version: u32,
+    /// Entries by palette index as Magica Voxel numbers them, from 1 to 255
+    matches: BTreeMap<u8, MatchEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MatchEntry {
+    /// Color of the index with its alpha, like `#ff000080`
+    #[serde(default)]
+    rgba: String,
+    /// Block most voxels of the color became
+    block: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    properties: BTreeMap<String, String>,
+    /// CIEDE2000 difference between the color and the block
+    #[serde(default)]
+    delta_e: f32,
+    #[serde(default)]
+    voxels: u32,
+    /// Set by hand to keep the block in later conversions instead of matching the color
+    #[serde(default)]
+    locked: bool,
+}
+
+/// Block of a palette index with its block state properties
+type MatchedBlock = (String, BTreeMap<String, String>);
+
+/// Matches of all converted models, by color index
+#[derive(Default)]
+pub struct MatchReport {
+    /// Difference and number of voxels of every block a color index became
+    blocks: BTreeMap<u8, BTreeMap<MatchedBlock, (f32, u32)>>,
+}
+
+impl MatchReport {
+    pub fn add(&mut self, color_matches: &[ColorMatch]) {
+        for color_match in color_matches {
+            let block = (color_match.block.clone(), color_match.properties.clone());
+            let entry = self
+                .blocks
+                .entry(color_match.index)
+                .or_default()
+                .entry(block)
+                .or_insert((color_match.delta_e, 0));
+            entry.1 += color_match.voxels;
+        }
+    }
+
+    /// Writes the block most voxels of every color index became, colors that several blocks
+    /// were dithered with count all their voxels. Indices locked by `--overrides` are marked
+    /// as locked again, so the file can be edited and read back as often as needed.
+    pub fn write_file(
+        &self,
+        path: &Path,
+        colors: &[dot_vox::Color],
+        locked: &BTreeSet<u8>,
+    ) -> std::io::Result<()> {
+        let matches = self
+            .blocks
+            .iter()
+            .filter_map(|(&index, blocks)| {
+                let ((block, properties), &(delta_e, _)) = blocks
+                    .iter()
+                    .max_by_key(|(block, &(_, voxels))| (voxels, std::cmp::Reverse(*block)))?;
+                let color = colors[index as usize];
+                // Indices in the file start at 1, the last one only holds a --fill-color
+                let index = index.checked_add(1)?;
+                let entry = MatchEntry {
+                    rgba: format!(
+                        "#{:02x}{:02x}{:02x}{:02x}",
+                        color.r, color.g, color.b, color.a
+                    ),
+                    block: block.clone(),
+                    properties: properties.clone(),
+                    delta_e: (delta_e * 100.0).round() / 100.0,
+                    voxels: blocks.values().map(|&(_, voxels)| voxels).sum(),
+                    locked: locked.contains(&index),
+                };
+                Some((index, entry))
+            })
+            .collect();
+        let file = MatchFile {
+            version: MATCHES_VERSION,
+            matches,
+        };
+        let mut output = serde_json::to_vec_pretty(&file)?;
+        output.push(b'\n');
+        std::fs::write(path, output)
+    }
+}
+
+/// Entries of a match file that are locked, as entries of an index map
+pub fn locked_entries(data: &[u8]) -> Result<Vec<(String, Value)>, String> {
+    let file: MatchFile = serde_json::from_slice(data).map_err(|e| e.to_string())?;
+    if file.version != MATCHES_VERSION {
+        return Err(tr!(
+            "matches-version-unsupported",
+            version = file.version,
+            supported = MATCHES_VERSION
+        ));
+    }
+    Ok(file
+        .matches
+        .into_iter()
+        .filter(|(_, entry)| entry.locked)
+        .map(|(index, entry)| {
+            let block = if entry.properties.is_empty() {
+                Value::String(entry.block)
+            } else {
+                serde_json::json!({ "name": entry.block, "properties": entry.properties })
+            };
+            (index.to_string(), block)
+        })
+        .collect())
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{i18n::tr, summary::SkippedChunk, Args, BlockPalette, PATTERN_EXTENSION};
+
+/// Version of the metadata file schema
+const METADATA_VERSION: u32 = 3;
+
+/// Time since the Unix epoch written into files, `SOURCE_DATE_EPOCH` in seconds if it is set
+/// so that repeated runs write the same bytes, otherwise the current time
+pub fn creation_time() -> Duration {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|seconds| seconds.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+        })
+}
+
+/// Provenance of the patterns created for one model
+#[derive(Serialize)]
+pub struct Metadata<'a> {
+    version: u32,
+    tool: Tool,
+    source: Source<'a>,
+    palette: Palette<'a>,
+    /// Options the conversion was run with, including defaults
+    options: &'a Args,
+    /// Seconds since the Unix epoch
+    timestamp: u64,
+    /// Pattern files written for the model
+    patterns: Vec<PatternHash>,
+    /// Chunks of the model no pattern was written for
+    skipped: &'a [SkippedChunk],
+}
+
+/// Pattern file listed in the metadata, the name is relative to the metadata file
+#[derive(Serialize, Deserialize)]
+struct PatternHash {
+    file: String,
+    sha256: String,
+}
+
+/// Part of the metadata needed to verify the patterns
+#[derive(Deserialize)]
+struct Manifest {
+    version: u32,
+    patterns: Vec<PatternHash>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    name: &'static str,
+    version: &'static str,
+    /// `git describe` of the commit the tool was built from
+    build: &'static str,
+}
+
+#[derive(Serialize)]
+struct Source<'a> {
+    path: &'a str,
+    sha256: &'a str,
+    /// Index of the model in the file, starting at 1
+    model: usize,
+}
+
+#[derive(Serialize)]
+struct Palette<'a> {
+    path: &'a str,
+    fingerprint: String,
+}
+
+impl<'a> Metadata<'a> {
+    pub fn new(
+        args: &'a Args,
+        source_sha256: &'a str,
+        model_index: usize,
+        block_palette: &BlockPalette,
+        patterns: &[&str],
+        skipped: &'a [SkippedChunk],
+    ) -> io::Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|path| {
+                let path = Path::new(path);
+                Ok(PatternHash {
+                    file: path.file_name().unwrap().to_string_lossy().into_owned(),
+                    sha256: sha256_file(path)?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            version: METADATA_VERSION,
+            tool: Tool {
+                name: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+                build: env!("GIT_DESCRIBE"),
+            },
+            source: Source {
+                path: args.model.as_deref().unwrap_or_default(),
+                sha256: source_sha256,
+                model: model_index + 1,
+            },
+            palette: Palette {
+                path: &args.palette,
+                fingerprint: palette_fingerprint(block_palette),
+            },
+            options: args,
+            timestamp: creation_time().as_secs(),
+            patterns,
+            skipped,
+        })
+    }
+
+    pub fn write_file(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Hashes a file without loading it into memory at once
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(File::open(path)?), &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Difference between the patterns listed in a metadata file and the files next to it
+#[derive(Debug, PartialEq)]
+enum Problem {
+    /// Listed but not found
+    Missing(String),
+    /// Found with a different hash than listed
+    Changed(String),
+    /// Named like a pattern of the model but not listed
+    Extra(String),
+}
+
+/// Checks the pattern files next to a metadata file against the hashes it lists and
+/// reports missing, changed and unlisted patterns. Returns whether all patterns match.
+pub fn verify(manifest_path: &Path) -> io::Result<bool> {
+    let manifest: Manifest = serde_json::from_reader(BufReader::new(File::open(manifest_path)?))?;
+    if manifest.version < 2 {
+        eprintln!("{}", tr!("verify-outdated", file = manifest_path.display()));
+        return Ok(false);
+    }
+
+    let problems = problems(manifest_path, &manifest)?;
+    for problem in &problems {
+        let message = match problem {
+            Problem::Missing(file) => tr!("verify-missing", file = file),
+            Problem::Changed(file) => tr!("verify-changed", file = file),
+            Problem::Extra(file) => tr!("verify-extra", file = file),
+        };
+        println!("{}", message);
+    }
+    if problems.is_empty() {
+        println!("{}", tr!("verify-ok", count = manifest.patterns.len()));
+    }
+    Ok(problems.is_empty())
+}
+
+/// Listed patterns that are missing or changed in listing order, followed by unlisted
+/// patterns of the same model
+fn problems(manifest_path: &Path, manifest: &Manifest) -> io::Result<Vec<Problem>> {
+    let directory = manifest_path.parent().unwrap_or(Path::new(""));
+    let mut problems = Vec::new();
+    for pattern in &manifest.patterns {
+        let path = directory.join(&pattern.file);
+        if !path.exists() {
+            problems.push(Problem::Missing(pattern.file.clone()));
+        } else if sha256_file(&path)? != pattern.sha256 {
+            problems.push(Problem::Changed(pattern.file.clone()));
+        }
+    }
+
+    // Patterns of the same model that are not listed, e.g. left over from an older conversion
+    let listed: BTreeSet<_> = manifest.patterns.iter().map(|p| p.file.as_str()).collect();
+    let manifest_name = manifest_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let prefix = manifest_name
+        .strip_suffix(".meta.json")
+        .unwrap_or(&manifest_name);
+    let mut extra = Vec::new();
+    for entry in std::fs::read_dir(if directory.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        directory
+    })? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if is_pattern_of(&name, prefix) && !listed.contains(name.as_str()) {
+            extra.push(name);
+        }
+    }
+    // The order of directory entries differs between file systems
+    extra.sort();
+    problems.extend(extra.into_iter().map(Problem::Extra));
+    Ok(problems)
+}
+
+/// Whether the file is named like a pattern written with the given prefix, either
+/// `<prefix>.cbsbp`, `<prefix>_x<x>_y<y>_z<z>.cbsbp` or `<prefix>_<index>.cbsbp`
+fn is_pattern_of(name: &str, prefix: &str) -> bool {
+    let Some(rest) = name
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_suffix(PATTERN_EXTENSION))
+    else {
+        return false;
+    };
+    let number = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+    let Some(suffix) = rest.strip_prefix('_') else {
+        return rest.is_empty();
+    };
+    let coordinates: Vec<_> = suffix.split('_').collect();
+    number(suffix)
+        || matches!(coordinates[..], [x, y, z] if [("x", x), ("y", y), ("z", z)]
+            .iter()
+            .all(|(axis, part)| part.strip_prefix(axis).is_some_and(number)))
+}
+
+/// Hash of the color to block mapping, independent of the formatting and order of the
+/// palette file. Blocks of material sections are prefixed with the material type.
+fn palette_fingerprint(block_palette: &BlockPalette) -> String {
+    let sections = std::iter::once((String::new(), block_palette)).chain(
+        block_palette
+            .materials
+            .iter()
+            .map(|(kind, section)| (format!("{} ", kind), section)),
+    );
+    let mut entries: Vec<_> = sections
+        .flat_map(|(prefix, palette)| {
+            palette.mapping.iter().map(move |block| {
+                let color = block.color;
+                // Weights change the matching, blocks without weight and properties keep
+                // the fingerprint of older versions
+                let weight = if block.weight == 1.0 {
+                    String::new()
+                } else {
+                    format!(" x{}", block.weight)
+                };
+                let properties = if block.properties.is_empty() {
+                    String::new()
+                } else {
+                    let properties: Vec<_> = block
+                        .properties
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", key, value))
+                        .collect();
+                    format!("[{}]", properties.join(","))
+                };
+                format!(
+                    "{}#{:02x}{:02x}{:02x} {}{}{}\n",
+                    prefix, color.red, color.green, color.blue, block.name, properties, weight
+                )
+            })
+        })
+        .collect();
+    entries.sort();
+    sha256_hex(entries.concat().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    /// SHA-256 of `abc` from FIPS 180-2
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    /// Writes two patterns of `castle` and their metadata, returns the metadata path
+    fn converted(directory: &Path) -> std::path::PathBuf {
+        let first = directory.join("castle_0.cbsbp");
+        let second = directory.join("castle_1.cbsbp");
+        std::fs::write(&first, "abc").unwrap();
+        std::fs::write(&second, "def").unwrap();
+
+        let args = Args::parse_from(["chisels_and_importers", "castle.vox"]);
+        let palette = crate::testing::palette();
+        let patterns = [first.to_str().unwrap(), second.to_str().unwrap()];
+        let path = directory.join("castle.meta.json");
+        Metadata::new(&args, "", 0, &palette, &patterns, &[])
+            .unwrap()
+            .write_file(&path)
+            .unwrap();
+        path
+    }
+
+    fn problems_of(path: &Path) -> Vec<Problem> {
+        let manifest = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        problems(path, &manifest).unwrap()
+    }
+
+    #[test]
+    fn lists_sha256_of_every_pattern() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = converted(directory.path());
+        let metadata: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(
+            metadata["patterns"],
+            serde_json::json!([
+                { "file": "castle_0.cbsbp", "sha256": ABC_SHA256 },
+                { "file": "castle_1.cbsbp", "sha256": sha256_hex(b"def") },
+            ])
+        );
+        assert_eq!(sha256_hex(b"abc"), ABC_SHA256);
+    }
+
+    #[test]
+    fn unchanged_patterns_verify() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = converted(directory.path());
+        // Files of other models and other kinds don't count
+        std::fs::write(directory.path().join("tower_0.cbsbp"), "").unwrap();
+        std::fs::write(directory.path().join("castle.png"), "").unwrap();
+        assert_eq!(problems_of(&path), []);
+        assert!(verify(&path).unwrap());
+    }
+
+    #[test]
+    fn reports_missing_pattern() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = converted(directory.path());
+        std::fs::remove_file(directory.path().join("castle_1.cbsbp")).unwrap();
+        assert_eq!(
+            problems_of(&path),
+            [Problem::Missing("castle_1.cbsbp".to_owned())]
+        );
+        assert!(!verify(&path).unwrap());
+    }
+
+    #[test]
+    fn reports_changed_pattern() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = converted(directory.path());
+        std::fs::write(directory.path().join("castle_0.cbsbp"), "abd").unwrap();
+        assert_eq!(
+            problems_of(&path),
+            [Problem::Changed("castle_0.cbsbp".to_owned())]
+        );
+    }
+
+    #[test]
+    fn reports_extra_patterns() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = converted(directory.path());
+        std::fs::write(directory.path().join("castle_x0_y16_z0.cbsbp"), "").unwrap();
+        std::fs::write(directory.path().join("castle_2.cbsbp"), "").unwrap();
+        assert_eq!(
+            problems_of(&path),
+            [
+                Problem::Extra("castle_2.cbsbp".to_owned()),
+                Problem::Extra("castle_x0_y16_z0.cbsbp".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_skipped_chunks() {
+        let args = Args::parse_from(["chisels_and_importers", "castle.vox"]);
+        let palette = crate::testing::palette();
+        // Two chunks long with a voxel only in the first chunk
+        let model = crate::testing::model([32, 16, 16], &[[3, 4, 5, 0]]);
+        let colors = crate::testing::colors(&[[128, 128, 128]]);
+        let block_model = crate::Converter::new(&palette).match_blocks(&model, &colors);
+        let skipped = SkippedChunk::of_model(&block_model);
+        let metadata = Metadata::new(&args, "", 0, &palette, &[], &skipped).unwrap();
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(
+            json["skipped"],
+            serde_json::json!([{ "offset": [16, 0, 0], "reason": "empty" }])
+        );
+    }
+
+    #[test]
+    fn options_hold_attribution() {
+        let args = Args::parse_from([
+            "chisels_and_importers",
+            "castle.vox",
+            "--author",
+            "Ann",
+            "--license",
+            "CC-BY-4.0",
+        ]);
+        let palette = crate::testing::palette();
+        let metadata = Metadata::new(&args, "", 0, &palette, &[], &[]).unwrap();
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(
+            json["options"]["attribution"],
+            serde_json::json!({ "author": "Ann", "license": "CC-BY-4.0", "description": null })
+        );
+    }
+}
@@ -0,0 +1,496 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{i18n::tr, jar::Jar, picture::Picture};
+
+/// Texture variables tried in this order for the color of a block, other variables are only
+/// used if none of these is set
+const TEXTURE_PREFERENCE: &[&str] = &["all", "side", "texture", "end", "top", "particle"];
+/// Parents followed at most, guards against models that are their own ancestor
+const MAX_MODEL_DEPTH: usize = 16;
+/// Blocks whose texture is on average less opaque are translucent, they are left out unless
+/// translucent blocks are included
+const MIN_ALPHA: u8 = 128;
+/// Blocks that are not full cubes or are drawn by code as block entities, by the path of
+/// their id in any namespace. A `*` at the start or the end matches any text.
+const DENY_LIST: &[&str] = &[
+    "*_slab",
+    "*_stairs",
+    "*_fence",
+    "*_fence_gate",
+    "*_wall",
+    "*_door",
+    "*_trapdoor",
+    "*_button",
+    "*_pressure_plate",
+    "*_carpet",
+    "*_pane",
+    "*_sign",
+    "*_banner",
+    "*_bed",
+    "*_head",
+    "*_skull",
+    "*shulker_box",
+    "*rail",
+    "*torch",
+    "*_sapling",
+    "*candle",
+    "*_coral_fan",
+    "potted_*",
+    "flower_pot",
+    "lantern",
+    "soul_lantern",
+    "chest",
+    "trapped_chest",
+    "ender_chest",
+    "bell",
+    "conduit",
+    "lectern",
+    "enchanting_table",
+    "cake",
+    "chain",
+    "ladder",
+    "lever",
+    "scaffolding",
+    "cobweb",
+];
+
+/// Why a block of a jar is not part of the palette
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Skip {
+    /// The block is on the deny list
+    Denied,
+    /// The block state lists no model or the model is in none of the jars
+    NoModel,
+    /// The models don't name a texture, e.g. blocks rendered by code
+    NoTexture,
+    /// The texture file is in none of the jars
+    MissingTexture,
+    Transparent,
+    /// Another block has exactly the same color and an earlier id
+    DuplicateColor,
+}
+
+impl Skip {
+    /// Describes the skipped blocks with the number of blocks and one of them
+    fn describe(self, blocks: &[String]) -> String {
+        let (count, example) = (blocks.len(), &blocks[0]);
+        match self {
+            Skip::Denied => tr!("jar-denied", count = count, example = example),
+            Skip::NoModel => tr!("jar-no-model", count = count, example = example),
+            Skip::NoTexture => tr!("jar-no-texture", count = count, example = example),
+            Skip::MissingTexture => tr!("jar-missing-texture", count = count, example = example),
+            Skip::Transparent => tr!("jar-transparent", count = count, example = example),
+            Skip::DuplicateColor => tr!("jar-duplicate-color", count = count, example = example),
+        }
+    }
+}
+
+/// Blocks found in one jar
+#[derive(Default)]
+struct JarReport {
+    added: usize,
+    /// Skipped blocks by reason
+    skipped: BTreeMap<Skip, Vec<String>>,
+}
+
+/// Jar or directory assets are read from. A directory holds the `assets` directory, like
+/// an extracted jar or a resource pack.
+enum Source {
+    Jar(Jar),
+    Directory {
+        root: PathBuf,
+        /// Files below the root, separated by `/` like in jars
+        names: Vec<String>,
+    },
+}
+
+impl Source {
+    fn open(path: &Path) -> Result<Self, String> {
+        if !path.is_dir() {
+            return Jar::open(path).map(Source::Jar);
+        }
+        let mut names = Vec::new();
+        list_files(path, "", &mut names)
+            .map_err(|e| tr!("file-read-failed", file = path.display(), error = e))?;
+        Ok(Source::Directory {
+            root: path.to_owned(),
+            names,
+        })
+    }
+
+    fn names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        match self {
+            Source::Jar(jar) => Box::new(jar.names()),
+            Source::Directory { names, .. } => Box::new(names.iter().map(String::as_str)),
+        }
+    }
+
+    /// Contents of a file, `None` if the source doesn't contain it
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            Source::Jar(jar) => jar.read(name),
+            Source::Directory { root, .. } => {
+                let path = root.join(name);
+                match std::fs::read(&path) {
+                    Ok(data) => Ok(Some(data)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                    Err(e) => Err(tr!("file-read-failed", file = path.display(), error = e)),
+                }
+            }
+        }
+    }
+}
+
+/// Adds the files below the directory to the names, prefixed with the path that leads to it
+fn list_files(directory: &Path, prefix: &str, names: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &format!("{}/", name), names)?;
+        } else {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// Assets of all jars and directories, a file in a later one replaces the same file in
+/// earlier ones like resource packs do
+struct Assets {
+    sources: Vec<Source>,
+}
+
+impl Assets {
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        Ok(self.read_with_source(name)?.map(|(_, data)| data))
+    }
+
+    /// Contents of a file together with the source it was read from
+    fn read_with_source(&self, name: &str) -> Result<Option<(&Source, Vec<u8>)>, String> {
+        for source in self.sources.iter().rev() {
+            if let Some(data) = source.read(name)? {
+                return Ok(Some((source, data)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_json(&self, name: &str) -> Result<Option<Value>, String> {
+        self.read(name)?
+            .map(|data| {
+                serde_json::from_slice(&data)
+                    .map_err(|e| tr!("file-invalid", file = name, error = e))
+            })
+            .transpose()
+    }
+
+    /// Textures of a model including the ones it inherits, `None` if the model is missing
+    fn model_textures(&self, model: &str) -> Result<Option<HashMap<String, String>>, String> {
+        let mut textures = HashMap::new();
+        let mut next = Some(model.to_owned());
+        for depth in 0..MAX_MODEL_DEPTH {
+            let Some(model) = next.take() else {
+                break;
+            };
+            let Some(json) = self.read_json(&asset_path(&model, "models", "json"))? else {
+                // Parents like builtin/generated only exist in the game code
+                if depth == 0 {
+                    return Ok(None);
+                }
+                break;
+            };
+            if let Some(own) = json.get("textures").and_then(Value::as_object) {
+                for (variable, texture) in own {
+                    if let Some(texture) = texture.as_str() {
+                        // Textures of the child override the ones of its parents
+                        textures
+                            .entry(variable.clone())
+                            .or_insert_with(|| texture.to_owned());
+                    }
+                }
+            }
+            next = json
+                .get("parent")
+                .and_then(Value::as_str)
+                .map(str::to_owned);
+        }
+        Ok(Some(textures))
+    }
+
+    /// Average color of the first frame of a texture, animated textures stack their frames
+    /// vertically. Like the game, the animation is only read from the jar or resource pack
+    /// the texture comes from.
+    fn texture_color(&self, texture: &str) -> Result<Option<[u8; 4]>, String> {
+        let path = asset_path(texture, "textures", "png");
+        let Some((source, data)) = self.read_with_source(&path)? else {
+            return Ok(None);
+        };
+        let picture = Picture::decode_png(&data[..])
+            .map_err(|e| tr!("file-error", file = path, error = e))?;
+
+        let meta_path = format!("{}.mcmeta", path);
+        let animation = match source.read(&meta_path)? {
+            Some(meta) => serde_json::from_slice::<Value>(&meta)
+                .map_err(|e| tr!("file-invalid", file = meta_path, error = e))?
+                .get("animation")
+                .cloned(),
+            None => None,
+        };
+        let (frame, height) = match &animation {
+            Some(animation) => animation_frame(animation, picture.width),
+            None => (0, picture.width),
+        };
+        let height = height.max(1);
+        // Frames past the end of the image fall back to the first one
+        let frame = if (frame + 1) * height <= picture.height {
+            frame
+        } else {
+            0
+        };
+        Ok(Some(picture.rows(frame * height, height).average()))
+    }
+
+    /// Color of a block from its block state file, or why it has none. Translucent blocks
+    /// are returned with their alpha, the caller decides whether to keep them.
+    fn block_color(&self, blockstate: &Value) -> Result<Result<[u8; 4], Skip>, String> {
+        let Some(model) = first_model(blockstate) else {
+            return Ok(Err(Skip::NoModel));
+        };
+        let Some(textures) = self.model_textures(&model)? else {
+            return Ok(Err(Skip::NoModel));
+        };
+        let Some(texture) = choose_texture(&textures) else {
+            return Ok(Err(Skip::NoTexture));
+        };
+        Ok(self.texture_color(&texture)?.ok_or(Skip::MissingTexture))
+    }
+}
+
+/// Palette file written by [`generate_palette`]
+#[derive(Serialize)]
+struct GeneratedPalette {
+    #[serde(flatten)]
+    blocks: BTreeMap<String, String>,
+    /// Translucent blocks, matched against glass materials and with `--alpha-mode glass`
+    #[serde(rename = "glass", skip_serializing_if = "BTreeMap::is_empty")]
+    translucent: BTreeMap<String, String>,
+}
+
+/// Creates a palette from the blocks of the jars or asset directories, every block gets the
+/// average color of its texture. Resource packs are layered over the jars in order, their
+/// textures, models and block states replace the ones of the jars but they add no blocks.
+/// Only blocks of the namespaces are used if any are given, translucent blocks are written
+/// to the `glass` section if they are included. Prints how many blocks were found in every
+/// jar and why others were left out.
+pub fn generate_palette(
+    jar_paths: &[PathBuf],
+    pack_paths: &[PathBuf],
+    output: &Path,
+    namespaces: &[String],
+    include_translucent: bool,
+) -> Result<(), String> {
+    // Resource packs come after the jars, so a block's jar has the same index in both
+    let sources = jar_paths
+        .iter()
+        .chain(pack_paths)
+        .map(|path| Source::open(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Block ids with the jar that defines them, a later jar may replace a block state
+    let mut blocks = BTreeMap::new();
+    for (index, source) in sources.iter().enumerate().take(jar_paths.len()) {
+        let mut found = false;
+        for name in source.names() {
+            if let Some(block) = blockstate_block(name) {
+                found = true;
+                let namespace = block.split_once(':').map_or("", |(namespace, _)| namespace);
+                if namespaces.is_empty() || namespaces.iter().any(|n| n == namespace) {
+                    blocks.insert(block, index);
+                }
+            }
+        }
+        if !found {
+            eprintln!(
+                "{}",
+                tr!(
+                    "warning",
+                    message = tr!("jar-no-blockstates", file = jar_paths[index].display())
+                )
+            );
+        }
+    }
+
+    let assets = Assets { sources };
+    let mut reports: Vec<_> = jar_paths.iter().map(|_| JarReport::default()).collect();
+    let mut palette = GeneratedPalette {
+        blocks: BTreeMap::new(),
+        translucent: BTreeMap::new(),
+    };
+    for (block, &jar) in &blocks {
+        let path = asset_path(block, "blockstates", "json");
+        let color = if denied(block) {
+            Err(Skip::Denied)
+        } else {
+            let blockstate = assets.read_json(&path)?.unwrap_or_default();
+            assets.block_color(&blockstate)?
+        };
+        let (color, section) = match color {
+            Ok([r, g, b, a]) => {
+                let section = if a >= MIN_ALPHA {
+                    &mut palette.blocks
+                } else if include_translucent {
+                    &mut palette.translucent
+                } else {
+                    let transparent = reports[jar].skipped.entry(Skip::Transparent);
+                    transparent.or_default().push(block.clone());
+                    continue;
+                };
+                (format!("#{:02x}{:02x}{:02x}", r, g, b), section)
+            }
+            Err(skip) => {
+                reports[jar]
+                    .skipped
+                    .entry(skip)
+                    .or_default()
+                    .push(block.clone());
+                continue;
+            }
+        };
+        // Blocks are visited in id order, the first block with a color keeps it
+        if section.contains_key(&color) {
+            let duplicates = reports[jar].skipped.entry(Skip::DuplicateColor);
+            duplicates.or_default().push(block.clone());
+            continue;
+        }
+        section.insert(color, block.clone());
+        reports[jar].added += 1;
+    }
+
+    for (path, report) in jar_paths.iter().zip(&reports) {
+        let skipped: usize = report.skipped.values().map(Vec::len).sum();
+        if report.added + skipped == 0 {
+            // Already reported as a jar without block states
+            continue;
+        }
+        println!(
+            "{}",
+            tr!(
+                "jar-summary",
+                file = path.display(),
+                added = report.added,
+                skipped = skipped
+            )
+        );
+        for (skip, blocks) in &report.skipped {
+            println!("  {}", skip.describe(blocks));
+        }
+    }
+    if palette.blocks.is_empty() {
+        return Err(tr!("jar-no-blocks"));
+    }
+
+    // Indented like the bundled palette
+    let mut json = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut json, formatter);
+    palette
+        .serialize(&mut serializer)
+        .map_err(|e| e.to_string())?;
+    std::fs::write(output, json)
+        .map_err(|e| tr!("file-write-failed", file = output.display(), error = e))
+}
+
+/// Frame shown first by an animation and the height of its frames, which are square unless
+/// the animation sets their height
+fn animation_frame(animation: &Value, width: u32) -> (u32, u32) {
+    let number = |value: Option<&Value>| value.and_then(Value::as_u64).map(|n| n as u32);
+    let height = number(animation.get("height")).unwrap_or(width);
+    let first = animation
+        .get("frames")
+        .and_then(Value::as_array)
+        .and_then(|frames| frames.first());
+    // Frames are given as an index or as an object with an index and a time
+    let frame = number(first).or_else(|| number(first.and_then(|frame| frame.get("index"))));
+    (frame.unwrap_or(0), height)
+}
+
+/// Block id of a block state file, e.g. `assets/create/blockstates/andesite_casing.json`
+/// belongs to `create:andesite_casing`
+fn blockstate_block(name: &str) -> Option<String> {
+    let rest = name.strip_prefix("assets/")?;
+    let (namespace, rest) = rest.split_once('/')?;
+    let path = rest.strip_prefix("blockstates/")?.strip_suffix(".json")?;
+    (!namespace.is_empty() && !path.is_empty()).then(|| format!("{}:{}", namespace, path))
+}
+
+/// Whether the block is on the deny list
+fn denied(block: &str) -> bool {
+    let path = block.split_once(':').map_or(block, |(_, path)| path);
+    DENY_LIST.iter().any(|pattern| {
+        if let Some(suffix) = pattern.strip_prefix('*') {
+            path.ends_with(suffix)
+        } else if let Some(prefix) = pattern.strip_suffix('*') {
+            path.starts_with(prefix)
+        } else {
+            path == *pattern
+        }
+    })
+}
+
+/// File of a resource location, which is in the `minecraft` namespace if it names none
+fn asset_path(location: &str, kind: &str, extension: &str) -> String {
+    let (namespace, path) = location.split_once(':').unwrap_or(("minecraft", location));
+    format!("assets/{}/{}/{}.{}", namespace, kind, path, extension)
+}
+
+/// Model of the default variant, or of the first variant or part if there is none
+fn first_model(blockstate: &Value) -> Option<String> {
+    // A variant is either a model or a list of models picked at random
+    let model = |variant: &Value| {
+        let variant = variant
+            .as_array()
+            .and_then(|list| list.first())
+            .unwrap_or(variant);
+        variant
+            .get("model")
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+    };
+    if let Some(variants) = blockstate.get("variants").and_then(Value::as_object) {
+        if let Some(default) = variants.get("").and_then(model) {
+            return Some(default);
+        }
+        return variants.values().find_map(model);
+    }
+    blockstate
+        .get("multipart")?
+        .as_array()?
+        .iter()
+        .filter_map(|part| part.get("apply"))
+        .find_map(model)
+}
+
+/// Texture that shows best what the block looks like, following `#variable` references
+fn choose_texture(textures: &HashMap<String, String>) -> Option<String> {
+    let variable = TEXTURE_PREFERENCE
+        .iter()
+        .find(|variable| textures.contains_key(**variable))
+        .map(|variable| variable.to_string())
+        .or_else(|| textures.keys().min().cloned())?;
+
+    let mut texture = textures.get(&variable)?;
+    for _ in 0..MAX_MODEL_DEPTH {
+        match texture.strip_prefix('#') {
+            Some(reference) => texture = textures.get(reference)?,
+            None => return Some(texture.clone()),
+        }
+    }
+    None
+}
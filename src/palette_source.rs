@@ -0,0 +1,120 @@
+use crate::i18n::tr;
+
+/// Reads a palette from a file, a URL or a published name like `@community/survival-1.20`.
+/// Downloaded palettes are cached, with `offline` only the cache is used.
+pub fn read_palette(source: &str, offline: bool) -> Result<Vec<u8>, String> {
+    let remote =
+        source.starts_with('@') || source.starts_with("https://") || source.starts_with("http://");
+    if !remote {
+        return std::fs::read(source).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => tr!("palette-not-found", file = source),
+            _ => tr!("file-read-failed", file = source, error = e),
+        });
+    }
+
+    #[cfg(feature = "network")]
+    return network::download(source, offline);
+    #[cfg(not(feature = "network"))]
+    {
+        let _ = offline;
+        Err(tr!("palette-no-network", source = source))
+    }
+}
+
+#[cfg(feature = "network")]
+mod network {
+    use std::{
+        io::Read,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::{i18n::tr, metadata::sha256_hex, BlockPalette};
+
+    /// Where `@name` palettes are published, the name is appended with a `.json` extension
+    const PALETTE_REGISTRY: &str =
+        "https://raw.githubusercontent.com/Alainx277/chisels-and-importers/main/palettes/";
+    /// Downloaded palettes older than this are fetched again
+    const CACHE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+    const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Uses the cached palette while it is fresh, otherwise downloads it again. Falls back
+    /// to an outdated cached palette with a warning if the download fails.
+    pub fn download(source: &str, offline: bool) -> Result<Vec<u8>, String> {
+        let url = match source.strip_prefix('@') {
+            Some(name) => format!("{}{}.json", PALETTE_REGISTRY, name),
+            None => source.to_owned(),
+        };
+        let cache_file = dirs::cache_dir()
+            .map(|directory| {
+                directory
+                    .join("chisels_and_importers")
+                    .join("palettes")
+                    .join(format!("{}.json", sha256_hex(url.as_bytes())))
+            })
+            .ok_or_else(|| tr!("palette-no-cache-directory"))?;
+        let cached = std::fs::read(&cache_file).ok();
+        let cache_age = std::fs::metadata(&cache_file)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+
+        if offline {
+            return cached.ok_or_else(|| tr!("palette-not-cached", source = source));
+        }
+        if let Some(cached) = &cached {
+            if cache_age.is_some_and(|age| age < CACHE_MAX_AGE) {
+                return Ok(cached.clone());
+            }
+        }
+
+        match fetch(&url) {
+            Ok(data) => {
+                // A palette that can't be cached still works for this run
+                let written = cache_file
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .and_then(|_| std::fs::write(&cache_file, &data));
+                if let Err(e) = written {
+                    eprintln!(
+                        "{}",
+                        tr!("warning", message = tr!("palette-cache-failed", error = e))
+                    );
+                }
+                Ok(data)
+            }
+            Err(error) => match cached {
+                Some(cached) => {
+                    let message = tr!("palette-download-fallback", url = url, error = error);
+                    eprintln!("{}", tr!("warning", message = message));
+                    Ok(cached)
+                }
+                None => Err(tr!("palette-download-failed", url = url, error = error)),
+            },
+        }
+    }
+
+    fn fetch(url: &str) -> Result<Vec<u8>, String> {
+        let response = ureq::AgentBuilder::new()
+            .timeout(DOWNLOAD_TIMEOUT)
+            .build()
+            .get(url)
+            .call()
+            .map_err(|e| e.to_string())?;
+        let mut data = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut data)
+            .map_err(|e| e.to_string())?;
+        validate(&data)?;
+        Ok(data)
+    }
+
+    /// Checks that the data is a palette file with at least one valid entry
+    fn validate(data: &[u8]) -> Result<(), String> {
+        let palette = BlockPalette::from_json(data)?;
+        if palette.mapping.is_empty() {
+            return Err(tr!("server-palette-empty"));
+        }
+        Ok(())
+    }
+}
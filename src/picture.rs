@@ -0,0 +1,163 @@
+use std::{fs::File, io::Read, path::Path};
+
+use crate::{i18n::tr, BlockPalette};
+
+/// Pixels with less opacity are left empty
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// RGBA image, the first row is the top
+pub struct Picture {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<[u8; 4]>,
+}
+
+impl Picture {
+    /// Decodes a PNG file of any color type into 8 bit RGBA
+    pub fn read_png(path: &Path) -> Result<Self, String> {
+        let file = File::open(path)
+            .map_err(|e| tr!("file-read-failed", file = path.display(), error = e))?;
+        Self::decode_png(file)
+    }
+
+    pub fn decode_png(reader: impl Read) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| tr!("png-invalid", error = e))?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buffer)
+            .map_err(|e| tr!("png-invalid", error = e))?;
+        let data = &buffer[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => data.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect(),
+            png::ColorType::Rgb => data.chunks(3).map(|p| [p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => {
+                data.chunks(2).map(|p| [p[0], p[0], p[0], p[1]]).collect()
+            }
+            png::ColorType::Grayscale => data.iter().map(|&g| [g, g, g, 255]).collect(),
+            // Expanded into RGB by the decoder
+            png::ColorType::Indexed => unreachable!(),
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    /// Pixels row by row, starting at the top
+    pub fn pixels(&self) -> &[[u8; 4]] {
+        &self.pixels
+    }
+
+    /// Rows of the image starting at the top row, fewer if the image ends before
+    pub fn rows(&self, top: u32, height: u32) -> Picture {
+        let top = top.min(self.height);
+        let height = height.min(self.height - top);
+        Picture {
+            width: self.width,
+            height,
+            pixels: self.pixels
+                [(self.width * top) as usize..(self.width * (top + height)) as usize]
+                .to_vec(),
+        }
+    }
+
+    /// Opacity weighted average of all pixels
+    pub fn average(&self) -> [u8; 4] {
+        self.resized(1, 1).pixels[0]
+    }
+
+    /// Shrinks the image by averaging the pixels that fall into each new pixel. Colors are
+    /// weighted by opacity so transparent pixels don't darken the edges.
+    pub fn resized(&self, width: u32, height: u32) -> Picture {
+        let source_range = |target: u32, target_size: u32, source_size: u32| {
+            let start = target * source_size / target_size;
+            let end = ((target + 1) * source_size / target_size).max(start + 1);
+            start..end
+        };
+
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0u64; 4];
+                let mut count = 0;
+                for source_y in source_range(y, height, self.height) {
+                    for source_x in source_range(x, width, self.width) {
+                        let [r, g, b, a] = self.pixels[(source_y * self.width + source_x) as usize];
+                        let a64 = a as u64;
+                        sum[0] += r as u64 * a64;
+                        sum[1] += g as u64 * a64;
+                        sum[2] += b as u64 * a64;
+                        sum[3] += a64;
+                        count += 1;
+                    }
+                }
+                let alpha = sum[3].max(1);
+                pixels.push([
+                    (sum[0] / alpha) as u8,
+                    (sum[1] / alpha) as u8,
+                    (sum[2] / alpha) as u8,
+                    (sum[3] / count) as u8,
+                ]);
+            }
+        }
+        Picture {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Matches every pixel to a block of the palette and returns the block indices, `None`
+    /// for transparent pixels. Dithering spreads the difference to the block color over the
+    /// following pixels (Floyd-Steinberg), so gradients keep their average color.
+    pub fn quantize(&self, palette: &BlockPalette, dither: bool) -> Vec<Option<usize>> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut colors: Vec<[f32; 3]> = self
+            .pixels
+            .iter()
+            .map(|&[r, g, b, _]| [r as f32, g as f32, b as f32])
+            .collect();
+        let opaque = |index: usize| self.pixels[index][3] >= ALPHA_THRESHOLD;
+
+        let mut blocks = vec![None; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * width + x;
+                if !opaque(index) {
+                    continue;
+                }
+                let [r, g, b] = colors[index].map(|value| value.clamp(0.0, 255.0).round() as u8);
+                let block = palette.closest_index(dot_vox::Color { r, g, b, a: 255 });
+                blocks[index] = Some(block);
+                if !dither {
+                    continue;
+                }
+
+                let matched = palette.mapping[block].color;
+                let matched = [matched.red, matched.green, matched.blue];
+                let error: [f32; 3] =
+                    std::array::from_fn(|channel| colors[index][channel] - matched[channel] as f32);
+                let neighbors = [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)];
+                for (dx, dy, weight) in neighbors {
+                    let (nx, ny) = (x as isize + dx, y + dy);
+                    if nx < 0 || nx as usize >= width || ny >= height {
+                        continue;
+                    }
+                    let neighbor = ny * width + nx as usize;
+                    if opaque(neighbor) {
+                        for channel in 0..3 {
+                            colors[neighbor][channel] += error[channel] * weight / 16.0;
+                        }
+                    }
+                }
+            }
+        }
+        blocks
+    }
+}
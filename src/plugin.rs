@@ -0,0 +1,420 @@
+//! Readers for model formats this crate doesn't know, loaded from WebAssembly modules given
+//! with `--plugin`. A plugin gets no functions of the host, so it can only compute on the
+//! bytes of the model file. Every file is read by a new instance that may use a limited
+//! amount of memory and run a limited number of instructions.
+//!
+//! A plugin exports its `memory` and these functions. Pointers are offsets into its memory,
+//! the file is copied to the memory reserved by `alloc` and passed as pointer and length.
+//!
+//! - `alloc(len: i32) -> i32` reserves `len` bytes and returns where they start
+//! - `probe(ptr: i32, len: i32) -> i32` returns 1 if the plugin reads the file, else 0
+//! - `list_models(ptr: i32, len: i32) -> i64` lists the models of the file
+//! - `read_voxels(ptr: i32, len: i32, model: i32) -> i64` returns the voxels of a model
+//!
+//! Both listing and reading return the pointer to their result in the upper 32 bits and its
+//! length in bytes in the lower ones, or a negative number if the file is invalid. The list
+//! holds 24 bytes for every model: its size and its position on the Magica Voxel axes, as
+//! three `u32` and three `i32`. Every voxel takes six bytes: x, y, z, red, green and blue.
+//! Numbers are little endian. `plugins/pvx.wat` is an example plugin.
+
+use std::path::Path;
+
+use chisels_and_importers::MAX_MODEL_SIZE;
+use dot_vox::DotVoxData;
+use wasmi::{
+    core::TrapCode, Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder,
+};
+
+use crate::{
+    i18n::tr,
+    imported::{to_vox_data, ImportedModel},
+};
+
+/// Instructions a plugin may run for one file, enough to read the largest Magica Voxel
+/// models many times over
+const FUEL: u64 = 10_000_000_000;
+/// Bytes of memory a plugin may grow to
+const MAX_MEMORY: usize = 512 << 20;
+/// Bytes of a model in the list of models
+const MODEL_ENTRY: usize = 24;
+/// Bytes of a voxel
+const VOXEL_ENTRY: usize = 6;
+
+/// Compiled plugin, instantiated anew for every file it reads
+pub struct Plugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    /// Instructions an instance may run
+    fuel: u64,
+}
+
+/// Plugin instance with a file copied into its memory
+struct Reader {
+    store: Store<StoreLimits>,
+    instance: Instance,
+    memory: Memory,
+    /// Pointer and length of the file
+    file: (i32, i32),
+}
+
+impl Plugin {
+    /// Compiles the WebAssembly module of the plugin
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &bytes).map_err(|e| e.to_string())?;
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        Ok(Plugin {
+            name,
+            engine,
+            module,
+            fuel: FUEL,
+        })
+    }
+
+    /// File name of the plugin
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the plugin reads the file
+    pub fn probe(&self, bytes: &[u8]) -> Result<bool, String> {
+        self.instantiate(bytes)?.probe()
+    }
+
+    /// Reads the models of the file
+    pub fn read(&self, bytes: &[u8]) -> Result<DotVoxData, String> {
+        let mut reader = self.instantiate(bytes)?;
+        let list = reader.list_models()?;
+        if list.len() % MODEL_ENTRY != 0 {
+            return Err(tr!("plugin-result-invalid", function = "list_models"));
+        }
+        let mut models = Vec::new();
+        for (index, entry) in list.chunks(MODEL_ENTRY).enumerate() {
+            let number = |i: usize| entry[i * 4..i * 4 + 4].try_into().unwrap();
+            let size = [0, 1, 2].map(|i| u32::from_le_bytes(number(i)));
+            let position = [3, 4, 5].map(|i| i32::from_le_bytes(number(i)));
+            if size.iter().any(|&side| side == 0 || side > MAX_MODEL_SIZE) {
+                return Err(tr!(
+                    "plugin-model-size-invalid",
+                    model = index,
+                    x = size[0],
+                    y = size[1],
+                    z = size[2]
+                ));
+            }
+
+            let voxels = reader.read_voxels(index as i32)?;
+            if voxels.len() % VOXEL_ENTRY != 0 {
+                return Err(tr!("plugin-result-invalid", function = "read_voxels"));
+            }
+            let voxels = voxels
+                .chunks(VOXEL_ENTRY)
+                .map(|voxel| {
+                    let position = [voxel[0], voxel[1], voxel[2]];
+                    if (0..3).any(|axis| position[axis] as u32 >= size[axis]) {
+                        return Err(tr!("plugin-voxel-outside", model = index));
+                    }
+                    Ok((position, [voxel[3], voxel[4], voxel[5]]))
+                })
+                .collect::<Result<_, _>>()?;
+            models.push(ImportedModel {
+                name: index.to_string(),
+                position,
+                size,
+                voxels,
+                hidden: false,
+            });
+        }
+        if models.is_empty() {
+            return Err(tr!("plugin-no-models"));
+        }
+        Ok(to_vox_data(models))
+    }
+
+    /// New instance of the plugin with the file copied into its memory
+    fn instantiate(&self, bytes: &[u8]) -> Result<Reader, String> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY)
+            .instances(1)
+            .memories(1)
+            .tables(1)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+        // No host functions, a plugin importing any fails here
+        let instance = Linker::new(&self.engine)
+            .instantiate(&mut store, &self.module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|e| e.to_string())?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| tr!("plugin-export-missing", export = "memory"))?;
+        let mut reader = Reader {
+            store,
+            instance,
+            memory,
+            file: (0, 0),
+        };
+
+        let len = i32::try_from(bytes.len()).map_err(|_| tr!("plugin-file-too-large"))?;
+        let ptr = reader.call::<i32, i32>("alloc", len)?;
+        reader
+            .memory
+            .write(&mut reader.store, ptr as u32 as usize, bytes)
+            .map_err(|_| tr!("plugin-result-invalid", function = "alloc"))?;
+        reader.file = (ptr, len);
+        Ok(reader)
+    }
+}
+
+impl Reader {
+    fn call<Params, Results>(&mut self, function: &str, params: Params) -> Result<Results, String>
+    where
+        Params: wasmi::WasmParams,
+        Results: wasmi::WasmResults,
+    {
+        let typed = self
+            .instance
+            .get_typed_func::<Params, Results>(&self.store, function)
+            .map_err(|_| tr!("plugin-export-missing", export = function))?;
+        typed
+            .call(&mut self.store, params)
+            .map_err(|e| match e.as_trap_code() {
+                Some(TrapCode::OutOfFuel) => tr!("plugin-out-of-fuel", function = function),
+                _ => tr!("plugin-trapped", function = function, error = e),
+            })
+    }
+
+    fn probe(&mut self) -> Result<bool, String> {
+        Ok(self.call::<(i32, i32), i32>("probe", self.file)? != 0)
+    }
+
+    fn list_models(&mut self) -> Result<Vec<u8>, String> {
+        let slice = self.call::<(i32, i32), i64>("list_models", self.file)?;
+        self.copy_out("list_models", slice)
+    }
+
+    fn read_voxels(&mut self, model: i32) -> Result<Vec<u8>, String> {
+        let (ptr, len) = self.file;
+        let slice = self.call::<(i32, i32, i32), i64>("read_voxels", (ptr, len, model))?;
+        self.copy_out("read_voxels", slice)
+    }
+
+    /// Copies the result of the function out of the memory of the plugin
+    fn copy_out(&self, function: &str, slice: i64) -> Result<Vec<u8>, String> {
+        if slice < 0 {
+            return Err(tr!(
+                "plugin-file-invalid",
+                function = function,
+                code = slice
+            ));
+        }
+        let (start, len) = ((slice >> 32) as usize, (slice as u32) as usize);
+        let mut result = vec![0; len];
+        self.memory
+            .read(&self.store, start, &mut result)
+            .map_err(|_| tr!("plugin-result-invalid", function = function))?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::Args;
+
+    /// Writes the module to a file in the directory and loads it
+    fn compiled(directory: &TempDir, wat: &str) -> Plugin {
+        let path = directory.path().join("plugin.wasm");
+        std::fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+        Plugin::load(&path).unwrap()
+    }
+
+    fn pvx_plugin(directory: &TempDir) -> Plugin {
+        compiled(directory, include_str!("../plugins/pvx.wat"))
+    }
+
+    /// PVX file with the models, given as size and voxels
+    fn pvx_file(models: &[([u8; 3], &[[u8; 6]])]) -> Vec<u8> {
+        let mut file = b"PVX1".to_vec();
+        file.push(models.len() as u8);
+        for (size, voxels) in models {
+            file.extend(size);
+            file.extend((voxels.len() as u16).to_le_bytes());
+            file.extend(voxels.concat());
+        }
+        file
+    }
+
+    fn two_models() -> Vec<u8> {
+        pvx_file(&[
+            ([2, 1, 1], &[[0, 0, 0, 255, 0, 0], [1, 0, 0, 0, 0, 255]]),
+            ([1, 1, 3], &[[0, 0, 2, 255, 0, 0]]),
+        ])
+    }
+
+    #[test]
+    fn example_plugin_probes_its_format() {
+        let directory = tempfile::tempdir().unwrap();
+        let plugin = pvx_plugin(&directory);
+        assert_eq!(plugin.name(), "plugin.wasm");
+        assert!(plugin.probe(&two_models()).unwrap());
+        let vox = crate::testing::vox_file(&[], &crate::testing::colors(&[]));
+        assert!(!plugin.probe(&vox).unwrap());
+        assert!(!plugin.probe(b"PVX").unwrap());
+    }
+
+    #[test]
+    fn example_plugin_reads_models() {
+        let directory = tempfile::tempdir().unwrap();
+        let data = pvx_plugin(&directory).read(&two_models()).unwrap();
+
+        let sizes: Vec<_> = data
+            .models
+            .iter()
+            .map(|model| [model.size.x, model.size.y, model.size.z])
+            .collect();
+        assert_eq!(sizes, [[2, 1, 1], [1, 1, 3]]);
+        let voxels: Vec<_> = data
+            .models
+            .iter()
+            .flat_map(|model| &model.voxels)
+            .map(|voxel| {
+                let color = data.palette[voxel.i as usize];
+                ([voxel.x, voxel.y, voxel.z], [color.r, color.g, color.b])
+            })
+            .collect();
+        assert_eq!(
+            voxels,
+            [
+                ([0, 0, 0], [255, 0, 0]),
+                ([1, 0, 0], [0, 0, 255]),
+                ([0, 0, 2], [255, 0, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut file = two_models();
+        file.pop();
+        let error = pvx_plugin(&directory).read(&file).unwrap_err();
+        assert!(error.contains("list_models"), "{}", error);
+    }
+
+    #[test]
+    fn voxels_outside_the_model_are_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = pvx_file(&[([1, 1, 1], &[[0, 1, 0, 255, 0, 0]])]);
+        let error = pvx_plugin(&directory).read(&file).unwrap_err();
+        assert!(error.contains("outside"), "{}", error);
+    }
+
+    #[test]
+    fn endless_plugin_runs_out_of_fuel() {
+        let directory = tempfile::tempdir().unwrap();
+        let plugin = Plugin {
+            fuel: 100_000,
+            ..compiled(
+                &directory,
+                r#"(module
+                    (memory (export "memory") 1)
+                    (func (export "alloc") (param i32) (result i32) (i32.const 0))
+                    (func (export "probe") (param i32 i32) (result i32)
+                        (loop $forever (br $forever))
+                        (i32.const 1)))"#,
+            )
+        };
+        let error = plugin.probe(b"").unwrap_err();
+        assert!(error.contains("ran too long"), "{}", error);
+    }
+
+    #[test]
+    fn memory_is_limited() {
+        let directory = tempfile::tempdir().unwrap();
+        // Ten thousand pages of 64 KiB are more than the limit
+        let too_large = compiled(&directory, r#"(module (memory (export "memory") 10000))"#);
+        assert!(too_large.probe(b"").is_err());
+
+        let growing = compiled(
+            &directory,
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) (i32.const 0))
+                (func (export "probe") (param i32 i32) (result i32)
+                    (i32.ne (memory.grow (i32.const 10000)) (i32.const -1))))"#,
+        );
+        assert!(!growing.probe(b"").unwrap());
+    }
+
+    #[test]
+    fn host_functions_are_not_provided() {
+        let directory = tempfile::tempdir().unwrap();
+        let plugin = compiled(
+            &directory,
+            r#"(module
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1))"#,
+        );
+        assert!(plugin.probe(b"").is_err());
+    }
+
+    #[test]
+    fn missing_exports_are_reported() {
+        let directory = tempfile::tempdir().unwrap();
+        let plugin = compiled(&directory, r#"(module (memory (export "memory") 1))"#);
+        let error = plugin.probe(b"").unwrap_err();
+        assert!(error.contains("alloc"), "{}", error);
+    }
+
+    #[test]
+    fn converts_with_plugin() {
+        let directory = tempfile::tempdir().unwrap();
+        let plugin_path = directory.path().join("pvx.wasm");
+        let wasm = wat::parse_str(include_str!("../plugins/pvx.wat")).unwrap();
+        std::fs::write(&plugin_path, wasm).unwrap();
+        let model_path = directory.path().join("castle.pvx");
+        std::fs::write(&model_path, two_models()).unwrap();
+
+        let args = Args::parse_from([
+            "chisels_and_importers".as_ref(),
+            model_path.as_os_str(),
+            "--plugin".as_ref(),
+            plugin_path.as_os_str(),
+            "--palette".as_ref(),
+            concat!(env!("CARGO_MANIFEST_DIR"), "/blocks.json").as_ref(),
+            "--output-dir".as_ref(),
+            directory.path().as_os_str(),
+            "--all-models".as_ref(),
+        ]);
+        let patterns = crate::convert(&args, &mut |_, _| {}).unwrap();
+        assert_eq!(patterns.len(), 1);
+
+        let pattern = std::fs::read(&patterns[0]).unwrap();
+        let pattern = chisels_and_importers::decode::decode_pattern(&pattern).unwrap();
+        pattern.check().unwrap();
+        let bits: u32 = pattern
+            .palette
+            .iter()
+            .zip(pattern.counts())
+            .filter(|(state, _)| !state.contains("minecraft:air"))
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(bits, 3);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use crate::raster::{shade, Image, Pixel, GLYPH_HEIGHT};
+
+/// Where and how large to render the preview of a model
+pub struct PreviewOptions {
+    pub path: PathBuf,
+    /// Size of the longest image side in pixels
+    pub image_size: u32,
+}
+
+/// Where to write the comparison of model and block colors
+pub struct CompareOptions {
+    pub path: PathBuf,
+    /// Size of the longest side of each panel in pixels
+    pub image_size: u32,
+    /// Add a panel marking voxels whose delta-E exceeds this value
+    pub threshold: Option<f32>,
+}
+
+/// Voxel with the color of the block it was converted to
+pub struct PreviewVoxel {
+    /// Position in Magica Voxel coordinates (z is up)
+    pub position: [u32; 3],
+    pub color: Pixel,
+}
+
+const MARGIN: u32 = 4;
+/// Space above each comparison panel for its label
+const LABEL_HEIGHT: u32 = GLYPH_HEIGHT * 2 + 8;
+const LABEL_COLOR: Pixel = [0, 0, 0, 255];
+const COMPARE_BACKGROUND: Pixel = [255, 255, 255, 255];
+const TOP_SHADE: f32 = 1.0;
+const RIGHT_SHADE: f32 = 0.8;
+const LEFT_SHADE: f32 = 0.6;
+
+/// Renders the voxels as an isometric projection, viewed from above the +x/+y corner.
+pub fn render_isometric(voxels: &[PreviewVoxel], size: [u32; 3], image_size: u32) -> Image {
+    let projection = Projection::fit(size, image_size);
+    let mut image = Image::new(projection.width, projection.height, [0, 0, 0, 0]);
+
+    let occupied: HashSet<[u32; 3]> = voxels.iter().map(|voxel| voxel.position).collect();
+    let is_occupied = |[x, y, z]: [u32; 3]| occupied.contains(&[x, y, z]);
+
+    // Painter's order: voxels further along the view diagonal are in front of the ones before
+    let mut order: Vec<&PreviewVoxel> = voxels.iter().collect();
+    order.sort_by_key(|voxel| voxel.position.iter().sum::<u32>());
+
+    for voxel in order {
+        let [x, y, z] = voxel.position;
+        let top = !is_occupied([x, y, z + 1]);
+        let right = !is_occupied([x + 1, y, z]);
+        let left = !is_occupied([x, y + 1, z]);
+        let (x, y, z) = (x as f32, y as f32, z as f32);
+
+        if top {
+            let face = [
+                (x, y, z + 1.0),
+                (x + 1.0, y, z + 1.0),
+                (x + 1.0, y + 1.0, z + 1.0),
+                (x, y + 1.0, z + 1.0),
+            ];
+            image.fill_convex_polygon(&projection.face(face), shade(voxel.color, TOP_SHADE));
+        }
+        if right {
+            let face = [
+                (x + 1.0, y, z),
+                (x + 1.0, y + 1.0, z),
+                (x + 1.0, y + 1.0, z + 1.0),
+                (x + 1.0, y, z + 1.0),
+            ];
+            image.fill_convex_polygon(&projection.face(face), shade(voxel.color, RIGHT_SHADE));
+        }
+        if left {
+            let face = [
+                (x, y + 1.0, z),
+                (x + 1.0, y + 1.0, z),
+                (x + 1.0, y + 1.0, z + 1.0),
+                (x, y + 1.0, z + 1.0),
+            ];
+            image.fill_convex_polygon(&projection.face(face), shade(voxel.color, LEFT_SHADE));
+        }
+    }
+
+    image
+}
+
+/// Renders labeled panels of the same model next to each other, all from the same viewpoint.
+pub fn render_comparison(
+    panels: &[(&str, &[PreviewVoxel])],
+    size: [u32; 3],
+    image_size: u32,
+) -> Image {
+    let renders: Vec<_> = panels
+        .iter()
+        .map(|(label, voxels)| (label, render_isometric(voxels, size, image_size)))
+        .collect();
+    let panel_width = renders
+        .iter()
+        .map(|(_, image)| image.width())
+        .max()
+        .unwrap_or(0);
+    let panel_height = renders
+        .iter()
+        .map(|(_, image)| image.height())
+        .max()
+        .unwrap_or(0);
+
+    let mut image = Image::new(
+        panel_width * renders.len() as u32,
+        LABEL_HEIGHT + panel_height,
+        COMPARE_BACKGROUND,
+    );
+    for (i, (label, render)) in renders.iter().enumerate() {
+        let x = (i as u32 * panel_width) as i64;
+        image.draw_text(x + MARGIN as i64, MARGIN as i64, label, 2, LABEL_COLOR);
+        image.draw_image(x, LABEL_HEIGHT as i64, render);
+    }
+    image
+}
+
+/// Isometric projection scaled to fit a model into the image
+struct Projection {
+    scale: f32,
+    origin: (f32, f32),
+    width: u32,
+    height: u32,
+}
+
+const COS_30: f32 = 0.866_025_4;
+
+impl Projection {
+    fn fit(size: [u32; 3], image_size: u32) -> Self {
+        let [x, y, z] = size.map(|side| side as f32);
+        let span_u = (x + y) * COS_30;
+        let span_v = (x + y) * 0.5 + z;
+
+        let available = image_size.saturating_sub(2 * MARGIN).max(1) as f32;
+        let scale = available / span_u.max(span_v).max(1.0);
+        Self {
+            scale,
+            origin: (
+                MARGIN as f32 + y * COS_30 * scale,
+                MARGIN as f32 + z * scale,
+            ),
+            width: (span_u * scale).ceil() as u32 + 2 * MARGIN,
+            height: (span_v * scale).ceil() as u32 + 2 * MARGIN,
+        }
+    }
+
+    fn project(&self, (x, y, z): (f32, f32, f32)) -> (f32, f32) {
+        (
+            self.origin.0 + (x - y) * COS_30 * self.scale,
+            self.origin.1 + ((x + y) * 0.5 - z) * self.scale,
+        )
+    }
+
+    fn face(&self, corners: [(f32, f32, f32); 4]) -> [(f32, f32); 4] {
+        corners.map(|corner| self.project(corner))
+    }
+}
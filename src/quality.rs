@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+
+use palette::{color_difference::Ciede2000, Srgb};
+
+use crate::{
+    console::{say, say_inline},
+    i18n::tr,
+    to_lch, BlockPalette, ColorMatch,
+};
+
+/// Upper bounds of the delta-E ranges shown in the distribution
+const DISTRIBUTION_BOUNDS: [f32; 5] = [1.0, 2.0, 5.0, 10.0, 20.0];
+const WORST_MATCHES: usize = 5;
+const SUGGESTIONS: usize = 2;
+
+/// Prints how far the blocks are from the model colors, the worst matched colors and
+/// vanilla blocks missing from the palette that would match them better.
+pub fn print_quality_report(
+    name: &str,
+    matches: &[ColorMatch],
+    palette: &BlockPalette,
+    vanilla: &BlockPalette,
+) {
+    let total_voxels: u32 = matches.iter().map(|m| m.voxels).sum();
+    if total_voxels == 0 {
+        return;
+    }
+    let share = |voxels: u32| voxels as f32 / total_voxels as f32 * 100.0;
+
+    say!("{}", tr!("quality-header", name = name));
+    let mut lower = 0.0;
+    for upper in DISTRIBUTION_BOUNDS
+        .into_iter()
+        .chain(std::iter::once(f32::INFINITY))
+    {
+        let voxels = matches
+            .iter()
+            .filter(|m| m.delta_e >= lower && m.delta_e < upper)
+            .map(|m| m.voxels)
+            .sum();
+        let range = if upper.is_finite() {
+            format!("{}-{}", lower, upper)
+        } else {
+            format!("{}+", lower)
+        };
+        say!(
+            "  {}",
+            tr!(
+                "quality-distribution",
+                range = format!("{:<6}", range),
+                share = format!("{:>6.1}", share(voxels)),
+            )
+        );
+        lower = upper;
+    }
+
+    let (score, mean_delta_e) = fidelity(matches);
+    say!(
+        "  {}",
+        tr!(
+            "quality-fidelity",
+            score = format!("{:.1}", score),
+            mean = format!("{:.1}", mean_delta_e),
+        )
+    );
+
+    let palette_names: HashSet<_> = palette.mapping.iter().map(|block| &block.name).collect();
+    let mut worst: Vec<_> = matches.iter().collect();
+    worst.sort_by(|l, r| r.delta_e.total_cmp(&l.delta_e));
+    say!("  {}", tr!("quality-worst"));
+    for m in worst.into_iter().take(WORST_MATCHES) {
+        let lch = to_lch(m.color);
+        let mut suggestions: Vec<_> = vanilla
+            .mapping
+            .iter()
+            .filter(|block| !palette_names.contains(&block.name))
+            .map(|block| (block.lch.difference(lch), block))
+            .filter(|(delta_e, _)| *delta_e < m.delta_e)
+            .collect();
+        suggestions.sort_by(|(l, _), (r, _)| l.total_cmp(r));
+        let suggestions: Vec<_> = suggestions
+            .iter()
+            .take(SUGGESTIONS)
+            .map(|(delta_e, block)| format!("{} ({:.1})", block.name, delta_e))
+            .collect();
+
+        say_inline!(
+            "    {}",
+            tr!(
+                "quality-match",
+                color = hex(m.color),
+                block = m.block,
+                delta = format!("{:.1}", m.delta_e),
+                share = format!("{:.1}", share(m.voxels)),
+            )
+        );
+        if suggestions.is_empty() {
+            say!();
+        } else {
+            let blocks = suggestions.join(&tr!("quality-or"));
+            say!("{}", tr!("quality-suggestion", blocks = blocks));
+        }
+    }
+}
+
+/// Score from 0 to 100 where 100 means every voxel matched exactly, and the voxel weighted
+/// mean delta-E it is derived from
+fn fidelity(matches: &[ColorMatch]) -> (f32, f32) {
+    let total_voxels: u32 = matches.iter().map(|m| m.voxels).sum();
+    let mean_delta_e = matches
+        .iter()
+        .map(|m| m.delta_e * m.voxels as f32)
+        .sum::<f32>()
+        / total_voxels as f32;
+    ((100.0 - mean_delta_e).max(0.0), mean_delta_e)
+}
+
+pub fn hex(color: Srgb<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
@@ -0,0 +1,78 @@
+//! Fewer colors for models that use more than they need, e.g. for gradients whose colors are
+//! matched to nearly the same blocks anyway
+
+use std::collections::BTreeMap;
+
+use palette::{IntoColor, Lab, Srgb};
+
+/// Groups the colors by median cut in Lab, weighted by their number of voxels, until there
+/// are `max_colors` groups. Returns the color every color is replaced with, the most used
+/// color of its group, so the voxels keep a color of the model.
+pub fn merge_colors(
+    usage: &BTreeMap<u8, u32>,
+    colors: &[dot_vox::Color],
+    max_colors: usize,
+) -> BTreeMap<u8, u8> {
+    let mut groups = vec![usage
+        .iter()
+        .map(|(&index, &count)| {
+            let color = colors[index as usize];
+            let lab: Lab = Srgb::new(color.r, color.g, color.b)
+                .into_linear::<f32>()
+                .into_color();
+            (index, [lab.l, lab.a, lab.b], count)
+        })
+        .collect::<Vec<_>>()];
+
+    while groups.len() < max_colors.max(1) {
+        // Split the group that spans the widest range of a channel
+        let widest = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, group)| group.len() > 1)
+            .map(|(index, group)| {
+                let (range, channel) = (0..3)
+                    .map(|channel| {
+                        let values = group.iter().map(|(_, lab, _)| lab[channel]);
+                        let min = values.clone().fold(f32::INFINITY, f32::min);
+                        let max = values.fold(f32::NEG_INFINITY, f32::max);
+                        (max - min, channel)
+                    })
+                    .max_by(|a, b| a.0.total_cmp(&b.0))
+                    .unwrap();
+                (range, index, channel)
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        let Some((_, index, channel)) = widest else {
+            break;
+        };
+        let mut group = groups.swap_remove(index);
+        group.sort_unstable_by(|a, b| a.1[channel].total_cmp(&b.1[channel]));
+        // At the color that reaches half of the voxels of the group
+        let total: u64 = group.iter().map(|&(_, _, count)| count as u64).sum();
+        let mut counted = 0;
+        let middle = group
+            .iter()
+            .position(|&(_, _, count)| {
+                counted += count as u64;
+                counted * 2 >= total
+            })
+            .map_or(1, |position| position.clamp(1, group.len() - 1));
+        let upper = group.split_off(middle);
+        groups.push(group);
+        groups.push(upper);
+    }
+
+    let mut replacements = BTreeMap::new();
+    for group in groups {
+        let representative = group
+            .iter()
+            .max_by_key(|&&(index, _, count)| (count, std::cmp::Reverse(index)))
+            .map(|&(index, _, _)| index)
+            .unwrap();
+        for (index, _, _) in group {
+            replacements.insert(index, representative);
+        }
+    }
+    replacements
+}
@@ -0,0 +1,235 @@
+//! Qubicle files (`.qb` and `.qbt`), read into the same data as Magica Voxel files. Every
+//! matrix becomes a model, named and placed in a scene like the models of the outliner.
+//! Qubicle stores a color for every voxel, the palette is made from the distinct colors.
+
+use chisels_and_importers::MAX_MODEL_SIZE;
+use dot_vox::DotVoxData;
+
+use crate::{
+    i18n::tr,
+    imported::{to_vox_data, ImportedModel, Reader},
+};
+/// `.qb` run-length encoding: the next values are a count and the repeated color
+const CODE_FLAG: u32 = 2;
+/// `.qb` run-length encoding: the slice is complete
+const NEXT_SLICE_FLAG: u32 = 6;
+const QBT_MAGIC: &[u8; 4] = b"QB 2";
+/// Nesting of `.qbt` nodes followed, guards against files that never end
+const MAX_DEPTH: usize = 64;
+
+/// Reads a `.qb` file of Qubicle 1.x
+pub fn parse_qb(bytes: &[u8]) -> Result<DotVoxData, String> {
+    let mut reader = Reader::new(bytes);
+    let _version = reader.u32()?;
+    let bgra = match reader.u32()? {
+        0 => false,
+        1 => true,
+        format => return Err(tr!("qubicle-color-format-unknown", format = format)),
+    };
+    let right_handed = reader.u32()? == 1;
+    let compressed = reader.u32()? == 1;
+    // With the visibility mask encoded, any alpha but 0 is a visible voxel
+    let _visibility_mask = reader.u32()?;
+    let matrix_count = reader.u32()?;
+
+    let mut matrices = Vec::new();
+    for _ in 0..matrix_count {
+        let name_length = reader.u8()? as usize;
+        let name = String::from_utf8_lossy(reader.take(name_length)?).into_owned();
+        let size = [reader.u32()?, reader.u32()?, reader.u32()?];
+        let position = [reader.i32()?, reader.i32()?, reader.i32()?];
+        check_size(&name, size)?;
+
+        let mut voxels = Vec::new();
+        let mut add = |x: u32, y: u32, z: u32, value: u32| {
+            let [r, g, b, a] = value.to_le_bytes();
+            if a != 0 {
+                let color = if bgra { [b, g, r] } else { [r, g, b] };
+                voxels.push(([x, y, z], color));
+            }
+        };
+        let [size_x, size_y, size_z] = size;
+        let slice = size_x * size_y;
+        if compressed {
+            for z in 0..size_z {
+                let mut index = 0;
+                loop {
+                    let value = reader.u32()?;
+                    if value == NEXT_SLICE_FLAG {
+                        break;
+                    }
+                    let (count, value) = if value == CODE_FLAG {
+                        (reader.u32()?, reader.u32()?)
+                    } else {
+                        (1, value)
+                    };
+                    if index + count > slice {
+                        return Err(tr!("qubicle-too-many-voxels", name = name));
+                    }
+                    for index in index..index + count {
+                        add(index % size_x, index / size_x, z, value);
+                    }
+                    index += count;
+                }
+            }
+        } else {
+            for z in 0..size_z {
+                for y in 0..size_y {
+                    for x in 0..size_x {
+                        add(x, y, z, reader.u32()?);
+                    }
+                }
+            }
+        }
+        matrices.push(to_vox_axes(name, position, size, voxels, right_handed));
+    }
+    Ok(to_vox_data(matrices))
+}
+
+/// Reads a `.qbt` file of Qubicle 3, the colors are either stored with every voxel or in the
+/// color map of the file
+pub fn parse_qbt(bytes: &[u8]) -> Result<DotVoxData, String> {
+    let mut reader = Reader::new(bytes);
+    if reader.take(4)? != QBT_MAGIC {
+        return Err(tr!("qubicle-tree-invalid"));
+    }
+    let _version = reader.take(2)?;
+    let _global_scale = reader.take(12)?;
+
+    let mut color_map = Vec::new();
+    let mut section = reader.take(8)?;
+    if section == b"COLORMAP" {
+        let count = reader.u32()? as usize;
+        color_map = reader
+            .take(count.saturating_mul(4))?
+            .chunks_exact(4)
+            .map(|color| [color[0], color[1], color[2]])
+            .collect();
+        section = reader.take(8)?;
+    }
+    if section != b"DATATREE" {
+        return Err(tr!("qubicle-tree-missing"));
+    }
+    let mut matrices = Vec::new();
+    read_qbt_node(&mut reader, &color_map, 0, &mut matrices)?;
+    Ok(to_vox_data(matrices))
+}
+
+fn read_qbt_node(
+    reader: &mut Reader,
+    color_map: &[[u8; 3]],
+    depth: usize,
+    matrices: &mut Vec<ImportedModel>,
+) -> Result<(), String> {
+    if depth > MAX_DEPTH {
+        return Err(tr!("qubicle-nested-too-deeply"));
+    }
+    let node_type = reader.u32()?;
+    let data_size = reader.u32()? as usize;
+    match node_type {
+        // Matrix, and compound which is a matrix with child nodes
+        0 | 2 => {
+            matrices.push(read_qbt_matrix(reader, color_map)?);
+            if node_type == 2 {
+                for _ in 0..reader.u32()? {
+                    read_qbt_node(reader, color_map, depth + 1, matrices)?;
+                }
+            }
+        }
+        // Model, a group of nodes
+        1 => {
+            for _ in 0..reader.u32()? {
+                read_qbt_node(reader, color_map, depth + 1, matrices)?;
+            }
+        }
+        // Unknown nodes are skipped
+        _ => {
+            reader.take(data_size)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_qbt_matrix(reader: &mut Reader, color_map: &[[u8; 3]]) -> Result<ImportedModel, String> {
+    let name_length = reader.u32()? as usize;
+    let name = String::from_utf8_lossy(reader.take(name_length)?).into_owned();
+    let position = [reader.i32()?, reader.i32()?, reader.i32()?];
+    let _local_scale = reader.take(12)?;
+    let _pivot = reader.take(12)?;
+    let size = [reader.u32()?, reader.u32()?, reader.u32()?];
+    check_size(&name, size)?;
+    let compressed_size = reader.u32()? as usize;
+    let data = miniz_oxide::inflate::decompress_to_vec_zlib(reader.take(compressed_size)?)
+        .map_err(|e| {
+            tr!(
+                "qubicle-decompress-failed",
+                name = name,
+                error = format!("{:?}", e)
+            )
+        })?;
+
+    let [size_x, size_y, size_z] = size;
+    let mut values = data.chunks_exact(4);
+    let mut voxels = Vec::new();
+    for x in 0..size_x {
+        for z in 0..size_z {
+            for y in 0..size_y {
+                let value = values
+                    .next()
+                    .ok_or_else(|| tr!("qubicle-missing-voxels", name = name))?;
+                // The last byte is the mask of visible sides, 0 for air
+                if value[3] == 0 {
+                    continue;
+                }
+                let color = if color_map.is_empty() {
+                    [value[0], value[1], value[2]]
+                } else {
+                    *color_map
+                        .get(value[0] as usize)
+                        .ok_or_else(|| tr!("qubicle-missing-color", name = name))?
+                };
+                voxels.push(([x, y, z], color));
+            }
+        }
+    }
+    Ok(to_vox_axes(name, position, size, voxels, true))
+}
+
+fn check_size(name: &str, size: [u32; 3]) -> Result<(), String> {
+    if size.iter().any(|&side| side > MAX_MODEL_SIZE) {
+        return Err(tr!(
+            "qubicle-too-large",
+            name = name,
+            size = format!("{}x{}x{}", size[0], size[1], size[2]),
+            max = MAX_MODEL_SIZE
+        ));
+    }
+    Ok(())
+}
+
+/// Turns a matrix with y pointing up into Magica Voxel axes. Qubicle's z axis points away
+/// from the viewer in left-handed files and towards them in right-handed ones, Magica Voxel's
+/// y axis always points away.
+fn to_vox_axes(
+    name: String,
+    position: [i32; 3],
+    size: [u32; 3],
+    voxels: Vec<([u32; 3], [u8; 3])>,
+    right_handed: bool,
+) -> ImportedModel {
+    let [size_x, size_y, size_z] = size;
+    let [x, y, z] = position;
+    let depth = |z: u32| if right_handed { size_z - 1 - z } else { z };
+    let depth_position = if right_handed { -z - size_z as i32 } else { z };
+    let voxels = voxels
+        .into_iter()
+        .map(|([x, y, z], color)| ([x, depth(z), y].map(|axis| axis as u8), color))
+        .collect();
+    ImportedModel {
+        name,
+        position: [x, depth_position, y],
+        size: [size_x, size_z, size_y],
+        voxels,
+        hidden: false,
+    }
+}
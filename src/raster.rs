@@ -0,0 +1,235 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+/// RGBA color of a single pixel
+pub type Pixel = [u8; 4];
+
+/// Simple RGBA image that can be saved as PNG
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<Pixel>,
+}
+
+impl Image {
+    /// Creates an image filled with the given color
+    pub fn new(width: u32, height: u32, background: Pixel) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![background; width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn set_pixel(&mut self, x: i64, y: i64, color: Pixel) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        self.pixels[y as usize * self.width as usize + x as usize] = color;
+    }
+
+    pub fn fill_rect(&mut self, x: i64, y: i64, width: u32, height: u32, color: Pixel) {
+        for py in y..y + height as i64 {
+            for px in x..x + width as i64 {
+                self.set_pixel(px, py, color);
+            }
+        }
+    }
+
+    /// Copies the non-transparent pixels of another image to the given position
+    pub fn draw_image(&mut self, x: i64, y: i64, image: &Image) {
+        for (i, pixel) in image.pixels.iter().enumerate() {
+            if pixel[3] > 0 {
+                let px = x + (i % image.width as usize) as i64;
+                let py = y + (i / image.width as usize) as i64;
+                self.set_pixel(px, py, *pixel);
+            }
+        }
+    }
+
+    /// Fills a convex polygon, a pixel is covered if its center lies inside the polygon.
+    pub fn fill_convex_polygon(&mut self, points: &[(f32, f32)], color: Pixel) {
+        let min_x = points.iter().map(|p| p.0).fold(f32::INFINITY, f32::min);
+        let max_x = points.iter().map(|p| p.0).fold(f32::NEG_INFINITY, f32::max);
+        let min_y = points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+        let max_y = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+
+        for y in min_y.floor() as i64..=max_y.ceil() as i64 {
+            for x in min_x.floor() as i64..=max_x.ceil() as i64 {
+                let center = (x as f32 + 0.5, y as f32 + 0.5);
+                if point_in_convex_polygon(center, points) {
+                    self.set_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Draws text with the built-in 3x5 pixel font, scaled up by an integer factor.
+    /// Letters are drawn in lower case, characters without a glyph are left blank.
+    pub fn draw_text(&mut self, x: i64, y: i64, text: &str, scale: u32, color: Pixel) {
+        let scale = scale as i64;
+        for (i, character) in text.chars().enumerate() {
+            let Some(rows) = glyph(character) else {
+                continue;
+            };
+            let glyph_x = x + i as i64 * GLYPH_ADVANCE as i64 * scale;
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        self.fill_rect(
+                            glyph_x + column * scale,
+                            y + row as i64 * scale,
+                            scale as u32,
+                            scale as u32,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn save_png(&self, path: &Path) -> std::io::Result<()> {
+        self.save_png_with_text(path, &[])
+    }
+
+    /// Saves the image with text chunks, given as keyword and text
+    pub fn save_png_with_text(&self, path: &Path, text: &[(&str, &str)]) -> std::io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        for (keyword, text) in text {
+            // iTXt holds UTF-8, tEXt only Latin-1
+            encoder.add_itxt_chunk(keyword.to_string(), text.to_string())?;
+        }
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(self.pixels.as_flattened())?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+fn point_in_convex_polygon(point: (f32, f32), polygon: &[(f32, f32)]) -> bool {
+    let mut sign = 0.0;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let cross = (b.0 - a.0) * (point.1 - a.1) - (b.1 - a.1) * (point.0 - a.0);
+        if cross == 0.0 {
+            continue;
+        }
+        if sign == 0.0 {
+            sign = cross.signum();
+        } else if sign != cross.signum() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Multiplies the color channels by the given factor, keeping alpha
+pub fn shade(color: Pixel, factor: f32) -> Pixel {
+    let scale = |channel: u8| (channel as f32 * factor).round().clamp(0.0, 255.0) as u8;
+    [scale(color[0]), scale(color[1]), scale(color[2]), color[3]]
+}
+
+/// Horizontal distance between two characters of the built-in font
+pub const GLYPH_ADVANCE: u32 = 4;
+/// Height of a character of the built-in font
+pub const GLYPH_HEIGHT: u32 = 5;
+
+/// Width of a text drawn with the built-in font at scale 1
+pub fn text_width(text: &str) -> u32 {
+    (text.chars().count() as u32 * GLYPH_ADVANCE).saturating_sub(1)
+}
+
+/// Rows of a 3x5 glyph, the highest of the three bits is the leftmost pixel
+fn glyph(character: char) -> Option<[u8; 5]> {
+    let rows = match character.to_ascii_lowercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'a' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'b' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'c' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'd' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'e' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'f' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'g' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'h' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'i' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'j' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'k' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'l' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'm' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'n' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'o' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'p' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'r' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        's' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        't' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'u' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'v' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'w' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'x' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_chunks_are_written() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("preview.png");
+        let text = [("Author", "Änn"), ("Copyright", "CC-BY-4.0")];
+        Image::new(2, 2, [0, 0, 0, 255])
+            .save_png_with_text(&path, &text)
+            .unwrap();
+
+        let reader = png::Decoder::new(File::open(path).unwrap())
+            .read_info()
+            .unwrap();
+        let written: Vec<_> = reader
+            .info()
+            .utf8_text
+            .iter()
+            .map(|chunk| (chunk.keyword.clone(), chunk.get_text().unwrap()))
+            .collect();
+        assert_eq!(
+            written,
+            [
+                ("Author".to_owned(), "Änn".to_owned()),
+                ("Copyright".to_owned(), "CC-BY-4.0".to_owned()),
+            ]
+        );
+    }
+}
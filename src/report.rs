@@ -0,0 +1,302 @@
+use std::path::{Path, PathBuf};
+
+use crate::{attribution::Attribution, bom::BillOfMaterials, i18n::tr, BLOCK_SIDE};
+
+const MARKDOWN_TEMPLATE: &str = include_str!("report.md");
+const BBCODE_TEMPLATE: &str = include_str!("report.bbcode");
+
+/// Markup a shareable report is written in
+#[derive(Clone, Copy)]
+pub enum Markup {
+    Markdown,
+    BBCode,
+}
+
+/// One converted model as listed in the report
+pub struct ReportModel {
+    pub name: String,
+    /// Size of the model in voxels, Magica Voxel orientation
+    pub size: dot_vox::Size,
+    pub patterns: usize,
+    pub preview: Option<PathBuf>,
+}
+
+/// Everything a report is rendered from, collected during the conversion
+pub struct ShareReport<'a> {
+    pub name: &'a str,
+    pub palette: &'a str,
+    pub attribution: &'a Attribution,
+    pub models: &'a [ReportModel],
+    pub bom: &'a BillOfMaterials,
+}
+
+impl ShareReport<'_> {
+    /// Fills the built-in template of the markup, or the given one. Placeholders are
+    /// written as `{{name}}`, unknown placeholders are an error.
+    pub fn render(&self, markup: Markup, template: Option<&str>) -> Result<String, String> {
+        let template = template.unwrap_or(match markup {
+            Markup::Markdown => MARKDOWN_TEMPLATE,
+            Markup::BBCode => BBCODE_TEMPLATE,
+        });
+
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| tr!("report-placeholder-unclosed"))?;
+            let placeholder = rest[start + 2..start + end].trim();
+            output.push_str(
+                &self
+                    .placeholder(placeholder, markup)
+                    .ok_or_else(|| tr!("report-placeholder-unknown", placeholder = placeholder))?,
+            );
+            rest = &rest[start + end + 2..];
+        }
+        output.push_str(rest);
+        Ok(collapse_blank_lines(&output).trim_end().to_owned() + "\n")
+    }
+
+    fn placeholder(&self, name: &str, markup: Markup) -> Option<String> {
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        Some(match name {
+            "name" => self.name.to_owned(),
+            "description" => text(&self.attribution.description),
+            "author" => text(&self.attribution.author),
+            "license" => text(&self.attribution.license),
+            "credits" => self.credits(),
+            "version" => env!("CARGO_PKG_VERSION").to_owned(),
+            "palette" => self.palette.to_owned(),
+            "patterns" => self
+                .models
+                .iter()
+                .map(|model| model.patterns)
+                .sum::<usize>()
+                .to_string(),
+            "blocks" => self
+                .bom
+                .entries()
+                .iter()
+                .map(|entry| entry.full_blocks)
+                .sum::<u64>()
+                .to_string(),
+            "size" => self
+                .models
+                .iter()
+                .map(|model| block_size(model.size))
+                .collect::<Vec<_>>()
+                .join(", "),
+            "preview" => self.previews(markup),
+            "models" => self.models_table(markup),
+            "materials" => self.materials_table(markup),
+            _ => return None,
+        })
+    }
+
+    fn credits(&self) -> String {
+        match (&self.attribution.author, &self.attribution.license) {
+            (Some(author), Some(license)) => format!("By {}, licensed under {}.", author, license),
+            (Some(author), None) => format!("By {}.", author),
+            (None, Some(license)) => format!("Licensed under {}.", license),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Images of the previews, referenced by file name so they can be uploaded next to it
+    fn previews(&self, markup: Markup) -> String {
+        self.models
+            .iter()
+            .filter_map(|model| {
+                let file = model.preview.as_deref()?.file_name()?.to_string_lossy();
+                Some(match markup {
+                    Markup::Markdown => format!("![{}]({})", model.name, file),
+                    Markup::BBCode => format!("[img]{}[/img]", file),
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn models_table(&self, markup: Markup) -> String {
+        let rows = self
+            .models
+            .iter()
+            .map(|model| {
+                vec![
+                    model.name.clone(),
+                    block_size(model.size),
+                    model.patterns.to_string(),
+                ]
+            })
+            .collect();
+        table(
+            markup,
+            &["Model", "Size (W × H × D blocks)", "Patterns"],
+            rows,
+        )
+    }
+
+    fn materials_table(&self, markup: Markup) -> String {
+        let rows = self
+            .bom
+            .entries()
+            .into_iter()
+            .map(|entry| {
+                vec![
+                    entry.block,
+                    entry.full_blocks.to_string(),
+                    entry.bits.to_string(),
+                    format!("{:.1}%", entry.percentage),
+                ]
+            })
+            .collect();
+        table(markup, &["Block", "Blocks", "Bits", "Share"], rows)
+    }
+}
+
+/// Width, height and depth of the model in Minecraft blocks
+fn block_size(size: dot_vox::Size) -> String {
+    let blocks = |voxels: u32| voxels.div_ceil(BLOCK_SIDE as u32);
+    // Magica Voxel's z axis points up, Minecraft's y axis does
+    format!(
+        "{} × {} × {}",
+        blocks(size.y),
+        blocks(size.z),
+        blocks(size.x)
+    )
+}
+
+fn table(markup: Markup, header: &[&str], rows: Vec<Vec<String>>) -> String {
+    match markup {
+        Markup::Markdown => {
+            let row = |cells: Vec<String>| {
+                let cells: Vec<_> = cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+                format!("| {} |", cells.join(" | "))
+            };
+            let mut lines = vec![
+                row(header.iter().map(|cell| cell.to_string()).collect()),
+                format!("|{}", "---|".repeat(header.len())),
+            ];
+            lines.extend(rows.into_iter().map(row));
+            lines.join("\n")
+        }
+        Markup::BBCode => {
+            let row = |cells: Vec<String>, tag: &str| {
+                let cells: String = cells
+                    .iter()
+                    .map(|cell| format!("[{0}]{1}[/{0}]", tag, cell))
+                    .collect();
+                format!("[tr]{}[/tr]", cells)
+            };
+            let mut lines = vec![
+                "[table]".to_owned(),
+                row(header.iter().map(|cell| cell.to_string()).collect(), "th"),
+            ];
+            lines.extend(rows.into_iter().map(|cells| row(cells, "td")));
+            lines.push("[/table]".to_owned());
+            lines.join("\n")
+        }
+    }
+}
+
+/// Removes the empty paragraphs left by placeholders without a value
+fn collapse_blank_lines(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut blank = true;
+    for line in text.lines() {
+        let is_blank = line.trim().is_empty();
+        if !(is_blank && blank) {
+            output.push_str(line);
+            output.push('\n');
+        }
+        blank = is_blank;
+    }
+    output
+}
+
+/// Writes the report, reading the template first if one is given
+pub fn write_report(
+    report: &ShareReport,
+    markup: Markup,
+    template: Option<&Path>,
+    path: &Path,
+) -> Result<(), String> {
+    let template = template
+        .map(|template| {
+            std::fs::read_to_string(template)
+                .map_err(|e| tr!("file-read-failed", file = template.display(), error = e))
+        })
+        .transpose()?;
+    let text = report.render(markup, template.as_deref())?;
+    std::fs::write(path, text)
+        .map_err(|e| tr!("file-write-failed", file = path.display(), error = e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(attribution: &Attribution, markup: Markup, template: Option<&str>) -> String {
+        let report = ShareReport {
+            name: "castle",
+            palette: "blocks.json",
+            attribution,
+            models: &[],
+            bom: &BillOfMaterials::default(),
+        };
+        report.render(markup, template).unwrap()
+    }
+
+    fn attribution() -> Attribution {
+        Attribution {
+            author: Some("Ann".to_owned()),
+            license: Some("CC-BY-4.0".to_owned()),
+            description: Some("A small castle".to_owned()),
+        }
+    }
+
+    #[test]
+    fn built_in_templates_credit_the_author() {
+        for markup in [Markup::Markdown, Markup::BBCode] {
+            let text = render(&attribution(), markup, None);
+            assert!(text.contains("A small castle"), "{}", text);
+            assert!(
+                text.contains("By Ann, licensed under CC-BY-4.0."),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn placeholders_hold_the_fields() {
+        let template = "{{author}}|{{license}}|{{description}}";
+        assert_eq!(
+            render(&attribution(), Markup::Markdown, Some(template)),
+            "Ann|CC-BY-4.0|A small castle\n"
+        );
+        assert_eq!(
+            render(&Attribution::default(), Markup::Markdown, Some(template)),
+            "||\n"
+        );
+    }
+
+    #[test]
+    fn credits_without_license_or_author() {
+        let author = Attribution {
+            author: Some("Ann".to_owned()),
+            ..Attribution::default()
+        };
+        let license = Attribution {
+            license: Some("CC0-1.0".to_owned()),
+            ..Attribution::default()
+        };
+        let template = Some("{{credits}}");
+        assert_eq!(render(&author, Markup::Markdown, template), "By Ann.\n");
+        assert_eq!(
+            render(&license, Markup::Markdown, template),
+            "Licensed under CC0-1.0.\n"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::str::FromStr;
+
+use chisels_and_importers::{BLOCK_SIDE, MAX_MODEL_SIZE};
+use serde::Serialize;
+
+use crate::i18n::tr;
+
+/// Factor the sides of a model are multiplied with, written as a number or a fraction like `1/4`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Scale(pub f32);
+
+impl FromStr for Scale {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || tr!("scale-factor-invalid", scale = value);
+        let scale = match value.split_once('/') {
+            Some((numerator, denominator)) => {
+                let numerator: f32 = numerator.trim().parse().map_err(|_| invalid())?;
+                let denominator: f32 = denominator.trim().parse().map_err(|_| invalid())?;
+                numerator / denominator
+            }
+            None => value.trim().parse().map_err(|_| invalid())?,
+        };
+        if scale.is_finite() && scale > 0.0 {
+            Ok(Scale(scale))
+        } else {
+            Err(tr!("scale-not-positive", scale = value))
+        }
+    }
+}
+
+/// Side of the cube of bits every voxel becomes, a power of two up to a whole block
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BitsPerVoxel(pub u32);
+
+impl FromStr for BitsPerVoxel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.parse::<u32>() {
+            Ok(bits) if bits.is_power_of_two() && bits as usize <= BLOCK_SIDE => {
+                Ok(BitsPerVoxel(bits))
+            }
+            _ => Err(tr!("bits-per-voxel-invalid", size = value)),
+        }
+    }
+}
+
+/// Scale that shrinks the model until its largest side fits into one block. Models that fit
+/// already keep their size.
+pub fn fit_scale(size: dot_vox::Size) -> Scale {
+    let largest = size.x.max(size.y).max(size.z);
+    Scale((BLOCK_SIDE as f32 / largest as f32).min(1.0))
+}
+
+/// Resamples the voxels of the model by the scale. Every resampled voxel covers a region of
+/// source voxels, partly covered voxels at its edges count by the share inside of it. It gets
+/// the color covering most of the region, or stays air if less than `min_fill` of the region
+/// is filled.
+pub fn resample(model: &mut dot_vox::Model, scale: Scale, min_fill: f32) -> Result<(), String> {
+    let source = [model.size.x, model.size.y, model.size.z];
+    let size = source.map(|side| ((side as f32 * scale.0).ceil() as u32).max(1));
+    if let Some(&side) = size.iter().find(|&&side| side > MAX_MODEL_SIZE) {
+        return Err(tr!("resample-too-large", side = side, max = MAX_MODEL_SIZE));
+    }
+
+    // Palette index + 1 of every source voxel, 0 for air
+    let index = |x: u32, y: u32, z: u32| ((x * source[1] + y) * source[2] + z) as usize;
+    let mut grid = vec![0u16; (source[0] * source[1] * source[2]) as usize];
+    for voxel in &model.voxels {
+        let (x, y, z) = (voxel.x as u32, voxel.y as u32, voxel.z as u32);
+        if x < source[0] && y < source[1] && z < source[2] {
+            grid[index(x, y, z)] = voxel.i as u16 + 1;
+        }
+    }
+
+    let coverage = [0, 1, 2].map(|axis| axis_coverage(source[axis], size[axis], scale.0));
+    let mut voxels = Vec::new();
+    let mut colors: Vec<(u16, f32)> = Vec::new();
+    for (x, x_sources) in coverage[0].iter().enumerate() {
+        for (y, y_sources) in coverage[1].iter().enumerate() {
+            for (z, z_sources) in coverage[2].iter().enumerate() {
+                colors.clear();
+                let mut volume = 0.0;
+                for &(source_x, x_share) in x_sources {
+                    for &(source_y, y_share) in y_sources {
+                        for &(source_z, z_share) in z_sources {
+                            let share = x_share * y_share * z_share;
+                            volume += share;
+                            let color = grid[index(source_x, source_y, source_z)];
+                            if color == 0 {
+                                continue;
+                            }
+                            match colors.iter_mut().find(|(known, _)| *known == color) {
+                                Some((_, weight)) => *weight += share,
+                                None => colors.push((color, share)),
+                            }
+                        }
+                    }
+                }
+                let filled: f32 = colors.iter().map(|(_, weight)| weight).sum();
+                if volume == 0.0 || filled / volume < min_fill {
+                    continue;
+                }
+                // Ties go to the lower palette index so the result doesn't depend on the order
+                // voxels were found in
+                let (color, _) = colors
+                    .iter()
+                    .copied()
+                    .max_by(|(a, a_weight), (b, b_weight)| {
+                        a_weight.total_cmp(b_weight).then(b.cmp(a))
+                    })
+                    .unwrap_or_default();
+                if color == 0 {
+                    continue;
+                }
+                voxels.push(dot_vox::Voxel {
+                    x: x as u8,
+                    y: y as u8,
+                    z: z as u8,
+                    i: (color - 1) as u8,
+                });
+            }
+        }
+    }
+
+    model.voxels = voxels;
+    model.size = dot_vox::Size {
+        x: size[0],
+        y: size[1],
+        z: size[2],
+    };
+    Ok(())
+}
+
+/// Source voxels covered by every resampled voxel along an axis, with the share of each that
+/// lies inside of it
+fn axis_coverage(source: u32, size: u32, scale: f32) -> Vec<Vec<(u32, f32)>> {
+    (0..size)
+        .map(|voxel| {
+            let start = voxel as f32 / scale;
+            let end = ((voxel + 1) as f32 / scale).min(source as f32);
+            let first = start.floor() as u32;
+            let last = (end.ceil() as u32).min(source);
+            (first..last)
+                .map(|source_voxel| {
+                    let share = end.min(source_voxel as f32 + 1.0) - start.max(source_voxel as f32);
+                    (source_voxel, share)
+                })
+                .filter(|&(_, share)| share > 0.0)
+                .collect()
+        })
+        .collect()
+}
+
+/// Turns every voxel into a cube of voxels of the same color, e.g. so a model of 8 voxels
+/// fills a block with 2 bits per voxel. Voxels outside of the size of the model are left out.
+pub fn expand(model: &mut dot_vox::Model, bits: BitsPerVoxel) -> Result<(), String> {
+    let bits = bits.0;
+    let size = [model.size.x, model.size.y, model.size.z].map(|side| side * bits);
+    if let Some(&side) = size.iter().find(|&&side| side > MAX_MODEL_SIZE) {
+        return Err(tr!("expand-too-large", side = side, max = MAX_MODEL_SIZE));
+    }
+
+    let source = model.size;
+    let bits = bits as u8;
+    let mut voxels = Vec::with_capacity(model.voxels.len() * (bits as usize).pow(3));
+    for voxel in model.voxels.iter().filter(|voxel| {
+        (voxel.x as u32) < source.x && (voxel.y as u32) < source.y && (voxel.z as u32) < source.z
+    }) {
+        for x in 0..bits {
+            for y in 0..bits {
+                for z in 0..bits {
+                    voxels.push(dot_vox::Voxel {
+                        x: voxel.x * bits + x,
+                        y: voxel.y * bits + y,
+                        z: voxel.z * bits + z,
+                        i: voxel.i,
+                    });
+                }
+            }
+        }
+    }
+    model.voxels = voxels;
+    model.size = dot_vox::Size {
+        x: size[0],
+        y: size[1],
+        z: size[2],
+    };
+    Ok(())
+}
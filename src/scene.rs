@@ -0,0 +1,504 @@
+use std::{collections::HashMap, convert::Infallible, str::FromStr};
+
+use chisels_and_importers::{minecraft_axes, voxel_axes, BLOCK_SIDE, MAX_MODEL_SIZE};
+use dot_vox::{DotVoxData, Frame, SceneNode};
+use serde::Serialize;
+
+use crate::i18n::tr;
+
+/// Deepest node nesting followed, guards against files whose nodes form a cycle
+const MAX_DEPTH: usize = 64;
+
+/// Placement of a node relative to its parent. Rotations in Magica Voxel only swap and
+/// mirror axes, so every row of the rotation holds a single 1 or -1.
+#[derive(Clone, Copy)]
+struct Transform {
+    rotation: [[i32; 3]; 3],
+    translation: [i32; 3],
+}
+
+impl Transform {
+    const IDENTITY: Transform = Transform {
+        rotation: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        translation: [0, 0, 0],
+    };
+
+    /// Reads the rotation (`_r`) and translation (`_t`) of the first animation frame
+    fn from_frame(frame: Option<&Frame>) -> Self {
+        let Some(frame) = frame else {
+            return Self::IDENTITY;
+        };
+        let mut transform = Self::IDENTITY;
+        if let Some(rotation) = frame
+            .attributes
+            .get("_r")
+            .and_then(|rotation| rotation.parse::<u8>().ok())
+        {
+            // Bits 0-1 and 2-3 are the column of the first and second row, bits 4-6 make
+            // the rows negative
+            let first = (rotation & 3) as usize;
+            let second = ((rotation >> 2) & 3) as usize;
+            if first < 3 && second < 3 && first != second {
+                let third = 3 - first - second;
+                transform.rotation = [[0; 3]; 3];
+                for (row, column) in [first, second, third].into_iter().enumerate() {
+                    let negative = rotation & (1 << (4 + row)) != 0;
+                    transform.rotation[row][column] = if negative { -1 } else { 1 };
+                }
+            }
+        }
+        if let Some(translation) = frame.attributes.get("_t") {
+            let values: Vec<_> = translation
+                .split_whitespace()
+                .filter_map(|value| value.parse::<i32>().ok())
+                .collect();
+            if let [x, y, z] = values[..] {
+                transform.translation = [x, y, z];
+            }
+        }
+        transform
+    }
+
+    fn rotate(&self, vector: [i32; 3]) -> [i32; 3] {
+        self.rotation
+            .map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+    }
+
+    /// Transform of a child node in the space of this node
+    fn then(&self, child: &Transform) -> Transform {
+        let columns = [0, 1, 2].map(|column| {
+            self.rotate([
+                child.rotation[0][column],
+                child.rotation[1][column],
+                child.rotation[2][column],
+            ])
+        });
+        let moved = self.rotate(child.translation);
+        Transform {
+            rotation: [0, 1, 2].map(|row| columns.map(|column| column[row])),
+            translation: [0, 1, 2].map(|axis| moved[axis] + self.translation[axis]),
+        }
+    }
+}
+
+/// Model placed into the scene, a model can be placed several times
+pub struct Instance {
+    pub model: usize,
+    transform: Transform,
+}
+
+/// Models of the scene graph with their placement, hidden nodes and layers are left out.
+/// Empty if the file has no scene graph.
+pub fn instances(data: &DotVoxData) -> Vec<Instance> {
+    let mut instances = Vec::new();
+    if !data.scenes.is_empty() {
+        collect_instances(data, 0, Transform::IDENTITY, 0, &mut instances);
+    }
+    instances
+}
+
+fn collect_instances(
+    data: &DotVoxData,
+    node: u32,
+    parent: Transform,
+    depth: usize,
+    instances: &mut Vec<Instance>,
+) {
+    let Some(node) = data.scenes.get(node as usize).filter(|_| depth < MAX_DEPTH) else {
+        return;
+    };
+    let hidden = |attributes: &dot_vox::Dict| attributes.get("_hidden").is_some_and(|h| h == "1");
+    match node {
+        SceneNode::Transform {
+            attributes,
+            frames,
+            child,
+            layer_id,
+        } => {
+            let layer_hidden = data
+                .layers
+                .get(*layer_id as usize)
+                .is_some_and(|layer| hidden(&layer.attributes));
+            if hidden(attributes) || layer_hidden {
+                return;
+            }
+            let transform = parent.then(&Transform::from_frame(frames.first()));
+            collect_instances(data, *child, transform, depth + 1, instances);
+        }
+        SceneNode::Group {
+            attributes,
+            children,
+        } => {
+            if hidden(attributes) {
+                return;
+            }
+            for child in children {
+                collect_instances(data, *child, parent, depth + 1, instances);
+            }
+        }
+        SceneNode::Shape { models, .. } => {
+            for shape_model in models {
+                if (shape_model.model_id as usize) < data.models.len() {
+                    instances.push(Instance {
+                        model: shape_model.model_id as usize,
+                        transform: parent,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Places the instances into one model. Later instances replace the voxels of earlier ones
+/// where they overlap.
+pub fn merge(instances: &[Instance], models: &[dot_vox::Model]) -> Result<dot_vox::Model, String> {
+    let mut placed = Vec::new();
+    for instance in instances {
+        let model = &models[instance.model];
+        let size = [model.size.x, model.size.y, model.size.z].map(|side| side as i32);
+        for voxel in &model.voxels {
+            // Positions are relative to the model center, doubled so they stay integers
+            let position = [voxel.x, voxel.y, voxel.z];
+            let centered = [0, 1, 2].map(|axis| 2 * position[axis] as i32 + 1 - size[axis]);
+            let rotated = instance.transform.rotate(centered);
+            let position = [0, 1, 2]
+                .map(|axis| rotated[axis].div_euclid(2) + instance.transform.translation[axis]);
+            placed.push((position, voxel.i));
+        }
+    }
+
+    let Some(&(first, _)) = placed.first() else {
+        return Err(tr!("scene-empty"));
+    };
+    let (mut min, mut max) = (first, first);
+    for (position, _) in &placed {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+    let size = [0, 1, 2].map(|axis| max[axis] - min[axis] + 1);
+    if size.iter().any(|&side| side > MAX_MODEL_SIZE as i32) {
+        return Err(tr!(
+            "scene-too-large",
+            size = format!("{}x{}x{}", size[0], size[1], size[2]),
+            max = MAX_MODEL_SIZE
+        ));
+    }
+
+    // Keep the last voxel placed at every position
+    let mut voxels = HashMap::new();
+    for (position, color) in placed {
+        let [x, y, z] = [0, 1, 2].map(|axis| (position[axis] - min[axis]) as u8);
+        voxels.insert((x, y, z), color);
+    }
+    let mut voxels: Vec<_> = voxels
+        .into_iter()
+        .map(|((x, y, z), i)| dot_vox::Voxel { x, y, z, i })
+        .collect();
+    voxels.sort_by_key(|voxel| (voxel.x, voxel.y, voxel.z));
+    Ok(dot_vox::Model {
+        size: dot_vox::Size {
+            x: size[0] as u32,
+            y: size[1] as u32,
+            z: size[2] as u32,
+        },
+        voxels,
+    })
+}
+
+/// Shrinks the model to the bounding box of its voxels, moving its lowest voxel to (0, 0, 0),
+/// so chunks start at the model instead of at the corner of its canvas. Models without voxels
+/// are left as they are.
+pub fn crop(model: &mut dot_vox::Model) {
+    let Some(first) = model.voxels.first() else {
+        return;
+    };
+    let (mut min, mut max) = ([first.x, first.y, first.z], [first.x, first.y, first.z]);
+    for voxel in &model.voxels {
+        let position = [voxel.x, voxel.y, voxel.z];
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+    for voxel in &mut model.voxels {
+        voxel.x -= min[0];
+        voxel.y -= min[1];
+        voxel.z -= min[2];
+    }
+    let [x, y, z] = [0, 1, 2].map(|axis| (max[axis] - min[axis]) as u32 + 1);
+    model.size = dot_vox::Size { x, y, z };
+}
+
+/// Axis of Minecraft given on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    /// Index of the axis of Magica Voxel that becomes this axis
+    fn voxel_axis(self) -> usize {
+        minecraft_axes([0, 1, 2])[self as usize]
+    }
+}
+
+impl FromStr for Axis {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "x" => Ok(Axis::X),
+            "y" => Ok(Axis::Y),
+            "z" => Ok(Axis::Z),
+            other => Err(tr!("axis-unknown", axis = other)),
+        }
+    }
+}
+
+/// Turn of the model around an axis of Minecraft, counterclockwise when looking from the
+/// positive end of the axis, e.g. `y:90` turns the model to the left seen from above
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Rotation {
+    pub axis: Axis,
+    pub degrees: u16,
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (axis, degrees) = value
+            .split_once(':')
+            .ok_or_else(|| tr!("rotation-invalid", value = value))?;
+        let axis = axis.parse()?;
+        let degrees = match degrees {
+            "90" | "180" | "270" => degrees.parse().unwrap(),
+            other => return Err(tr!("rotation-angle-unsupported", angle = other)),
+        };
+        Ok(Self { axis, degrees })
+    }
+}
+
+/// Turns the model by the rotations in order, then mirrors it along the axes. The model keeps
+/// its lowest corner at (0, 0, 0) and its canvas turns with it. Voxels outside of the size of
+/// the model are left out.
+pub fn orient(model: &mut dot_vox::Model, rotations: &[Rotation], mirrors: &[Axis]) {
+    if rotations.is_empty() && mirrors.is_empty() {
+        return;
+    }
+    let mut size = [model.size.x, model.size.y, model.size.z].map(|side| side as i32);
+    let mut positions: Vec<_> = model
+        .voxels
+        .iter()
+        .map(|voxel| ([voxel.x, voxel.y, voxel.z].map(i32::from), voxel.i))
+        .filter(|(position, _)| (0..3).all(|axis| position[axis] < size[axis]))
+        .collect();
+
+    for rotation in rotations {
+        // The two other axes in the order of the right hand rule, a quarter turn moves the
+        // first of them onto the second
+        let axis = rotation.axis as usize;
+        let [first, second] = [(axis + 1) % 3, (axis + 2) % 3]
+            .map(|minecraft_axis| minecraft_axes([0, 1, 2])[minecraft_axis]);
+        for _ in 0..rotation.degrees / 90 {
+            for (position, _) in &mut positions {
+                let turned_second = position[first];
+                position[first] = size[second] - 1 - position[second];
+                position[second] = turned_second;
+            }
+            size.swap(first, second);
+        }
+    }
+    for mirror in mirrors {
+        let axis = mirror.voxel_axis();
+        for (position, _) in &mut positions {
+            position[axis] = size[axis] - 1 - position[axis];
+        }
+    }
+
+    model.voxels = positions
+        .into_iter()
+        .map(|([x, y, z], i)| dot_vox::Voxel {
+            x: x as u8,
+            y: y as u8,
+            z: z as u8,
+            i,
+        })
+        .collect();
+    let [x, y, z] = size.map(|side| side as u32);
+    model.size = dot_vox::Size { x, y, z };
+}
+
+/// Where a model sits along one axis of the blocks it is split into
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Anchor {
+    /// The lowest voxel is at the start of a block
+    #[default]
+    Min,
+    /// The middle of the model is at the middle of a block
+    Center,
+    /// The highest voxel is at the end of a block
+    Max,
+}
+
+/// Anchor of the model along an axis of Minecraft, e.g. `y=max`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Alignment {
+    pub axis: Axis,
+    pub anchor: Anchor,
+}
+
+impl FromStr for Alignment {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (axis, anchor) = value
+            .split_once('=')
+            .ok_or_else(|| tr!("alignment-invalid", value = value))?;
+        let axis = axis.parse()?;
+        let anchor = match anchor {
+            "min" => Anchor::Min,
+            "center" => Anchor::Center,
+            "max" => Anchor::Max,
+            other => return Err(tr!("alignment-anchor-unknown", anchor = other)),
+        };
+        Ok(Self { axis, anchor })
+    }
+}
+
+/// Pads the model with air before its lowest corner so it sits in the blocks as the
+/// alignments ask, axes without an alignment keep their start at a block. Fails if a voxel
+/// would end up outside of the 256 voxels a model can span.
+pub fn align(model: &mut dot_vox::Model, alignments: &[Alignment]) -> Result<(), String> {
+    let side = BLOCK_SIDE as u32;
+    let mut padding = [0; 3];
+    let sizes = [model.size.x, model.size.y, model.size.z];
+    for alignment in alignments {
+        let axis = alignment.axis.voxel_axis();
+        let size = sizes[axis];
+        padding[axis] = match alignment.anchor {
+            Anchor::Min => 0,
+            Anchor::Center => (side / 2 + side - size / 2 % side) % side,
+            Anchor::Max => (side - size % side) % side,
+        };
+    }
+    if let Some(axis) = (0..3).find(|&axis| sizes[axis] + padding[axis] > MAX_MODEL_SIZE) {
+        return Err(tr!(
+            "alignment-too-large",
+            size = sizes[axis],
+            axis = voxel_axes(['x', 'y', 'z'])[axis],
+            padding = padding[axis],
+            max = MAX_MODEL_SIZE
+        ));
+    }
+
+    for voxel in &mut model.voxels {
+        voxel.x += padding[0] as u8;
+        voxel.y += padding[1] as u8;
+        voxel.z += padding[2] as u8;
+    }
+    let [x, y, z] = [0, 1, 2].map(|axis| sizes[axis] + padding[axis]);
+    model.size = dot_vox::Size { x, y, z };
+    Ok(())
+}
+
+/// Model chosen on the command line, by its number starting at 1 or by its name in the scene
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ModelSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for ModelSelector {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value.parse() {
+            Ok(index) => ModelSelector::Index(index),
+            Err(_) => ModelSelector::Name(value.to_owned()),
+        })
+    }
+}
+
+/// Names given to models in the outliner, stored on the transform node above each shape.
+/// A model placed several times can have several names.
+pub fn model_names(data: &DotVoxData) -> Vec<(String, usize)> {
+    data.scenes
+        .iter()
+        .filter_map(|node| match node {
+            SceneNode::Transform {
+                attributes, child, ..
+            } => Some((attributes.get("_name")?, data.scenes.get(*child as usize)?)),
+            _ => None,
+        })
+        .filter_map(|(name, child)| match child {
+            SceneNode::Shape { models, .. } => {
+                Some((name.clone(), models.first()?.model_id as usize))
+            }
+            _ => None,
+        })
+        .filter(|(_, model)| *model < data.models.len())
+        .collect()
+}
+
+/// Indices of the selected models, starting at 0. Names are matched exactly, or ignoring
+/// case if no name matches exactly, and select every model with that name.
+pub fn select_models(data: &DotVoxData, selectors: &[ModelSelector]) -> Result<Vec<usize>, String> {
+    let model_count = data.models.len();
+    let names = model_names(data);
+    let mut indices = Vec::new();
+    for selector in selectors {
+        match selector {
+            ModelSelector::Index(index) => {
+                if !(1..=model_count).contains(index) {
+                    return Err(tr!(
+                        "invalid-model-index",
+                        index = index,
+                        count = model_count
+                    ));
+                }
+                indices.push(index - 1);
+            }
+            ModelSelector::Name(name) => {
+                let mut matches: Vec<_> = names
+                    .iter()
+                    .filter(|(model_name, _)| model_name == name)
+                    .map(|(_, model)| *model)
+                    .collect();
+                if matches.is_empty() {
+                    matches = names
+                        .iter()
+                        .filter(|(model_name, _)| model_name.to_lowercase() == name.to_lowercase())
+                        .map(|(_, model)| *model)
+                        .collect();
+                }
+                if matches.is_empty() {
+                    let mut available: Vec<_> =
+                        names.iter().map(|(name, _)| name.as_str()).collect();
+                    available.sort();
+                    available.dedup();
+                    return Err(if available.is_empty() {
+                        tr!("unknown-model-name-unnamed", name = name)
+                    } else {
+                        tr!(
+                            "unknown-model-name",
+                            name = name,
+                            names = available.join(", ")
+                        )
+                    });
+                }
+                matches.sort();
+                matches.dedup();
+                indices.extend(matches);
+            }
+        }
+    }
+    Ok(indices)
+}
@@ -0,0 +1,419 @@
+//! Sponge schematics (`.schem`) as read and written by WorldEdit. Builds are written with a
+//! block for every voxel, and read back with a voxel for every block to make miniatures.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chisels_and_importers::{gzip, voxel_axes, BlockPalette, PaletteBlock, MAX_MODEL_SIZE};
+use dot_vox::DotVoxData;
+use fastnbt::{ByteArray, IntArray, Value};
+use serde::Serialize;
+
+use crate::{
+    console,
+    i18n::tr,
+    imported::{to_vox_data, ImportedModel},
+    structure::Structure,
+};
+
+pub const SCHEMATIC_EXTENSION: &str = ".schem";
+/// Version of the Sponge schematic format that is written
+const SCHEMATIC_VERSION: i32 = 3;
+const AIR: &str = "minecraft:air";
+/// Blocks that are left out without a warning, they are air in the game too
+const AIR_BLOCKS: [&str; 3] = [AIR, "minecraft:cave_air", "minecraft:void_air"];
+/// Color of the blocks when there's no palette to look them up in, e.g. for `--list`
+const UNMAPPED_COLOR: [u8; 3] = [0x80, 0x80, 0x80];
+
+/// The structure as a gzip compressed Sponge schematic, as read by WorldEdit
+pub fn encode(structure: &Structure, data_version: i32) -> Vec<u8> {
+    let [width, height, length] = structure.size.map(|side| side as usize);
+
+    // Air is the first entry, it fills every position without a block
+    let mut palette = BTreeMap::from([(AIR.to_owned(), 0)]);
+    let mut indices = vec![0u32; width * height * length];
+    for &(position, block) in &structure.blocks {
+        let next = palette.len() as i32;
+        let index = *palette.entry(block_state(block)).or_insert(next);
+        let [x, y, z] = position.map(|axis| axis as usize);
+        // Positions are ordered by y, then z, then x
+        indices[(y * length + z) * width + x] = index as u32;
+    }
+
+    let mut data = Vec::with_capacity(indices.len());
+    for index in indices {
+        write_varint(&mut data, index);
+    }
+    let file = SchematicFile {
+        schematic: Schematic {
+            version: SCHEMATIC_VERSION,
+            data_version,
+            width: width as i16,
+            height: height as i16,
+            length: length as i16,
+            offset: IntArray::new(vec![0, 0, 0]),
+            blocks: BlockContainer {
+                palette,
+                data: ByteArray::new(data),
+            },
+        },
+    };
+    gzip::compress(&fastnbt::to_bytes(&file).unwrap())
+}
+
+/// Block state in the text form of commands, e.g. `minecraft:oak_log[axis=y]`
+fn block_state(block: &PaletteBlock) -> String {
+    if block.properties.is_empty() {
+        return block.name.clone();
+    }
+    let properties: Vec<_> = block
+        .properties
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    format!("{}[{}]", block.name, properties.join(","))
+}
+
+/// Colors the blocks of a schematic are turned into, so matching the colors against the
+/// palette gives back the same blocks
+pub struct ReversePalette {
+    /// Color of every block state and block name of the palette
+    colors: HashMap<String, [u8; 3]>,
+    /// Colors of the `--block-map` file, `None` for blocks that are left out
+    overrides: HashMap<String, Option<[u8; 3]>>,
+}
+
+impl ReversePalette {
+    /// The colors of the blocks of the palette, with the blocks of the map replaced by a block
+    /// of the palette, a color code or `minecraft:air`
+    pub fn new(palette: &BlockPalette, block_map: HashMap<String, String>) -> Result<Self, String> {
+        let mut colors = HashMap::new();
+        for block in &palette.mapping {
+            let color = [block.color.red, block.color.green, block.color.blue];
+            colors.insert(block.state(), color);
+            // A block of another state gets the color of the first state of its name
+            colors.entry(block.name.clone()).or_insert(color);
+        }
+        let overrides = block_map
+            .into_iter()
+            .map(|(block, target)| {
+                let color = if AIR_BLOCKS.contains(&target.as_str()) {
+                    None
+                } else if let Some(color) = colors.get(&target) {
+                    Some(*color)
+                } else {
+                    let color = target
+                        .parse::<palette::Srgb<u8>>()
+                        .map_err(|_| tr!("block-map-target-invalid", target = target))?;
+                    Some([color.red, color.green, color.blue])
+                };
+                Ok((block, color))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { colors, overrides })
+    }
+
+    /// What a block state like `minecraft:oak_log[axis=y]` becomes, the block map decides
+    /// even for blocks with data
+    fn map(&self, state: &str, with_data: bool) -> Mapped {
+        let name = block_name(state);
+        if let Some(color) = self
+            .overrides
+            .get(state)
+            .or_else(|| self.overrides.get(name))
+        {
+            return color.map_or(Mapped::Air, Mapped::Color);
+        }
+        if with_data {
+            return Mapped::WithData;
+        }
+        match self.colors.get(state).or_else(|| self.colors.get(name)) {
+            Some(&color) => Mapped::Color(color),
+            None => Mapped::Unmapped,
+        }
+    }
+}
+
+/// What a block of a schematic becomes
+enum Mapped {
+    Color([u8; 3]),
+    /// Left out by the block map
+    Air,
+    /// Left out since the data of the block would be lost
+    WithData,
+    /// Left out since the palette has no such block
+    Unmapped,
+}
+
+/// Reads a `.schem` file, of version 1 and 2 or of version 3 as written by [`encode`], into a
+/// model with a voxel for every block. Blocks are given the color of the same block in the
+/// palette. Blocks the palette doesn't have and blocks with data like chests become air and
+/// are listed in a warning. Without a palette every block is kept in one color.
+pub fn parse_schem(bytes: &[u8], palette: Option<&ReversePalette>) -> Result<DotVoxData, String> {
+    let nbt = gzip::decompress(bytes)?;
+    let root: Value = fastnbt::from_bytes(&nbt).map_err(|e| e.to_string())?;
+    let root = compound(&root).ok_or_else(|| tr!("schem-invalid"))?;
+    // Version 3 nests the schematic and its blocks, earlier versions have them at the root
+    let (schematic, blocks) = match root.get("Schematic").and_then(compound) {
+        Some(schematic) => {
+            let blocks = schematic
+                .get("Blocks")
+                .and_then(compound)
+                .ok_or_else(|| tr!("schem-blocks-missing"))?;
+            (schematic, blocks)
+        }
+        None => (root, root),
+    };
+    let size = ["Width", "Height", "Length"].map(|key| {
+        // Sides are stored as shorts, but of up to 65535 blocks
+        schematic
+            .get(key)
+            .and_then(integer)
+            .map(|side| side as u16 as u32)
+    });
+    let [Some(width), Some(height), Some(length)] = size else {
+        return Err(tr!("schem-size-missing"));
+    };
+    if [width, height, length]
+        .iter()
+        .any(|&side| side > MAX_MODEL_SIZE)
+    {
+        return Err(tr!(
+            "schem-too-large",
+            size = format!("{}x{}x{}", width, height, length),
+            max = MAX_MODEL_SIZE
+        ));
+    }
+
+    let mut states = HashMap::new();
+    let palette_entries = blocks
+        .get("Palette")
+        .and_then(compound)
+        .ok_or_else(|| tr!("schem-palette-missing"))?;
+    for (state, index) in palette_entries {
+        let index = integer(index).ok_or_else(|| tr!("schem-palette-invalid"))?;
+        states.insert(index as u32, state.as_str());
+    }
+    let data = match blocks.get("Data").or_else(|| blocks.get("BlockData")) {
+        Some(Value::ByteArray(data)) => data,
+        _ => return Err(tr!("schem-data-missing")),
+    };
+    // Blocks that store more than their state, e.g. the items of a chest
+    let mut with_data = HashSet::new();
+    let block_entities = blocks
+        .get("BlockEntities")
+        .or_else(|| blocks.get("TileEntities"));
+    if let Some(Value::List(block_entities)) = block_entities {
+        for block_entity in block_entities.iter().filter_map(compound) {
+            // An int array, some tools write a list of ints
+            let position: Option<Vec<i64>> = match block_entity.get("Pos") {
+                Some(Value::IntArray(position)) => {
+                    Some(position.iter().map(|&axis| axis as i64).collect())
+                }
+                Some(Value::List(position)) => position.iter().map(integer).collect(),
+                _ => None,
+            };
+            if let Some(&[x, y, z]) = position.as_deref() {
+                with_data.insert([x, y, z].map(|axis| axis as u32));
+            }
+        }
+    }
+
+    let mut bytes = data.iter().map(|&byte| byte as u8);
+    let mut voxels = Vec::new();
+    let mut unmapped = BTreeMap::<&str, usize>::new();
+    let mut left_out_with_data = BTreeMap::<&str, usize>::new();
+    // Positions are ordered by y, then z, then x
+    for y in 0..height {
+        for z in 0..length {
+            for x in 0..width {
+                let index = read_varint(&mut bytes).ok_or_else(|| tr!("schem-data-ends-early"))?;
+                let state = *states
+                    .get(&index)
+                    .ok_or_else(|| tr!("schem-entry-missing", index = index))?;
+                let name = block_name(state);
+                if AIR_BLOCKS.contains(&name) {
+                    continue;
+                }
+                let mapped = match palette {
+                    None => Mapped::Color(UNMAPPED_COLOR),
+                    Some(palette) => palette.map(state, with_data.contains(&[x, y, z])),
+                };
+                match mapped {
+                    Mapped::Color(color) => {
+                        let position = voxel_axes([x, y, z]).map(|axis| axis as u8);
+                        voxels.push((position, color));
+                    }
+                    Mapped::Air => {}
+                    Mapped::WithData => *left_out_with_data.entry(name).or_default() += 1,
+                    Mapped::Unmapped => *unmapped.entry(name).or_default() += 1,
+                }
+            }
+        }
+    }
+
+    if let Some((count, blocks)) = summary(&unmapped) {
+        let message = tr!("schem-unmapped", count = count, blocks = blocks);
+        console::warn(message);
+    }
+    if let Some((count, blocks)) = summary(&left_out_with_data) {
+        let message = tr!("schem-block-entities", count = count, blocks = blocks);
+        console::warn(message);
+    }
+    Ok(to_vox_data(vec![ImportedModel {
+        name: "schematic".to_owned(),
+        position: [0; 3],
+        size: voxel_axes([width, height, length]),
+        voxels,
+        hidden: false,
+    }]))
+}
+
+/// Number of left out blocks and a list of them with the count of every block
+fn summary(blocks: &BTreeMap<&str, usize>) -> Option<(usize, String)> {
+    if blocks.is_empty() {
+        return None;
+    }
+    let list: Vec<_> = blocks
+        .iter()
+        .map(|(name, count)| format!("{} ({})", name, count))
+        .collect();
+    Some((blocks.values().sum(), list.join(", ")))
+}
+
+/// Name of a block state without its properties
+fn block_name(state: &str) -> &str {
+    state.split('[').next().unwrap_or(state)
+}
+
+fn compound(value: &Value) -> Option<&HashMap<String, Value>> {
+    match value {
+        Value::Compound(compound) => Some(compound),
+        _ => None,
+    }
+}
+
+/// Value of a tag of any integer type, the type of some tags differs between versions
+fn integer(value: &Value) -> Option<i64> {
+    match *value {
+        Value::Byte(value) => Some(value as i64),
+        Value::Short(value) => Some(value as i64),
+        Value::Int(value) => Some(value as i64),
+        Value::Long(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// Reads a value written by [`write_varint`]
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Option<u32> {
+    let mut value = 0u32;
+    for shift in (0..32).step_by(7) {
+        let byte = bytes.next()?;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Appends the value in 7 bit groups, lowest first, with the top bit set on all but the last
+fn write_varint(data: &mut Vec<i8>, mut value: u32) {
+    while value >= 0x80 {
+        data.push((value as u8 & 0x7f | 0x80) as i8);
+        value >>= 7;
+    }
+    data.push(value as i8);
+}
+
+#[derive(Serialize)]
+struct SchematicFile {
+    #[serde(rename = "Schematic")]
+    schematic: Schematic,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Schematic {
+    version: i32,
+    data_version: i32,
+    width: i16,
+    height: i16,
+    length: i16,
+    offset: IntArray,
+    blocks: BlockContainer,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct BlockContainer {
+    palette: BTreeMap<String, i32>,
+    data: ByteArray,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Voxels of the model with the color of every voxel, sorted by position
+    fn colored_voxels(data: &DotVoxData) -> Vec<([u8; 3], [u8; 3])> {
+        let mut voxels: Vec<_> = data.models[0]
+            .voxels
+            .iter()
+            .map(|voxel| {
+                let color = data.palette[voxel.i as usize];
+                ([voxel.x, voxel.y, voxel.z], [color.r, color.g, color.b])
+            })
+            .collect();
+        voxels.sort();
+        voxels
+    }
+
+    #[test]
+    fn written_schematic_reads_back() {
+        let palette = testing::palette();
+        let blocks: Vec<_> = palette.mapping.iter().take(3).collect();
+        // Every block at an uneven position, so mixed up axes would show
+        let structure = Structure {
+            offset: [0, 0, 0],
+            size: [3, 2, 4],
+            blocks: vec![
+                ([0, 0, 0], blocks[0]),
+                ([2, 0, 1], blocks[1]),
+                ([1, 1, 3], blocks[2]),
+                ([2, 1, 3], blocks[0]),
+            ],
+        };
+        let reverse_palette = ReversePalette::new(&palette, HashMap::new()).unwrap();
+        let data = parse_schem(&encode(&structure, 3465), Some(&reverse_palette)).unwrap();
+
+        let size = data.models[0].size;
+        assert_eq!([size.x, size.y, size.z], voxel_axes([3, 2, 4]));
+        let mut expected: Vec<_> = structure
+            .blocks
+            .iter()
+            .map(|&(position, block)| {
+                let color = [block.color.red, block.color.green, block.color.blue];
+                (voxel_axes(position).map(|axis| axis as u8), color)
+            })
+            .collect();
+        expected.sort();
+        assert_eq!(colored_voxels(&data), expected);
+    }
+
+    #[test]
+    fn varints_read_back() {
+        let values = [0, 1, 127, 128, 300, 16_383, 16_384, u32::MAX];
+        let mut data = Vec::new();
+        for value in values {
+            write_varint(&mut data, value);
+        }
+        let mut bytes = data.iter().map(|&byte| byte as u8);
+        for value in values {
+            assert_eq!(read_varint(&mut bytes), Some(value));
+        }
+        assert_eq!(read_varint(&mut bytes), None);
+        assert_eq!(data[..3], [0, 1, 127]);
+        assert_eq!(data[3..5], [0x80u8 as i8, 1]);
+    }
+}
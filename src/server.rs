@@ -0,0 +1,672 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Read,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+    sync::Arc,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    archive::ZipWriter,
+    bom::{BillOfMaterials, BomEntry},
+    create_patterns,
+    error::Error,
+    i18n::tr,
+    BlockPalette, InputFormat, ModelOutputs, VoxPalette,
+};
+
+/// Conversion options sent as JSON, the model is a base64 encoded model file
+#[derive(Deserialize)]
+struct ConvertRequest {
+    model: String,
+    /// Format of the model like `--input-format`, Magica Voxel if missing
+    format: Option<String>,
+    /// Color codes mapped to block ids, the server palette is used if missing
+    palette: Option<HashMap<String, String>>,
+    /// Models to convert, starting at 1. All models are converted if missing.
+    models: Option<Vec<usize>>,
+}
+
+/// Model file and options of a request, however they were sent
+struct Upload {
+    model: Vec<u8>,
+    format: InputFormat,
+    palette: Option<HashMap<String, String>>,
+    models: Option<Vec<usize>>,
+}
+
+/// Headers of a request that decide how its body is read and how to respond
+struct RequestHeaders {
+    content_type: String,
+    /// `X-Model-Format`, the format of the model file
+    format: Option<String>,
+    /// `X-Models`, indices of the models to convert like `1,2`
+    models: Option<String>,
+    /// Whether the patterns should be sent as a zip archive
+    zip: bool,
+}
+
+impl RequestHeaders {
+    fn of(request: &Request) -> Self {
+        let header = |name: &'static str| {
+            request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv(name))
+                .map(|header| header.value.as_str().to_owned())
+        };
+        Self {
+            content_type: header("Content-Type").unwrap_or_default(),
+            format: header("X-Model-Format"),
+            models: header("X-Models"),
+            zip: header("Accept").is_some_and(|accept| accept.contains(ZIP_TYPE)),
+        }
+    }
+}
+
+/// Content type of pattern archives
+const ZIP_TYPE: &str = "application/zip";
+
+#[derive(Serialize)]
+struct ConvertResponse {
+    models: Vec<ConvertedModel>,
+}
+
+#[derive(Serialize)]
+struct ConvertedModel {
+    /// Index of the model in the file, starting at 1
+    index: usize,
+    patterns: Vec<EncodedPattern>,
+    materials: Vec<BomEntry>,
+}
+
+#[derive(Serialize)]
+struct EncodedPattern {
+    file: String,
+    /// Base64 encoded content of the pattern file
+    data: String,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Status code and message of a failed request
+type Failure = (u16, String);
+
+/// Converts models sent to `POST /convert` until the process is stopped. Requests are
+/// handled by one worker thread per CPU core.
+pub fn serve(
+    host: &str,
+    port: u16,
+    palette: BlockPalette,
+    max_body_size: usize,
+) -> Result<(), Error> {
+    let server = bind(host, port)?;
+    println!(
+        "{}",
+        tr!("server-listening", address = server.server_addr())
+    );
+    run(server, palette, max_body_size);
+    Ok(())
+}
+
+/// Listens on the address, fails if it is in use or not an address of this machine
+fn bind(host: &str, port: u16) -> Result<Server, Error> {
+    Server::http((host, port)).map_err(|error| {
+        Error::Input(tr!(
+            "server-bind-failed",
+            address = format!("{}:{}", host, port),
+            error = error
+        ))
+    })
+}
+
+fn run(server: Server, palette: BlockPalette, max_body_size: usize) {
+    let server = Arc::new(server);
+    let palette = Arc::new(palette);
+
+    let workers = std::thread::available_parallelism().map_or(1, |count| count.get());
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let palette = Arc::clone(&palette);
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request, &palette, max_body_size);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+fn handle(mut request: Request, palette: &BlockPalette, max_body_size: usize) {
+    let headers = RequestHeaders::of(&request);
+    let result = if request.url() != "/convert" {
+        Err((404, tr!("server-unknown-path")))
+    } else if *request.method() != Method::Post {
+        Err((405, tr!("server-method-unsupported")))
+    } else {
+        read_body(&mut request, max_body_size).and_then(|body| {
+            let upload = read_upload(&headers, body)?;
+            // A panic while converting only fails this request
+            catch_unwind(AssertUnwindSafe(|| convert(upload, palette)))
+                .unwrap_or_else(|_| Err((500, tr!("server-conversion-failed"))))
+        })
+    };
+
+    let (status, content_type, body) = match result {
+        Ok(models) if headers.zip => match zip_patterns(&models) {
+            Ok(archive) => (200, ZIP_TYPE, archive),
+            Err(error) => (500, "application/json", error_body(error.to_string())),
+        },
+        Ok(models) => (200, "application/json", json_body(models)),
+        Err((status, error)) => (status, "application/json", error_body(error)),
+    };
+    let response = Response::from_data(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", content_type).unwrap());
+    if let Err(e) = request.respond(response) {
+        eprintln!("{}", tr!("server-respond-failed", error = e));
+    }
+}
+
+/// Patterns of every model base64 encoded, with the bill of materials of the model
+fn json_body(models: Vec<Converted>) -> Vec<u8> {
+    let models = models
+        .into_iter()
+        .map(|model| ConvertedModel {
+            index: model.index,
+            patterns: model
+                .patterns
+                .into_iter()
+                .map(|(file, data)| EncodedPattern {
+                    file,
+                    data: STANDARD.encode(data),
+                })
+                .collect(),
+            materials: model.materials,
+        })
+        .collect();
+    serde_json::to_vec(&ConvertResponse { models }).unwrap()
+}
+
+fn error_body(error: String) -> Vec<u8> {
+    serde_json::to_vec(&ErrorResponse { error }).unwrap()
+}
+
+/// Pattern files of every model in one archive, named like in the JSON response
+fn zip_patterns(models: &[Converted]) -> std::io::Result<Vec<u8>> {
+    let mut archive = Vec::new();
+    let mut zip = ZipWriter::new(&mut archive);
+    for (file, data) in models.iter().flat_map(|model| &model.patterns) {
+        zip.add(file, data)?;
+    }
+    zip.finish()?;
+    Ok(archive)
+}
+
+fn read_body(request: &mut Request, max_body_size: usize) -> Result<Vec<u8>, Failure> {
+    let too_large = || (413, tr!("server-body-too-large", max = max_body_size));
+    if request
+        .body_length()
+        .is_some_and(|length| length > max_body_size)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(max_body_size as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| (400, tr!("server-body-read-failed", error = e)))?;
+    if body.len() > max_body_size {
+        return Err(too_large());
+    }
+    Ok(body)
+}
+
+/// Reads the model and options from a JSON body, a `multipart/form-data` form or a body
+/// that is the model file itself
+fn read_upload(headers: &RequestHeaders, body: Vec<u8>) -> Result<Upload, Failure> {
+    let bad_request = |message: String| (400, message);
+    let header_format = headers
+        .format
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(bad_request)?;
+    let header_models = headers
+        .models
+        .as_deref()
+        .map(parse_indices)
+        .transpose()
+        .map_err(bad_request)?;
+    let media_type = headers.content_type.split(';').next().unwrap_or_default();
+
+    if media_type.trim().eq_ignore_ascii_case("application/json") {
+        let request: ConvertRequest = serde_json::from_slice(&body)
+            .map_err(|e| bad_request(tr!("server-request-invalid", error = e)))?;
+        let model = STANDARD
+            .decode(&request.model)
+            .map_err(|e| bad_request(tr!("server-base64-invalid", error = e)))?;
+        let format = request
+            .format
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(bad_request)?;
+        return Ok(Upload {
+            model,
+            format: header_format.or(format).unwrap_or_default(),
+            palette: request.palette,
+            models: header_models.or(request.models),
+        });
+    }
+
+    if media_type
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        let boundary = parameter(&headers.content_type, "boundary")
+            .ok_or_else(|| bad_request(tr!("server-boundary-missing")))?;
+        let mut upload = Upload {
+            model: Vec::new(),
+            format: header_format.unwrap_or_default(),
+            palette: None,
+            models: header_models,
+        };
+        let mut has_model = false;
+        for part in form_parts(&body, &boundary).map_err(bad_request)? {
+            match part.name.as_str() {
+                "model" => {
+                    // The file name tells the format if the header doesn't
+                    if let Some(format) = header_format.or_else(|| {
+                        InputFormat::from_extension(Path::new(part.file_name.as_deref()?))
+                    }) {
+                        upload.format = format;
+                    }
+                    upload.model = part.data.to_vec();
+                    has_model = true;
+                }
+                "palette" => {
+                    upload.palette = Some(
+                        serde_json::from_slice(part.data)
+                            .map_err(|e| bad_request(tr!("server-palette-invalid", error = e)))?,
+                    );
+                }
+                "models" => {
+                    let indices = std::str::from_utf8(part.data)
+                        .map_err(|_| bad_request(tr!("server-models-not-text")))?;
+                    upload.models = Some(parse_indices(indices).map_err(bad_request)?);
+                }
+                other => return Err(bad_request(tr!("server-field-unknown", field = other))),
+            }
+        }
+        if !has_model {
+            return Err(bad_request(tr!("server-model-missing")));
+        }
+        return Ok(upload);
+    }
+
+    Ok(Upload {
+        model: body,
+        format: header_format.unwrap_or_default(),
+        palette: None,
+        models: header_models,
+    })
+}
+
+/// Model indices separated by commas, like `1,2`
+fn parse_indices(indices: &str) -> Result<Vec<usize>, String> {
+    indices
+        .split(',')
+        .map(|index| {
+            index
+                .trim()
+                .parse()
+                .map_err(|_| tr!("server-model-index-invalid", index = index))
+        })
+        .collect()
+}
+
+/// Value of a parameter of a header like `multipart/form-data; boundary=x`, without quotes
+fn parameter(header: &str, name: &str) -> Option<String> {
+    header.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_owned())
+    })
+}
+
+/// Field of a `multipart/form-data` body
+struct FormPart<'a> {
+    name: String,
+    file_name: Option<String>,
+    data: &'a [u8],
+}
+
+/// Splits a `multipart/form-data` body into its fields
+fn form_parts<'a>(body: &'a [u8], boundary: &str) -> Result<Vec<FormPart<'a>>, String> {
+    let find = |haystack: &[u8], needle: &[u8]| {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    };
+    let invalid = || tr!("server-multipart-invalid");
+    // The delimiter is preceded by a line break, except at the start of the body
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    let mut rest = if body.starts_with(&delimiter[2..]) {
+        &body[delimiter.len() - 2..]
+    } else {
+        let start = find(body, &delimiter).ok_or_else(invalid)?;
+        &body[start + delimiter.len()..]
+    };
+
+    let mut parts = Vec::new();
+    // The last delimiter is followed by `--`
+    while !rest.starts_with(b"--") {
+        rest = rest.strip_prefix(b"\r\n").ok_or_else(invalid)?;
+        let end = find(rest, &delimiter).ok_or_else(invalid)?;
+        let part = &rest[..end];
+        rest = &rest[end + delimiter.len()..];
+
+        let headers_end = find(part, b"\r\n\r\n").ok_or_else(invalid)?;
+        let headers = std::str::from_utf8(&part[..headers_end]).map_err(|_| invalid())?;
+        let disposition = headers
+            .split("\r\n")
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("Content-Disposition")
+                    .then_some(value)
+            })
+            .ok_or_else(invalid)?;
+        parts.push(FormPart {
+            name: parameter(disposition, "name").ok_or_else(invalid)?,
+            file_name: parameter(disposition, "filename"),
+            data: &part[headers_end + 4..],
+        });
+    }
+    Ok(parts)
+}
+
+/// Patterns of a converted model
+struct Converted {
+    /// Index of the model in the file, starting at 1
+    index: usize,
+    /// Names and contents of the pattern files
+    patterns: Vec<(String, Vec<u8>)>,
+    materials: Vec<BomEntry>,
+}
+
+fn convert(upload: Upload, server_palette: &BlockPalette) -> Result<Vec<Converted>, Failure> {
+    let bad_request = |message: String| (400, message);
+    let voxel_data = upload
+        .format
+        .parse(&upload.model, None)
+        .map_err(|e| bad_request(tr!("server-model-invalid", error = e)))?;
+
+    let request_palette = upload
+        .palette
+        .map(BlockPalette::from_mapping)
+        .transpose()
+        .map_err(|code| bad_request(tr!("server-palette-color-invalid", color = code)))?;
+    let palette = request_palette.as_ref().unwrap_or(server_palette);
+    if palette.mapping.is_empty() {
+        return Err(bad_request(tr!("server-palette-empty")));
+    }
+
+    let model_count = voxel_data.models.len();
+    let indices = upload.models.unwrap_or_else(|| (1..=model_count).collect());
+    if let Some(index) = indices
+        .iter()
+        .find(|index| !(1..=model_count).contains(*index))
+    {
+        return Err(bad_request(tr!(
+            "server-model-index-invalid",
+            index = index
+        )));
+    }
+
+    let mut models = Vec::new();
+    for (i, &index) in indices.iter().enumerate() {
+        let prefix = if indices.len() == 1 {
+            "pattern".to_owned()
+        } else {
+            format!("pattern_{}", i)
+        };
+        let mut bom = BillOfMaterials::default();
+        let mut patterns = Vec::new();
+        create_patterns(
+            &voxel_data.models[index - 1],
+            palette,
+            VoxPalette {
+                colors: &voxel_data.palette,
+                materials: &voxel_data.materials,
+                fill_color: None,
+                index_map: None,
+                locked: &BTreeSet::new(),
+            },
+            &prefix,
+            &mut bom,
+            &ModelOutputs::default(),
+            &mut |file, pattern| {
+                patterns.push((file.to_owned(), pattern.to_vec()));
+                Ok(())
+            },
+        )
+        .map_err(|error| (500, error.to_string()))?;
+        models.push(Converted {
+            index,
+            patterns,
+            materials: bom.entries(),
+        });
+    }
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        net::{SocketAddr, TcpStream},
+    };
+
+    use chisels_and_importers::decode::decode_pattern;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{jar::Jar, testing};
+
+    /// Starts a server on a free port of the loopback interface
+    fn start() -> SocketAddr {
+        let server = bind("127.0.0.1", 0).unwrap();
+        let address = server.server_addr().to_ip().unwrap();
+        std::thread::spawn(|| run(server, testing::palette(), 1 << 20));
+        address
+    }
+
+    /// Sends `POST /convert` and returns the status code and body of the response
+    fn post(address: SocketAddr, headers: &[(&str, &str)], body: &[u8]) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        // HTTP/1.0 so the response is neither chunked nor kept alive
+        let mut request = format!(
+            "POST /convert HTTP/1.0\r\nContent-Length: {}\r\n",
+            body.len()
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let headers_end = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap();
+        let status_line = String::from_utf8_lossy(&response[..headers_end]).into_owned();
+        let status = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, response[headers_end + 4..].to_vec())
+    }
+
+    /// File with a model of 10 red voxels and one of 20 blue voxels, one block each
+    fn two_models() -> Vec<u8> {
+        let red: Vec<_> = (0..10).map(|x| [x, 0, 0, 0]).collect();
+        let blue: Vec<_> = (0..20)
+            .map(|index| [index % 16, index / 16, 0, 1])
+            .collect();
+        testing::vox_file(
+            &[
+                testing::model([16, 16, 16], &red),
+                testing::model([16, 16, 16], &blue),
+            ],
+            &testing::colors(&[[255, 0, 0], [0, 0, 255]]),
+        )
+    }
+
+    /// Bits of every block state of a pattern, checked against its statistics
+    fn blocks(pattern: &[u8]) -> Vec<(String, u32)> {
+        let pattern = decode_pattern(pattern).unwrap();
+        pattern.check().unwrap();
+        pattern
+            .palette
+            .iter()
+            .zip(pattern.counts())
+            .filter(|(state, _)| !state.contains("minecraft:air"))
+            .map(|(state, count)| (state.clone(), count))
+            .collect()
+    }
+
+    fn json_patterns(body: &[u8]) -> Vec<(usize, Vec<u8>)> {
+        let response: Value = serde_json::from_slice(body).unwrap();
+        response["models"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|model| {
+                let index = model["index"].as_u64().unwrap() as usize;
+                model["patterns"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(move |pattern| {
+                        (
+                            index,
+                            STANDARD.decode(pattern["data"].as_str().unwrap()).unwrap(),
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn converts_raw_model() {
+        let address = start();
+        let (status, body) = post(
+            address,
+            &[
+                ("Content-Type", "application/octet-stream"),
+                ("X-Model-Format", "vox"),
+            ],
+            &two_models(),
+        );
+        assert_eq!(status, 200, "{}", String::from_utf8_lossy(&body));
+        let patterns = json_patterns(&body);
+        let bits: Vec<_> = patterns
+            .iter()
+            .map(|(index, pattern)| {
+                let blocks = blocks(pattern);
+                assert_eq!(blocks.len(), 1);
+                (*index, blocks[0].1)
+            })
+            .collect();
+        assert_eq!(bits, [(1, 10), (2, 20)]);
+    }
+
+    #[test]
+    fn converts_multipart_form() {
+        let address = start();
+        let mut form = Vec::new();
+        for (disposition, data) in [
+            (r#"name="model"; filename="castle.vox""#, two_models()),
+            (
+                r#"name="palette""#,
+                br##"{"#0000ff": "minecraft:blue_wool"}"##.to_vec(),
+            ),
+            (r#"name="models""#, b"2".to_vec()),
+        ] {
+            form.extend(
+                format!(
+                    "--fence\r\nContent-Disposition: form-data; {}\r\n\r\n",
+                    disposition
+                )
+                .as_bytes(),
+            );
+            form.extend(data);
+            form.extend(b"\r\n");
+        }
+        form.extend(b"--fence--\r\n");
+
+        let (status, body) = post(
+            address,
+            &[("Content-Type", "multipart/form-data; boundary=fence")],
+            &form,
+        );
+        assert_eq!(status, 200, "{}", String::from_utf8_lossy(&body));
+        let patterns = json_patterns(&body);
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].0, 2);
+        let blocks = blocks(&patterns[0].1);
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].0.contains("minecraft:blue_wool"), "{:?}", blocks);
+        assert_eq!(blocks[0].1, 20);
+    }
+
+    #[test]
+    fn responds_with_zip() {
+        let address = start();
+        let (status, body) = post(
+            address,
+            &[("Accept", ZIP_TYPE), ("X-Models", "1")],
+            &two_models(),
+        );
+        assert_eq!(status, 200, "{}", String::from_utf8_lossy(&body));
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("patterns.zip");
+        std::fs::write(&path, body).unwrap();
+        let archive = Jar::open(&path).unwrap();
+        assert_eq!(archive.names().collect::<Vec<_>>(), ["pattern.cbsbp"]);
+        let pattern = archive.read("pattern.cbsbp").unwrap().unwrap();
+        assert_eq!(blocks(&pattern)[0].1, 10);
+    }
+
+    #[test]
+    fn rejects_unknown_format() {
+        let address = start();
+        let (status, body) = post(address, &[("X-Model-Format", "obj")], &two_models());
+        assert_eq!(status, 400);
+        let response: Value = serde_json::from_slice(&body).unwrap();
+        assert!(response["error"].as_str().unwrap().contains("obj"));
+    }
+
+    #[test]
+    fn taken_port_is_an_error() {
+        let server = bind("127.0.0.1", 0).unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        assert!(matches!(bind("127.0.0.1", port), Err(Error::Input(_))));
+    }
+}
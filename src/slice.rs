@@ -0,0 +1,172 @@
+use std::{fmt::Write, io::IsTerminal, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{console::say_inline, i18n::tr, preview::PreviewVoxel, raster::Pixel};
+
+/// Axis in Minecraft orientation, y is up
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SliceAxis {
+    X,
+    Y,
+    Z,
+}
+
+/// Layer of the model to print, e.g. `y=12`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SliceSpec {
+    pub axis: SliceAxis,
+    pub position: u32,
+}
+
+impl FromStr for SliceSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (axis, position) = s
+            .split_once('=')
+            .ok_or_else(|| tr!("slice-invalid", value = s))?;
+        let axis = match axis.trim() {
+            "x" | "X" => SliceAxis::X,
+            "y" | "Y" => SliceAxis::Y,
+            "z" | "Z" => SliceAxis::Z,
+            other => return Err(tr!("axis-unknown", axis = other)),
+        };
+        let position = position
+            .trim()
+            .parse()
+            .map_err(|_| tr!("slice-layer-invalid", layer = position))?;
+        Ok(Self { axis, position })
+    }
+}
+
+/// Maps a Magica Voxel position to the layer, column and row of a slice
+type CellMapping = fn([u32; 3]) -> (u32, u32, u32);
+
+const BLOCK_SIDE: u32 = 16;
+/// Characters from dark to bright for terminals without color support
+const RAMP: &[u8] = b"@%#*+=-:.";
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
+
+/// Prints one layer of the voxels to stdout, two characters per voxel.
+/// Fails with a message if the layer lies outside the model.
+pub fn print_slice(spec: SliceSpec, size: [u32; 3], voxels: &[PreviewVoxel]) -> Result<(), String> {
+    // Minecraft x, y and z are Magica Voxel y, z and x, the slice is shown as seen from the
+    // positive side of its axis with the rows ordered top to bottom
+    let (layers, columns, rows, to_cell): (u32, u32, u32, CellMapping) = match spec.axis {
+        SliceAxis::X => (size[1], size[0], size[2], |[x, y, z]| (y, x, z)),
+        SliceAxis::Y => (size[2], size[0], size[1], |[x, y, z]| (z, x, y)),
+        SliceAxis::Z => (size[0], size[1], size[2], |[x, y, z]| (x, y, z)),
+    };
+    if spec.position >= layers {
+        return Err(tr!("slice-outside", layer = spec.position, layers = layers));
+    }
+
+    let mut grid = vec![None; columns as usize * rows as usize];
+    for voxel in voxels {
+        let (layer, column, row) = to_cell(voxel.position);
+        if layer == spec.position {
+            grid[row as usize * columns as usize + column as usize] = Some(voxel.color);
+        }
+    }
+
+    let terminal_width = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(DEFAULT_TERMINAL_WIDTH);
+    let true_color = std::io::stdout().is_terminal()
+        && std::env::var("COLORTERM").is_ok_and(|term| term == "truecolor" || term == "24bit");
+
+    say_inline!(
+        "{}",
+        render(&grid, columns, rows, terminal_width, true_color)
+    );
+    Ok(())
+}
+
+fn render(
+    grid: &[Option<Pixel>],
+    columns: u32,
+    rows: u32,
+    terminal_width: usize,
+    true_color: bool,
+) -> String {
+    // Every cell takes two characters plus one for each chunk boundary
+    let needed_width = columns as usize * 2 + (columns / BLOCK_SIDE) as usize;
+    let step = needed_width.div_ceil(terminal_width.max(1)).max(1) as u32;
+
+    let sampled_columns: Vec<u32> = (0..columns).step_by(step as usize).collect();
+    let sampled_rows: Vec<u32> = (0..rows).rev().step_by(step as usize).collect();
+
+    let mut output = String::new();
+    if step > 1 {
+        writeln!(output, "{}", tr!("slice-scaled", factor = step)).unwrap();
+    }
+    let mut previous_row_chunk = sampled_rows.first().map(|row| row / BLOCK_SIDE);
+    for &row in &sampled_rows {
+        if Some(row / BLOCK_SIDE) != previous_row_chunk {
+            output.push_str(&boundary_line(&sampled_columns));
+            previous_row_chunk = Some(row / BLOCK_SIDE);
+        }
+
+        let mut previous_column_chunk = 0;
+        for &column in &sampled_columns {
+            if column / BLOCK_SIDE != previous_column_chunk {
+                output.push('|');
+                previous_column_chunk = column / BLOCK_SIDE;
+            }
+            let cell = sample(grid, columns, rows, column, row, step);
+            match (cell, true_color) {
+                (None, _) => output.push_str("  "),
+                (Some([r, g, b, _]), true) => {
+                    write!(output, "\x1b[48;2;{};{};{}m  \x1b[0m", r, g, b).unwrap()
+                }
+                (Some(color), false) => {
+                    let character = ramp_character(color);
+                    output.push(character);
+                    output.push(character);
+                }
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Picks the first filled voxel inside the block of cells that is shown as one cell
+fn sample(
+    grid: &[Option<Pixel>],
+    columns: u32,
+    rows: u32,
+    column: u32,
+    row: u32,
+    step: u32,
+) -> Option<Pixel> {
+    let row_start = row.saturating_sub(step - 1);
+    (row_start..=row)
+        .rev()
+        .flat_map(|r| (column..(column + step).min(columns)).map(move |c| (c, r)))
+        .filter(|&(_, r)| r < rows)
+        .find_map(|(c, r)| grid[r as usize * columns as usize + c as usize])
+}
+
+fn boundary_line(sampled_columns: &[u32]) -> String {
+    let mut line = String::new();
+    let mut previous_chunk = 0;
+    for &column in sampled_columns {
+        if column / BLOCK_SIDE != previous_chunk {
+            line.push('+');
+            previous_chunk = column / BLOCK_SIDE;
+        }
+        line.push_str("--");
+    }
+    line.push('\n');
+    line
+}
+
+fn ramp_character([r, g, b, _]: Pixel) -> char {
+    let luminance = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0;
+    let index = (luminance * (RAMP.len() - 1) as f32).round() as usize;
+    RAMP[index] as char
+}
@@ -0,0 +1,133 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chisels_and_importers::{gzip, minecraft_axes, BlockModel, PaletteBlock};
+use serde::Serialize;
+
+pub const STRUCTURE_EXTENSION: &str = ".nbt";
+/// Longest side of a structure that structure blocks can save and load
+const MAX_STRUCTURE_SIDE: u32 = 48;
+/// Data version of Minecraft 1.20.1, the game upgrades structures of older versions
+pub const DATA_VERSION: i32 = 3465;
+
+/// Part of a model built from full blocks, one block for every voxel. Positions follow the
+/// Minecraft axes, the z axis of Magica Voxel points up like the y axis of Minecraft.
+pub struct Structure<'a> {
+    /// Position of the structure in the model, in blocks
+    pub offset: [u32; 3],
+    pub size: [u32; 3],
+    /// Non-air blocks with their position in the structure
+    pub blocks: Vec<([u32; 3], &'a PaletteBlock)>,
+}
+
+/// Splits the model into structures that fit into a structure block, or places it into one
+/// structure of any size if it shouldn't be split. Parts without voxels are left out.
+pub fn split_model<'a>(block_model: &BlockModel<'a>, split: bool) -> Vec<Structure<'a>> {
+    let size = block_model.size();
+    let size = minecraft_axes([size.x, size.y, size.z]);
+    let side = if split { MAX_STRUCTURE_SIDE } else { u32::MAX };
+
+    let mut structures = BTreeMap::new();
+    for voxel in block_model.voxels() {
+        let position = minecraft_axes([voxel.x, voxel.y, voxel.z]).map(u32::from);
+        let tile = position.map(|axis| axis / side);
+        let structure = structures.entry(tile).or_insert_with(|| {
+            let offset = tile.map(|axis| axis * side);
+            Structure {
+                offset,
+                size: [0, 1, 2].map(|axis| side.min(size[axis] - offset[axis])),
+                blocks: Vec::new(),
+            }
+        });
+        let position = [0, 1, 2].map(|axis| position[axis] - structure.offset[axis]);
+        structure.blocks.push((position, block_model.block(voxel)));
+    }
+    structures.into_values().collect()
+}
+
+impl<'a> Structure<'a> {
+    /// Block of every non-air position
+    pub fn blocks(&self) -> impl Iterator<Item = &'a PaletteBlock> + '_ {
+        self.blocks.iter().map(|&(_, block)| block)
+    }
+
+    /// Number of block states the structure lists in its palette
+    pub fn state_count(&self) -> usize {
+        self.palette().1.len()
+    }
+
+    /// Palette index of every block with the block states in order of their first use
+    fn palette(&self) -> (Vec<i32>, Vec<&'a PaletteBlock>) {
+        let mut indices = HashMap::new();
+        let mut states = Vec::new();
+        let blocks = self
+            .blocks()
+            .map(|block| {
+                *indices.entry(block.state()).or_insert_with(|| {
+                    states.push(block);
+                    states.len() as i32 - 1
+                })
+            })
+            .collect();
+        (blocks, states)
+    }
+
+    /// The structure as a gzip compressed NBT file, like the game saves it
+    pub fn to_nbt(&self, data_version: i32) -> Vec<u8> {
+        let (indices, states) = self.palette();
+        let file = StructureFile {
+            data_version,
+            size: self.size.map(|side| side as i32).to_vec(),
+            palette: states.into_iter().map(NbtBlockState::new).collect(),
+            blocks: self
+                .blocks
+                .iter()
+                .zip(indices)
+                .map(|(&(position, _), state)| StructureBlock {
+                    pos: position.map(|axis| axis as i32).to_vec(),
+                    state,
+                })
+                .collect(),
+            entities: Vec::new(),
+        };
+        gzip::compress(&fastnbt::to_bytes(&file).unwrap())
+    }
+}
+
+/// Block state in the NBT form structure files use
+#[derive(Serialize)]
+pub struct NbtBlockState<'a> {
+    #[serde(rename = "Name")]
+    name: &'a str,
+    #[serde(rename = "Properties", skip_serializing_if = "BTreeMap::is_empty")]
+    properties: &'a BTreeMap<String, String>,
+}
+
+impl<'a> NbtBlockState<'a> {
+    pub const AIR: NbtBlockState<'static> = NbtBlockState {
+        name: "minecraft:air",
+        properties: &BTreeMap::new(),
+    };
+
+    pub fn new(block: &'a PaletteBlock) -> Self {
+        Self {
+            name: &block.name,
+            properties: &block.properties,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StructureFile<'a> {
+    #[serde(rename = "DataVersion")]
+    data_version: i32,
+    size: Vec<i32>,
+    palette: Vec<NbtBlockState<'a>>,
+    blocks: Vec<StructureBlock>,
+    entities: Vec<fastnbt::Value>,
+}
+
+#[derive(Serialize)]
+struct StructureBlock {
+    pos: Vec<i32>,
+    state: i32,
+}
@@ -0,0 +1,333 @@
+use std::{collections::BTreeSet, fmt, time::Instant};
+
+use chisels_and_importers::BlockModel;
+use serde::Serialize;
+
+use crate::{bom::BillOfMaterials, console, i18n::tr, ChunkReport, ColorMatch, ColorReduction};
+
+/// Results of converting a single model
+#[derive(Default)]
+pub struct ModelReport {
+    /// Written pattern files with their statistics
+    pub patterns: Vec<(String, ChunkReport)>,
+    /// Written structure files with a description of their contents, when writing
+    /// structures instead of patterns
+    pub structures: Vec<(String, String)>,
+    /// Chunks that did not produce a pattern
+    pub skipped_chunks: Vec<SkippedChunk>,
+    pub warnings: Vec<String>,
+    /// Block chosen for every color of the model
+    pub color_matches: Vec<ColorMatch>,
+    /// Colors merged by --max-colors
+    pub color_reduction: Option<ColorReduction>,
+}
+
+/// Block sized part of a model that no pattern was written for
+#[derive(Serialize)]
+pub struct SkippedChunk {
+    /// Position of the chunk in the model, in voxels
+    pub offset: (u8, u8, u8),
+    pub reason: SkipReason,
+}
+
+impl SkippedChunk {
+    /// Chunks of the model that are not encoded with their reason, in chunk order
+    pub fn of_model(block_model: &BlockModel) -> Vec<SkippedChunk> {
+        // Chunks without any voxels would only contain air
+        block_model
+            .chunks()
+            .iter()
+            .filter(|chunk| !block_model.is_encoded(chunk))
+            .map(|chunk| SkippedChunk {
+                offset: chunk.offset,
+                reason: if chunk.left_out > 0 {
+                    SkipReason::LeftOut
+                } else {
+                    SkipReason::Empty
+                },
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// The chunk contains no voxels
+    Empty,
+    /// Every voxel of the chunk was left out, as translucent with `--alpha skip` or mapped to
+    /// air by the index map
+    LeftOut,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Empty => write!(f, "{}", tr!("skip-empty")),
+            SkipReason::LeftOut => write!(f, "{}", tr!("skip-left-out")),
+        }
+    }
+}
+
+/// Totals over every model converted in one run, or over every file of a directory
+pub struct RunSummary {
+    started: Instant,
+    patterns_written: usize,
+    patterns_skipped: usize,
+    structures_written: usize,
+    warnings: usize,
+    /// Colors of the models with merged colors, before and after merging
+    colors_before: usize,
+    colors_after: usize,
+    /// Light-emitting blocks, called out since they are expensive to gather
+    light_sources: BTreeSet<String>,
+    /// Blocks of every written pattern or structure
+    materials: BillOfMaterials,
+}
+
+impl RunSummary {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            patterns_written: 0,
+            patterns_skipped: 0,
+            structures_written: 0,
+            warnings: 0,
+            colors_before: 0,
+            colors_after: 0,
+            light_sources: BTreeSet::new(),
+            materials: BillOfMaterials::default(),
+        }
+    }
+
+    /// Lists how many of the blocks the build needs in the summary
+    pub fn with_light_sources(mut self, blocks: BTreeSet<String>) -> Self {
+        self.light_sources = blocks;
+        self
+    }
+
+    /// Counts the blocks of the bill of materials in the summary
+    pub fn with_materials(mut self, bom: BillOfMaterials) -> Self {
+        self.materials = bom;
+        self
+    }
+
+    /// Adds the totals of another file, the time is still counted from the start of this one
+    pub fn merge(&mut self, other: RunSummary) {
+        self.patterns_written += other.patterns_written;
+        self.patterns_skipped += other.patterns_skipped;
+        self.structures_written += other.structures_written;
+        self.warnings += other.warnings;
+        self.colors_before += other.colors_before;
+        self.colors_after += other.colors_after;
+        self.light_sources.extend(other.light_sources);
+        self.materials.merge(&other.materials);
+    }
+
+    /// Counts the patterns or structures of a converted model and prints its warnings
+    pub fn add_model(&mut self, report: &ModelReport) {
+        self.patterns_written += report.patterns.len();
+        self.patterns_skipped += report.skipped_chunks.len();
+        self.structures_written += report.structures.len();
+        if let Some(reduction) = report.color_reduction {
+            self.colors_before += reduction.colors_before;
+            self.colors_after += reduction.colors_after;
+        }
+        for warning in &report.warnings {
+            self.warn(warning);
+        }
+    }
+
+    /// Prints a warning and counts it
+    pub fn warn(&mut self, warning: &str) {
+        console::warn(warning);
+        self.warnings += 1;
+    }
+
+    /// Combines the counts with the block totals of the bill of materials
+    pub fn finish(&self) -> impl fmt::Display + '_ {
+        FinishedSummary {
+            summary: self,
+            bom: &self.materials,
+        }
+    }
+}
+
+struct FinishedSummary<'a> {
+    summary: &'a RunSummary,
+    bom: &'a BillOfMaterials,
+}
+
+impl fmt::Display for FinishedSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = self.summary;
+        if summary.structures_written > 0 {
+            let message = tr!(
+                "summary-structures",
+                written = summary.structures_written,
+                blocks = self.bom.full_blocks(),
+                seconds = format!("{:.2}", summary.started.elapsed().as_secs_f64()),
+                warnings = summary.warnings,
+            );
+            f.write_str(&message)?;
+            return self.write_light_sources(f);
+        }
+        let message = tr!(
+            "summary",
+            written = summary.patterns_written,
+            skipped = summary.patterns_skipped,
+            bits = self.bom.total_bits(),
+            blocks = self.bom.block_count(),
+            seconds = format!("{:.2}", summary.started.elapsed().as_secs_f64()),
+            warnings = summary.warnings,
+        );
+        f.write_str(&message)?;
+        if summary.colors_before > 0 {
+            let merged = tr!(
+                "summary-colors-merged",
+                before = summary.colors_before,
+                after = summary.colors_after,
+            );
+            write!(f, "\n{}", merged)?;
+        }
+        self.write_light_sources(f)
+    }
+}
+
+impl FinishedSummary<'_> {
+    /// Line with the full blocks of every light-emitting block the build uses, if any
+    fn write_light_sources(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<_> = self
+            .bom
+            .entries()
+            .into_iter()
+            .filter(|entry| self.summary.light_sources.contains(&entry.block))
+            .collect();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let blocks: Vec<_> = entries
+            .iter()
+            .map(|entry| format!("{} {}", entry.full_blocks, entry.block))
+            .collect();
+        let message = tr!(
+            "summary-light-sources",
+            count = entries.iter().map(|entry| entry.full_blocks).sum::<u64>(),
+            blocks = blocks.join(", "),
+        );
+        write!(f, "\n{}", message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chisels_and_importers::AlphaMode;
+
+    use super::*;
+    use crate::{testing, Converter};
+
+    /// Model two chunks long with voxels only in the first chunk
+    fn half_empty_model() -> dot_vox::Model {
+        dot_vox::Model {
+            size: dot_vox::Size {
+                x: 32,
+                y: 16,
+                z: 16,
+            },
+            voxels: vec![dot_vox::Voxel {
+                x: 3,
+                y: 4,
+                z: 5,
+                i: 0,
+            }],
+        }
+    }
+
+    fn skipped(keep_empty: bool) -> Vec<SkippedChunk> {
+        let palette = testing::palette();
+        let converter = Converter::new(&palette).with_empty_chunks(keep_empty);
+        let gray = dot_vox::Color {
+            r: 128,
+            g: 128,
+            b: 128,
+            a: 255,
+        };
+        let colors = vec![gray; 256];
+        SkippedChunk::of_model(&converter.match_blocks(&half_empty_model(), &colors))
+    }
+
+    #[test]
+    fn empty_chunk_is_skipped_as_empty() {
+        let skipped = skipped(false);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].offset, (16, 0, 0));
+        assert!(matches!(skipped[0].reason, SkipReason::Empty));
+        assert_eq!(skipped[0].reason.to_string(), "empty");
+    }
+
+    #[test]
+    fn translucent_chunk_is_skipped_as_left_out() {
+        let palette = testing::palette();
+        let converter = Converter::new(&palette).with_alpha(AlphaMode::Skip, 128);
+        // The second chunk only has a voxel of the translucent second color
+        let mut model = half_empty_model();
+        model.voxels.push(dot_vox::Voxel {
+            x: 20,
+            y: 4,
+            z: 5,
+            i: 1,
+        });
+        let mut colors = testing::colors(&[[128, 128, 128], [0, 0, 255]]);
+        colors[1].a = 64;
+        let skipped = SkippedChunk::of_model(&converter.match_blocks(&model, &colors));
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].offset, (16, 0, 0));
+        assert!(matches!(skipped[0].reason, SkipReason::LeftOut));
+    }
+
+    #[test]
+    fn kept_empty_chunk_is_not_skipped() {
+        assert!(skipped(true).is_empty());
+    }
+
+    #[test]
+    fn summary_counts_skipped_chunks() {
+        let mut summary = RunSummary::start();
+        summary.add_model(&ModelReport {
+            skipped_chunks: skipped(false),
+            ..Default::default()
+        });
+        let line = summary.finish().to_string();
+        assert!(
+            line.starts_with("0 patterns written, 1 skipped,"),
+            "{}",
+            line
+        );
+    }
+
+    #[test]
+    fn summaries_of_files_are_added_up() {
+        let file_summary = |blocks: &[&str]| {
+            let mut bom = BillOfMaterials::default();
+            bom.add_chunk(blocks.iter().map(|&block| (block, 4096)));
+            let mut summary = RunSummary::start().with_materials(bom);
+            summary.add_model(&ModelReport {
+                skipped_chunks: skipped(false),
+                ..Default::default()
+            });
+            summary.warn("large");
+            summary
+        };
+        let mut total = RunSummary::start();
+        total.merge(file_summary(&["minecraft:stone"]));
+        total.merge(file_summary(&["minecraft:stone", "minecraft:dirt"]));
+
+        let line = total.finish().to_string();
+        assert!(
+            line.starts_with("0 patterns written, 2 skipped, 12288 bits of 2 blocks in"),
+            "{}",
+            line
+        );
+        assert!(line.ends_with("2 warnings"), "{}", line);
+    }
+}
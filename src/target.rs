@@ -0,0 +1,87 @@
+//! Versions of Chisels & Bits that pattern files are written for. Every version stores the
+//! same bits and block palette, they differ in the version numbers the data is wrapped with.
+//! Only versions whose format is known from the mod or was tried in game are listed, a new
+//! one needs a [`PatternTarget`] and an entry in [`TARGETS`].
+
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer};
+
+use crate::i18n::tr;
+
+/// Serialization variant of the pattern files of a version of Chisels & Bits
+pub trait PatternTarget: Sync {
+    /// Name the target is picked with, e.g. `cb-forge-1.20`
+    fn name(&self) -> &'static str;
+    /// `version` of the NBT container around the compressed chisel data
+    fn container_version(&self) -> u32;
+    /// `version` of the pattern JSON
+    fn file_version(&self) -> &'static str;
+}
+
+/// Chisels & Bits for Forge on Minecraft 1.20
+struct Forge120;
+
+impl PatternTarget for Forge120 {
+    fn name(&self) -> &'static str {
+        "cb-forge-1.20"
+    }
+
+    fn container_version(&self) -> u32 {
+        0
+    }
+
+    fn file_version(&self) -> &'static str {
+        "1.0"
+    }
+}
+
+/// Every target, the first is the default
+pub const TARGETS: [&dyn PatternTarget; 1] = [&Forge120];
+
+/// Version of Chisels & Bits the patterns are written for, picked by name
+#[derive(Clone, Copy)]
+pub struct Target(&'static dyn PatternTarget);
+
+impl Target {
+    pub fn name(self) -> &'static str {
+        self.0.name()
+    }
+
+    pub fn format(self) -> &'static dyn PatternTarget {
+        self.0
+    }
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self(TARGETS[0])
+    }
+}
+
+impl std::fmt::Debug for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TARGETS
+            .into_iter()
+            .find(|target| target.name() == s)
+            .map(Target)
+            .ok_or_else(|| {
+                let names: Vec<_> = TARGETS.iter().map(|target| target.name()).collect();
+                tr!("target-unknown", target = s, targets = names.join(", "))
+            })
+    }
+}
+
+impl Serialize for Target {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
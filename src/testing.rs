@@ -0,0 +1,136 @@
+//! Models and palettes shared by the tests of several modules
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use chisels_and_importers::BlockPalette;
+
+/// Counts the bytes every thread has allocated, so tests can check how much memory a
+/// conversion needs at once
+struct CountingAllocator;
+
+thread_local! {
+    /// Bytes allocated by the thread and not freed, freeing memory of other threads lowers it
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+    /// Most bytes allocated at once since the last call of [`peak_allocation`]
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+fn count(bytes: isize) {
+    // The thread locals can be gone while a thread exits
+    let _ = ALLOCATED.try_with(|allocated| {
+        allocated.set(allocated.get() + bytes);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(allocated.get())));
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(-(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size as isize - layout.size() as isize);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Result of the function with the most bytes the current thread had allocated at once while
+/// running it, on top of those it had allocated before
+pub fn peak_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.get();
+    PEAK.set(before);
+    let result = f();
+    (result, (PEAK.get() - before) as usize)
+}
+
+/// The palette of the repository
+pub fn palette() -> BlockPalette {
+    BlockPalette::from_json(include_bytes!("../blocks.json")).unwrap()
+}
+
+/// Palette with the given colors at the first indices, the rest black
+pub fn colors(first: &[[u8; 3]]) -> Vec<dot_vox::Color> {
+    let mut colors = vec![
+        dot_vox::Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255
+        };
+        256
+    ];
+    for (color, &[r, g, b]) in colors.iter_mut().zip(first) {
+        *color = dot_vox::Color { r, g, b, a: 255 };
+    }
+    colors
+}
+
+pub fn model(size: [u32; 3], voxels: &[[u8; 4]]) -> dot_vox::Model {
+    dot_vox::Model {
+        size: dot_vox::Size {
+            x: size[0],
+            y: size[1],
+            z: size[2],
+        },
+        voxels: voxels
+            .iter()
+            .map(|&[x, y, z, i]| dot_vox::Voxel { x, y, z, i })
+            .collect(),
+    }
+}
+
+/// Chunk of a Magica Voxel file without children
+fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend((content.len() as u32).to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(content);
+    bytes
+}
+
+/// The models as a Magica Voxel file without a scene graph
+pub fn vox_file(models: &[dot_vox::Model], colors: &[dot_vox::Color]) -> Vec<u8> {
+    let mut children = Vec::new();
+    for model in models {
+        let size = [model.size.x, model.size.y, model.size.z];
+        children.extend(chunk(b"SIZE", &size.map(u32::to_le_bytes).concat()));
+        let mut xyzi = (model.voxels.len() as u32).to_le_bytes().to_vec();
+        for voxel in &model.voxels {
+            // Indices in the file start at 1
+            xyzi.extend([voxel.x, voxel.y, voxel.z, voxel.i + 1]);
+        }
+        children.extend(chunk(b"XYZI", &xyzi));
+    }
+    let rgba: Vec<u8> = colors.iter().flat_map(|c| [c.r, c.g, c.b, c.a]).collect();
+    children.extend(chunk(b"RGBA", &rgba));
+
+    let mut file = b"VOX ".to_vec();
+    file.extend(150u32.to_le_bytes());
+    file.extend(b"MAIN");
+    file.extend(0u32.to_le_bytes());
+    file.extend((children.len() as u32).to_le_bytes());
+    file.extend(children);
+    file
+}
+
+/// The file with an IMAP chunk, which shows the palette index at every position in Magica
+/// Voxel
+pub fn with_index_map(mut file: Vec<u8>, index_map: &[u8; 256]) -> Vec<u8> {
+    file.extend(chunk(b"IMAP", index_map));
+    // Size of the children of the main chunk
+    let children = u32::from_le_bytes(file[16..20].try_into().unwrap()) + 12 + 256;
+    file[16..20].copy_from_slice(&children.to_le_bytes());
+    file
+}
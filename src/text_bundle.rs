@@ -0,0 +1,192 @@
+use std::{fmt::Write as _, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chisels_and_importers::gzip::crc32;
+
+use crate::{attribution::Attribution, i18n::tr};
+
+const HEADER: &str = "chisels_and_importers text bundle";
+/// Version of the bundle format, written after the header
+const BUNDLE_VERSION: u32 = 1;
+/// Base64 characters per line, short enough for chat messages to not wrap them
+const LINE_WIDTH: usize = 76;
+
+/// Pattern files collected into a single text file that can be pasted anywhere
+#[derive(Default)]
+pub struct TextBundle {
+    patterns: Vec<BundledPattern>,
+}
+
+struct BundledPattern {
+    file: String,
+    /// Position of the chunk in the model, in voxels
+    offset: (u8, u8, u8),
+    data: Vec<u8>,
+}
+
+impl TextBundle {
+    pub fn add(&mut self, file: &str, offset: (u8, u8, u8), data: Vec<u8>) {
+        let file = Path::new(file).file_name().unwrap().to_string_lossy();
+        self.patterns.push(BundledPattern {
+            file: file.into_owned(),
+            offset,
+            data,
+        });
+    }
+
+    /// Writes the bundle with a header listing the model name, its attribution and the
+    /// blocks it needs. Every line of pattern data starts with its CRC-32 to detect damaged
+    /// lines.
+    pub fn write_file(
+        &self,
+        path: &Path,
+        name: &str,
+        attribution: &Attribution,
+        blocks: &[String],
+    ) -> std::io::Result<()> {
+        let mut output = String::new();
+        writeln!(output, "{} {}", HEADER, BUNDLE_VERSION).unwrap();
+        writeln!(output, "model: {}", name).unwrap();
+        for (field, value) in attribution.fields() {
+            writeln!(output, "{}: {}", field, value).unwrap();
+        }
+        writeln!(output, "blocks: {}", blocks.join(", ")).unwrap();
+        writeln!(output, "patterns: {}", self.patterns.len()).unwrap();
+
+        for pattern in &self.patterns {
+            let encoded = STANDARD.encode(&pattern.data);
+            let lines: Vec<_> = encoded.as_bytes().chunks(LINE_WIDTH).collect();
+            let (x, y, z) = pattern.offset;
+            writeln!(output).unwrap();
+            writeln!(
+                output,
+                "pattern {} at {},{},{} lines {}",
+                pattern.file,
+                x,
+                y,
+                z,
+                lines.len()
+            )
+            .unwrap();
+            for line in lines {
+                // Base64 is ASCII, so every chunk is valid UTF-8
+                let line = std::str::from_utf8(line).unwrap();
+                writeln!(output, "{:08x} {}", crc32(line.as_bytes()), line).unwrap();
+            }
+        }
+        std::fs::write(path, output)
+    }
+}
+
+/// Recreates the pattern files of a bundle in the directory and returns their names.
+/// Fails without writing anything if a line is damaged.
+pub fn import(path: &Path, directory: &Path) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| tr!("bundle-read-failed", error = e))?;
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()));
+
+    let version = lines
+        .next()
+        .and_then(|(_, line)| line.strip_prefix(HEADER))
+        .and_then(|version| version.trim().parse::<u32>().ok())
+        .ok_or_else(|| tr!("bundle-invalid"))?;
+    if version > BUNDLE_VERSION {
+        return Err(tr!(
+            "bundle-version-unsupported",
+            version = version,
+            supported = BUNDLE_VERSION
+        ));
+    }
+
+    let mut patterns = Vec::new();
+    while let Some((number, line)) = lines.next() {
+        let Some(description) = line.strip_prefix("pattern ") else {
+            continue;
+        };
+        // Split from the end, the file name may contain spaces
+        let parts: Vec<_> = description.rsplitn(5, ' ').collect();
+        let (file, line_count) = match parts[..] {
+            [count, "lines", _, "at", file] => (file, count.parse::<usize>().ok()),
+            _ => (description, None),
+        };
+        let line_count = line_count.ok_or_else(|| tr!("bundle-header-invalid", line = number))?;
+        // Only plain file names, a bundle must not write outside the directory
+        if Path::new(file).file_name() != Some(file.as_ref()) {
+            return Err(tr!("bundle-file-name-invalid", line = number, file = file));
+        }
+
+        let mut encoded = String::new();
+        for _ in 0..line_count {
+            let (number, line) = lines
+                .next()
+                .ok_or_else(|| tr!("bundle-pattern-incomplete", file = file))?;
+            let (checksum, data) = line
+                .split_once(' ')
+                .ok_or_else(|| tr!("bundle-checksum-missing", line = number))?;
+            if u32::from_str_radix(checksum, 16).ok() != Some(crc32(data.as_bytes())) {
+                return Err(tr!("bundle-checksum-mismatch", line = number));
+            }
+            encoded.push_str(data);
+        }
+        let data = STANDARD
+            .decode(&encoded)
+            .map_err(|e| tr!("bundle-base64-invalid", file = file, error = e))?;
+        patterns.push((file.to_owned(), data));
+    }
+
+    for (file, data) in &patterns {
+        std::fs::write(directory.join(file), data)
+            .map_err(|e| tr!("write-failed", file = file, error = e))?;
+    }
+    Ok(patterns.into_iter().map(|(file, _)| file).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_lists_attribution() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("castle.txt");
+        let attribution = Attribution {
+            author: Some("Ann".to_owned()),
+            license: Some("CC-BY-4.0".to_owned()),
+            description: Some("Towers\nand walls".to_owned()),
+        };
+        let mut bundle = TextBundle::default();
+        bundle.add("castle_0.cbsbp", (0, 0, 0), vec![1, 2, 3]);
+        bundle
+            .write_file(
+                &path,
+                "castle",
+                &attribution,
+                &["minecraft:stone".to_owned()],
+            )
+            .unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let header: Vec<_> = text.lines().take(6).collect();
+        assert_eq!(
+            header,
+            [
+                "chisels_and_importers text bundle 1",
+                "model: castle",
+                "author: Ann",
+                "license: CC-BY-4.0",
+                "description: Towers and walls",
+                "blocks: minecraft:stone",
+            ]
+        );
+        // The header doesn't get in the way of importing
+        let imported = directory.path().join("imported");
+        std::fs::create_dir(&imported).unwrap();
+        assert_eq!(import(&path, &imported).unwrap(), ["castle_0.cbsbp"]);
+        assert_eq!(
+            std::fs::read(imported.join("castle_0.cbsbp")).unwrap(),
+            [1, 2, 3]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+/// Prints a notice if a newer release than this build has been published. Nothing is
+/// downloaded besides the release information.
+#[cfg(feature = "network")]
+pub fn check_for_update() {
+    use crate::i18n::tr;
+
+    const LATEST_RELEASE: &str =
+        "https://api.github.com/repos/Alainx277/chisels-and-importers/releases/latest";
+
+    match newer_release(LATEST_RELEASE, env!("CARGO_PKG_VERSION")) {
+        Ok(Some(notice)) => println!("{}", notice),
+        Ok(None) => {}
+        Err(error) => eprintln!(
+            "{}",
+            tr!("warning", message = tr!("update-failed", error = error))
+        ),
+    }
+}
+
+#[cfg(not(feature = "network"))]
+pub fn check_for_update() {
+    use crate::i18n::tr;
+
+    eprintln!("{}", tr!("warning", message = tr!("update-no-network")));
+}
+
+/// Asks the GitHub API at the URL for the latest release and returns a notice if it is
+/// newer than the current version
+#[cfg(feature = "network")]
+fn newer_release(url: &str, current: &str) -> Result<Option<String>, String> {
+    use std::time::Duration;
+
+    use serde::Deserialize;
+
+    use crate::i18n::tr;
+
+    /// Short so that checking without a connection doesn't hold up the conversion
+    const TIMEOUT: Duration = Duration::from_secs(3);
+
+    #[derive(Deserialize)]
+    struct Release {
+        tag_name: String,
+        html_url: String,
+    }
+
+    let release: Release = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .build()
+        .get(url)
+        .set("User-Agent", env!("CARGO_PKG_NAME"))
+        .call()
+        .map_err(|e| e.to_string())
+        .and_then(|response| {
+            serde_json::from_reader(response.into_reader()).map_err(|e| e.to_string())
+        })?;
+
+    let notice = match (parse_version(&release.tag_name), parse_version(current)) {
+        (Some(latest), Some(running)) if latest > running => Some(tr!(
+            "update-available",
+            latest = release.tag_name.trim_start_matches('v'),
+            current = current,
+            url = release.html_url,
+        )),
+        _ => None,
+    };
+    Ok(notice)
+}
+
+/// Parses `major.minor.patch` with an optional `v` prefix, ignoring pre-release and build
+/// suffixes
+#[cfg(feature = "network")]
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim().trim_start_matches('v');
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+    let version = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(version)
+}
+
+#[cfg(all(test, feature = "network"))]
+mod tests {
+    use tiny_http::{Response, Server};
+
+    use super::*;
+
+    /// URL of a server on the loopback interface that answers one request with the status
+    /// and body, like the GitHub API would
+    fn mock_release(status: u16, body: &'static str) -> String {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/releases/latest", server.server_addr());
+        std::thread::spawn(move || {
+            let request = server.recv().unwrap();
+            let response = Response::from_string(body).with_status_code(status);
+            request.respond(response).unwrap();
+        });
+        url
+    }
+
+    const RELEASE: &str = r#"{
+        "tag_name": "v1.3.0",
+        "html_url": "https://github.com/Alainx277/chisels-and-importers/releases/tag/v1.3.0",
+        "name": "Chisels and Importers 1.3"
+    }"#;
+
+    #[test]
+    fn newer_release_is_announced() {
+        let notice = newer_release(&mock_release(200, RELEASE), "1.2.9")
+            .unwrap()
+            .unwrap();
+        assert!(notice.contains("1.3.0"), "{}", notice);
+        assert!(notice.contains("1.2.9"), "{}", notice);
+        assert!(notice.contains("/releases/tag/v1.3.0"), "{}", notice);
+    }
+
+    #[test]
+    fn current_and_older_releases_are_not_announced() {
+        assert_eq!(
+            newer_release(&mock_release(200, RELEASE), "1.3.0"),
+            Ok(None)
+        );
+        assert_eq!(
+            newer_release(&mock_release(200, RELEASE), "2.0.0"),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn failed_requests_are_errors() {
+        let not_found = mock_release(404, r#"{"message": "Not Found"}"#);
+        assert!(newer_release(&not_found, "1.0.0").is_err());
+        assert!(newer_release(&mock_release(200, "<html>"), "1.0.0").is_err());
+    }
+
+    #[test]
+    fn versions_are_compared_by_number() {
+        assert_eq!(parse_version("v1.10.0"), Some((1, 10, 0)));
+        assert_eq!(parse_version("2.0.0-beta.1+build5"), Some((2, 0, 0)));
+        assert!(parse_version("v1.10.0") > parse_version("1.9.3"));
+        assert_eq!(parse_version("1.2"), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+    }
+}
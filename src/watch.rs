@@ -0,0 +1,144 @@
+//! Converting a model again every time it or its palette is saved
+
+use std::{
+    path::Path,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::{console::say, convert, error::Error, i18n::tr, writes_archive, Args, STDIO};
+
+/// Time without further changes before converting again, Magica Voxel writes a file in
+/// several passes
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+enum Event {
+    Changed,
+    Stop,
+}
+
+/// Converts the model, then again every time the model or the palette file changes until
+/// Ctrl+C is pressed. A failed conversion is reported and the files are watched on. Patterns
+/// of the previous conversion that the new one didn't write again are removed, so a smaller
+/// model leaves no blocks of the old one behind.
+pub fn watch(args: &Args) -> Result<(), Error> {
+    let model = args.model.as_deref().unwrap_or_default();
+    if model == STDIO {
+        return Err(Error::Input(tr!("watch-stdin")));
+    }
+    if !Path::new(model).is_file() {
+        return Err(Error::Input(tr!("model-not-found", file = model)));
+    }
+    let failed = |error: &dyn std::fmt::Display| Error::Input(tr!("watch-failed", error = error));
+
+    // The directories are watched, saving often replaces a file with a new one
+    let mut files = Vec::new();
+    let mut directories = Vec::new();
+    for path in [model, &args.palette].map(Path::new) {
+        let Some(name) = path.file_name().filter(|_| path.is_file()) else {
+            continue;
+        };
+        let directory = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        let directory = directory.canonicalize().map_err(|e| failed(&e))?;
+        files.push(directory.join(name));
+        if !directories.contains(&directory) {
+            directories.push(directory);
+        }
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let stop = sender.clone();
+    ctrlc::set_handler(move || {
+        let _ = stop.send(Event::Stop);
+    })
+    .map_err(|e| failed(&e))?;
+    let watched = files.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let changed = event.is_ok_and(|event| {
+            !event.kind.is_access() && event.paths.iter().any(|path| watched.contains(path))
+        });
+        if changed {
+            let _ = sender.send(Event::Changed);
+        }
+    })
+    .map_err(|e| failed(&e))?;
+    for directory in &directories {
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(|e| failed(&e))?;
+    }
+
+    let names: Vec<_> = files
+        .iter()
+        .map(|file| file.display().to_string())
+        .collect();
+    say!("{}", tr!("watch-start", files = names.join(", ")));
+    // Loose pattern files of the previous conversion
+    let mut written: Vec<String> = Vec::new();
+    loop {
+        say!("{}", tr!("watch-run", time = time_of_day(), file = model));
+        match convert(args, &mut |_, _| {}) {
+            Ok(patterns) => {
+                if !writes_archive(args) && args.output != STDIO {
+                    remove_stale(&written, &patterns);
+                    written = patterns;
+                }
+            }
+            Err(error) => eprintln!("{}", tr!("error", message = error)),
+        }
+        if !wait_for_change(&receiver) {
+            break;
+        }
+    }
+    say!("{}", tr!("watch-stopped"));
+    Ok(())
+}
+
+/// Waits until a watched file changed and no further changes followed for the debounce time.
+/// Returns false once Ctrl+C was pressed.
+fn wait_for_change(receiver: &Receiver<Event>) -> bool {
+    match receiver.recv() {
+        Ok(Event::Changed) => {}
+        Ok(Event::Stop) | Err(_) => return false,
+    }
+    loop {
+        match receiver.recv_timeout(DEBOUNCE) {
+            Ok(Event::Changed) => {}
+            Ok(Event::Stop) | Err(RecvTimeoutError::Disconnected) => return false,
+            Err(RecvTimeoutError::Timeout) => return true,
+        }
+    }
+}
+
+/// Removes the patterns of the previous conversion that weren't written again
+fn remove_stale(previous: &[String], current: &[String]) {
+    for file in previous.iter().filter(|file| !current.contains(file)) {
+        match std::fs::remove_file(file) {
+            Ok(()) => say!("{}", tr!("watch-removed", file = file)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                let message = tr!("watch-remove-failed", file = file, error = e);
+                eprintln!("{}", tr!("warning", message = message));
+            }
+        }
+    }
+}
+
+/// Current time as `HH:MM:SS` in UTC
+fn time_of_day() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+        % (24 * 60 * 60);
+    format!(
+        "{:02}:{:02}:{:02} UTC",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}